@@ -0,0 +1,847 @@
+use crate::ast::{Ast, AstNode, Pattern, Symbol};
+use crate::error_handling::{Diagnostic, Label, Suggestion};
+use crate::runtime::{CheckedProgram, Session};
+use crate::type_inferencer::ast::Term;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A name bound by a declaration, a parameter, a `let` or a pattern
+#[derive(PartialEq, Debug, Clone)]
+struct Binding {
+    name: String,
+    /// The type variable of the bound value, if it has one
+    label: Option<Symbol>,
+    /// Where the name is written where it's bound
+    span: Range<usize>,
+}
+
+/// An expression along with the names it binds and the local names in scope at it
+struct Scoped<'a> {
+    expr: &'a Ast,
+    binders: Vec<Binding>,
+    locals: Vec<Binding>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CompletionKind {
+    Function,
+    Variable,
+    Constructor,
+}
+
+/// A name that is in scope at some position, along with its type if it's known
+#[derive(PartialEq, Debug, Clone)]
+pub struct Completion {
+    pub name: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+}
+
+/// A span of a file, relative to the start of the file
+#[derive(PartialEq, Debug, Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub span: Range<usize>,
+}
+
+/// A document that has been checked in the context of a session, which answers the questions
+/// an editor asks about it. Positions are byte offsets into the document's text.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Analysis {
+    checked: CheckedProgram,
+    /// Where the document is in the checked source
+    document: Range<usize>,
+}
+
+impl Analysis {
+    pub fn new(session: &Session, path: &Path, text: &str) -> Self {
+        let checked = session.check(path, text);
+        let document = checked.offset..(checked.offset + text.len());
+        Analysis { checked, document }
+    }
+
+    /// Returns whether the document parsed, so that there are expressions to ask about
+    pub fn parsed(&self) -> bool {
+        !self.checked.program.is_empty()
+    }
+
+    /// Returns the problems found in the document, with spans relative to it. Problems in the
+    /// files it imports are placed at the start of the document, with the file's name in the
+    /// message, and labels and suggestions outside of the document are left out.
+    pub fn problems(&self) -> Vec<Diagnostic> {
+        self.checked
+            .problems
+            .iter()
+            .map(|problem| {
+                let mut problem = problem.clone();
+                problem.labels = (problem.labels.into_iter())
+                    .filter_map(|label| {
+                        let span = self.in_document(&label.span)?;
+                        Some(Label { span, ..label })
+                    })
+                    .collect();
+                problem.suggestions = (problem.suggestions.into_iter())
+                    .filter_map(|suggestion| {
+                        let span = self.in_document(&suggestion.span)?;
+                        Some(Suggestion { span, ..suggestion })
+                    })
+                    .collect();
+                if let Some(span) = &problem.span {
+                    match self.in_document(span) {
+                        Some(span) => problem.span = Some(span),
+                        None => {
+                            let (path, _, _) =
+                                self.checked.source_map.locate(span, &self.checked.source);
+                            problem.message = format!("In {}: {}", path.display(), problem.message);
+                            problem.span = Some(0..0);
+                        }
+                    }
+                }
+                problem
+            })
+            .collect()
+    }
+
+    /// Returns the inferred type of the name or expression at a position, along with the
+    /// span it applies to
+    pub fn hover(&self, index: usize) -> Option<(Range<usize>, String)> {
+        let index = self.document.start + index;
+        let scoped = self.scoped_expressions();
+        if let Some(binder) = scoped
+            .iter()
+            .flat_map(|scoped| scoped.binders.iter())
+            .find(|binder| contains(&binder.span, index))
+        {
+            let term = self.binding_type(binder)?;
+            let span = self.in_document(&binder.span)?;
+            return Some((span, format!("{}: {}", binder.name, display_term(&term))));
+        }
+
+        let scoped = innermost(&scoped, index, contains)?;
+        let term = self.type_of(scoped.expr.label)?;
+        let span = self.in_document(&scoped.expr.src_loc.span)?;
+        match &scoped.expr.node {
+            AstNode::VarNode(name) => Some((span, format!("{}: {}", name, display_term(&term)))),
+            _ => Some((span, display_term(&term))),
+        }
+    }
+
+    /// Finds where the name at a position is bound: a parameter, `let` or pattern variable
+    /// for local names, or a `def`, top-level `let` or data variant otherwise
+    pub fn definition(&self, index: usize) -> Option<Location> {
+        let index = self.document.start + index;
+        let scoped = self.scoped_expressions();
+        let binding = match scoped
+            .iter()
+            .flat_map(|scoped| scoped.binders.iter())
+            .find(|binder| contains(&binder.span, index))
+        {
+            Some(binder) => binder.clone(),
+            None => {
+                let scoped = innermost(&scoped, index, contains)?;
+                let name = match &scoped.expr.node {
+                    AstNode::VarNode(name) => name.clone(),
+                    AstNode::MatchNode(_, _) => self.constructor_at(scoped.expr, index)?,
+                    _ => return None,
+                };
+                let local = scoped.locals.iter().rev().find(|local| local.name == name);
+                match local {
+                    Some(local) => local.clone(),
+                    None => self
+                        .globals()
+                        .into_iter()
+                        .find(|global| global.name == name)?,
+                }
+            }
+        };
+        let (path, _, span) = self
+            .checked
+            .source_map
+            .locate(&binding.span, &self.checked.source);
+        Some(Location { path, span })
+    }
+
+    /// Returns the names in scope at a position: the local names bound around it, the
+    /// document's declarations and those of the session (including the prelude)
+    pub fn completions(&self, index: usize) -> Vec<Completion> {
+        let index = self.document.start + index;
+        let scoped = self.scoped_expressions();
+        let touches = |span: &Range<usize>, index| span.start <= index && index <= span.end;
+        // The innermost local names come first, so that they shadow the rest
+        let mut bindings = match innermost(&scoped, index, touches) {
+            Some(scoped) => scoped.locals.iter().rev().cloned().collect(),
+            None => vec![],
+        };
+        bindings.extend(self.globals());
+
+        let mut completions: Vec<Completion> = vec![];
+        let mut add = |name: &str, kind, term: Option<Term>| {
+            if !completions.iter().any(|completion| completion.name == name) {
+                completions.push(Completion {
+                    name: name.to_string(),
+                    kind: match (kind, &term) {
+                        (CompletionKind::Variable, Some(Term::Constructor(id, _)))
+                            if id == "Function" =>
+                        {
+                            CompletionKind::Function
+                        }
+                        _ => kind,
+                    },
+                    detail: term.map(|term| display_term(&term)),
+                })
+            }
+        };
+        let table = &self.checked.data_decl_table;
+        for binding in &bindings {
+            let kind = match binding.label {
+                None if table.get(&binding.name).is_some() => CompletionKind::Constructor,
+                _ => CompletionKind::Variable,
+            };
+            add(&binding.name, kind, self.binding_type(binding));
+        }
+        let schemes = &self.checked.inference_state.schemes;
+        for (name, scheme) in schemes {
+            add(name, CompletionKind::Variable, Some(scheme.term.clone()));
+        }
+        for variant in table.table.keys() {
+            add(
+                variant,
+                CompletionKind::Constructor,
+                table.instantiate_constructor(variant),
+            );
+        }
+        completions.sort_by(|a, b| a.name.cmp(&b.name));
+        completions
+    }
+
+    /// Converts a span of the checked source to a span of the document, if it's in the
+    /// document
+    fn in_document(&self, span: &Range<usize>) -> Option<Range<usize>> {
+        if self.document.start <= span.start && span.end <= self.document.end {
+            Some((span.start - self.document.start)..(span.end - self.document.start))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the inferred type of an expression or binding
+    fn type_of(&self, label: Symbol) -> Option<Term> {
+        let substitutions = &self.checked.inference_state.substitutions;
+        substitutions
+            .get(&label)
+            .map(|term| term.substitute(substitutions))
+    }
+
+    /// Returns the type of a binding. Data variants have the type of their constructor.
+    fn binding_type(&self, binding: &Binding) -> Option<Term> {
+        match binding.label {
+            Some(label) => self.type_of(label),
+            None => self
+                .checked
+                .data_decl_table
+                .instantiate_constructor(&binding.name),
+        }
+    }
+
+    /// Returns every expression in the document along with the names in scope at it
+    fn scoped_expressions(&self) -> Vec<Scoped<'_>> {
+        let mut scoped = vec![];
+        for expr in &self.checked.program {
+            if self.in_document(&expr.src_loc.span).is_some() {
+                self.scope(expr, &[], &mut scoped);
+            }
+        }
+        scoped
+    }
+
+    /// Adds an expression and those inside it to `scoped`, tracking the local names that
+    /// each one is in scope of
+    fn scope<'a>(&self, expr: &'a Ast, locals: &[Binding], scoped: &mut Vec<Scoped<'a>>) {
+        let span = expr.src_loc.span.clone();
+        let mut binders = vec![];
+        match &expr.node {
+            AstNode::NumberNode(_)
+            | AstNode::BigNumberNode(_)
+            | AstNode::FloatNode(_, _)
+            | AstNode::BoolNode(_)
+            | AstNode::StringNode(_)
+            | AstNode::VarNode(_)
+            | AstNode::ImportNode(_, _)
+            | AstNode::ErrorNode(_, _) => (),
+            AstNode::LetNodeTopLevel(id, binding) => {
+                binders.push(self.binding(&id.id, Some(id.label), span));
+                self.scope(binding, locals, scoped);
+            }
+            AstNode::LetNode(id, binding, body) => {
+                binders.push(self.binding(&id.id, Some(id.label), span));
+                self.scope(binding, locals, scoped);
+                self.scope(body, &[locals, &binders].concat(), scoped);
+            }
+            AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+                binders.extend(self.pattern_bindings(pattern, span));
+                self.scope(binding, locals, scoped);
+            }
+            AstNode::LetPatternNode(pattern, binding, body) => {
+                binders.extend(self.pattern_bindings(pattern, span));
+                self.scope(binding, locals, scoped);
+                self.scope(body, &[locals, &binders].concat(), scoped);
+            }
+            AstNode::IfNode(conditions_and_bodies, alternate) => {
+                for (condition, body) in conditions_and_bodies {
+                    self.scope(condition, locals, scoped);
+                    self.scope(body, locals, scoped);
+                }
+                self.scope(alternate, locals, scoped);
+            }
+            AstNode::BinOpNode(_, e1, e2) => {
+                self.scope(e1, locals, scoped);
+                self.scope(e2, locals, scoped);
+            }
+            AstNode::FunCallNode(fun, args) => {
+                self.scope(fun, locals, scoped);
+                for arg in args {
+                    self.scope(arg, locals, scoped);
+                }
+            }
+            AstNode::LambdaNode(params, body) => {
+                let mut rest = span;
+                for param in params {
+                    let binding = self.binding(&param.id, Some(param.label), rest.clone());
+                    rest = binding.span.end..rest.end;
+                    binders.push(binding);
+                }
+                self.scope(body, &[locals, &binders].concat(), scoped);
+            }
+            AstNode::FunctionNode(name, _, params, _, body) => {
+                let function = self.binding(name, Some(expr.label), span.clone());
+                let mut rest = function.span.end..span.end;
+                binders.push(function);
+                for param in params {
+                    let binding = self.binding(&param.id, Some(param.label), rest.clone());
+                    rest = binding.span.end..rest.end;
+                    binders.push(binding);
+                }
+                self.scope(body, &[locals, &binders[1..]].concat(), scoped);
+            }
+            AstNode::DataDeclarationNode(_, _, variants) => {
+                let mut rest = span;
+                for (variant, _) in variants {
+                    let binding = self.binding(variant, None, rest.clone());
+                    rest = binding.span.end..rest.end;
+                    binders.push(binding);
+                }
+            }
+            AstNode::DataLiteralNode(_, fields) => {
+                for field in fields {
+                    self.scope(field, locals, scoped);
+                }
+            }
+            AstNode::ListNode(items, rest) => {
+                for item in items.iter().chain(rest.as_deref()) {
+                    self.scope(item, locals, scoped);
+                }
+            }
+            AstNode::TupleNode(items) => {
+                for item in items {
+                    self.scope(item, locals, scoped);
+                }
+            }
+            AstNode::RecordNode(fields) => {
+                for (_, value) in fields {
+                    self.scope(value, locals, scoped);
+                }
+            }
+            AstNode::RecordUpdateNode(record, fields) => {
+                self.scope(record, locals, scoped);
+                for (_, value) in fields {
+                    self.scope(value, locals, scoped);
+                }
+            }
+            AstNode::FieldAccessNode(value, _) => self.scope(value, locals, scoped),
+            AstNode::MatchNode(expression_to_match, branches) => {
+                self.scope(expression_to_match, locals, scoped);
+                // Patterns don't have spans, so their variables are found in the text
+                // between the end of the previous branch and the start of their branch
+                let mut start = expression_to_match.src_loc.span.end;
+                for (pattern, guard, body, _) in branches {
+                    let pattern_binders =
+                        self.pattern_bindings(pattern, start..pattern_end(guard, body));
+                    let branch_locals = [locals, &pattern_binders].concat();
+                    if let Some(guard) = guard {
+                        self.scope(guard, &branch_locals, scoped);
+                    }
+                    self.scope(body, &branch_locals, scoped);
+                    binders.extend(pattern_binders);
+                    start = body.src_loc.span.end;
+                }
+            }
+        }
+        scoped.push(Scoped {
+            expr,
+            binders,
+            locals: locals.to_vec(),
+        });
+    }
+
+    /// Returns the name of the constructor written at a position in one of a match
+    /// expression's patterns
+    fn constructor_at(&self, expr: &Ast, index: usize) -> Option<String> {
+        let branches = match &expr.node {
+            AstNode::MatchNode(expression_to_match, branches) => {
+                let mut start = expression_to_match.src_loc.span.end;
+                let mut ranges = vec![];
+                for (pattern, guard, body, _) in branches {
+                    ranges.push((pattern, start..pattern_end(guard, body)));
+                    start = body.src_loc.span.end;
+                }
+                ranges
+            }
+            _ => return None,
+        };
+        let (pattern, range) = branches
+            .into_iter()
+            .find(|(_, range)| contains(range, index))?;
+        let mut rest = range;
+        for constructor in pattern_constructors(pattern) {
+            let span = find_name(&self.checked.source, &constructor, rest.clone())?;
+            if contains(&span, index) {
+                return Some(constructor);
+            }
+            rest = span.end..rest.end;
+        }
+        None
+    }
+
+    /// Returns the names declared at the top level of the checked program, including those
+    /// of the files it imports
+    fn globals(&self) -> Vec<Binding> {
+        let mut globals = vec![];
+        for expr in &self.checked.program {
+            let span = expr.src_loc.span.clone();
+            match &expr.node {
+                AstNode::FunctionNode(name, _, _, _, _) => {
+                    globals.push(self.binding(name, Some(expr.label), span))
+                }
+                AstNode::LetNodeTopLevel(id, _) => {
+                    globals.push(self.binding(&id.id, Some(id.label), span))
+                }
+                AstNode::LetPatternNodeTopLevel(pattern, _) => {
+                    globals.extend(self.pattern_bindings(pattern, span))
+                }
+                AstNode::DataDeclarationNode(_, _, variants) => {
+                    let mut rest = span;
+                    for (variant, _) in variants {
+                        let binding = self.binding(variant, None, rest.clone());
+                        rest = binding.span.end..rest.end;
+                        globals.push(binding);
+                    }
+                }
+                _ => (),
+            }
+        }
+        globals
+    }
+
+    /// Creates a binding for each variable of a pattern, locating them in order in a span.
+    /// Pattern variables aren't labelled, so their types aren't known.
+    fn pattern_bindings(&self, pattern: &Pattern, span: Range<usize>) -> Vec<Binding> {
+        let mut rest = span;
+        let mut bindings = vec![];
+        for name in pattern.variables() {
+            let binding = self.binding(&name, None, rest.clone());
+            rest = binding.span.end..rest.end;
+            bindings.push(binding);
+        }
+        bindings
+    }
+
+    /// Creates a binding, locating its name in the first place it's written in a span
+    fn binding(&self, name: &str, label: Option<Symbol>, span: Range<usize>) -> Binding {
+        Binding {
+            name: name.to_string(),
+            label,
+            span: find_name(&self.checked.source, name, span.clone())
+                .unwrap_or(span.start..span.start),
+        }
+    }
+}
+
+/// Where the text of a match branch's pattern ends, which is at its guard if it has one
+fn pattern_end(guard: &Option<Ast>, body: &Ast) -> usize {
+    match guard {
+        Some(guard) => guard.src_loc.span.start,
+        None => body.src_loc.span.start,
+    }
+}
+
+/// Returns whether a span contains the character at an index
+fn contains(span: &Range<usize>, index: usize) -> bool {
+    span.start <= index && index < span.end
+}
+
+/// Returns the expression with the smallest span that includes an index
+fn innermost<'s, 'a>(
+    scoped: &'s [Scoped<'a>],
+    index: usize,
+    includes: impl Fn(&Range<usize>, usize) -> bool,
+) -> Option<&'s Scoped<'a>> {
+    scoped
+        .iter()
+        .filter(|scoped| includes(&scoped.expr.src_loc.span, index))
+        .min_by_key(|scoped| scoped.expr.src_loc.span.len())
+}
+
+/// Finds the first place a name is written as a whole word within a span of the source.
+/// Names from other modules are written without their module's name where they're declared.
+fn find_name(source: &str, name: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let name = name.rsplit("::").next().unwrap_or(name);
+    let text = source.get(span.clone())?;
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let mut searched = 0;
+    while let Some(found) = text[searched..].find(name) {
+        let start = searched + found;
+        let end = start + name.len();
+        let (before, after) = (&text[..start], &text[end..]);
+        let joined_before = before.ends_with("::") || before.ends_with(is_identifier);
+        let joined_after = after.starts_with("::") || after.starts_with(is_identifier);
+        if !joined_before && !joined_after {
+            return Some((span.start + start)..(span.start + end));
+        }
+        searched = end;
+    }
+    None
+}
+
+/// Returns the constructors a pattern uses, in the order they're written
+fn pattern_constructors(pattern: &Pattern) -> Vec<String> {
+    match pattern {
+        Pattern::Data(constructor, patterns) => std::iter::once(constructor.clone())
+            .chain(patterns.iter().flat_map(pattern_constructors))
+            .collect(),
+        Pattern::List(patterns, rest) => (patterns.iter().chain(rest.as_deref()))
+            .flat_map(pattern_constructors)
+            .collect(),
+        Pattern::Record(fields) => fields
+            .iter()
+            .flat_map(|(_, pattern)| pattern_constructors(pattern))
+            .collect(),
+        Pattern::Tuple(patterns) | Pattern::Or(patterns) => {
+            patterns.iter().flat_map(pattern_constructors).collect()
+        }
+        Pattern::As(pattern, _) => pattern_constructors(pattern),
+        _ => vec![],
+    }
+}
+
+/// Writes a type the way it's written in annotations, naming its type variables `A`, `B`, ...
+/// in the order they appear
+fn display_term(term: &Term) -> String {
+    let mut vars = vec![];
+    display_term_with_vars(term, &mut vars)
+}
+
+fn display_term_with_vars(term: &Term, vars: &mut Vec<Symbol>) -> String {
+    match term {
+        Term::Var(label) => {
+            let i = match vars.iter().position(|var| var == label) {
+                Some(i) => i,
+                None => {
+                    vars.push(*label);
+                    vars.len() - 1
+                }
+            };
+            match i {
+                0..=25 => ((b'A' + i as u8) as char).to_string(),
+                _ => format!("T{}", i),
+            }
+        }
+        Term::Constructor(id, args) if id == "Function" && !args.is_empty() => {
+            let params: Vec<String> = args
+                .iter()
+                .take(args.len() - 1)
+                .map(|arg| display_term_with_vars(arg, vars))
+                .collect();
+            let return_type = display_term_with_vars(&args[args.len() - 1], vars);
+            let takes_one_value = params.len() == 1
+                && !matches!(&args[0], Term::Constructor(id, _) if id == "Function");
+            if takes_one_value {
+                format!("{} -> {}", params[0], return_type)
+            } else {
+                format!("({}) -> {}", params.join(", "), return_type)
+            }
+        }
+        Term::Record(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(field, arg)| format!("{}: {}", field, display_term_with_vars(arg, vars)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Term::Constructor(id, args) if args.is_empty() => id.clone(),
+        Term::Constructor(id, args) => format!(
+            "{}<{}>",
+            id,
+            args.iter()
+                .map(|arg| display_term_with_vars(arg, vars))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use super::*;
+    use crate::prelude;
+
+    fn analyze(text: &str) -> Analysis {
+        Analysis::new(&Session::new(), Path::new("test.boat"), text)
+    }
+
+    #[test]
+    fn reports_problems() {
+        // Every syntax error is reported
+        let analysis = analyze("def f(x) x end\nlet y = f(1,, 2)");
+        let problems = analysis.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems.iter().all(|problem| problem.is_error()));
+        assert!(!analysis.parsed());
+
+        let text = "match some(1):\n    | some(n) => n\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "Non-exhaustive match expression");
+        assert_eq!(problems[0].code, "E0301");
+        assert!(!problems[0].is_error());
+        assert_eq!(problems[0].span.as_ref().unwrap().end, text.len());
+        assert_eq!(problems[0].notes, vec!["Pattern `none()` not covered"]);
+
+        // Branches that earlier branches cover are pointed at
+        let text = "match some(1):\n    | _ => 0\n    | none() => 1 + 2\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].code, "E0308");
+        assert!(!problems[0].is_error());
+        let span = problems[0].span.clone().unwrap();
+        assert_eq!(&text[span], "none()");
+        let text = "match some(1):\n    | some(n) => n\n    | none() => 0\n    | some(1) | none() if true => 0\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(&text[problems[0].span.clone().unwrap()], "some(1) | none()");
+
+        // Matches are checked even when inference fails, using the types of the patterns
+        let text = "def f(o):\n    mystery(o)\nend\nmatch none():\n    | some(n) => n\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].code, "E0201");
+        assert_eq!(problems[1].code, "E0301");
+        assert_eq!(problems[1].notes, vec!["Pattern `none()` not covered"]);
+    }
+
+    #[test]
+    fn names_missing_patterns_as_they_are_in_scope() {
+        let text = "import \"tests/files/success/modules/colors.boat\" as c\nmatch c::red():\n    | c::red() => 0\nend";
+        let problems = analyze(text).problems();
+        let notes: Vec<_> = problems.iter().flat_map(|problem| &problem.notes).collect();
+        assert_eq!(
+            notes,
+            vec![
+                "Pattern `blue()` not covered",
+                "Patterns `c::green()` and `c::blue()` not covered"
+            ]
+        );
+
+        let text = "import Color, green from \"tests/files/success/modules/colors.boat\"\nmatch green():\n    | green() => 0\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(
+            problems[1].notes,
+            vec!["Patterns `colors::red()` and `colors::blue()` not covered"]
+        );
+    }
+
+    #[test]
+    fn points_type_errors_at_the_conflicting_code() {
+        let text = "def describe(n: Number, s: String) -> Boolean:\n    n == s\nend";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].code, "E0203");
+        assert_eq!(
+            problems[0].message,
+            "Type mismatch: expected Number, found String"
+        );
+        assert_eq!(&text[problems[0].span.clone().unwrap()], "s");
+        let label = &problems[0].labels[0];
+        assert_eq!(&text[label.span.clone()], "n: Number");
+        assert_eq!(
+            label.message,
+            "expected Number because of the annotation here"
+        );
+
+        // Without an annotation to blame, the error is where the type is needed
+        let text = "let total = 1 + 2\nif total: 1 else: 2 end";
+        let problems = analyze(text).problems();
+        assert_eq!(
+            problems[0].message,
+            "Type mismatch: expected Boolean, found Number"
+        );
+        assert_eq!(problems[0].span, Some(21..26));
+    }
+
+    #[test]
+    fn keeps_type_parameters_rigid_in_their_functions() {
+        let text = "def bad<T>(x: T) -> T: x * 2 end";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].message,
+            "Type mismatch: expected T, found Number"
+        );
+        assert_eq!(&text[problems[0].labels[0].span.clone()], "x: T");
+
+        // Outside of the function, the parameter can be any type
+        let text = "def first<T>(x: T, y: T) -> T: x end
+first(1, 2)
+first(true, false)";
+        assert!(analyze(text).problems().is_empty());
+    }
+
+    #[test]
+    fn reports_additions_of_values_that_cant_be_added() {
+        let text = "def f(x: Boolean) -> Boolean: x + x end";
+        let problems = analyze(text).problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].code, "E0212");
+        assert_eq!(
+            problems[0].message,
+            "Can't use `+` on a value of type Boolean"
+        );
+
+        // Until the type of an operand is known, it can be either a number or a string
+        let text = "def add(a, b): a + b end
+add(1, 2)
+add(\"a\", \"b\")";
+        assert!(analyze(text).problems().is_empty());
+    }
+
+    #[test]
+    fn shows_types_on_hover() {
+        let text = "def double(n: Number) -> Number: n * 2 end\ndouble(4)";
+        let analysis = analyze(text);
+        let call = text.rfind("double").unwrap();
+        assert_eq!(
+            analysis.hover(call),
+            Some((call..call + 6, "double: Number -> Number".to_string()))
+        );
+        let param = text.find("n:").unwrap();
+        assert_eq!(
+            analysis.hover(param),
+            Some((param..param + 1, "n: Number".to_string()))
+        );
+        let argument = text.find("4").unwrap();
+        assert_eq!(
+            analysis.hover(argument),
+            Some((argument..argument + 1, "Number".to_string()))
+        );
+        let text = "def apply<T>(f: T -> T, x: T) -> T: f(x) end";
+        assert_eq!(
+            analyze(text).hover(text.find("apply").unwrap()),
+            Some((4..9, "apply: (A -> A, A) -> A".to_string()))
+        );
+    }
+
+    #[test]
+    fn shows_types_on_hover_despite_type_errors() {
+        let text = "let total = 1 + true
+def double(n: Number) -> Number: n * 2 end
+double(total)";
+        let analysis = analyze(text);
+        assert_eq!(analysis.problems().len(), 1);
+        let call = text.rfind("double").unwrap();
+        assert_eq!(
+            analysis.hover(call),
+            Some((call..call + 6, "double: Number -> Number".to_string()))
+        );
+        let argument = text.rfind("total").unwrap();
+        assert_eq!(
+            analysis.hover(argument),
+            Some((argument..argument + 5, "total: Number".to_string()))
+        );
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let text = "let base = 1\ndef add(n): let m = n + base\nm end\ndata Shape: | circle(r) | dot() end\nmatch circle(1):\n    | circle(v) if v > 0 => v\n    | dot() => some(0)\nend";
+        let analysis = analyze(text);
+        let definition_of = |use_index: usize| analysis.definition(use_index).unwrap();
+
+        let base = text.find("base").unwrap();
+        assert_eq!(
+            definition_of(text.rfind("base").unwrap()),
+            Location {
+                path: PathBuf::from("test.boat"),
+                span: base..base + 4
+            }
+        );
+        let m = text.find("m =").unwrap();
+        let m_use = text.find("\nm").unwrap() + 1;
+        assert_eq!(definition_of(m_use).span, m..m + 1);
+        let n = text.find("n)").unwrap();
+        assert_eq!(definition_of(text.find("n +").unwrap()).span, n..n + 1);
+        let v = text.find("v)").unwrap();
+        assert_eq!(definition_of(text.rfind("v").unwrap()).span, v..v + 1);
+        assert_eq!(definition_of(text.find("v >").unwrap()).span, v..v + 1);
+
+        // Constructors are found in expressions and patterns
+        let circle = text.find("circle").unwrap();
+        assert_eq!(
+            definition_of(text.find("circle(1)").unwrap()).span,
+            circle..circle + 6
+        );
+        let dot = text.find("dot").unwrap();
+        assert_eq!(definition_of(text.rfind("dot").unwrap()).span, dot..dot + 3);
+
+        // The prelude isn't a file that can be gone to
+        assert_eq!(analysis.definition(text.find("some").unwrap()), None);
+    }
+
+    #[test]
+    fn completes_names_in_scope() {
+        let text = "let base = 1\ndef add(n: Number): n + 1 end\n";
+        let analysis = analyze(text);
+        let in_body = text.find("n + 1").unwrap();
+        let completions = analysis.completions(in_body);
+        let completion = |name: &str| {
+            completions
+                .iter()
+                .find(|completion| completion.name == name)
+                .cloned()
+        };
+        assert_eq!(
+            completion("n"),
+            Some(Completion {
+                name: "n".to_string(),
+                kind: CompletionKind::Variable,
+                detail: Some("Number".to_string())
+            })
+        );
+        assert_eq!(
+            completion("add").map(|c| c.kind),
+            Some(CompletionKind::Function)
+        );
+        assert_eq!(
+            completion("base").and_then(|c| c.detail),
+            Some("Number".to_string())
+        );
+        assert_eq!(
+            completion("some").map(|c| c.kind),
+            Some(CompletionKind::Constructor)
+        );
+        assert!(completion("map").is_some());
+
+        // Parameters aren't in scope outside of their function
+        assert!(analysis
+            .completions(text.len())
+            .iter()
+            .all(|completion| completion.name != "n"));
+    }
+}
@@ -1,7 +1,10 @@
+use crate::interpreter::{bigint::BigInt, numeric};
+use crate::parser::util::ParseErrorKind;
 use colored::Colorize;
-use im::{HashMap, Vector};
+use im::{HashMap, OrdMap, Vector};
+use std::hash::{Hash, Hasher};
 use std::sync::Mutex;
-use std::{fmt, ops::Range, usize};
+use std::{fmt, ops::Range, rc::Rc, usize};
 
 pub type Symbol = usize;
 lazy_static! {
@@ -15,18 +18,28 @@ pub fn gensym() -> Symbol {
 
 pub type Env = HashMap<String, Val>;
 pub type Program = Vec<Ast>;
-#[derive(PartialEq, Debug, Clone, Hash)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum AstNode {
     /// (val)
     NumberNode(i64),
+    /// (val) An integer literal too big for a `NumberNode`
+    BigNumberNode(Rc<BigInt>),
+    /// (val, the literal as written, which the formatter keeps)
+    FloatNode(f64, String),
     /// (val)
     BoolNode(bool),
     /// (val)
+    StringNode(String),
+    /// (val)
     VarNode(String),
     /// (id, expr)
     LetNodeTopLevel(Identifier, Box<Ast>),
     /// (id, expr, body)
     LetNode(Identifier, Box<Ast>, Box<Ast>),
+    /// (pattern, expr) A let that binds the variables of a pattern (e.g. `let (x, y) = point`)
+    LetPatternNodeTopLevel(Pattern, Box<Ast>),
+    /// (pattern, expr, body)
+    LetPatternNode(Pattern, Box<Ast>, Box<Ast>),
     /// (conditions_and_bodies, alternate)
     IfNode(Vec<(Ast, Ast)>, Box<Ast>),
     /// (operator, operand1, operand2)
@@ -35,14 +48,30 @@ pub enum AstNode {
     FunCallNode(Box<Ast>, Vec<Ast>),
     /// (param_list, body)
     LambdaNode(Vec<Identifier>, Box<Ast>),
-    /// (function_name, param_list, body)
-    FunctionNode(String, Vec<Identifier>, Option<Type>, Box<Ast>),
-    /// (data_name, data_Variants)
-    DataDeclarationNode(String, Vec<(String, Vec<Identifier>)>),
+    /// (function_name, type_params, param_list, return_type, body)
+    FunctionNode(String, Vec<String>, Vec<Identifier>, Option<Type>, Box<Ast>),
+    /// (data_name, type_params, data_Variants)
+    DataDeclarationNode(String, Vec<String>, Vec<(String, Vec<Identifier>)>),
     /// (discriminant, values)
     DataLiteralNode(Discriminant, Vec<Box<Ast>>),
-    /// (expression_to_match, branches)
-    MatchNode(Box<Ast>, Vec<(Pattern, Ast)>),
+    /// (items, rest) A list literal. The items are put in front of the `...rest` list, if any.
+    ListNode(Vec<Ast>, Option<Box<Ast>>),
+    /// (items) A tuple of two or more values
+    TupleNode(Vec<Ast>),
+    /// (fields) A record literal, with its fields in the order they were written
+    RecordNode(Vec<(String, Ast)>),
+    /// (record, fields) A copy of a record (or data value) with some of its fields replaced
+    RecordUpdateNode(Box<Ast>, Vec<(String, Ast)>),
+    /// (value, field)
+    FieldAccessNode(Box<Ast>, String),
+    /// (expression_to_match, branches) Each branch has a pattern, an optional guard that has to
+    /// be true for the branch to be taken, a body and the span of its pattern (since patterns
+    /// don't have locations of their own)
+    MatchNode(Box<Ast>, Vec<(Pattern, Option<Ast>, Ast, Range<usize>)>),
+    /// (path, imported_names)
+    ImportNode(String, ImportNames),
+    /// (message, kind) Stands in for code with a syntax error, which the parser skipped over
+    ErrorNode(String, ParseErrorKind),
 }
 
 /// Represents an identifier. This includes identifiers used in let statements
@@ -52,6 +81,8 @@ pub struct Identifier {
     pub id: String,
     pub type_decl: Option<Type>,
     pub label: Symbol,
+    /// Where the identifier and its type annotation are, if it was parsed from source
+    pub src_loc: SrcLoc,
 }
 impl fmt::Display for Identifier {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -73,6 +104,7 @@ impl Identifier {
             id,
             type_decl,
             label: gensym(),
+            src_loc: SrcLoc::default(),
         }
     }
     pub fn new_without_type(id: String) -> Identifier {
@@ -80,6 +112,7 @@ impl Identifier {
             id,
             type_decl: None,
             label: gensym(),
+            src_loc: SrcLoc::default(),
         }
     }
     pub fn new_with_type(id: String, type_decl: Type) -> Identifier {
@@ -87,15 +120,16 @@ impl Identifier {
             id,
             type_decl: Some(type_decl),
             label: gensym(),
+            src_loc: SrcLoc::default(),
         }
     }
 }
 
-#[derive(PartialEq, Debug, Clone, Hash)]
+#[derive(Eq, PartialEq, Debug, Clone, Hash, Default)]
 pub struct SrcLoc {
     pub span: Range<usize>,
 }
-#[derive(PartialEq, Debug, Clone, Hash)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Ast {
     pub node: AstNode,
     pub src_loc: SrcLoc,
@@ -119,7 +153,10 @@ impl Ast {
     fn pretty_print_helper(&self, indent_level: usize) -> String {
         let content = match &self.node {
             AstNode::NumberNode(e) => format!("NumberNode({})", e),
+            AstNode::BigNumberNode(e) => format!("BigNumberNode({})", e),
+            AstNode::FloatNode(e, _) => format!("FloatNode({})", numeric::format_float(*e)),
             AstNode::BoolNode(e) => format!("BoolNode({})", e),
+            AstNode::StringNode(e) => format!("StringNode({:?})", e),
             AstNode::VarNode(e) => format!("VarNode({})", e),
             AstNode::LetNodeTopLevel(id, binding) => format!(
                 "LetNodeTopLevel(id: {}, binding: {})",
@@ -132,6 +169,17 @@ impl Ast {
                 binding.pretty_print_helper(indent_level + 1),
                 body.pretty_print_helper(indent_level + 1)
             ),
+            AstNode::LetPatternNodeTopLevel(pattern, binding) => format!(
+                "LetPatternNodeTopLevel(pattern: {:?}, binding: {})",
+                pattern,
+                binding.pretty_print_helper(indent_level + 1)
+            ),
+            AstNode::LetPatternNode(pattern, binding, body) => format!(
+                "LetPatternNode(pattern: {:?}, binding: {}, body: {})",
+                pattern,
+                binding.pretty_print_helper(indent_level + 1),
+                body.pretty_print_helper(indent_level + 1)
+            ),
             AstNode::IfNode(conditions_and_bodies, altern) => format!(
                 "IfNode(conditions_and_bodies: {}, altern: {})",
                 conditions_and_bodies
@@ -168,9 +216,10 @@ impl Ast {
                     .join(", \n"),
                 body.pretty_print_helper(indent_level + 1)
             ),
-            AstNode::FunctionNode(name, params, return_type, body) => format!(
-                "FunctionNode(name: {}, params: {}, return_type: {:?}, body: {})",
+            AstNode::FunctionNode(name, type_params, params, return_type, body) => format!(
+                "FunctionNode(name: {}, type_params: [{}], params: {}, return_type: {:?}, body: {})",
                 name,
+                type_params.join(", "),
                 params
                     .iter()
                     .map(|param| format!("{}", param))
@@ -179,9 +228,10 @@ impl Ast {
                 return_type,
                 body.pretty_print_helper(indent_level + 1)
             ),
-            AstNode::DataDeclarationNode(name, variants) => format!(
-                "DataNode(name: {}, variants: {})",
+            AstNode::DataDeclarationNode(name, type_params, variants) => format!(
+                "DataNode(name: {}, type_params: [{}], variants: {})",
                 name,
+                type_params.join(", "),
                 variants
                     .iter()
                     .map(|(name, fields)| format!(
@@ -205,20 +255,63 @@ impl Ast {
                     .collect::<Vec<String>>()
                     .join(",\n")
             ),
+            AstNode::ListNode(items, rest) => format!(
+                "ListNode(items: {}, rest: {})",
+                items
+                    .iter()
+                    .map(|x| x.pretty_print_helper(indent_level + 1))
+                    .collect::<Vec<String>>()
+                    .join(",\n"),
+                match rest {
+                    Some(rest) => rest.pretty_print_helper(indent_level + 1),
+                    None => "None".to_string(),
+                }
+            ),
+            AstNode::TupleNode(items) => format!(
+                "TupleNode(items: {})",
+                items
+                    .iter()
+                    .map(|x| x.pretty_print_helper(indent_level + 1))
+                    .collect::<Vec<String>>()
+                    .join(",\n")
+            ),
+            AstNode::RecordNode(fields) => format!(
+                "RecordNode(fields: {})",
+                fields_pretty_print(fields, indent_level + 1)
+            ),
+            AstNode::RecordUpdateNode(record, fields) => format!(
+                "RecordUpdateNode(record: {}, fields: {})",
+                record.pretty_print_helper(indent_level + 1),
+                fields_pretty_print(fields, indent_level + 1)
+            ),
+            AstNode::FieldAccessNode(value, field) => format!(
+                "FieldAccessNode(value: {}, field: {})",
+                value.pretty_print_helper(indent_level + 1),
+                field
+            ),
             AstNode::MatchNode(expression_to_match, branches) => format!(
                 "MatchNode(expression_to_match: {}, branches: {})",
                 expression_to_match.pretty_print_helper(indent_level + 1),
                 branches
                     .iter()
-                    .map(|(pattern, expr)| format!(
-                        "{:?} => {}",
+                    .map(|(pattern, guard, expr, _)| format!(
+                        "{:?}{} => {}",
                         pattern,
+                        match guard {
+                            Some(guard) =>
+                                format!(" if {}", guard.pretty_print_helper(indent_level + 1)),
+                            None => "".to_string(),
+                        },
                         expr.pretty_print_helper(indent_level + 1)
                     )
                     .to_string())
                     .collect::<Vec<String>>()
                     .join(",\n")
             ),
+            AstNode::ImportNode(path, names) => {
+                format!("ImportNode(path: {:?}, names: {:?})", path, names)
+            }
+            AstNode::ErrorNode(message, _) => format!("ErrorNode({:?})", message),
         };
         format!(
             "\n{:4}:{}{}",
@@ -231,13 +324,20 @@ impl Ast {
     pub fn into_vec(&self) -> Vec<&Ast> {
         let mut out = vec![self];
         match &self.node {
-            AstNode::NumberNode(_) | AstNode::BoolNode(_) | AstNode::VarNode(_) => (),
+            AstNode::NumberNode(_)
+            | AstNode::BigNumberNode(_)
+            | AstNode::FloatNode(_, _)
+            | AstNode::BoolNode(_)
+            | AstNode::StringNode(_)
+            | AstNode::VarNode(_) => (),
             // Add the let binding to the environment and then interpret the body
-            AstNode::LetNode(_, binding, body) => {
+            AstNode::LetNode(_, binding, body) | AstNode::LetPatternNode(_, binding, body) => {
                 out.extend(binding.into_vec());
                 out.extend(body.into_vec());
             }
-            AstNode::LetNodeTopLevel(_, binding) => out.extend(binding.into_vec()),
+            AstNode::LetNodeTopLevel(_, binding) | AstNode::LetPatternNodeTopLevel(_, binding) => {
+                out.extend(binding.into_vec())
+            }
             AstNode::BinOpNode(_, e1, e2) => {
                 out.extend(e1.into_vec());
                 out.extend(e2.into_vec());
@@ -256,18 +356,48 @@ impl Ast {
                 }
                 out.extend(alternate.into_vec());
             }
-            AstNode::FunctionNode(_, _, _, body) => {
+            AstNode::FunctionNode(_, _, _, _, body) => {
                 out.extend(body.into_vec());
             }
-            AstNode::DataDeclarationNode(_, _) => (),
+            AstNode::DataDeclarationNode(_, _, _)
+            | AstNode::ImportNode(_, _)
+            | AstNode::ErrorNode(_, _) => (),
             AstNode::DataLiteralNode(_, fields) => {
                 for field in fields {
                     out.extend(field.into_vec());
                 }
             }
+            AstNode::ListNode(items, rest) => {
+                for item in items {
+                    out.extend(item.into_vec());
+                }
+                if let Some(rest) = rest {
+                    out.extend(rest.into_vec());
+                }
+            }
+            AstNode::TupleNode(items) => {
+                for item in items {
+                    out.extend(item.into_vec());
+                }
+            }
+            AstNode::RecordNode(fields) => {
+                for (_, value) in fields {
+                    out.extend(value.into_vec());
+                }
+            }
+            AstNode::RecordUpdateNode(record, fields) => {
+                out.extend(record.into_vec());
+                for (_, value) in fields {
+                    out.extend(value.into_vec());
+                }
+            }
+            AstNode::FieldAccessNode(value, _) => out.extend(value.into_vec()),
             AstNode::MatchNode(expression_to_match, branches) => {
                 out.extend(expression_to_match.into_vec());
-                for (_, expr) in branches {
+                for (_, guard, expr, _) in branches {
+                    if let Some(guard) = guard {
+                        out.extend(guard.into_vec());
+                    }
                     out.extend(expr.into_vec());
                 }
             }
@@ -276,16 +406,27 @@ impl Ast {
     }
 }
 
+fn fields_pretty_print(fields: &[(String, Ast)], indent_level: usize) -> String {
+    fields
+        .iter()
+        .map(|(field, value)| format!("{}: {}", field, value.pretty_print_helper(indent_level)))
+        .collect::<Vec<String>>()
+        .join(",\n")
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Hash, Default)]
 pub struct Discriminant {
     source_type: String,
     variant: String,
+    /// The names of the variant's fields, in order. Shared between every value of the variant.
+    fields: Rc<Vec<String>>,
 }
 impl Discriminant {
-    pub fn new(source_type: &str, variant: &str) -> Self {
+    pub fn new(source_type: &str, variant: &str, fields: Vec<String>) -> Self {
         Discriminant {
             source_type: source_type.to_string(),
             variant: variant.to_string(),
+            fields: Rc::new(fields),
         }
     }
     pub fn get_type(&self) -> &str {
@@ -294,19 +435,53 @@ impl Discriminant {
     pub fn get_variant(&self) -> &str {
         &self.variant
     }
+    /// Returns the position of the field with the given name among the variant's fields
+    pub fn field_index(&self, field: &str) -> Option<usize> {
+        self.fields.iter().position(|name| name == field)
+    }
 }
 impl fmt::Display for Discriminant {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.variant)
+        // Variants from other modules are printed without the module's name
+        let variant = self.variant.rsplit("::").next().unwrap_or(&self.variant);
+        write!(f, "{}", variant)
     }
 }
 
+/// The names an import brings into scope from another module
+#[derive(PartialEq, Debug, Clone, Hash)]
+pub enum ImportNames {
+    /// `import "lists.boat"` brings in every name, both unqualified and qualified with the
+    /// module's name (e.g. `map` and `lists::map`)
+    All,
+    /// `import "lists.boat" as l` brings in every name qualified with the alias (e.g. `l::map`)
+    Alias(String),
+    /// `import map, filter from "lists.boat"` brings in only the listed names, unqualified
+    Selected(Vec<String>),
+}
+
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub enum Pattern {
     NumLiteral(i64),
     BoolLiteral(bool),
+    StringLiteral(String),
     Data(String, Vec<Pattern>),
     Identifier(String),
+    /// (items, rest) Matches a list that starts with the items. Without a rest pattern the list
+    /// can't have any more items, and with one the rest pattern matches the remaining list.
+    List(Vec<Pattern>, Option<Box<Pattern>>),
+    /// (fields) Matches a record that has each of the fields, whatever other fields it has
+    Record(Vec<(String, Pattern)>),
+    /// (items) Matches a tuple with the same number of items
+    Tuple(Vec<Pattern>),
+    /// Matches any value without binding it (`_`)
+    Wildcard,
+    /// (alternatives) Matches a value that any of the alternatives match. Every alternative
+    /// binds the same variables.
+    Or(Vec<Pattern>),
+    /// (pattern, name) Matches what the pattern matches, binding the whole value to the name
+    /// as well (e.g. `link(f, r) as whole`)
+    As(Box<Pattern>, String),
 }
 
 impl Pattern {
@@ -320,6 +495,11 @@ impl Pattern {
         matches!(self, Self::BoolLiteral(..))
     }
 
+    /// Returns `true` if the pattern is [`StringLiteral`].
+    pub fn is_string_literal(&self) -> bool {
+        matches!(self, Self::StringLiteral(..))
+    }
+
     /// Returns `true` if the pattern is [`Data`].
     pub fn is_data(&self) -> bool {
         matches!(self, Self::Data(..))
@@ -329,6 +509,67 @@ impl Pattern {
     pub fn is_identifier(&self) -> bool {
         matches!(self, Self::Identifier(..))
     }
+
+    /// Returns `true` if the pattern is [`List`].
+    pub fn is_list(&self) -> bool {
+        matches!(self, Self::List(..))
+    }
+
+    /// Returns `true` if the pattern is [`Record`].
+    pub fn is_record(&self) -> bool {
+        matches!(self, Self::Record(..))
+    }
+
+    /// Returns `true` if the pattern is [`Tuple`].
+    pub fn is_tuple(&self) -> bool {
+        matches!(self, Self::Tuple(..))
+    }
+
+    /// Returns `true` if the pattern is [`Wildcard`].
+    pub fn is_wildcard(&self) -> bool {
+        matches!(self, Self::Wildcard)
+    }
+
+    /// Returns `true` if the pattern is [`Or`].
+    pub fn is_or(&self) -> bool {
+        matches!(self, Self::Or(..))
+    }
+
+    /// Returns `true` if the pattern is [`As`].
+    pub fn is_as(&self) -> bool {
+        matches!(self, Self::As(..))
+    }
+
+    /// Returns the variables the pattern binds, in the order they're written
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            Pattern::Identifier(name) => vec![name.clone()],
+            Pattern::Data(_, patterns) | Pattern::Tuple(patterns) => {
+                patterns.iter().flat_map(Pattern::variables).collect()
+            }
+            Pattern::List(patterns, rest) => (patterns.iter().chain(rest.as_deref()))
+                .flat_map(Pattern::variables)
+                .collect(),
+            Pattern::Record(fields) => fields
+                .iter()
+                .flat_map(|(_, pattern)| pattern.variables())
+                .collect(),
+            // Every alternative binds the same variables, so the first one stands in for them
+            Pattern::Or(alternatives) => alternatives
+                .first()
+                .map(Pattern::variables)
+                .unwrap_or_default(),
+            Pattern::As(pattern, name) => {
+                let mut variables = pattern.variables();
+                variables.push(name.clone());
+                variables
+            }
+            Pattern::NumLiteral(_)
+            | Pattern::BoolLiteral(_)
+            | Pattern::StringLiteral(_)
+            | Pattern::Wildcard => vec![],
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Copy, Hash)]
@@ -351,58 +592,106 @@ pub enum BinOp {
     BitXor,
 }
 
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Times => "*",
+            BinOp::Divide => "/",
+            BinOp::Modulo => "%",
+            BinOp::Exp => "**",
+            BinOp::Eq => "==",
+            BinOp::Gt => ">",
+            BinOp::Lt => "<",
+            BinOp::GtEq => ">=",
+            BinOp::LtEq => "<=",
+            BinOp::LAnd => "and",
+            BinOp::LOr => "or",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+        };
+        write!(f, "{}", text)
+    }
+}
+
 /// Represents a Skiff type. This includes primitives like `Number`, but also more complex
-/// types like `List<_>` and user-defined types.
-#[derive(Eq, PartialEq, Debug, Clone, Hash, Default)]
-pub struct Type {
-    pub id: String,
-    pub args: Vector<Type>,
+/// types like `List<_>`, records and user-defined types.
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub enum Type {
+    /// (name, arguments) A type constructor applied to its arguments, like `Number` or `List<T>`
+    Constructor(String, Vector<Type>),
+    /// (fields) The type of records with the given fields, in order of their names. Record
+    /// types are structural, so any two records with the same fields have the same type.
+    Record(Vec<(String, Type)>),
 }
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.args.len() == 0 {
-            write!(f, "{}", self.id)
-        } else {
-            write!(
+        match self {
+            Type::Record(fields) => write!(
                 f,
-                "{}<{}>",
-                self.id,
-                self.args
+                "{{{}}}",
+                fields
                     .iter()
+                    .map(|(field, t)| format!("{}: {}", field, t))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Type::Constructor(id, args) if args.len() == 0 => write!(f, "{}", id),
+            Type::Constructor(id, args) => write!(
+                f,
+                "{}<{}>",
+                id,
+                args.iter()
                     .map(|arg| format!("{}", arg))
                     .collect::<Vec<String>>()
                     .join(", ")
-            )
+            ),
         }
     }
 }
 impl Type {
     pub fn new(id: String, args: Vector<Type>) -> Type {
-        return Type { id, args };
+        return Type::Constructor(id, args);
     }
     pub fn new_unit(id: String) -> Type {
-        return Type {
-            id,
-            args: Vector::new(),
-        };
+        return Type::Constructor(id, Vector::new());
     }
     pub fn new_number() -> Type {
-        return Type {
-            id: "Number".to_string(),
-            args: Vector::new(),
-        };
+        return Type::new_unit("Number".to_string());
     }
     pub fn new_boolean() -> Type {
-        return Type {
-            id: "Boolean".to_string(),
-            args: Vector::new(),
-        };
+        return Type::new_unit("Boolean".to_string());
+    }
+    pub fn new_string() -> Type {
+        return Type::new_unit("String".to_string());
+    }
+    pub fn new_list(item: Type) -> Type {
+        return Type::Constructor("List".to_string(), Vector::unit(item));
+    }
+    pub fn new_tuple(items: Vector<Type>) -> Type {
+        return Type::Constructor("Tuple".to_string(), items);
+    }
+    /// The type of records with the given fields, which can be in any order
+    pub fn new_record(mut fields: Vec<(String, Type)>) -> Type {
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        return Type::Record(fields);
     }
     pub fn new_any() -> Type {
-        return Type {
-            id: "Any".to_string(),
-            args: Vector::new(),
-        };
+        return Type::new_unit("Any".to_string());
+    }
+    /// Whether the type is the type constructor with the given name
+    pub fn is(&self, name: &str) -> bool {
+        matches!(self, Type::Constructor(id, _) if id == name)
+    }
+    /// The type's arguments: the arguments of a type constructor, or the types of a record's
+    /// fields
+    pub fn args(&self) -> Vec<&Type> {
+        match self {
+            Type::Constructor(_, args) => args.iter().collect(),
+            Type::Record(fields) => fields.iter().map(|(_, t)| t).collect(),
+        }
     }
     pub fn none_to_any(type_decl: Option<Type>) -> Option<Type> {
         match type_decl {
@@ -410,23 +699,98 @@ impl Type {
             _ => type_decl,
         }
     }
+    /// Replaces any type parameters in a type with the corresponding type arguments
+    pub fn substitute_params(&self, params: &[String], args: &Vector<Type>) -> Type {
+        match self {
+            Type::Constructor(id, type_args) => match params.iter().position(|param| param == id) {
+                Some(i) if type_args.is_empty() => match args.get(i) {
+                    Some(arg) => arg.clone(),
+                    None => Type::new_any(),
+                },
+                _ => Type::new(
+                    id.clone(),
+                    type_args
+                        .iter()
+                        .map(|arg| arg.substitute_params(params, args))
+                        .collect(),
+                ),
+            },
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(field, t)| (field.clone(), t.substitute_params(params, args)))
+                    .collect(),
+            ),
+        }
+    }
     pub fn new_func(args: Vector<Type>, return_type: Type) -> Type {
         let mut combined_args_and_return = args.clone();
         combined_args_and_return.push_back(return_type);
-        return Type {
-            id: "Function".to_string(),
-            args: combined_args_and_return,
-        };
+        return Type::Constructor("Function".to_string(), combined_args_and_return);
     }
 }
 
-#[derive(PartialEq, Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 pub enum Val {
     Num(i64),
+    /// An integer that doesn't fit in a `Num`
+    BigNum(Rc<BigInt>),
+    Float(f64),
     Bool(bool),
-    Lam(Vec<String>, Ast, Env),
-    // (discriminant, values)
-    Data(Discriminant, Vec<Val>),
+    Str(String),
+    Lam(Vec<String>, Rc<Ast>, Env),
+    Native(Rc<NativeFunction>),
+    // (discriminant, values). The values are shared so that data values are cheap to copy.
+    Data(Discriminant, Rc<Vec<Val>>),
+    /// A persistent list, so lists that are built from one another share their items
+    List(Vector<Val>),
+    /// A record, whose fields are kept in order of their names
+    Record(OrdMap<String, Val>),
+    Tuple(Rc<Vec<Val>>),
+    /// (function, cast) A function that was given a function type at runtime, whose calls
+    /// check their arguments and result against the type
+    Cast(Rc<Val>, Rc<FunctionCast>),
+}
+
+// Integers and floats are compared by value, so `Num(1)` equals `Float(1.0)`
+impl PartialEq for Val {
+    fn eq(&self, other: &Self) -> bool {
+        if let (Some(x), Some(y)) = (self.to_number(), other.to_number()) {
+            return numeric::comparison(BinOp::Eq, &x, &y);
+        }
+        match (self, other) {
+            (Val::Bool(x), Val::Bool(y)) => x == y,
+            (Val::Str(x), Val::Str(y)) => x == y,
+            (Val::Lam(x1, x2, x3), Val::Lam(y1, y2, y3)) => x1 == y1 && x2 == y2 && x3 == y3,
+            (Val::Native(x), Val::Native(y)) => x == y,
+            (Val::Data(x1, x2), Val::Data(y1, y2)) => x1 == y1 && x2 == y2,
+            (Val::List(x), Val::List(y)) => x == y,
+            (Val::Record(x), Val::Record(y)) => x == y,
+            (Val::Tuple(x), Val::Tuple(y)) => x == y,
+            (Val::Cast(x1, x2), Val::Cast(y1, y2)) => x1 == y1 && x2 == y2,
+            _ => false,
+        }
+    }
+}
+impl Hash for Val {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if let Some(number) = self.to_number() {
+            return numeric::hash_key(&number).hash(state);
+        }
+        match self {
+            Val::Bool(v) => v.hash(state),
+            Val::Str(v) => v.hash(state),
+            // Function bodies aren't hashable, which only makes for more collisions
+            Val::Lam(params, _, env) => (params, env).hash(state),
+            Val::Native(native) => native.hash(state),
+            Val::Data(discriminant, values) => (discriminant, values).hash(state),
+            Val::List(items) => items.hash(state),
+            Val::Record(fields) => fields.hash(state),
+            Val::Tuple(items) => items.hash(state),
+            Val::Cast(function, _) => function.hash(state),
+            Val::Num(_) | Val::BigNum(_) | Val::Float(_) => (),
+        }
+    }
 }
 
 impl fmt::Display for Val {
@@ -434,8 +798,11 @@ impl fmt::Display for Val {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Val::Num(n) => write!(f, "{}", n),
+            Val::BigNum(n) => write!(f, "{}", n),
+            Val::Float(x) => write!(f, "{}", numeric::format_float(*x)),
             Val::Bool(v) => write!(f, "{}", v),
-            Val::Lam(_, _, _) => write!(f, "<function>"),
+            Val::Str(v) => write!(f, "{:?}", v),
+            Val::Lam(_, _, _) | Val::Native(_) | Val::Cast(_, _) => write!(f, "<function>"),
             Val::Data(discriminant, values) => write!(
                 f,
                 "{}({})",
@@ -446,6 +813,111 @@ impl fmt::Display for Val {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
+            Val::List(items) => write!(
+                f,
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| format!("{}", item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Val::Tuple(items) => write!(
+                f,
+                "({})",
+                items
+                    .iter()
+                    .map(|item| format!("{}", item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Val::Record(fields) => write!(
+                f,
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(field, value)| format!("{}: {}", field, value))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
         }
     }
 }
+
+/// A function type that a function was cast to, either by its own annotations or by being
+/// passed into code that expects a function of that type. Arguments of the wrong type are
+/// blamed on the call, and results of the wrong type on where the function got the type.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunctionCast {
+    /// The name errors refer to the function by
+    pub name: String,
+    /// The types of the parameters, where None (like `Any`) allows any value
+    pub params: Vec<Option<Type>>,
+    pub return_type: Option<Type>,
+    /// Where the function got the type (e.g. its definition or the annotation of the
+    /// parameter it was passed as)
+    pub src_loc: SrcLoc,
+}
+
+/// The Rust code behind a native function. Takes the (already evaluated) arguments and
+/// returns either a value or the message of a runtime error.
+pub type NativeCode = dyn Fn(&[Val]) -> Result<Val, String>;
+
+/// A function implemented in Rust that Skiff programs can call like any other function. Its
+/// signature is what the type inferencer checks calls against, and calls with the wrong number
+/// of arguments are rejected before the Rust code runs.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub type_params: Vec<String>,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    pub code: Rc<NativeCode>,
+}
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        type_params: Vec<&str>,
+        params: Vec<Type>,
+        return_type: Type,
+        code: impl Fn(&[Val]) -> Result<Val, String> + 'static,
+    ) -> Self {
+        NativeFunction {
+            name: name.to_string(),
+            type_params: type_params.into_iter().map(String::from).collect(),
+            params,
+            return_type,
+            code: Rc::new(code),
+        }
+    }
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+    /// Calls the function, checking the number of arguments first
+    pub fn call(&self, args: &[Val]) -> Result<Val, String> {
+        if args.len() != self.arity() {
+            return Err(format!(
+                "Function takes {} arguments but {} were provided",
+                self.arity(),
+                args.len()
+            ));
+        }
+        (self.code)(args)
+    }
+}
+// Rust closures can't be compared, so native functions are only equal if they share their code
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.code, &other.code)
+    }
+}
+impl Hash for NativeFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
@@ -0,0 +1,331 @@
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as LspRequest};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticRelatedInformation, DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use skiff::analysis::{Analysis, CompletionKind};
+use skiff::runtime::Session;
+use std::collections::HashMap;
+use std::error;
+use std::path::PathBuf;
+
+/// An open document, along with the analysis of its latest text
+struct Document {
+    text: String,
+    analysis: Analysis,
+    /// The latest analysis of the document that parsed, used for completion while the
+    /// document is being edited and doesn't parse
+    last_parsed: Option<Analysis>,
+}
+
+/// A language server for Skiff, which speaks the Language Server Protocol over stdio
+struct Server {
+    connection: Connection,
+    /// The session every document is checked in, which holds the prelude
+    session: Session,
+    documents: HashMap<Url, Document>,
+}
+
+fn main() -> Result<(), Box<dyn error::Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(Default::default()),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let server = Server {
+        connection,
+        session: Session::new(),
+        documents: HashMap::new(),
+    };
+    server.run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+impl Server {
+    /// Handles messages until the client shuts the server down. The connection is dropped
+    /// when it returns, which lets the IO threads finish.
+    fn run(mut self) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let Request { id, method, params } = request;
+        match method.as_str() {
+            HoverRequest::METHOD => respond::<HoverRequest>(id, params, |p| self.hover(p)),
+            GotoDefinition::METHOD => respond::<GotoDefinition>(id, params, |p| self.definition(p)),
+            Completion::METHOD => respond::<Completion>(id, params, |p| self.completion(p)),
+            _ => Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request: {}", method),
+            ),
+        }
+    }
+
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+        let Notification { method, params } = notification;
+        match method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Some(params) = parse_params::<DidOpenTextDocument>(params) {
+                    self.update(params.text_document.uri, params.text_document.text)?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                // Documents are synced in full, so the last change holds the whole text
+                if let Some(mut params) = parse_params::<DidChangeTextDocument>(params) {
+                    if let Some(change) = params.content_changes.pop() {
+                        self.update(params.text_document.uri, change.text)?;
+                    }
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Some(params) = parse_params::<DidCloseTextDocument>(params) {
+                    self.documents.remove(&params.text_document.uri);
+                    self.publish(params.text_document.uri, vec![])?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Checks the new text of a document and publishes the problems found in it
+    fn update(
+        &mut self,
+        uri: Url,
+        text: String,
+    ) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+        let analysis = Analysis::new(&self.session, &path_of(&uri), &text);
+        let diagnostics = analysis
+            .problems()
+            .into_iter()
+            .map(|problem| Diagnostic {
+                range: range_of(&text, problem.span.clone().unwrap_or(0..0)),
+                severity: Some(match problem.is_error() {
+                    true => DiagnosticSeverity::ERROR,
+                    false => DiagnosticSeverity::WARNING,
+                }),
+                code: Some(NumberOrString::String(problem.code.to_string())),
+                source: Some("skiff".to_string()),
+                // Notes and suggestions have nowhere else to go, so they follow the message
+                message: std::iter::once(problem.message)
+                    .chain(
+                        problem
+                            .notes
+                            .into_iter()
+                            .map(|note| format!("note: {}", note)),
+                    )
+                    .chain(
+                        (problem.suggestions.into_iter()).map(|s| format!("help: {}", s.message)),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                related_information: Some(
+                    (problem.labels.into_iter())
+                        .map(|label| DiagnosticRelatedInformation {
+                            location: Location::new(uri.clone(), range_of(&text, label.span)),
+                            message: label.message,
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            })
+            .collect();
+
+        let last_parsed = match self.documents.remove(&uri) {
+            _ if analysis.parsed() => Some(analysis.clone()),
+            Some(document) if document.analysis.parsed() => Some(document.analysis),
+            Some(document) => document.last_parsed,
+            None => None,
+        };
+        self.documents.insert(
+            uri.clone(),
+            Document {
+                text,
+                analysis,
+                last_parsed,
+            },
+        );
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let index = index_of(&document.text, position.position);
+        let (span, text) = document.analysis.hover(index)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```skiff\n{}\n```", text),
+            }),
+            range: Some(range_of(&document.text, span)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let document = self.documents.get(&uri)?;
+        let index = index_of(&document.text, position.position);
+        let location = document.analysis.definition(index)?;
+
+        // The definition may be in an imported file rather than the document. Definitions in
+        // the prelude don't have a file to go to.
+        let (uri, text) = if location.path == path_of(&uri) {
+            (uri, document.text.clone())
+        } else {
+            let path = std::fs::canonicalize(&location.path).ok()?;
+            let text = std::fs::read_to_string(&path).ok()?;
+            (Url::from_file_path(path).ok()?, text)
+        };
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri,
+            range: range_of(&text, location.span),
+        }))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let index = index_of(&document.text, position.position);
+        let analysis = match &document.last_parsed {
+            Some(last_parsed) if !document.analysis.parsed() => last_parsed,
+            _ => &document.analysis,
+        };
+        let items = analysis
+            .completions(index)
+            .into_iter()
+            .map(|completion| CompletionItem {
+                label: completion.name,
+                kind: Some(match completion.kind {
+                    CompletionKind::Function => CompletionItemKind::FUNCTION,
+                    CompletionKind::Variable => CompletionItemKind::VARIABLE,
+                    CompletionKind::Constructor => CompletionItemKind::CONSTRUCTOR,
+                }),
+                detail: completion.detail,
+                ..Default::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// Answers a request, or reports that its parameters are invalid
+fn respond<R: LspRequest>(
+    id: RequestId,
+    params: serde_json::Value,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(params) {
+        Ok(params) => Response::new_ok(id, handler(params)),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+/// Reads the parameters of a notification. Notifications can't be answered, so ones with
+/// invalid parameters are ignored.
+fn parse_params<N: LspNotification>(params: serde_json::Value) -> Option<N::Params> {
+    serde_json::from_value(params).ok()
+}
+
+/// The path of a document, which imports are relative to
+fn path_of(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|_| PathBuf::from(uri.path()))
+}
+
+/// Converts a position in a document, whose character is counted in UTF-16 code units, to a
+/// byte index
+fn index_of(text: &str, position: Position) -> usize {
+    let mut index = 0;
+    for (line_number, line) in text.split_inclusive('\n').enumerate() {
+        if line_number == position.line as usize {
+            let mut units = 0;
+            for (i, c) in line.char_indices() {
+                if units >= position.character as usize || c == '\n' {
+                    return index + i;
+                }
+                units += c.len_utf16();
+            }
+            return index + line.len();
+        }
+        index += line.len();
+    }
+    text.len()
+}
+
+/// Converts a byte index in a document to a position
+fn position_of(text: &str, index: usize) -> Position {
+    let before = &text[..index.min(text.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Position::new(line as u32, character as u32)
+}
+
+fn range_of(text: &str, span: std::ops::Range<usize>) -> Range {
+    Range::new(position_of(text, span.start), position_of(text, span.end))
+}
+
+#[cfg(test)]
+mod lsp_tests {
+    use super::*;
+
+    #[test]
+    fn converts_positions() {
+        let text = "let é = 1\nlet x = é + 1\n";
+        assert_eq!(index_of(text, Position::new(1, 8)), 19);
+        assert_eq!(position_of(text, 19), Position::new(1, 8));
+        assert_eq!(index_of(text, Position::new(0, 100)), 10);
+        assert_eq!(index_of(text, Position::new(5, 0)), text.len());
+        assert_eq!(position_of(text, text.len()), Position::new(2, 0));
+    }
+}
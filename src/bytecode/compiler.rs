@@ -0,0 +1,710 @@
+use super::instruction::{Capture, Function, Instruction, LocalInfo, PatternCode, Value};
+use crate::ast::{Ast, AstNode, FunctionCast, Identifier, Pattern, Program};
+use crate::interpreter::casts::{annotation, annotation_cast};
+use crate::interpreter::interpret::{
+    find_data_declarations, InterpError, InterpErrorKind, StackFrame,
+};
+use im::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A top-level expression or let binding, compiled into a function that takes no arguments
+#[derive(PartialEq, Debug, Clone)]
+pub enum TopLevelItem {
+    Expression(Rc<Function>),
+    /// (global_slot, binding)
+    Let(usize, Rc<Function>),
+    /// (global_slots, binding) A let with a pattern, whose binding makes a tuple of the values
+    /// of the pattern's variables to go in their slots
+    LetPattern(Vec<usize>, Rc<Function>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CompiledProgram {
+    /// Each top-level function (including data constructors) and the slot it belongs in
+    pub functions: Vec<(usize, Rc<Function>)>,
+    pub items: Vec<TopLevelItem>,
+    pub global_slots: HashMap<String, usize>,
+    pub function_slots: HashMap<String, usize>,
+}
+
+/// The names that previously compiled programs have assigned to global and function slots
+#[derive(PartialEq, Debug, Clone)]
+pub struct CompilerState {
+    /// Map from a top-level let binding's name to its slot. A binding that shadows an earlier
+    /// one gets a new slot, so closures over the earlier binding still see the earlier value.
+    pub global_slots: HashMap<String, usize>,
+    pub num_globals: usize,
+    /// Map from a top-level function's name to its slot. A function that shadows an earlier
+    /// one reuses its slot, so earlier functions call the newer definition.
+    pub function_slots: HashMap<String, usize>,
+}
+impl CompilerState {
+    pub fn new() -> Self {
+        CompilerState {
+            global_slots: HashMap::new(),
+            num_globals: 0,
+            function_slots: HashMap::new(),
+        }
+    }
+}
+
+/// Compiles a program to bytecode on top of the top-level bindings of earlier programs
+pub fn compile_program(
+    program: &Program,
+    state: &CompilerState,
+) -> Result<CompiledProgram, InterpError> {
+    let data_funcs_ast = find_data_declarations(program)?;
+
+    // Every function gets a slot before any code is compiled, so that functions can refer
+    // to functions declared after them
+    let mut function_slots = state.function_slots.clone();
+    let function_nodes: Vec<&Ast> = program
+        .iter()
+        .chain(data_funcs_ast.iter())
+        .filter(|expr| matches!(expr.node, AstNode::FunctionNode(_, _, _, _, _)))
+        .collect();
+    for expr in &function_nodes {
+        if let AstNode::FunctionNode(name, _, _, _, _) = &expr.node {
+            if !function_slots.contains_key(name) {
+                let slot = function_slots.len();
+                function_slots.insert(name.clone(), slot);
+            }
+        }
+    }
+
+    let mut compiler = Compiler {
+        frames: vec![],
+        global_slots: state.global_slots.clone(),
+        function_slots: &function_slots,
+    };
+
+    let mut functions = vec![];
+    for expr in function_nodes {
+        if let AstNode::FunctionNode(name, _, params, return_type, body) = &expr.node {
+            let cast = annotation_cast(&format!("`{}`", name), params, return_type, &expr.src_loc);
+            let function =
+                compiler.compile_function(FunctionKind::Def, name, params, body, cast)?;
+            functions.push((function_slots[name], function));
+        }
+    }
+
+    let mut items = vec![];
+    let mut num_globals = state.num_globals;
+    for expr in program {
+        match &expr.node {
+            AstNode::LetNodeTopLevel(id, binding) => {
+                // An annotated binding is compiled as `let id = binding; id`, so that its value
+                // is checked like a nested let's
+                let checked_binding;
+                let binding = match annotation(&id.type_decl) {
+                    Some(_) => {
+                        let value = AstNode::VarNode(id.id.clone());
+                        checked_binding = Ast::new(
+                            AstNode::LetNode(
+                                id.clone(),
+                                binding.clone(),
+                                Box::new(Ast::new(value, binding.src_loc.clone())),
+                            ),
+                            expr.src_loc.clone(),
+                        );
+                        &checked_binding
+                    }
+                    None => &**binding,
+                };
+                let function = compiler.compile_function(
+                    FunctionKind::TopLevel,
+                    &id.id,
+                    &[],
+                    binding,
+                    None,
+                )?;
+                // The binding is only visible to the expressions after it
+                compiler.global_slots.insert(id.id.clone(), num_globals);
+                items.push(TopLevelItem::Let(num_globals, function));
+                num_globals += 1;
+            }
+            AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+                let variables = unique_variables(pattern);
+                let values = variables
+                    .iter()
+                    .map(|name| Ast::new(AstNode::VarNode(name.clone()), binding.src_loc.clone()))
+                    .collect();
+                let body = Ast::new(
+                    AstNode::LetPatternNode(
+                        pattern.clone(),
+                        binding.clone(),
+                        Box::new(Ast::new(
+                            AstNode::TupleNode(values),
+                            binding.src_loc.clone(),
+                        )),
+                    ),
+                    expr.src_loc.clone(),
+                );
+                let function = compiler.compile_function(
+                    FunctionKind::TopLevel,
+                    "<top level>",
+                    &[],
+                    &body,
+                    None,
+                )?;
+                let mut slots = vec![];
+                for name in variables {
+                    compiler.global_slots.insert(name, num_globals);
+                    slots.push(num_globals);
+                    num_globals += 1;
+                }
+                items.push(TopLevelItem::LetPattern(slots, function));
+            }
+            AstNode::FunctionNode(_, _, _, _, _)
+            | AstNode::DataDeclarationNode(_, _, _)
+            | AstNode::ImportNode(_, _) => (),
+            AstNode::LetNode(_, _, _) | AstNode::LetPatternNode(_, _, _) => {
+                return Err(compile_error(
+                    "Found LetNode instead of LetNodeToplevel on top level",
+                    expr,
+                ))
+            }
+            _ => {
+                let function = compiler.compile_function(
+                    FunctionKind::TopLevel,
+                    "<top level>",
+                    &[],
+                    expr,
+                    None,
+                )?;
+                items.push(TopLevelItem::Expression(function));
+            }
+        }
+    }
+
+    Ok(CompiledProgram {
+        functions,
+        items,
+        global_slots: compiler.global_slots,
+        function_slots,
+    })
+}
+
+fn compile_error(message: &str, expr: &Ast) -> InterpError {
+    InterpError(
+        message.to_string(),
+        expr.src_loc.span.clone(),
+        HashMap::new(),
+        StackFrame::new_stack(),
+        InterpErrorKind::Invalid,
+    )
+}
+
+/// Returns the variables a pattern binds, leaving out any repeats of a variable, which
+/// `compile_pattern` doesn't bind
+fn unique_variables(pattern: &Pattern) -> Vec<String> {
+    let mut variables: Vec<String> = vec![];
+    for name in pattern.variables() {
+        if !variables.contains(&name) {
+            variables.push(name);
+        }
+    }
+    variables
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum FunctionKind {
+    /// A top-level expression or let binding
+    TopLevel,
+    Def,
+    Lambda,
+}
+
+/// The state of a function that is being compiled
+struct FunctionCompiler {
+    kind: FunctionKind,
+    function: Function,
+    /// The variables in scope, innermost last, along with their slots and (for named
+    /// variables) their index in `function.locals`
+    scope: Vec<(String, usize, Option<usize>)>,
+}
+
+struct Compiler<'a> {
+    /// The functions being compiled, innermost last
+    frames: Vec<FunctionCompiler>,
+    global_slots: HashMap<String, usize>,
+    function_slots: &'a HashMap<String, usize>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile_function(
+        &mut self,
+        kind: FunctionKind,
+        name: &str,
+        params: &[Identifier],
+        body: &Ast,
+        cast: Option<Rc<FunctionCast>>,
+    ) -> Result<Rc<Function>, InterpError> {
+        // Only code nested in a top-level expression can see top-level let bindings
+        let globals = match self.frames.first() {
+            Some(frame) if frame.kind != FunctionKind::TopLevel => None,
+            _ if kind == FunctionKind::Def => None,
+            _ => Some(self.global_slots.clone()),
+        };
+        self.frames.push(FunctionCompiler {
+            kind,
+            function: Function {
+                name: name.to_string(),
+                params: params.iter().map(|param| param.id.clone()).collect(),
+                body: Rc::new(body.clone()),
+                num_slots: 0,
+                captures: vec![],
+                upvalue_names: vec![],
+                code: vec![],
+                spans: vec![],
+                constants: vec![],
+                names: vec![],
+                discriminants: vec![],
+                functions: vec![],
+                patterns: vec![],
+                bindings: vec![],
+                locals: vec![],
+                globals,
+                cast,
+            },
+            scope: vec![],
+        });
+
+        for param in params {
+            self.declare_local(&param.id);
+        }
+        // Calls at the end of a function's body reuse its frame
+        self.compile_expr(body, kind != FunctionKind::TopLevel)?;
+        self.emit(Instruction::Return, body.src_loc.span.clone());
+
+        let FunctionCompiler { mut function, .. } = self.frames.pop().unwrap();
+        for local in function.locals.iter_mut() {
+            local.end = local.end.min(function.code.len());
+        }
+        Ok(Rc::new(function))
+    }
+
+    fn current(&mut self) -> &mut FunctionCompiler {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Range<usize>) -> usize {
+        let function = &mut self.current().function;
+        function.code.push(instruction);
+        function.spans.push(span);
+        function.code.len() - 1
+    }
+
+    /// Points a previously emitted jump at the next instruction to be emitted
+    fn patch_jump(&mut self, index: usize) {
+        let target = self.current().function.code.len();
+        match &mut self.current().function.code[index] {
+            Instruction::Jump(t) | Instruction::JumpIfFalse(t) | Instruction::Match(_, _, t) => {
+                *t = target
+            }
+            _ => panic!("Tried to patch a non-jump instruction"),
+        }
+    }
+
+    fn declare_local(&mut self, name: &str) -> usize {
+        let frame = self.current();
+        let slot = frame.scope.len();
+        frame.function.num_slots = frame.function.num_slots.max(slot + 1);
+        frame.function.locals.push(LocalInfo {
+            name: name.to_string(),
+            slot,
+            start: frame.function.code.len(),
+            end: usize::MAX,
+        });
+        let info = frame.function.locals.len() - 1;
+        frame.scope.push((name.to_string(), slot, Some(info)));
+        slot
+    }
+
+    /// Reserves a slot for a value that can't be referred to by name
+    fn declare_hidden(&mut self) -> usize {
+        let frame = self.current();
+        let slot = frame.scope.len();
+        frame.function.num_slots = frame.function.num_slots.max(slot + 1);
+        frame.scope.push((String::new(), slot, None));
+        slot
+    }
+
+    /// Removes the most recently declared variables from scope
+    fn end_scope(&mut self, count: usize) {
+        let frame = self.current();
+        for _ in 0..count {
+            if let Some((_, _, Some(info))) = frame.scope.pop() {
+                frame.function.locals[info].end = frame.function.code.len();
+            }
+        }
+    }
+
+    fn resolve_local(frame: &FunctionCompiler, name: &str) -> Option<usize> {
+        frame
+            .scope
+            .iter()
+            .rev()
+            .find(|(local_name, _, info)| info.is_some() && local_name == name)
+            .map(|(_, slot, _)| *slot)
+    }
+
+    /// Finds a variable from an enclosing function and captures it in the function at
+    /// `depth` (and every function in between), returning its upvalue index
+    fn resolve_upvalue(&mut self, depth: usize, name: &str) -> Option<usize> {
+        // Only lambdas capture their environment
+        if depth == 0 || self.frames[depth].kind != FunctionKind::Lambda {
+            return None;
+        }
+        let capture = match Compiler::resolve_local(&self.frames[depth - 1], name) {
+            Some(slot) => Capture::Local(slot),
+            None => Capture::Upvalue(self.resolve_upvalue(depth - 1, name)?),
+        };
+
+        let function = &mut self.frames[depth].function;
+        match function.upvalue_names.iter().position(|n| n == name) {
+            Some(index) => Some(index),
+            None => {
+                function.upvalue_names.push(name.to_string());
+                function.captures.push(capture);
+                Some(function.captures.len() - 1)
+            }
+        }
+    }
+
+    fn compile_var(&mut self, name: &str, span: Range<usize>) {
+        let depth = self.frames.len() - 1;
+        // Variables are looked up in the environment and then in the function table
+        let instruction = if let Some(slot) = Compiler::resolve_local(&self.frames[depth], name) {
+            Instruction::GetLocal(slot)
+        } else if let Some(index) = self.resolve_upvalue(depth, name) {
+            Instruction::GetUpvalue(index)
+        } else if let Some(slot) = self
+            .current()
+            .function
+            .globals
+            .as_ref()
+            .and_then(|globals| globals.get(name).cloned())
+        {
+            Instruction::GetGlobal(slot)
+        } else if let Some(slot) = self.function_slots.get(name) {
+            Instruction::GetFunction(*slot)
+        } else {
+            let names = &mut self.current().function.names;
+            names.push(name.to_string());
+            Instruction::GetFunctionByName(names.len() - 1)
+        };
+        self.emit(instruction, span);
+    }
+
+    fn compile_constant(&mut self, value: Value, span: Range<usize>) {
+        let constants = &mut self.current().function.constants;
+        constants.push(value);
+        let index = constants.len() - 1;
+        self.emit(Instruction::Constant(index), span);
+    }
+
+    /// Compiles an expression, leaving its value on top of the stack. `tail` is whether the
+    /// expression's value is returned from the enclosing `def` or lambda.
+    fn compile_expr(&mut self, expr: &Ast, tail: bool) -> Result<(), InterpError> {
+        let span = expr.src_loc.span.clone();
+        match &expr.node {
+            AstNode::NumberNode(n) => self.compile_constant(Value::Num(*n), span),
+            AstNode::BigNumberNode(n) => self.compile_constant(Value::BigNum(n.clone()), span),
+            AstNode::FloatNode(x, _) => self.compile_constant(Value::Float(*x), span),
+            AstNode::BoolNode(b) => self.compile_constant(Value::Bool(*b), span),
+            AstNode::StringNode(s) => self.compile_constant(Value::Str(s.as_str().into()), span),
+            AstNode::VarNode(id) => self.compile_var(id, span),
+            AstNode::LetNode(id, binding, body) => {
+                self.compile_expr(binding, false)?;
+                if annotation(&id.type_decl).is_some() {
+                    let bindings = &mut self.current().function.bindings;
+                    bindings.push(id.clone());
+                    let index = bindings.len() - 1;
+                    self.emit(Instruction::CheckBinding(index), span.clone());
+                }
+                let slot = self.declare_local(&id.id);
+                self.emit(Instruction::SetLocal(slot), span);
+                self.compile_expr(body, tail)?;
+                self.end_scope(1);
+            }
+            AstNode::LetPatternNode(pattern, binding, body) => {
+                self.compile_expr(binding, false)?;
+                let target = self.declare_hidden();
+                self.emit(Instruction::SetLocal(target), span.clone());
+
+                let mut bound = vec![];
+                let pattern = self.compile_pattern(pattern, &mut bound, None);
+                let patterns = &mut self.current().function.patterns;
+                patterns.push(pattern);
+                let pattern_index = patterns.len() - 1;
+
+                let failed_jump =
+                    self.emit(Instruction::Match(target, pattern_index, 0), span.clone());
+                self.compile_expr(body, tail)?;
+                self.end_scope(bound.len());
+                let end_jump = self.emit(Instruction::Jump(0), span.clone());
+                self.patch_jump(failed_jump);
+                self.emit(Instruction::LetMatchFailed(target), span);
+                self.patch_jump(end_jump);
+                self.end_scope(1);
+            }
+            AstNode::LetNodeTopLevel(_, _) | AstNode::LetPatternNodeTopLevel(_, _) => {
+                return Err(compile_error(
+                    "Found LetNodeTopLevel instead of LetNode in expression",
+                    expr,
+                ))
+            }
+            AstNode::BinOpNode(op, e1, e2) => {
+                self.compile_expr(e1, false)?;
+                self.compile_expr(e2, false)?;
+                self.emit(Instruction::BinOp(*op), span);
+            }
+            AstNode::LambdaNode(params, body) => {
+                let cast = annotation_cast("a lambda", params, &None, &expr.src_loc);
+                let function =
+                    self.compile_function(FunctionKind::Lambda, "<lambda>", params, body, cast)?;
+                let functions = &mut self.current().function.functions;
+                functions.push(function);
+                let index = functions.len() - 1;
+                self.emit(Instruction::Closure(index), span);
+            }
+            AstNode::FunCallNode(fun, args) => {
+                self.compile_expr(fun, false)?;
+                self.emit(Instruction::CheckCall(args.len()), span.clone());
+                for arg in args {
+                    self.compile_expr(arg, false)?;
+                }
+                if tail {
+                    self.emit(Instruction::TailCall(args.len()), span);
+                } else {
+                    self.emit(Instruction::Call(args.len()), span);
+                }
+            }
+            AstNode::IfNode(conditions_and_bodies, alternate) => {
+                let mut end_jumps = vec![];
+                for (condition, body) in conditions_and_bodies {
+                    self.compile_expr(condition, false)?;
+                    let next_jump = self.emit(Instruction::JumpIfFalse(0), span.clone());
+                    self.compile_expr(body, tail)?;
+                    end_jumps.push(self.emit(Instruction::Jump(0), span.clone()));
+                    self.patch_jump(next_jump);
+                }
+                self.compile_expr(alternate, tail)?;
+                for jump in end_jumps {
+                    self.patch_jump(jump);
+                }
+            }
+            AstNode::FunctionNode(_, _, _, _, _) => {
+                return Err(compile_error("Function node not at top level", expr))
+            }
+            AstNode::DataDeclarationNode(_, _, _) => {
+                return Err(compile_error(
+                    "Found DataDeclarationNode instead of LetNode in expression",
+                    expr,
+                ))
+            }
+            AstNode::ImportNode(_, _) => {
+                return Err(compile_error("Import not at top level", expr))
+            }
+            AstNode::ErrorNode(message, _) => return Err(compile_error(message, expr)),
+            AstNode::DataLiteralNode(discriminant, fields) => {
+                for field in fields {
+                    self.compile_expr(field, false)?;
+                }
+                let discriminants = &mut self.current().function.discriminants;
+                discriminants.push(discriminant.clone());
+                let index = discriminants.len() - 1;
+                self.emit(Instruction::MakeData(index, fields.len()), span);
+            }
+            AstNode::ListNode(items, rest) => {
+                for item in items {
+                    self.compile_expr(item, false)?;
+                }
+                if let Some(rest) = rest {
+                    self.compile_expr(rest, false)?;
+                }
+                self.emit(Instruction::MakeList(items.len(), rest.is_some()), span);
+            }
+            AstNode::TupleNode(items) => {
+                for item in items {
+                    self.compile_expr(item, false)?;
+                }
+                self.emit(Instruction::MakeTuple(items.len()), span);
+            }
+            AstNode::RecordNode(fields) => {
+                for (_, value) in fields {
+                    self.compile_expr(value, false)?;
+                }
+                let index = self.add_field_names(fields);
+                self.emit(Instruction::MakeRecord(index, fields.len()), span);
+            }
+            AstNode::RecordUpdateNode(record, fields) => {
+                self.compile_expr(record, false)?;
+                for (_, value) in fields {
+                    self.compile_expr(value, false)?;
+                }
+                let index = self.add_field_names(fields);
+                self.emit(Instruction::UpdateRecord(index, fields.len()), span);
+            }
+            AstNode::FieldAccessNode(value, field) => {
+                self.compile_expr(value, false)?;
+                let names = &mut self.current().function.names;
+                names.push(field.clone());
+                let index = names.len() - 1;
+                self.emit(Instruction::GetField(index), span);
+            }
+            AstNode::MatchNode(expression_to_match, branches) => {
+                self.compile_expr(expression_to_match, false)?;
+                let target = self.declare_hidden();
+                self.emit(Instruction::SetLocal(target), span.clone());
+
+                let mut end_jumps = vec![];
+                for (pattern, guard, body, _) in branches {
+                    let mut bound = vec![];
+                    let pattern = self.compile_pattern(pattern, &mut bound, None);
+                    let patterns = &mut self.current().function.patterns;
+                    patterns.push(pattern);
+                    let pattern_index = patterns.len() - 1;
+
+                    let next_jump =
+                        self.emit(Instruction::Match(target, pattern_index, 0), span.clone());
+                    // A false guard moves on to the next branch like a pattern that didn't match
+                    let guard_jump = match guard {
+                        Some(guard) => {
+                            self.compile_expr(guard, false)?;
+                            Some(self.emit(Instruction::JumpIfFalse(0), span.clone()))
+                        }
+                        None => None,
+                    };
+                    self.compile_expr(body, tail)?;
+                    self.end_scope(bound.len());
+                    end_jumps.push(self.emit(Instruction::Jump(0), span.clone()));
+                    self.patch_jump(next_jump);
+                    if let Some(guard_jump) = guard_jump {
+                        self.patch_jump(guard_jump);
+                    }
+                }
+                self.emit(Instruction::MatchFailed, span);
+                for jump in end_jumps {
+                    self.patch_jump(jump);
+                }
+                self.end_scope(1);
+            }
+        };
+        Ok(())
+    }
+
+    /// Adds the names of a record's fields to the names pool, returning the index of the first
+    fn add_field_names(&mut self, fields: &[(String, Ast)]) -> usize {
+        let names = &mut self.current().function.names;
+        let index = names.len();
+        names.extend(fields.iter().map(|(field, _)| field.clone()));
+        index
+    }
+
+    /// Assigns a slot to each identifier in a pattern. If an identifier appears more than
+    /// once then only its first occurrence is bound. The later alternatives of an or-pattern
+    /// bind their variables to the slots that the first alternative `declared`.
+    fn compile_pattern(
+        &mut self,
+        pattern: &Pattern,
+        bound: &mut Vec<String>,
+        declared: Option<&[String]>,
+    ) -> PatternCode {
+        match pattern {
+            Pattern::NumLiteral(n) => PatternCode::NumLiteral(*n),
+            Pattern::BoolLiteral(b) => PatternCode::BoolLiteral(*b),
+            Pattern::StringLiteral(s) => PatternCode::StringLiteral(s.clone()),
+            Pattern::Wildcard => PatternCode::Ignore,
+            Pattern::Identifier(id) => match self.bind_variable(id, bound, declared) {
+                Some(slot) => PatternCode::Bind(slot),
+                None => PatternCode::Ignore,
+            },
+            Pattern::As(pattern, name) => {
+                let pattern = self.compile_pattern(pattern, bound, declared);
+                match self.bind_variable(name, bound, declared) {
+                    Some(slot) => PatternCode::As(Box::new(pattern), slot),
+                    None => pattern,
+                }
+            }
+            Pattern::Or(alternatives) => {
+                let before = bound.len();
+                let mut codes = vec![];
+                let mut first_variables = vec![];
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i == 0 {
+                        codes.push(self.compile_pattern(alternative, bound, declared));
+                        first_variables = bound[before..].to_vec();
+                    } else {
+                        let mut alternative_bound = bound[..before].to_vec();
+                        codes.push(self.compile_pattern(
+                            alternative,
+                            &mut alternative_bound,
+                            Some(&first_variables),
+                        ));
+                    }
+                }
+                PatternCode::Or(codes)
+            }
+            Pattern::Data(variant, patterns) => PatternCode::Data(
+                variant.clone(),
+                patterns
+                    .iter()
+                    .map(|pattern| self.compile_pattern(pattern, bound, declared))
+                    .collect(),
+            ),
+            Pattern::List(patterns, rest) => PatternCode::List(
+                patterns
+                    .iter()
+                    .map(|pattern| self.compile_pattern(pattern, bound, declared))
+                    .collect(),
+                rest.as_ref()
+                    .map(|rest| Box::new(self.compile_pattern(rest, bound, declared))),
+            ),
+            Pattern::Tuple(patterns) => PatternCode::Tuple(
+                patterns
+                    .iter()
+                    .map(|pattern| self.compile_pattern(pattern, bound, declared))
+                    .collect(),
+            ),
+            Pattern::Record(fields) => PatternCode::Record(
+                fields
+                    .iter()
+                    .map(|(field, pattern)| {
+                        (
+                            field.clone(),
+                            self.compile_pattern(pattern, bound, declared),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns the slot a variable in a pattern is bound to, or None if the pattern shouldn't
+    /// bind it
+    fn bind_variable(
+        &mut self,
+        name: &str,
+        bound: &mut Vec<String>,
+        declared: Option<&[String]>,
+    ) -> Option<usize> {
+        if bound.iter().any(|bound_name| bound_name == name) {
+            return None;
+        }
+        match declared {
+            None => {
+                bound.push(name.to_string());
+                Some(self.declare_local(name))
+            }
+            Some(declared) if declared.iter().any(|declared_name| declared_name == name) => {
+                bound.push(name.to_string());
+                Compiler::resolve_local(self.current(), name)
+            }
+            // Variables that the first alternative doesn't bind aren't in scope
+            Some(_) => None,
+        }
+    }
+}
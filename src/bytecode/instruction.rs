@@ -0,0 +1,327 @@
+use crate::ast::{Ast, BinOp, Discriminant, Env, FunctionCast, Identifier, NativeFunction, Val};
+use crate::interpreter::bigint::BigInt;
+use crate::interpreter::casts::{Castable, View};
+use crate::interpreter::numeric::{self, Number};
+use im::{HashMap, OrdMap, Vector};
+use std::{ops::Range, rc::Rc};
+
+/// A single bytecode instruction. Operands are indices into the pools of the function that
+/// contains the instruction, or into the slots of the current call frame.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Instruction {
+    /// (constant_index)
+    Constant(usize),
+    /// (slot)
+    GetLocal(usize),
+    /// Pops the top of the stack into a slot. (slot)
+    SetLocal(usize),
+    /// (upvalue_index)
+    GetUpvalue(usize),
+    /// Pushes the value of a top-level let binding. (global_slot)
+    GetGlobal(usize),
+    /// Pushes a top-level function. (function_slot)
+    GetFunction(usize),
+    /// Pushes a top-level function that wasn't declared when the instruction was compiled,
+    /// looking it up by name when the instruction is executed. (name_index)
+    GetFunctionByName(usize),
+    /// Pops two operands and pushes the result of the operation. (operator)
+    BinOp(BinOp),
+    /// (target)
+    Jump(usize),
+    /// Pops a condition and jumps if it's false. (target)
+    JumpIfFalse(usize),
+    /// Creates a closure over the current frame. (function_index)
+    Closure(usize),
+    /// Checks that the value on top of the stack is a function that takes the given number of
+    /// arguments. This happens before the arguments are evaluated. (arg_count)
+    CheckCall(usize),
+    /// Calls the function below the arguments on top of the stack. (arg_count)
+    Call(usize),
+    /// Calls the function below the arguments on top of the stack, replacing the current
+    /// frame. Used for calls whose value is returned straight away. (arg_count)
+    TailCall(usize),
+    Return,
+    /// Pops the fields of a data value and pushes the value. (discriminant_index, field_count)
+    MakeData(usize, usize),
+    /// Pops the items of a list, and then the list they go in front of if there is one, and
+    /// pushes the new list. (item_count, has_rest)
+    MakeList(usize, bool),
+    /// Pops the items of a tuple and pushes the tuple. (item_count)
+    MakeTuple(usize),
+    /// Pops the values of a record's fields and pushes the record. The fields' names are
+    /// `names[name_index..name_index + field_count]`. (name_index, field_count)
+    MakeRecord(usize, usize),
+    /// Pops new values for some of the fields of a record or data value, and then the value
+    /// itself, and pushes the updated value. The fields' names are found like `MakeRecord`'s.
+    /// (name_index, field_count)
+    UpdateRecord(usize, usize),
+    /// Pops a record or data value and pushes the value of one of its fields. (name_index)
+    GetField(usize),
+    /// Matches the value in a slot against a pattern, storing any bound values in their own
+    /// slots. Jumps if the pattern doesn't match. (slot, pattern_index, target)
+    Match(usize, usize, usize),
+    /// Raises the error for a match expression where no branch matched
+    MatchFailed,
+    /// Raises the error for a let whose pattern didn't match the value in a slot. (slot)
+    LetMatchFailed(usize),
+    /// Checks the value on top of the stack against the type a let binding is annotated
+    /// with, replacing it with the checked value. (binding_index)
+    CheckBinding(usize),
+}
+
+/// A pattern whose identifiers have been assigned slots in the enclosing function
+#[derive(PartialEq, Debug, Clone)]
+pub enum PatternCode {
+    NumLiteral(i64),
+    BoolLiteral(bool),
+    StringLiteral(String),
+    Data(String, Vec<PatternCode>),
+    /// (items, rest)
+    List(Vec<PatternCode>, Option<Box<PatternCode>>),
+    /// (fields)
+    Record(Vec<(String, PatternCode)>),
+    /// (items)
+    Tuple(Vec<PatternCode>),
+    /// Binds the matched value to a slot
+    Bind(usize),
+    /// Matches anything without binding it
+    Ignore,
+    /// (alternatives) Matches if any of the alternatives do, trying them in order
+    Or(Vec<PatternCode>),
+    /// (pattern, slot) Binds the matched value to a slot if the pattern matches it
+    As(Box<PatternCode>, usize),
+}
+
+/// How a closure captures one of its upvalues from the function that creates it
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Capture {
+    /// (slot)
+    Local(usize),
+    /// (upvalue_index)
+    Upvalue(usize),
+}
+
+/// Records which slot holds a named variable while a range of instructions runs. Used to
+/// rebuild the environment when reporting errors.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LocalInfo {
+    pub name: String,
+    pub slot: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A compiled function (a `def`, a lambda or a top-level expression) along with the pools
+/// that its instructions refer to
+#[derive(PartialEq, Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    /// The function's body, which is kept so closures can be converted back into `Val`s
+    pub body: Rc<Ast>,
+    /// The number of slots in the function's call frame, including the parameters
+    pub num_slots: usize,
+    pub captures: Vec<Capture>,
+    pub upvalue_names: Vec<String>,
+    pub code: Vec<Instruction>,
+    /// The source span of each instruction, used for error messages
+    pub spans: Vec<Range<usize>>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub discriminants: Vec<Discriminant>,
+    pub functions: Vec<Rc<Function>>,
+    pub patterns: Vec<PatternCode>,
+    /// The identifiers of annotated let bindings, whose values are checked at runtime
+    pub bindings: Vec<Identifier>,
+    pub locals: Vec<LocalInfo>,
+    /// The top-level let bindings the function can see, if it's nested in a top-level
+    /// expression rather than a `def`
+    pub globals: Option<HashMap<String, usize>>,
+    /// The cast the function's annotations give it, which wraps the function when it's
+    /// defined
+    pub cast: Option<Rc<FunctionCast>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Value>,
+}
+impl Closure {
+    /// The environment captured by the closure
+    pub fn env(&self) -> Env {
+        self.function
+            .upvalue_names
+            .iter()
+            .zip(&self.upvalues)
+            .map(|(name, value)| (name.clone(), value.to_val()))
+            .collect()
+    }
+}
+
+/// A value in the VM. Unlike `Val`, closures and data values are reference counted so they
+/// are cheap to copy onto the stack.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Num(i64),
+    BigNum(Rc<BigInt>),
+    Float(f64),
+    Bool(bool),
+    Str(Rc<str>),
+    Closure(Rc<Closure>),
+    Native(Rc<NativeFunction>),
+    Data(Rc<(Discriminant, Vec<Value>)>),
+    List(Vector<Value>),
+    Record(OrdMap<String, Value>),
+    Tuple(Rc<Vec<Value>>),
+    /// (function, cast) A function whose calls check their arguments and result
+    Cast(Rc<Value>, Rc<FunctionCast>),
+}
+impl Value {
+    /// Returns the number this value holds, if it's a number
+    pub fn to_number(&self) -> Option<Number> {
+        match self {
+            Value::Num(n) => Some(Number::Int(*n)),
+            Value::BigNum(n) => Some(Number::Big(n.clone())),
+            Value::Float(x) => Some(Number::Float(*x)),
+            _ => None,
+        }
+    }
+    pub fn to_val(&self) -> Val {
+        match self {
+            Value::Num(n) => Val::Num(*n),
+            Value::BigNum(n) => Val::BigNum(n.clone()),
+            Value::Float(x) => Val::Float(*x),
+            Value::Bool(b) => Val::Bool(*b),
+            Value::Str(s) => Val::Str(s.to_string()),
+            Value::Closure(closure) => Val::Lam(
+                closure.function.params.clone(),
+                closure.function.body.clone(),
+                closure.env(),
+            ),
+            Value::Native(native) => Val::Native(native.clone()),
+            Value::Data(data) => Val::Data(
+                data.0.clone(),
+                Rc::new(data.1.iter().map(|v| v.to_val()).collect()),
+            ),
+            Value::List(items) => Val::List(items.iter().map(|v| v.to_val()).collect()),
+            Value::Record(fields) => Val::Record(
+                fields
+                    .iter()
+                    .map(|(field, v)| (field.clone(), v.to_val()))
+                    .collect(),
+            ),
+            Value::Tuple(items) => Val::Tuple(Rc::new(items.iter().map(|v| v.to_val()).collect())),
+            Value::Cast(function, cast) => Val::Cast(Rc::new(function.to_val()), cast.clone()),
+        }
+    }
+    /// Converts a `Val` into a value. A `Val` doesn't keep the compiled code of a Skiff
+    /// function, so any Skiff function in it is matched up with one of `functions` (e.g. the
+    /// arguments of the native function that returned it). Returns None if there's no match.
+    pub fn from_val(val: Val, functions: &[Value]) -> Option<Value> {
+        match val {
+            Val::Num(n) => Some(Value::Num(n)),
+            Val::BigNum(n) => Some(Value::BigNum(n)),
+            Val::Float(x) => Some(Value::Float(x)),
+            Val::Bool(b) => Some(Value::Bool(b)),
+            Val::Str(s) => Some(Value::Str(s.into())),
+            Val::Lam(_, _, _) => functions
+                .iter()
+                .find(|function| matches!(function, Value::Closure(_)) && function.to_val() == val)
+                .cloned(),
+            Val::Native(native) => Some(Value::Native(native)),
+            Val::Cast(function, cast) => Some(Value::Cast(
+                Rc::new(Value::from_val((*function).clone(), functions)?),
+                cast,
+            )),
+            Val::Data(discriminant, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| Value::from_val(field.clone(), functions))
+                    .collect::<Option<Vec<Value>>>()?;
+                Some(Value::Data(Rc::new((discriminant, fields))))
+            }
+            Val::List(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| Value::from_val(item, functions))
+                    .collect::<Option<Vector<Value>>>()?;
+                Some(Value::List(items))
+            }
+            Val::Record(fields) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(field, value)| Some((field, Value::from_val(value, functions)?)))
+                    .collect::<Option<OrdMap<String, Value>>>()?;
+                Some(Value::Record(fields))
+            }
+            Val::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| Value::from_val(item.clone(), functions))
+                    .collect::<Option<Vec<Value>>>()?;
+                Some(Value::Tuple(Rc::new(items)))
+            }
+        }
+    }
+}
+impl From<Number> for Value {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(n) => Value::Num(n),
+            Number::Big(n) => Value::BigNum(n),
+            Number::Float(x) => Value::Float(x),
+        }
+    }
+}
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        // Integers and floats are compared by value
+        if let (Some(x), Some(y)) = (self.to_number(), other.to_number()) {
+            return numeric::comparison(BinOp::Eq, &x, &y);
+        }
+        match (self, other) {
+            (Value::Bool(x), Value::Bool(y)) => x == y,
+            (Value::Str(x), Value::Str(y)) => x == y,
+            // Closures are equal if they come from the same code and captured the same values
+            (Value::Closure(x), Value::Closure(y)) => {
+                Rc::ptr_eq(&x.function, &y.function) && x.upvalues == y.upvalues
+            }
+            (Value::Native(x), Value::Native(y)) => x == y,
+            (Value::Data(x), Value::Data(y)) => x == y,
+            (Value::List(x), Value::List(y)) => x == y,
+            (Value::Record(x), Value::Record(y)) => x == y,
+            (Value::Tuple(x), Value::Tuple(y)) => x == y,
+            (Value::Cast(x1, x2), Value::Cast(y1, y2)) => x1 == y1 && x2 == y2,
+            _ => false,
+        }
+    }
+}
+impl Castable for Value {
+    fn view(&self) -> View<'_, Self> {
+        match self {
+            Value::Num(_) | Value::BigNum(_) | Value::Float(_) => View::Number,
+            Value::Bool(_) => View::Boolean,
+            Value::Str(_) => View::String,
+            Value::Closure(closure) => View::Function(closure.function.params.len(), None),
+            Value::Native(native) => View::Function(native.arity(), None),
+            Value::Cast(_, cast) => View::Function(cast.params.len(), Some(cast)),
+            Value::Data(data) => View::Data(&data.0, &data.1),
+            Value::List(items) => View::List(items),
+            Value::Record(fields) => View::Record(fields),
+            Value::Tuple(items) => View::Tuple(items),
+        }
+    }
+    fn new_list(items: Vector<Self>) -> Self {
+        Value::List(items)
+    }
+    fn new_tuple(items: Vec<Self>) -> Self {
+        Value::Tuple(Rc::new(items))
+    }
+    fn new_record(fields: OrdMap<String, Self>) -> Self {
+        Value::Record(fields)
+    }
+    fn new_cast(function: Self, cast: Rc<FunctionCast>) -> Self {
+        Value::Cast(Rc::new(function), cast)
+    }
+}
@@ -0,0 +1,1186 @@
+use super::compiler::{compile_program, CompilerState, TopLevelItem};
+use super::instruction::{Capture, Closure, Function, Instruction, PatternCode, Value};
+use crate::ast::{AstNode, BinOp, Env, FunctionCast, NativeFunction, Program, SrcLoc, Val};
+use crate::interpreter::casts::{self, DataType, DataTypes};
+use crate::interpreter::interpret::{InterpError, InterpErrorKind, StackFrame};
+use crate::interpreter::limits::{Budget, EvalLimits};
+use crate::interpreter::numeric;
+use im::{HashMap, OrdMap, Vector};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The top-level bindings, functions and data types that persist between successive calls
+/// to `run_incremental` (e.g. between lines entered into the REPL)
+#[derive(PartialEq, Debug, Clone)]
+pub struct VmState {
+    pub compiler_state: CompilerState,
+    pub globals: Vec<Value>,
+    pub functions: Vec<Option<Value>>,
+    pub data_types: DataTypes,
+}
+impl VmState {
+    pub fn new() -> Self {
+        VmState {
+            compiler_state: CompilerState::new(),
+            globals: vec![],
+            functions: vec![],
+            data_types: DataTypes::new(),
+        }
+    }
+
+    /// Puts a value from the host in a function slot, so that function bodies can see it
+    /// as well as top-level code. It shadows any earlier binding with the same name.
+    pub fn define_host_value(&mut self, name: &str, value: Value) {
+        self.compiler_state.global_slots.remove(name);
+        self.set_function_slot(name, value);
+    }
+
+    /// Puts a native function in a function slot, where programs' own functions can shadow it
+    pub fn define_native(&mut self, native: &NativeFunction) {
+        self.set_function_slot(&native.name, Value::Native(Rc::new(native.clone())));
+    }
+
+    fn set_function_slot(&mut self, name: &str, value: Value) {
+        let function_slots = &mut self.compiler_state.function_slots;
+        let slot = match function_slots.get(name) {
+            Some(slot) => *slot,
+            None => {
+                let slot = function_slots.len();
+                function_slots.insert(name.to_string(), slot);
+                slot
+            }
+        };
+        self.functions.resize(function_slots.len(), None);
+        self.functions[slot] = Some(value);
+    }
+}
+
+/// Compile a Skiff program to bytecode and run it, possibly returning a runtime error
+pub fn run(program: &Program) -> Result<Vec<Val>, InterpError> {
+    let (vals, _) = run_incremental(program, &VmState::new(), &EvalLimits::default())?;
+    Ok(vals)
+}
+
+/// Compile and run a Skiff program on top of the definitions from previously run programs.
+/// Returns the values of the program's expressions along with the extended state. Raises an
+/// error if the program exceeds any of the limits.
+pub fn run_incremental(
+    program: &Program,
+    state: &VmState,
+    limits: &EvalLimits,
+) -> Result<(Vec<Val>, VmState), InterpError> {
+    let compiled = compile_program(program, &state.compiler_state)?;
+
+    let mut state = state.clone();
+    state.functions.resize(compiled.function_slots.len(), None);
+    for (slot, function) in compiled.functions {
+        state.functions[slot] = Some(with_cast(Rc::new(Closure {
+            function,
+            upvalues: vec![],
+        })));
+    }
+    for expr in program {
+        if let AstNode::DataDeclarationNode(name, type_params, variants) = &expr.node {
+            state
+                .data_types
+                .insert(name.clone(), DataType::new(type_params, variants));
+        }
+    }
+    state.compiler_state = CompilerState {
+        global_slots: compiled.global_slots,
+        num_globals: state.compiler_state.num_globals,
+        function_slots: compiled.function_slots,
+    };
+
+    let mut vals = vec![];
+    let budget = Budget::new(limits);
+    for item in compiled.items {
+        match item {
+            TopLevelItem::Expression(function) => {
+                vals.push(Vm::new(&state, &budget).run(function)?.to_val());
+            }
+            TopLevelItem::Let(slot, function) => {
+                // Slots are assigned in order, so the binding always goes in the next slot
+                let value = Vm::new(&state, &budget).run(function)?;
+                debug_assert_eq!(slot, state.globals.len());
+                state.globals.push(value);
+                state.compiler_state.num_globals = slot + 1;
+            }
+            TopLevelItem::LetPattern(slots, function) => {
+                // The binding makes a tuple of the values of the pattern's variables
+                let values = match Vm::new(&state, &budget).run(function)? {
+                    Value::Tuple(values) => values,
+                    _ => unreachable!("A let pattern's binding always makes a tuple"),
+                };
+                for (slot, value) in slots.into_iter().zip(values.iter()) {
+                    debug_assert_eq!(slot, state.globals.len());
+                    state.globals.push(value.clone());
+                    state.compiler_state.num_globals = slot + 1;
+                }
+            }
+        }
+    }
+
+    Ok((vals, state))
+}
+
+/// Calls a function from the state of previously run programs (a `def`, a native function or
+/// a top-level binding to a function) with arguments that have already been evaluated. Raises
+/// an error if the call exceeds any of the limits.
+pub fn call_incremental(
+    name: &str,
+    args: Vec<Val>,
+    state: &VmState,
+    limits: &EvalLimits,
+) -> Result<Val, InterpError> {
+    let error_of_kind = |kind: InterpErrorKind, message: String| {
+        InterpError(message, 0..0, Env::new(), StackFrame::new_stack(), kind)
+    };
+    let error = |message: String| error_of_kind(InterpErrorKind::Invalid, message);
+    let compiler_state = &state.compiler_state;
+    let function = compiler_state
+        .global_slots
+        .get(name)
+        .map(|slot| state.globals[*slot].clone())
+        .or_else(|| {
+            let slot = compiler_state.function_slots.get(name)?;
+            state.functions[*slot].clone()
+        })
+        .ok_or_else(|| error(format!("Couldn't find var in environment: {}", name)))?;
+
+    let arity = match innermost_function(&function) {
+        Value::Closure(closure) => closure.function.params.len(),
+        Value::Native(native) => native.arity(),
+        _ => return Err(error("Function call with non-function value".to_string())),
+    };
+    if arity != args.len() {
+        return Err(error(format!(
+            "Function takes {} arguments but {} were provided",
+            arity,
+            args.len()
+        )));
+    }
+    // Values can only be rebuilt from `Val`s if they don't contain Skiff functions
+    let mut args = args
+        .into_iter()
+        .map(|arg| Value::from_val(arg, &[]))
+        .collect::<Option<Vec<Value>>>()
+        .ok_or_else(|| error("Functions can't be passed to the bytecode VM".to_string()))?;
+    let mut results = vec![];
+    let function = cast_arguments(
+        function,
+        &mut args,
+        &state.data_types,
+        &(0..0),
+        &mut results,
+    )
+    .map_err(|message| error_of_kind(InterpErrorKind::CastFailed, message))?;
+
+    let value = match function {
+        Value::Closure(closure) => {
+            let budget = Budget::new(limits);
+            let mut vm = Vm::new(state, &budget);
+            vm.stack.push(Value::Closure(closure.clone()));
+            let arg_count = args.len();
+            vm.stack.extend(args);
+            let frame = vm.new_frame(closure, arg_count, 0..0);
+            vm.execute(frame)?
+        }
+        Value::Native(native) => {
+            let vals: Vec<Val> = args.iter().map(|arg| arg.to_val()).collect();
+            let result = native
+                .call(&vals)
+                .map_err(|message| error_of_kind(InterpErrorKind::NativeFailed, message))?;
+            Value::from_val(result, &args).ok_or_else(|| {
+                error(format!(
+                    "{} returned a function it wasn't given",
+                    native.name
+                ))
+            })?
+        }
+        _ => return Err(error("Function call with non-function value".to_string())),
+    };
+    cast_results(value, &results, &state.data_types)
+        .map(|value| value.to_val())
+        .map_err(|(message, span)| {
+            let kind = InterpErrorKind::CastFailed;
+            InterpError(message, span, Env::new(), StackFrame::new_stack(), kind)
+        })
+}
+
+/// Wraps a top-level function or a lambda in the cast its annotations give it, if any
+fn with_cast(closure: Rc<Closure>) -> Value {
+    match closure.function.cast.clone() {
+        Some(function_cast) => Value::Cast(Rc::new(Value::Closure(closure)), function_cast),
+        None => Value::Closure(closure),
+    }
+}
+
+/// The function underneath any casts around a value
+fn innermost_function(function: &Value) -> &Value {
+    match function {
+        Value::Cast(inner, _) => innermost_function(inner),
+        _ => function,
+    }
+}
+
+/// Checks the arguments of a call against the parameter types of the casts around a
+/// function, from the outermost cast in, like the tree-walking interpreter does. Casts whose
+/// results have to be checked are added to `results` unless an identical cast is already
+/// there. Returns the function underneath the casts, or the message for an argument of the
+/// wrong type.
+fn cast_arguments(
+    function: Value,
+    args: &mut [Value],
+    data_types: &DataTypes,
+    call_span: &Range<usize>,
+    results: &mut Vec<Rc<FunctionCast>>,
+) -> Result<Value, String> {
+    let src_loc = SrcLoc {
+        span: call_span.clone(),
+    };
+    let mut function = function;
+    while let Value::Cast(inner, function_cast) = function {
+        for (i, (arg, t)) in args.iter_mut().zip(&function_cast.params).enumerate() {
+            let t = match t {
+                Some(t) => t,
+                None => continue,
+            };
+            let name = || casts::argument_name(&function_cast, i);
+            match casts::cast(arg.clone(), t, data_types, &name, &src_loc) {
+                Some(checked) => *arg = checked,
+                None => return Err(casts::argument_error(&function_cast, i, t, &arg.to_val())),
+            }
+        }
+        if function_cast.return_type.is_some() && !results.contains(&function_cast) {
+            results.push(function_cast);
+        }
+        function = (*inner).clone();
+    }
+    Ok(function)
+}
+
+/// Checks the result of a call against the return types of casts, from the innermost cast
+/// (the last one) out. Returns the message for a result of the wrong type along with the span
+/// of where its cast came from.
+fn cast_results(
+    value: Value,
+    results: &[Rc<FunctionCast>],
+    data_types: &DataTypes,
+) -> Result<Value, (String, Range<usize>)> {
+    let mut value = value;
+    for function_cast in results.iter().rev() {
+        let t = match &function_cast.return_type {
+            Some(t) => t,
+            None => continue,
+        };
+        let name = || casts::result_name(function_cast);
+        value = match casts::cast(value.clone(), t, data_types, &name, &function_cast.src_loc) {
+            Some(checked) => checked,
+            None => {
+                let message = casts::result_error(function_cast, t, &value.to_val());
+                return Err((message, function_cast.src_loc.span.clone()));
+            }
+        };
+    }
+    Ok(value)
+}
+
+/// A function call that is in progress
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// The index in the value stack of the frame's first slot
+    base: usize,
+    /// The span of the call expression that created the frame
+    call_span: Range<usize>,
+    /// The number of frames this one replaced through tail calls
+    tail_calls: usize,
+    /// The casts whose return types the frame's result is checked against when it returns
+    results: Vec<Rc<FunctionCast>>,
+}
+
+struct Vm<'a> {
+    state: &'a VmState,
+    budget: &'a Budget,
+    stack: Vec<Value>,
+    /// Every frame except the one that is currently running
+    frames: Vec<CallFrame>,
+}
+
+impl<'a> Vm<'a> {
+    fn new(state: &'a VmState, budget: &'a Budget) -> Self {
+        Vm {
+            state,
+            budget,
+            stack: vec![],
+            frames: vec![],
+        }
+    }
+
+    /// Runs a top-level expression to produce either a value or an error
+    fn run(&mut self, function: Rc<Function>) -> Result<Value, InterpError> {
+        let closure = Rc::new(Closure {
+            function,
+            upvalues: vec![],
+        });
+        self.stack.push(Value::Closure(closure.clone()));
+        let frame = self.new_frame(closure, 0, 0..0);
+        self.execute(frame)
+    }
+
+    /// Makes a frame for a closure whose arguments are on top of the stack, reserving the
+    /// rest of the function's slots
+    fn new_frame(
+        &mut self,
+        closure: Rc<Closure>,
+        arg_count: usize,
+        call_span: Range<usize>,
+    ) -> CallFrame {
+        let base = self.stack.len() - arg_count;
+        let num_slots = closure.function.num_slots;
+        if num_slots > arg_count {
+            self.stack.resize(base + num_slots, Value::Bool(false));
+        }
+        CallFrame {
+            closure,
+            ip: 0,
+            base,
+            call_span,
+            tail_calls: 0,
+            results: vec![],
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Value stack underflow")
+    }
+
+    fn execute(&mut self, mut frame: CallFrame) -> Result<Value, InterpError> {
+        loop {
+            let instruction = frame.closure.function.code[frame.ip];
+            frame.ip += 1;
+            if let Err(message) = self.budget.step() {
+                return Err(self.error_of_kind(&frame, InterpErrorKind::LimitExceeded, message));
+            }
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    let value = frame.closure.function.constants[index].clone();
+                    self.stack.push(value);
+                }
+                Instruction::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot].clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetLocal(slot) => {
+                    let value = self.pop();
+                    self.stack[frame.base + slot] = value;
+                }
+                Instruction::GetUpvalue(index) => {
+                    let value = frame.closure.upvalues[index].clone();
+                    self.stack.push(value);
+                }
+                Instruction::GetGlobal(slot) => self.stack.push(self.state.globals[slot].clone()),
+                Instruction::GetFunction(slot) => match &self.state.functions[slot] {
+                    Some(function) => self.stack.push(function.clone()),
+                    None => return Err(self.error(&frame, "Function slot is empty".to_string())),
+                },
+                Instruction::GetFunctionByName(index) => {
+                    let name = &frame.closure.function.names[index];
+                    let function = self
+                        .state
+                        .compiler_state
+                        .function_slots
+                        .get(name)
+                        .and_then(|slot| self.state.functions[*slot].clone());
+                    match function {
+                        Some(function) => self.stack.push(function),
+                        None => {
+                            let message = format!("Couldn't find var in environment: {}", name);
+                            return Err(self.error(&frame, message));
+                        }
+                    }
+                }
+                Instruction::BinOp(op) => {
+                    let v2 = self.pop();
+                    let v1 = self.pop();
+                    if let (BinOp::Plus, Value::Str(s1), Value::Str(s2)) = (op, &v1, &v2) {
+                        if let Err(message) = self.budget.allocate(s1.len() + s2.len()) {
+                            return Err(self.error_of_kind(
+                                &frame,
+                                InterpErrorKind::LimitExceeded,
+                                message,
+                            ));
+                        }
+                    }
+                    match binop(op, v1, v2) {
+                        Ok(value) => {
+                            if let Value::BigNum(n) = &value {
+                                if let Err(message) = self.budget.allocate(n.size_in_bytes()) {
+                                    return Err(self.error_of_kind(
+                                        &frame,
+                                        InterpErrorKind::LimitExceeded,
+                                        message,
+                                    ));
+                                }
+                            }
+                            self.stack.push(value)
+                        }
+                        Err((kind, message)) => {
+                            return Err(self.error_of_kind(&frame, kind, message))
+                        }
+                    }
+                }
+                Instruction::Jump(target) => frame.ip = target,
+                Instruction::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => (),
+                    Value::Bool(false) => frame.ip = target,
+                    _ => {
+                        return Err(self.error(
+                            &frame,
+                            "Conditional expression with non-boolean condition".to_string(),
+                        ))
+                    }
+                },
+                Instruction::Closure(index) => {
+                    let function = frame.closure.function.functions[index].clone();
+                    let upvalues = function
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.stack[frame.base + slot].clone(),
+                            Capture::Upvalue(index) => frame.closure.upvalues[*index].clone(),
+                        })
+                        .collect();
+                    if let Err(message) = self.budget.allocate_value(0) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    self.stack
+                        .push(with_cast(Rc::new(Closure { function, upvalues })));
+                }
+                Instruction::CheckCall(arg_count) => {
+                    match self.stack.last().map(innermost_function) {
+                        Some(Value::Closure(closure)) => {
+                            let param_count = closure.function.params.len();
+                            if param_count != arg_count {
+                                let message = format!(
+                                    "Function takes {} arguments but {} were provided",
+                                    param_count, arg_count
+                                );
+                                return Err(self.error(&frame, message));
+                            }
+                        }
+                        Some(Value::Native(native)) => {
+                            if native.arity() != arg_count {
+                                let message = format!(
+                                    "Function takes {} arguments but {} were provided",
+                                    native.arity(),
+                                    arg_count
+                                );
+                                return Err(self.error(&frame, message));
+                            }
+                        }
+                        _ => {
+                            return Err(self.error(
+                                &frame,
+                                "Function call with non-function value".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Instruction::Call(arg_count) => {
+                    let mut results = vec![];
+                    self.cast_arguments(&frame, arg_count, &mut results)?;
+                    let closure = match &self.stack[self.stack.len() - arg_count - 1] {
+                        Value::Closure(closure) => closure.clone(),
+                        Value::Native(native) => {
+                            let native = native.clone();
+                            self.call_native(&frame, &native, arg_count)?;
+                            let result = self.pop();
+                            let result = self.cast_results(&frame, result, &results)?;
+                            self.stack.push(result);
+                            continue;
+                        }
+                        _ => {
+                            return Err(self.error(
+                                &frame,
+                                "Function call with non-function value".to_string(),
+                            ))
+                        }
+                    };
+                    let call_span = frame.closure.function.spans[frame.ip - 1].clone();
+                    let mut new_frame = self.new_frame(closure, arg_count, call_span);
+                    new_frame.results = results;
+                    // The bottom frame is the top-level expression, which isn't a call
+                    if let Err(message) = self.budget.check_call_depth(self.frames.len() + 1) {
+                        // Report the error from the caller, with the new call on the stack
+                        let mut error =
+                            self.error_of_kind(&frame, InterpErrorKind::LimitExceeded, message);
+                        error.3.push_back(self.stack_frame(&new_frame));
+                        return Err(error);
+                    }
+                    self.frames.push(std::mem::replace(&mut frame, new_frame));
+                }
+                Instruction::TailCall(arg_count) => {
+                    // The new frame returns the current frame's result, so the result is
+                    // checked against the casts of both
+                    let mut results = std::mem::take(&mut frame.results);
+                    self.cast_arguments(&frame, arg_count, &mut results)?;
+                    let closure = match &self.stack[self.stack.len() - arg_count - 1] {
+                        Value::Closure(closure) => closure.clone(),
+                        // Native functions don't have a frame, so the current one is kept and
+                        // returns the result
+                        Value::Native(native) => {
+                            let native = native.clone();
+                            self.call_native(&frame, &native, arg_count)?;
+                            frame.results = results;
+                            continue;
+                        }
+                        _ => {
+                            return Err(self.error(
+                                &frame,
+                                "Function call with non-function value".to_string(),
+                            ))
+                        }
+                    };
+                    // Move the function and its arguments down over the current frame
+                    let start = self.stack.len() - arg_count - 1;
+                    self.stack.drain(frame.base - 1..start);
+                    let call_span = frame.closure.function.spans[frame.ip - 1].clone();
+                    let tail_calls = frame.tail_calls + 1;
+                    frame = self.new_frame(closure, arg_count, call_span);
+                    frame.tail_calls = tail_calls;
+                    frame.results = results;
+                }
+                Instruction::Return => {
+                    let result = self.pop();
+                    // Remove the frame's slots along with the function that was called
+                    self.stack.truncate(frame.base - 1);
+                    let results = std::mem::take(&mut frame.results);
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            let result = self.cast_results(&frame, result, &results)?;
+                            self.stack.push(result);
+                        }
+                        None => return Ok(result),
+                    }
+                }
+                Instruction::MakeData(index, field_count) => {
+                    if let Err(message) = self.budget.allocate_value(field_count) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    let fields = self.stack.split_off(self.stack.len() - field_count);
+                    let discriminant = frame.closure.function.discriminants[index].clone();
+                    self.stack
+                        .push(Value::Data(Rc::new((discriminant, fields))));
+                }
+                Instruction::MakeList(item_count, has_rest) => {
+                    let rest = match has_rest {
+                        true => match self.pop() {
+                            Value::List(rest) => rest,
+                            value => {
+                                return Err(self.error(
+                                    &frame,
+                                    format!(
+                                        "Can only put items in front of a list, not {}",
+                                        value.to_val()
+                                    ),
+                                ))
+                            }
+                        },
+                        false => Vector::new(),
+                    };
+                    // The items of the rest are shared rather than copied
+                    if let Err(message) = self.budget.allocate_value(item_count) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    let mut items: Vector<Value> = self
+                        .stack
+                        .split_off(self.stack.len() - item_count)
+                        .into_iter()
+                        .collect();
+                    items.append(rest);
+                    self.stack.push(Value::List(items));
+                }
+                Instruction::MakeTuple(item_count) => {
+                    if let Err(message) = self.budget.allocate_value(item_count) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    let items = self.stack.split_off(self.stack.len() - item_count);
+                    self.stack.push(Value::Tuple(Rc::new(items)));
+                }
+                Instruction::MakeRecord(index, field_count) => {
+                    if let Err(message) = self.budget.allocate_value(field_count) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    let values = self.stack.split_off(self.stack.len() - field_count);
+                    let names = &frame.closure.function.names[index..index + field_count];
+                    let fields: OrdMap<String, Value> = names.iter().cloned().zip(values).collect();
+                    self.stack.push(Value::Record(fields));
+                }
+                Instruction::UpdateRecord(index, field_count) => {
+                    let values = self.stack.split_off(self.stack.len() - field_count);
+                    let record = self.pop();
+                    let names = &frame.closure.function.names[index..index + field_count];
+                    let record = update_fields(record, names, values)
+                        .map_err(|message| self.error(&frame, message))?;
+                    if let Err(message) = self.budget.allocate_value(field_count) {
+                        return Err(self.error_of_kind(
+                            &frame,
+                            InterpErrorKind::LimitExceeded,
+                            message,
+                        ));
+                    }
+                    self.stack.push(record);
+                }
+                Instruction::GetField(index) => {
+                    let value = self.pop();
+                    let field = &frame.closure.function.names[index];
+                    let field_value =
+                        get_field(&value, field).map_err(|message| self.error(&frame, message))?;
+                    self.stack.push(field_value);
+                }
+                Instruction::Match(slot, pattern_index, target) => {
+                    let value = self.stack[frame.base + slot].clone();
+                    let pattern = &frame.closure.function.patterns[pattern_index];
+                    if !match_pattern(pattern, &value, &mut self.stack, frame.base) {
+                        frame.ip = target;
+                    }
+                }
+                Instruction::MatchFailed => {
+                    return Err(self.error_of_kind(
+                        &frame,
+                        InterpErrorKind::NoMatch,
+                        "No branch of match expression matched value".to_string(),
+                    ))
+                }
+                Instruction::LetMatchFailed(slot) => {
+                    let value = self.stack[frame.base + slot].to_val();
+                    return Err(self.error_of_kind(
+                        &frame,
+                        InterpErrorKind::NoMatch,
+                        format!("Pattern of let statement didn't match value {}", value),
+                    ));
+                }
+                Instruction::CheckBinding(index) => {
+                    let value = self.pop();
+                    let id = &frame.closure.function.bindings[index];
+                    let t = id
+                        .type_decl
+                        .as_ref()
+                        .expect("Only annotated bindings are checked");
+                    let name = || format!("`{}`", id.id);
+                    match casts::cast(value.clone(), t, &self.state.data_types, &name, &id.src_loc)
+                    {
+                        Some(checked) => self.stack.push(checked),
+                        None => {
+                            let message = casts::binding_error(id, t, &value.to_val());
+                            return Err(self.error_of_kind(
+                                &frame,
+                                InterpErrorKind::CastFailed,
+                                message,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks the arguments on top of the stack against the casts around the function below
+    /// them (see `cast_arguments`), replacing the function with the one underneath the casts
+    fn cast_arguments(
+        &mut self,
+        frame: &CallFrame,
+        arg_count: usize,
+        results: &mut Vec<Rc<FunctionCast>>,
+    ) -> Result<(), InterpError> {
+        let function_index = self.stack.len() - arg_count - 1;
+        if !matches!(self.stack[function_index], Value::Cast(_, _)) {
+            return Ok(());
+        }
+        let function = self.stack[function_index].clone();
+        let call_span = &frame.closure.function.spans[frame.ip - 1];
+        let args = &mut self.stack[function_index + 1..];
+        match cast_arguments(function, args, &self.state.data_types, call_span, results) {
+            Ok(function) => {
+                self.stack[function_index] = function;
+                Ok(())
+            }
+            Err(message) => Err(self.error_of_kind(frame, InterpErrorKind::CastFailed, message)),
+        }
+    }
+
+    /// Checks the result of a call against the return types of casts (see `cast_results`).
+    /// A result of the wrong type is reported from the caller's frame, blaming where the cast
+    /// came from.
+    fn cast_results(
+        &self,
+        caller: &CallFrame,
+        result: Value,
+        results: &[Rc<FunctionCast>],
+    ) -> Result<Value, InterpError> {
+        if results.is_empty() {
+            return Ok(result);
+        }
+        cast_results(result, results, &self.state.data_types).map_err(|(message, span)| {
+            let mut error = self.error_of_kind(caller, InterpErrorKind::CastFailed, message);
+            error.1 = span;
+            error
+        })
+    }
+
+    /// Calls a native function with the arguments on top of the stack, replacing them and the
+    /// function with the result
+    fn call_native(
+        &mut self,
+        frame: &CallFrame,
+        native: &NativeFunction,
+        arg_count: usize,
+    ) -> Result<(), InterpError> {
+        let args = self.stack.split_off(self.stack.len() - arg_count);
+        self.pop();
+        let vals: Vec<Val> = args.iter().map(|arg| arg.to_val()).collect();
+        let result = native
+            .call(&vals)
+            .map_err(|message| self.error_of_kind(frame, InterpErrorKind::NativeFailed, message))?;
+        match Value::from_val(result, &args) {
+            Some(value) => {
+                self.stack.push(value);
+                Ok(())
+            }
+            None => Err(self.error(
+                frame,
+                format!("{} returned a function it wasn't given", native.name),
+            )),
+        }
+    }
+
+    /// Creates an error for the instruction that the frame just executed, including the
+    /// environment and call stack at that point
+    fn error(&self, frame: &CallFrame, message: String) -> InterpError {
+        self.error_of_kind(frame, InterpErrorKind::Invalid, message)
+    }
+
+    fn error_of_kind(
+        &self,
+        frame: &CallFrame,
+        kind: InterpErrorKind,
+        message: String,
+    ) -> InterpError {
+        let span = frame.closure.function.spans[frame.ip - 1].clone();
+        let env = self.frame_env(frame, frame.ip - 1);
+
+        let mut stack = StackFrame::new_stack();
+        // The bottom frame is the top-level expression, which isn't a call
+        for call in self.frames.iter().chain(std::iter::once(frame)).skip(1) {
+            stack.push_back(self.stack_frame(call));
+        }
+
+        InterpError(message, span, env, stack, kind)
+    }
+
+    /// Makes the stack trace entry for a call, recording the arguments it was called with
+    fn stack_frame(&self, call: &CallFrame) -> StackFrame {
+        let args: Env = call
+            .closure
+            .function
+            .params
+            .iter()
+            .enumerate()
+            .map(|(slot, param)| (param.clone(), self.stack[call.base + slot].to_val()))
+            .collect();
+        let mut stack_frame = StackFrame::new(
+            SrcLoc {
+                span: call.call_span.clone(),
+            },
+            args,
+        );
+        stack_frame.tail_calls = call.tail_calls;
+        stack_frame
+    }
+
+    /// Rebuilds the environment of a frame at an instruction from the names of its slots
+    fn frame_env(&self, frame: &CallFrame, ip: usize) -> Env {
+        let function = &frame.closure.function;
+        let mut env = HashMap::new();
+        if let Some(globals) = &function.globals {
+            for (name, slot) in globals {
+                env.insert(name.clone(), self.state.globals[*slot].to_val());
+            }
+        }
+        env.extend(frame.closure.env());
+        for local in &function.locals {
+            if local.start <= ip && ip < local.end {
+                env.insert(
+                    local.name.clone(),
+                    self.stack[frame.base + local.slot].to_val(),
+                );
+            }
+        }
+        env
+    }
+}
+
+/// Replaces some of the fields of a record or data value, returning an error message if it
+/// doesn't have one of them
+fn update_fields(mut record: Value, names: &[String], values: Vec<Value>) -> Result<Value, String> {
+    for (field, value) in names.iter().zip(values) {
+        let updated = match &mut record {
+            Value::Record(fields) if fields.contains_key(field) => {
+                fields.insert(field.clone(), value);
+                true
+            }
+            Value::Data(data) => match data.0.field_index(field) {
+                Some(i) => {
+                    Rc::make_mut(data).1[i] = value;
+                    true
+                }
+                None => false,
+            },
+            Value::Record(_) => false,
+            _ => {
+                return Err(format!(
+                    "Can only update the fields of a record or data value, not {}",
+                    record.to_val()
+                ))
+            }
+        };
+        if !updated {
+            return Err(format!("{} has no field {}", record.to_val(), field));
+        }
+    }
+    Ok(record)
+}
+
+/// Gets a field of a record or data value, returning an error message if it doesn't have it
+fn get_field(value: &Value, field: &str) -> Result<Value, String> {
+    let field_value = match value {
+        Value::Record(fields) => fields.get(field).cloned(),
+        Value::Data(data) => data.0.field_index(field).map(|i| data.1[i].clone()),
+        _ => {
+            return Err(format!(
+                "Can only get the fields of a record or data value, not {}",
+                value.to_val()
+            ))
+        }
+    };
+    field_value.ok_or_else(|| format!("{} has no field {}", value.to_val(), field))
+}
+
+/// Attempts to match a pattern against a value, storing the values of any identifiers in
+/// their slots. Returns whether the pattern matched.
+fn match_pattern(
+    pattern: &PatternCode,
+    value: &Value,
+    stack: &mut Vec<Value>,
+    base: usize,
+) -> bool {
+    match pattern {
+        PatternCode::NumLiteral(n) => *value == Value::Num(*n),
+        PatternCode::BoolLiteral(b) => *value == Value::Bool(*b),
+        PatternCode::StringLiteral(s) => matches!(value, Value::Str(v) if **v == **s),
+        PatternCode::Bind(slot) => {
+            stack[base + slot] = value.clone();
+            true
+        }
+        PatternCode::Ignore => true,
+        PatternCode::Or(alternatives) => alternatives
+            .iter()
+            .any(|alternative| match_pattern(alternative, value, stack, base)),
+        PatternCode::As(pattern, slot) => {
+            let matches = match_pattern(pattern, value, stack, base);
+            if matches {
+                stack[base + slot] = value.clone();
+            }
+            matches
+        }
+        PatternCode::Data(variant, patterns) => match value {
+            // The pattern matches if the variants match and the pattern has the right
+            // number of fields
+            Value::Data(data) => {
+                let (discriminant, values) = &**data;
+                discriminant.get_variant() == variant
+                    && patterns.len() == values.len()
+                    && patterns
+                        .iter()
+                        .zip(values)
+                        .all(|(pattern, value)| match_pattern(pattern, value, stack, base))
+            }
+            _ => false,
+        },
+        PatternCode::List(patterns, rest) => match value {
+            // Without a rest pattern, the list must have exactly as many items as the pattern
+            Value::List(values) => {
+                let fits = match rest {
+                    Some(_) => values.len() >= patterns.len(),
+                    None => values.len() == patterns.len(),
+                };
+                fits && patterns
+                    .iter()
+                    .zip(values)
+                    .all(|(pattern, value)| match_pattern(pattern, value, stack, base))
+                    && match rest {
+                        Some(rest) => {
+                            let rest_value = Value::List(values.skip(patterns.len()));
+                            match_pattern(rest, &rest_value, stack, base)
+                        }
+                        None => true,
+                    }
+            }
+            _ => false,
+        },
+        PatternCode::Tuple(patterns) => match value {
+            Value::Tuple(values) => {
+                patterns.len() == values.len()
+                    && patterns
+                        .iter()
+                        .zip(values.iter())
+                        .all(|(pattern, value)| match_pattern(pattern, value, stack, base))
+            }
+            _ => false,
+        },
+        PatternCode::Record(patterns) => match value {
+            // Fields that aren't in the pattern can have any value
+            Value::Record(values) => {
+                patterns
+                    .iter()
+                    .all(|(field, pattern)| match values.get(field) {
+                        Some(value) => match_pattern(pattern, value, stack, base),
+                        None => false,
+                    })
+            }
+            _ => false,
+        },
+    }
+}
+
+macro_rules! vm_binop {
+    ($value1:ident, $value2:ident, $op:tt, $type1:ident, $type2:ident, $output_type:ident) => {
+        match ($value1, $value2) {
+            (Value::$type1(xv), Value::$type2(yv)) => Ok(Value::$output_type(xv $op yv)),
+            (Value::$type1(_), e) => Err(invalid(format!("Bad second op to {}: {}", stringify!($op), e.to_val()))),
+            (e, Value::$type2(_)) => Err(invalid(format!("Bad first op to {}: {}", stringify!($op), e.to_val()))),
+            (e1, e2) => Err(invalid(format!(
+                "Bad ops to {}: {}\n{}",
+                stringify!($op),
+                e1.to_val(),
+                e2.to_val()
+            ))),
+        }
+    };
+}
+
+/// Applies a binary operator to two values, returning the kind of error and its message if
+/// the operands have the wrong types or the operator fails
+fn binop(op: BinOp, v1: Value, v2: Value) -> Result<Value, (InterpErrorKind, String)> {
+    // Strings can be concatenated and compared, so handle them before the numeric cases
+    if let (Value::Str(s1), Value::Str(s2)) = (&v1, &v2) {
+        match op {
+            BinOp::Plus => return Ok(Value::Str(format!("{}{}", s1, s2).into())),
+            BinOp::Gt => return Ok(Value::Bool(s1 > s2)),
+            BinOp::Lt => return Ok(Value::Bool(s1 < s2)),
+            BinOp::GtEq => return Ok(Value::Bool(s1 >= s2)),
+            BinOp::LtEq => return Ok(Value::Bool(s1 <= s2)),
+            _ => (),
+        }
+    }
+
+    match op {
+        BinOp::Eq => Ok(Value::Bool(v1 == v2)),
+        BinOp::LAnd => vm_binop!(v1, v2, &&, Bool, Bool, Bool),
+        BinOp::LOr => vm_binop!(v1, v2, ||, Bool, Bool, Bool),
+        _ => match (v1.to_number(), v2.to_number()) {
+            (Some(x), Some(y)) => match op {
+                BinOp::Gt | BinOp::Lt | BinOp::GtEq | BinOp::LtEq => {
+                    Ok(Value::Bool(numeric::comparison(op, &x, &y)))
+                }
+                _ => numeric::arithmetic(op, &x, &y).map(Value::from),
+            },
+            (Some(_), None) => Err(invalid(format!("Bad second op to {}: {}", op, v2.to_val()))),
+            (None, Some(_)) => Err(invalid(format!("Bad first op to {}: {}", op, v1.to_val()))),
+            (None, None) => Err(invalid(format!(
+                "Bad ops to {}: {}\n{}",
+                op,
+                v1.to_val(),
+                v2.to_val()
+            ))),
+        },
+    }
+}
+
+fn invalid(message: String) -> (InterpErrorKind, String) {
+    (InterpErrorKind::Invalid, message)
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::*;
+    use crate::interpreter::interpret::{interpret, interpret_incremental, InterpreterState};
+    use crate::lexer::lex::Token;
+    use crate::parser::parse::parse_program;
+    use logos::Logos;
+
+    fn parse(source: &str) -> Program {
+        let mut tokens: Vec<_> = Token::lexer(source).spanned().collect();
+        tokens.reverse();
+        parse_program(&mut tokens).unwrap()
+    }
+
+    #[test]
+    fn matches_tree_walker_values() {
+        let program = parse(
+            "let x = 1
+            let f = lambda(): x end
+            let x = 2
+            f()
+            x
+            let adder = lambda(a): lambda(b): a + b end end
+            adder(3)(4)
+            data List: | link(f, r) | empty() end
+            match link(1, empty()):
+                | link(x, empty()) => x + 100
+                | empty() => 0
+            end
+            def fact(n): if n == 0: 1 else: n * fact(n - 1) end end
+            fact(10)",
+        );
+        assert_eq!(run(&program), interpret(&program));
+    }
+
+    #[test]
+    fn matches_tree_walker_errors() {
+        let program = parse(
+            "def f(x): g(x, 1) end
+            def g(a, b):
+                let c = a + b
+                c + true
+            end
+            f(1)",
+        );
+        let error = run(&program).unwrap_err();
+        assert_eq!(error.0, "Bad second op to +: true");
+        // The call to g is in tail position, so it replaces f's frame
+        assert_eq!(error.3.len(), 2);
+        assert_eq!(error.3.back().unwrap().tail_calls, 1);
+        assert_eq!(Err(error), interpret(&program));
+    }
+
+    #[test]
+    fn reports_division_by_zero_with_a_stack_trace() {
+        let program = parse(
+            "def average(total, count): total / count end
+            def report(total): average(total, 0) + 1 end
+            report(10)",
+        );
+        let error = run(&program).unwrap_err();
+        assert_eq!(error.0, "Division by zero");
+        assert_eq!(error.3.len(), 3);
+        assert_eq!(Err(error), interpret(&program));
+    }
+
+    #[test]
+    fn matches_tree_walker_limit_errors() {
+        let program = parse(
+            "data List: | link(f, r) | empty() end
+            def build(n): if n == 0: empty() else: link(n, build(n - 1)) end end
+            build(100)",
+        );
+        let limits = EvalLimits {
+            max_call_depth: Some(20),
+            ..EvalLimits::default()
+        };
+        let error = run_incremental(&program, &VmState::new(), &limits).unwrap_err();
+        assert_eq!(error.0, "Maximum call depth of 20 exceeded");
+        let tree_walker_error =
+            interpret_incremental(&program, &InterpreterState::new(), &limits).unwrap_err();
+        assert_eq!(error, tree_walker_error);
+
+        let limits = EvalLimits {
+            max_memory: Some(1000),
+            ..EvalLimits::default()
+        };
+        let error = run_incremental(&program, &VmState::new(), &limits).unwrap_err();
+        assert_eq!(error.0, "Memory limit of 1000 bytes exceeded");
+        let tree_walker_error =
+            interpret_incremental(&program, &InterpreterState::new(), &limits).unwrap_err();
+        assert_eq!(error, tree_walker_error);
+    }
+
+    #[test]
+    fn matches_tree_walker_cast_errors() {
+        let definitions = "def id(x): x end
+            def inc(n: Number) -> Number: n + 1 end
+            def bad(x) -> String: id(x) end
+            def apply(f: (Number) -> Number, n: Number): f(n) end
+            data Box: | box(v) end
+            def unbox(b: Box): match b: | box(v) => v end end
+            ";
+        let cases = [
+            "inc(id(true))",
+            "bad(1)",
+            "let s: String = id(1)",
+            "def f(x): let n: Number = x\n n end f(\"n\")",
+            "unbox(id([1]))",
+            "apply(id(lambda(n): n == 1 end), 1)",
+            "apply(lambda(n: String): n end, 1)",
+            "let g: (Number) -> Number = id(lambda(n): true end)\ng(1)",
+        ];
+        for case in cases.iter() {
+            let program = parse(&(definitions.to_string() + case));
+            let error = run(&program).unwrap_err();
+            let tree_walker_error = interpret(&program).unwrap_err();
+            assert_eq!(
+                (&error.0, &error.1),
+                (&tree_walker_error.0, &tree_walker_error.1),
+                "{}",
+                case
+            );
+        }
+        let program = parse(&(definitions.to_string() + "inc(1)\nunbox(box(2))"));
+        assert_eq!(run(&program), Ok(vec![Val::Num(2), Val::Num(2)]));
+    }
+
+    #[test]
+    fn doesnt_overflow_the_native_stack() {
+        let program = parse(
+            "def count(n): if n == 0: 0 else: 1 + count(n - 1) end end
+            count(100000)",
+        );
+        assert_eq!(run(&program), Ok(vec![Val::Num(100000)]));
+    }
+
+    #[test]
+    fn reuses_frames_for_tail_calls() {
+        let program = parse(
+            "def count(n, acc): if n == 0: acc + true else: count(n - 1, acc + 1) end end
+            count(1000, 0)",
+        );
+        let error = run(&program).unwrap_err();
+        assert_eq!(error.3.len(), 2);
+        assert_eq!(error.3.back().unwrap().tail_calls, 1000);
+        assert_eq!(Err(error), interpret(&program));
+    }
+}
@@ -0,0 +1,364 @@
+use crate::ast::{NativeFunction, Type, Val};
+use crate::interpreter::limits::EvalLimits;
+use crate::prelude;
+use crate::runtime::{CliArgs, Session, SkiffError};
+use std::rc::Rc;
+use std::{error, fmt};
+
+/// An error from evaluating or calling Skiff code through an `Engine`
+#[derive(PartialEq, Debug, Clone, Hash)]
+pub enum EngineError {
+    /// Lexing, parsing, type checking or running the code failed
+    Skiff(SkiffError),
+    /// A result couldn't be converted to the requested Rust type. (expected type, result)
+    Conversion(&'static str, Val),
+}
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Skiff(e) => write!(f, "{}", e),
+            EngineError::Conversion(expected, val) => {
+                write!(f, "Expected {} but the result was {}", expected, val)
+            }
+        }
+    }
+}
+impl error::Error for EngineError {}
+
+/// Rust types that the results of Skiff code can be converted into
+pub trait FromVal: Sized {
+    fn from_val(val: Val) -> Result<Self, EngineError>;
+}
+impl FromVal for Val {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        Ok(val)
+    }
+}
+impl FromVal for i64 {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::Num(n) => Ok(n),
+            val => Err(EngineError::Conversion("a number", val)),
+        }
+    }
+}
+impl FromVal for f64 {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::Num(n) => Ok(n as f64),
+            Val::BigNum(n) => Ok(n.to_f64()),
+            Val::Float(x) => Ok(x),
+            val => Err(EngineError::Conversion("a number", val)),
+        }
+    }
+}
+impl FromVal for bool {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::Bool(b) => Ok(b),
+            val => Err(EngineError::Conversion("a boolean", val)),
+        }
+    }
+}
+impl FromVal for String {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::Str(s) => Ok(s),
+            val => Err(EngineError::Conversion("a string", val)),
+        }
+    }
+}
+impl<T: FromVal> FromVal for Vec<T> {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::List(items) => items.into_iter().map(T::from_val).collect(),
+            val => Err(EngineError::Conversion("a list", val)),
+        }
+    }
+}
+impl<A: FromVal, B: FromVal> FromVal for (A, B) {
+    fn from_val(val: Val) -> Result<Self, EngineError> {
+        match val {
+            Val::Tuple(items) if items.len() == 2 => Ok((
+                A::from_val(items[0].clone())?,
+                B::from_val(items[1].clone())?,
+            )),
+            val => Err(EngineError::Conversion("a pair", val)),
+        }
+    }
+}
+
+impl From<i64> for Val {
+    fn from(n: i64) -> Self {
+        Val::Num(n)
+    }
+}
+impl From<f64> for Val {
+    fn from(x: f64) -> Self {
+        Val::Float(x)
+    }
+}
+impl From<bool> for Val {
+    fn from(b: bool) -> Self {
+        Val::Bool(b)
+    }
+}
+impl From<String> for Val {
+    fn from(s: String) -> Self {
+        Val::Str(s)
+    }
+}
+impl From<&str> for Val {
+    fn from(s: &str) -> Self {
+        Val::Str(s.to_string())
+    }
+}
+impl<T: Into<Val>> From<Vec<T>> for Val {
+    fn from(items: Vec<T>) -> Self {
+        Val::List(items.into_iter().map(Into::into).collect())
+    }
+}
+impl<A: Into<Val>, B: Into<Val>> From<(A, B)> for Val {
+    fn from((a, b): (A, B)) -> Self {
+        Val::Tuple(Rc::new(vec![a.into(), b.into()]))
+    }
+}
+
+/// Runs Skiff code from a Rust program. Declarations persist between calls to `eval`, so a
+/// script can be loaded once and its functions called many times. Nothing is written to
+/// stdout: errors are returned, and text printed by Skiff code is kept until `take_printed`.
+pub struct Engine {
+    session: Session,
+    args: CliArgs,
+    printed: prelude::Output,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        let mut engine = Engine {
+            session: Session::new(),
+            args: CliArgs::new(std::path::PathBuf::from("<engine>")),
+            printed: Rc::default(),
+        };
+        // Keep printed text apart from the diagnostics that the session writes
+        let print = prelude::print(&engine.printed);
+        engine.session.define_native(&print);
+        engine
+    }
+
+    /// Limits the resources that each call to `eval` or `call` can use
+    pub fn set_limits(&mut self, limits: EvalLimits) {
+        self.args.limits = limits;
+    }
+
+    /// Runs code on the bytecode VM instead of the tree-walking interpreter
+    pub fn set_use_bytecode(&mut self, use_bytecode: bool) {
+        self.args.use_bytecode = use_bytecode;
+    }
+
+    /// Makes a function implemented in Rust available to Skiff code. Calls to it are type
+    /// checked against its signature.
+    pub fn register_function(&mut self, function: NativeFunction) {
+        self.session.define_native(&function);
+    }
+
+    /// Binds a name to a value from the host with the given type. Functions declared by Skiff
+    /// code can use it, like the functions registered by the host.
+    pub fn set_value(
+        &mut self,
+        name: &str,
+        value: impl Into<Val>,
+        type_decl: Type,
+    ) -> Result<(), EngineError> {
+        self.session
+            .define_value(name, value.into(), &type_decl)
+            .map_err(EngineError::Skiff)
+    }
+
+    /// Evaluates a program, returning the value of each of its top-level expressions. Its
+    /// declarations are kept if it succeeds.
+    pub fn eval(&mut self, source: &str) -> Result<Vec<Val>, EngineError> {
+        let mut diagnostics = String::new();
+        match self
+            .session
+            .evaluate(&self.args, source.to_string(), &mut diagnostics)
+        {
+            Ok(output) => Ok(output
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(val, _)| val)
+                .collect()),
+            Err(e) => Err(EngineError::Skiff(e)),
+        }
+    }
+
+    /// Calls a function declared by an evaluated program (or registered by the host) and
+    /// converts its result to a Rust type. The arguments are type checked before the call.
+    pub fn call<T: FromVal>(&mut self, name: &str, args: Vec<Val>) -> Result<T, EngineError> {
+        let mut diagnostics = String::new();
+        let (val, _) = self
+            .session
+            .call(&self.args, name, args, &mut diagnostics)
+            .map_err(EngineError::Skiff)?;
+        T::from_val(val)
+    }
+
+    /// Returns the text that Skiff code has printed since the last call
+    pub fn take_printed(&mut self) -> String {
+        self.printed.borrow_mut().take()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use super::*;
+    use crate::interpreter::interpret::InterpError;
+    use crate::type_inferencer::type_inference::InferenceError;
+
+    fn engines() -> Vec<Engine> {
+        let tree_walk = Engine::new();
+        let mut bytecode = Engine::new();
+        bytecode.set_use_bytecode(true);
+        vec![tree_walk, bytecode]
+    }
+
+    #[test]
+    fn calls_registered_functions() {
+        for mut engine in engines() {
+            engine.register_function(NativeFunction::new(
+                "shout",
+                vec![],
+                vec![Type::new_string()],
+                Type::new_string(),
+                |args| match &args[0] {
+                    Val::Str(s) => Ok(Val::Str(s.to_uppercase() + "!")),
+                    v => Err(format!("Can't shout {}", v)),
+                },
+            ));
+
+            let output = engine.eval("shout(\"hi\")").unwrap();
+            assert_eq!(output, vec![Val::Str("HI!".to_string())]);
+            // Calls are checked against the function's signature
+            assert!(matches!(
+                engine.eval("shout(1)"),
+                Err(EngineError::Skiff(SkiffError::Inference(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn uses_values_from_the_host() {
+        for mut engine in engines() {
+            engine
+                .set_value("threshold", 10, Type::new_number())
+                .unwrap();
+
+            let output = engine.eval("threshold * 2").unwrap();
+            assert_eq!(output, vec![Val::Num(20)]);
+            assert!(matches!(
+                engine.eval("threshold + \"units\""),
+                Err(EngineError::Skiff(SkiffError::Inference(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn uses_values_from_the_host_in_functions() {
+        for mut engine in engines() {
+            engine.eval("let base = 1").unwrap();
+            engine.set_value("base", 20, Type::new_number()).unwrap();
+            engine
+                .eval("def score(bonus: Number) -> Number: base * 2 + bonus end")
+                .unwrap();
+
+            assert_eq!(engine.call::<i64>("score", vec![2.into()]), Ok(42));
+            // The host's value shadows the earlier binding at the top level too
+            assert_eq!(engine.eval("base").unwrap(), vec![Val::Num(20)]);
+        }
+    }
+
+    #[test]
+    fn calls_functions_by_name() {
+        for mut engine in engines() {
+            engine
+                .eval("def add(a: Number, b: Number) -> Number: a + b end")
+                .unwrap();
+
+            let sum: i64 = engine.call("add", vec![1.into(), 2.into()]).unwrap();
+            assert_eq!(sum, 3);
+            assert_eq!(
+                engine.call::<String>("add", vec![1.into(), 2.into()]),
+                Err(EngineError::Conversion("a string", Val::Num(3)))
+            );
+            assert_eq!(engine.call::<i64>("max", vec![4.into(), 7.into()]), Ok(7));
+            engine
+                .eval("def split(p: Tuple<Number, String>) -> Tuple<String, Number>: match p: | (n, s) => (s, n) end end")
+                .unwrap();
+            assert_eq!(
+                engine.call::<(String, i64)>("split", vec![(1, "one").into()]),
+                Ok(("one".to_string(), 1))
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_arguments_of_calls() {
+        for mut engine in engines() {
+            engine
+                .eval("def first<T>(l: List<T>) -> Option<T>: match l: | [f, ...r] => some(f) | [] => none() end end")
+                .unwrap();
+
+            assert_eq!(
+                engine
+                    .call::<Val>("first", vec![vec![1, 2].into()])
+                    .unwrap()
+                    .to_string(),
+                "some(1)"
+            );
+            let abs = engine.eval("abs").unwrap().remove(0);
+            assert_eq!(
+                engine.call::<Vec<i64>>("map", vec![abs, vec![-1, 2].into()]),
+                Ok(vec![1, 2])
+            );
+            assert!(matches!(
+                engine.call::<Val>("first", vec![1.into()]),
+                Err(EngineError::Skiff(SkiffError::Inference(
+                    InferenceError::ConstructorMismatch(..)
+                )))
+            ));
+        }
+    }
+
+    #[test]
+    fn returns_runtime_errors() {
+        for mut engine in engines() {
+            match engine.call::<Val>("missing", vec![]) {
+                Err(EngineError::Skiff(SkiffError::Interpret(InterpError(
+                    message,
+                    _,
+                    _,
+                    _,
+                    _,
+                )))) => {
+                    assert_eq!(message, "Couldn't find var in environment: missing")
+                }
+                result => panic!("Expected a runtime error, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_printed_text() {
+        for mut engine in engines() {
+            engine.eval("print(\"hello\")\nprint(1 + 2)").unwrap();
+            assert_eq!(engine.take_printed(), "hello\n3\n");
+            assert_eq!(engine.take_printed(), "");
+        }
+    }
+}
@@ -1,76 +1,521 @@
+use crate::formatter::pattern_text;
+use crate::interpreter::interpret::{InterpError, InterpErrorKind};
+use crate::modules::ModuleError;
+use crate::parser::util::{ParseError, ParseErrorKind};
+use crate::static_checking::exhaustiveness::{
+    ExhaustivenessError, NonExhaustiveMatch, UnreachableBranch,
+};
+use crate::type_inferencer::ast::Origin;
+use crate::type_inferencer::type_inference::InferenceError;
 use colored::*;
-use std::{fmt::Write, ops::Range};
+use std::{fmt::Write, ops::Range, str::FromStr};
 
-pub fn pretty_print_error(
-    message: &str,
-    span: Range<usize>,
+/// How serious a diagnostic is
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Severity {
+    /// The problem stops the program from running
+    Error,
+    Warning,
+}
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+    fn color(&self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        }
+    }
+}
+
+/// A span that a diagnostic points at besides its primary one, with a message explaining its
+/// part in the problem
+#[derive(PartialEq, Debug, Clone)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// A change to the source that would fix the problem a diagnostic describes
+#[derive(PartialEq, Debug, Clone)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+/// A problem found in a program by any stage, from lexing to running it. Spans index into the
+/// source of the whole program, which a `SourceMap` splits back into files.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable code for the kind of problem (e.g. `E0203` for a type mismatch)
+    pub code: &'static str,
+    pub message: String,
+    /// Where the problem is, if the stage that found it knows
+    pub span: Option<Range<usize>>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message.into())
+    }
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message.into())
+    }
+    fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            span: None,
+            labels: vec![],
+            notes: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+    pub fn with_span(mut self, span: Range<usize>) -> Self {
+        self.span = Some(span);
+        self
+    }
+    pub fn with_label(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Range<usize>,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    /// Points the diagnostic at the origin of a type constraint, if it has a location
+    fn at(self, origin: &Option<Origin>) -> Self {
+        match origin {
+            Some(origin) if !origin.src_loc.span.is_empty() => {
+                self.with_span(origin.src_loc.span.clone())
+            }
+            _ => self,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Prints the diagnostic for a person to read, with the source lines it points at
+    pub fn pretty_print(&self, source_map: &SourceMap, source: &str, printer: &mut impl Write) {
+        let color = self.severity.color();
+        let header = format!("{}[{}]", self.severity.name().to_uppercase(), self.code);
+        let header = header.color(color);
+        // The file and line that were printed last, so that a label on the same line doesn't
+        // print it again
+        let mut last_line = match &self.span {
+            Some(span) => {
+                let (path, file_source, span) = source_map.locate(span, source);
+                let _ = writeln!(printer, "{} in {:?}: {}", header, path, self.message);
+                let line = print_snippet(file_source, span, "^", color, "", None, printer);
+                Some((path, line))
+            }
+            None => {
+                let _ = writeln!(printer, "{}: {}", header, self.message);
+                None
+            }
+        };
+
+        for label in &self.labels {
+            let (label_path, file_source, span) = source_map.locate(&label.span, source);
+            // Only the first line of a label is shown, which is enough to recognize a
+            // declaration by
+            let first_line_end = file_source[span.start..].find('\n');
+            let span = match first_line_end {
+                Some(end) if span.start + end < span.end => span.start..(span.start + end),
+                _ => span,
+            };
+            let printed_line = match &last_line {
+                Some((path, line)) if *path == label_path => Some(*line),
+                Some(_) | None => {
+                    let _ = writeln!(
+                        printer,
+                        "{:>4} {}",
+                        "-->".blue().bold(),
+                        add_position_info_to_filename(file_source, span.start, &label_path)
+                    );
+                    None
+                }
+            };
+            let line = print_snippet(
+                file_source,
+                span,
+                "-",
+                Color::Blue,
+                &label.message,
+                printed_line,
+                printer,
+            );
+            last_line = Some((label_path, line));
+        }
+        for note in &self.notes {
+            let _ = writeln!(printer, "{:4} {} {}", "", "= note:".bold(), note);
+        }
+        for suggestion in &self.suggestions {
+            let _ = writeln!(
+                printer,
+                "{:4} {} {}",
+                "",
+                "= help:".bold(),
+                suggestion.message
+            );
+        }
+    }
+
+    /// Renders the diagnostic as a single line of JSON for tools to read. Spans are byte
+    /// offsets into their file, along with the 1-based line and column they start at.
+    pub fn to_json(&self, source_map: &SourceMap, source: &str) -> String {
+        let located = |span: &Range<usize>| {
+            let (path, file_source, span) = source_map.locate(span, source);
+            let (line, column) = index_to_file_position(file_source, span.start);
+            format!(
+                "\"file\":{},\"span\":{{\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+                json_string(&path.display().to_string()),
+                span.start,
+                span.end,
+                line + 1,
+                column + 1
+            )
+        };
+        let location = match &self.span {
+            Some(span) => located(span),
+            None => "\"file\":null,\"span\":null".to_string(),
+        };
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{{{},\"message\":{}}}",
+                    located(&label.span),
+                    json_string(&label.message)
+                )
+            })
+            .collect();
+        let notes: Vec<_> = self.notes.iter().map(|note| json_string(note)).collect();
+        let suggestions: Vec<_> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                format!(
+                    "{{\"message\":{},{},\"replacement\":{}}}",
+                    json_string(&suggestion.message),
+                    located(&suggestion.span),
+                    json_string(&suggestion.replacement)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},{},\"labels\":[{}],\"notes\":[{}],\"suggestions\":[{}]}}",
+            self.severity.name(),
+            self.code,
+            json_string(&self.message),
+            location,
+            labels.join(","),
+            notes.join(","),
+            suggestions.join(",")
+        )
+    }
+}
+
+/// How diagnostics are printed
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorFormat {
+    /// Colored messages with the source lines they point at
+    Human,
+    /// One JSON object per line
+    Json,
+}
+impl ErrorFormat {
+    pub fn print(
+        &self,
+        diagnostic: &Diagnostic,
+        source_map: &SourceMap,
+        source: &str,
+        printer: &mut impl Write,
+    ) {
+        match self {
+            ErrorFormat::Human => diagnostic.pretty_print(source_map, source, printer),
+            ErrorFormat::Json => {
+                let _ = writeln!(printer, "{}", diagnostic.to_json(source_map, source));
+            }
+        }
+    }
+}
+impl FromStr for ErrorFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Unknown error format {:?}", s)),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let ParseError(message, span, kind) = error;
+        let code = match kind {
+            ParseErrorKind::Invalid => "E0100",
+            ParseErrorKind::EndOfFile => "E0101",
+            ParseErrorKind::UnexpectedToken => "E0102",
+            ParseErrorKind::ExpectedToken(_) => "E0103",
+        };
+        let mut diagnostic = Diagnostic::error(code, message.clone());
+        if let Some(span) = span {
+            diagnostic = diagnostic.with_span(span.clone());
+            let expected = match kind {
+                ParseErrorKind::ExpectedToken(name) => token_text(name),
+                _ => None,
+            };
+            if let Some(text) = expected {
+                diagnostic = diagnostic.with_suggestion(
+                    format!("insert `{}` here", text),
+                    span.start..span.start,
+                    format!("{} ", text),
+                );
+            }
+        }
+        diagnostic
+    }
+}
+
+/// The source text of the tokens the parser expects in particular places, by their names
+fn token_text(name: &str) -> Option<&'static str> {
+    match name {
+        "Colon" => Some(":"),
+        "End" => Some("end"),
+        "Eq" => Some("="),
+        "FatArrow" => Some("=>"),
+        "From" => Some("from"),
+        "LParen" => Some("("),
+        "Pipe" => Some("|"),
+        "RParen" => Some(")"),
+        "ThinArrow" => Some("->"),
+        _ => None,
+    }
+}
+
+impl From<&ModuleError> for Diagnostic {
+    fn from(error: &ModuleError) -> Self {
+        let ModuleError(message, span) = error;
+        Diagnostic::error("E0501", message.clone()).with_span(span.clone())
+    }
+}
+
+impl From<&InferenceError> for Diagnostic {
+    fn from(error: &InferenceError) -> Self {
+        match error {
+            InferenceError::UnboundIdentifier(name, src_loc) => {
+                Diagnostic::error("E0201", format!("Unbound identifier `{}`", name))
+                    .with_span(src_loc.span.clone())
+            }
+            InferenceError::UnboundPattern(name, src_loc) => Diagnostic::error(
+                "E0202",
+                format!("Unknown constructor `{}` in pattern", name),
+            )
+            .with_span(src_loc.span.clone()),
+            InferenceError::ConstructorMismatch(expected, found, origin, reason) => {
+                let message = format!("Type mismatch: expected {}, found {}", expected, found);
+                let mut diagnostic = Diagnostic::error("E0203", message).at(origin);
+                if let Some(reason) = reason
+                    .as_ref()
+                    .filter(|reason| !reason.src_loc.span.is_empty())
+                {
+                    let message = match reason.is_annotation {
+                        true => format!("expected {} because of the annotation here", expected),
+                        false => format!("expected {} because of this", expected),
+                    };
+                    diagnostic = diagnostic.with_label(reason.src_loc.span.clone(), message);
+                }
+                diagnostic
+            }
+            InferenceError::InfiniteType(origin) => {
+                Diagnostic::error("E0204", "Infinite type detected").at(origin)
+            }
+            InferenceError::MalformedPattern(_, src_loc) => Diagnostic::error(
+                "E0205",
+                "Pattern has a different number of fields than its constructor",
+            )
+            .with_span(src_loc.span.clone()),
+            InferenceError::MissingAnnotation(span) => {
+                Diagnostic::error("E0206", "Missing type annotation").with_span(span.clone())
+            }
+            InferenceError::TopLevelError(src_loc) => {
+                Diagnostic::error("E0207", "Found a nested `let` at the top level")
+                    .with_span(src_loc.span.clone())
+            }
+            InferenceError::TopLevelExpressionOutOfPlace(src_loc) => {
+                Diagnostic::error("E0208", "Found a top-level declaration in an expression")
+                    .with_span(src_loc.span.clone())
+            }
+            InferenceError::DataDeclarationError(InterpError(message, span, _, _, _)) => {
+                Diagnostic::error("E0209", message.clone()).with_span(span.clone())
+            }
+            InferenceError::MissingField(t, field, origin) => {
+                Diagnostic::error("E0210", format!("Type {} has no field `{}`", t, field))
+                    .at(origin)
+            }
+            InferenceError::UnsupportedOperand(t, operator, types, origin) => {
+                let message = format!("Can't use `{}` on a value of type {}", operator, t);
+                Diagnostic::error("E0212", message)
+                    .at(origin)
+                    .with_note(format!(
+                        "`{}` only works on {}",
+                        operator,
+                        types.join(" or ")
+                    ))
+            }
+            InferenceError::UnevenAlternatives(name, src_loc) => Diagnostic::error(
+                "E0211",
+                format!(
+                    "Variable `{}` isn't bound by every alternative of an or-pattern",
+                    name
+                ),
+            )
+            .with_span(src_loc.span.clone()),
+        }
+    }
+}
+
+impl From<&ExhaustivenessError> for Diagnostic {
+    fn from(error: &ExhaustivenessError) -> Self {
+        let diagnostic = match error {
+            ExhaustivenessError::UnknownTypeToMatchOn(_) => {
+                Diagnostic::error("E0302", "Couldn't find the type of a matched expression")
+            }
+            ExhaustivenessError::UnknownTypeVariant(_) => {
+                Diagnostic::error("E0303", "Unknown data variant in pattern")
+            }
+            ExhaustivenessError::CantMatchFunction(_) => {
+                Diagnostic::error("E0304", "Functions can't be matched against patterns")
+            }
+            ExhaustivenessError::NotEnoughArgsInPattern(_) => {
+                Diagnostic::error("E0306", "Not enough fields in pattern")
+            }
+            ExhaustivenessError::TooManyArgsInPattern(_) => {
+                Diagnostic::error("E0307", "Too many fields in pattern")
+            }
+        };
+        diagnostic.with_span(error.src_loc().span.clone())
+    }
+}
+
+impl From<&NonExhaustiveMatch> for Diagnostic {
+    fn from(non_exhaustive_match: &NonExhaustiveMatch) -> Self {
+        // Only the first few missing patterns are listed
+        let mut missing: Vec<String> = non_exhaustive_match
+            .missing
+            .iter()
+            .take(3)
+            .map(|pattern| format!("`{}`", pattern_text(pattern)))
+            .collect();
+        let more = non_exhaustive_match.missing.len() - missing.len();
+        let note = match missing.pop() {
+            Some(last) if missing.is_empty() => format!("Pattern {} not covered", last),
+            Some(last) if more == 0 => {
+                format!("Patterns {} and {} not covered", missing.join(", "), last)
+            }
+            Some(last) => format!(
+                "Patterns {}, {} and {} more not covered",
+                missing.join(", "),
+                last,
+                more
+            ),
+            None => "Some values aren't covered".to_string(),
+        };
+        Diagnostic::warning("E0301", "Non-exhaustive match expression")
+            .with_span(non_exhaustive_match.src_loc.span.clone())
+            .with_note(note)
+    }
+}
+
+impl From<&UnreachableBranch> for Diagnostic {
+    fn from(branch: &UnreachableBranch) -> Self {
+        Diagnostic::warning("E0308", "Unreachable match branch")
+            .with_span(branch.src_loc.span.clone())
+            .with_note("The branches before it match every value this branch does")
+    }
+}
+
+impl From<&InterpError> for Diagnostic {
+    fn from(error: &InterpError) -> Self {
+        let InterpError(message, span, _, _, kind) = error;
+        let code = match kind {
+            InterpErrorKind::Invalid => "E0401",
+            InterpErrorKind::DivisionByZero => "E0402",
+            InterpErrorKind::LimitExceeded => "E0403",
+            InterpErrorKind::CastFailed => "E0404",
+            InterpErrorKind::NoMatch => "E0405",
+            InterpErrorKind::NativeFailed => "E0406",
+        };
+        let diagnostic = Diagnostic::error(code, message.clone());
+        // Errors raised outside of any expression have an empty span
+        match span.is_empty() {
+            true => diagnostic,
+            false => diagnostic.with_span(span.clone()),
+        }
+    }
+}
+
+/// Prints the lines of a file that a span covers, underlining the span with a marker and
+/// following the underline with a message. A line that was just printed is only underlined.
+/// Returns the last line printed.
+fn print_snippet(
     source: &str,
-    filename: std::path::PathBuf,
-    printer: &mut impl Write,
-) -> () {
-    pretty_print_diagnostic(
-        message,
-        span,
-        source,
-        filename,
-        printer,
-        "ERROR",
-        Color::Red,
-    );
-}
-
-pub fn pretty_print_warning(
-    message: &str,
     span: Range<usize>,
-    source: &str,
-    filename: std::path::PathBuf,
-    printer: &mut impl Write,
-) -> () {
-    pretty_print_diagnostic(
-        message,
-        span,
-        source,
-        filename,
-        printer,
-        "WARNING",
-        Color::Yellow,
-    );
-}
-
-/// Prints an error message along with the location in the source file where the error occurred
-fn pretty_print_diagnostic(
+    marker: &str,
+    color: Color,
     message: &str,
-    span: Range<usize>,
-    source: &str,
-    filename: std::path::PathBuf,
+    printed_line: Option<usize>,
     printer: &mut impl Write,
-    diagnostic_type: &str,
-    color: Color,
-) -> () {
-    // Find the start and end of the error as line/col pair.
+) -> usize {
+    // Find the start and end of the span as line/col pair.
     let (start_line, start_col) = index_to_file_position(source, span.start);
     let (end_line, end_col) = index_to_file_position(source, span.end);
 
     let lines: Vec<_> = source.split("\n").collect();
 
-    // let the user know there has been an error
-    let _ = writeln!(
-        printer,
-        "{} in {:?}: {}",
-        diagnostic_type.color(color),
-        filename,
-        message
-    );
-    if span == (0..0) {
-        return;
-    }
-
-    // Print the error location
     for i in start_line..(end_line + 1) {
         // print the line from the source file
-        let _ = writeln!(printer, "{:4} {} {}", i + 1, "|".blue().bold(), lines[i]);
+        if printed_line != Some(i) {
+            let _ = writeln!(printer, "{:4} {} {}", i + 1, "|".blue().bold(), lines[i]);
+        }
 
-        // print the error underline (some amount of blank followed by the underline)
+        // print the underline (some amount of blank followed by the underline)
         let blank_size;
         let mut underline_size;
         if i == start_line && i == end_line {
@@ -89,15 +534,40 @@ fn pretty_print_diagnostic(
         if underline_size <= 0 {
             underline_size = 1;
         }
+        let message = if i == end_line && !message.is_empty() {
+            format!(" {}", message)
+        } else {
+            String::new()
+        };
         let _ = writeln!(
             printer,
-            "{:4} {} {}{}",
+            "{:4} {} {}{}{}",
             "",
             "|".blue().bold(),
             " ".repeat(blank_size),
-            "^".repeat(underline_size).color(color).bold()
+            marker.repeat(underline_size).color(color).bold(),
+            message.color(color)
         );
     }
+    end_line
+}
+
+/// Quotes a string for JSON
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 /// Converts an index into a string into a line/column pair for that same string
@@ -130,3 +600,141 @@ pub fn add_position_info_to_filename(
     let (line, column) = index_to_file_position(source, index);
     format!("{}:{}:{}", filename.display(), line, column)
 }
+
+/// Maps spans in the concatenated source of every file in a program (or every input in a REPL
+/// session) back to the file they came from
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<(std::path::PathBuf, Range<usize>)>,
+}
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap { files: vec![] }
+    }
+
+    /// Records that a range of the concatenated source came from a file. Files must be added
+    /// in the order they appear in the source.
+    pub fn add_file(&mut self, path: std::path::PathBuf, range: Range<usize>) {
+        self.files.push((path, range));
+    }
+
+    /// Finds the file that contains a span, returning the file's path and source along with
+    /// the span relative to the start of the file
+    pub fn locate<'a>(
+        &self,
+        span: &Range<usize>,
+        source: &'a str,
+    ) -> (std::path::PathBuf, &'a str, Range<usize>) {
+        let file = self
+            .files
+            .iter()
+            .rev()
+            .find(|(_, range)| range.start <= span.start)
+            .or_else(|| self.files.first());
+        match file {
+            Some((path, range)) if span.end <= range.end => (
+                path.clone(),
+                &source[range.clone()],
+                (span.start - range.start)..(span.end - range.start),
+            ),
+            _ => (std::path::PathBuf::from("<unknown>"), source, span.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod diagnostic_tests {
+    use super::*;
+    use crate::ast::SrcLoc;
+    use crate::type_inferencer::ast::Term;
+    use im::HashMap;
+
+    fn source_map(source: &str) -> SourceMap {
+        let mut source_map = SourceMap::new();
+        source_map.add_file(std::path::PathBuf::from("main.boat"), 0..source.len());
+        source_map
+    }
+
+    #[test]
+    fn converts_errors_from_every_stage() {
+        let parse_error = ParseError(
+            "Didn't get expected token Colon".to_string(),
+            Some(4..5),
+            ParseErrorKind::ExpectedToken("Colon".to_string()),
+        );
+        let diagnostic = Diagnostic::from(&parse_error);
+        assert_eq!(diagnostic.code, "E0103");
+        assert_eq!(diagnostic.span, Some(4..5));
+        assert_eq!(diagnostic.suggestions[0].span, 4..4);
+        assert_eq!(diagnostic.suggestions[0].replacement, ": ");
+
+        let mismatch =
+            InferenceError::ConstructorMismatch(Term::number(), Term::boolean(), None, None);
+        assert_eq!(Diagnostic::from(&mismatch).code, "E0203");
+        let exhaustiveness_error = ExhaustivenessError::CantMatchFunction(SrcLoc { span: 6..11 });
+        assert_eq!(Diagnostic::from(&exhaustiveness_error).code, "E0304");
+        assert_eq!(Diagnostic::from(&exhaustiveness_error).span, Some(6..11));
+
+        // Runtime errors raised outside of any expression have no location
+        let interp_error = InterpError(
+            "Oops".to_string(),
+            0..0,
+            HashMap::new(),
+            im::Vector::new(),
+            InterpErrorKind::Invalid,
+        );
+        assert_eq!(Diagnostic::from(&interp_error).span, None);
+        let division_error = InterpError(
+            "Division by zero".to_string(),
+            4..9,
+            HashMap::new(),
+            im::Vector::new(),
+            InterpErrorKind::DivisionByZero,
+        );
+        assert_eq!(Diagnostic::from(&division_error).code, "E0402");
+    }
+
+    #[test]
+    fn renders_json() {
+        let source = "let x = \"a\"\nx + 1";
+        let diagnostic = Diagnostic::error("E0203", "Type mismatch: \"a\" is not a Number")
+            .with_span(12..17)
+            .with_label(0..11, "defined here")
+            .with_note("strings can't be added to numbers")
+            .with_suggestion("remove the `+ 1`", 13..17, "");
+        assert_eq!(
+            diagnostic.to_json(&source_map(source), source),
+            concat!(
+                r#"{"severity":"error","code":"E0203","message":"Type mismatch: \"a\" is not a Number","#,
+                r#""file":"main.boat","span":{"start":12,"end":17,"line":2,"column":1},"#,
+                r#""labels":[{"file":"main.boat","span":{"start":0,"end":11,"line":1,"column":1},"message":"defined here"}],"#,
+                r#""notes":["strings can't be added to numbers"],"#,
+                r#""suggestions":[{"message":"remove the `+ 1`","file":"main.boat","span":{"start":13,"end":17,"line":2,"column":2},"replacement":""}]}"#
+            )
+        );
+
+        let diagnostic = Diagnostic::warning("E0204", "Infinite type detected");
+        assert!(diagnostic
+            .to_json(&source_map(source), source)
+            .contains(r#""severity":"warning","code":"E0204","message":"Infinite type detected","file":null,"span":null"#));
+    }
+
+    #[test]
+    fn prints_labels_notes_and_suggestions() {
+        let source = "let x = \"a\"\nx + 1";
+        let diagnostic = Diagnostic::error("E0203", "Type mismatch")
+            .with_span(12..17)
+            .with_label(8..11, "defined here")
+            .with_note("strings can't be added to numbers")
+            .with_suggestion("remove the `+ 1`", 13..17, "");
+        let mut printed = String::new();
+        diagnostic.pretty_print(&source_map(source), source, &mut printed);
+
+        assert!(printed.contains("E0203"));
+        assert!(printed.contains("x + 1"));
+        assert!(printed.contains("let x = \"a\""));
+        assert!(printed.contains("defined here"));
+        assert!(printed.contains("strings can't be added to numbers"));
+        assert!(printed.contains("remove the `+ 1`"));
+    }
+}
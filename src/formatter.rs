@@ -0,0 +1,884 @@
+use crate::ast::{Ast, AstNode, BinOp, Identifier, ImportNames, Pattern, Program, Type};
+use crate::lexer::lex::{self, Token};
+use crate::parser::parse::{get_binding_power, parse_program};
+use crate::parser::util::ast_op_to_token_op;
+use crate::runtime::SkiffError;
+use logos::Logos;
+use std::ops::Range;
+
+/// Lines are broken up where the syntax allows it to keep them within this many characters
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats a program in the canonical Skiff style. Blocks are indented by four spaces, `def`,
+/// `data`, `if` and `match` always span several lines, and expressions that don't fit on a
+/// line are broken up. Comments and single blank lines between statements are kept.
+/// Formatting is idempotent: formatting the output again doesn't change it.
+pub fn format(source: &str) -> Result<String, SkiffError> {
+    let tokens: Vec<_> = Token::lexer(source).spanned().collect();
+    if tokens.iter().any(|(token, _)| *token == Token::Error) {
+        return Err(SkiffError::Lex());
+    }
+    let mut token_stack = tokens.clone();
+    token_stack.reverse();
+    let program = parse_program(&mut token_stack).map_err(SkiffError::Parse)?;
+
+    let mut formatter = Formatter::new(source, tokens);
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+struct Comment {
+    span: Range<usize>,
+    /// Whether the comment follows code on the same line, rather than being on its own line
+    trailing: bool,
+}
+
+/// Writes out a parsed program, weaving the comments of its source back in. The AST doesn't
+/// have spans for keywords like `else` and `|`, so they're found in the tokens of the source.
+struct Formatter<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, Range<usize>)>,
+    comments: Vec<Comment>,
+    /// The index of the first comment that hasn't been written yet
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// How far into the source has been written, which blank lines are looked for after
+    written_to: usize,
+    /// Whether the last line opened a block, whose first line shouldn't be preceded by a
+    /// blank line
+    opened_block: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, tokens: Vec<(Token, Range<usize>)>) -> Self {
+        let comments = lex::comments(source)
+            .into_iter()
+            .map(|span| {
+                let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+                Comment {
+                    trailing: !source[line_start..span.start].trim().is_empty(),
+                    span,
+                }
+            })
+            .collect();
+        Formatter {
+            source,
+            tokens,
+            comments,
+            next_comment: 0,
+            out: String::new(),
+            indent: 0,
+            written_to: 0,
+            opened_block: false,
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        for item in program {
+            self.statement(item);
+        }
+        self.write_comments(self.source.len());
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes a block: a chain of `let`s on lines of their own, followed by an expression
+    fn block(&mut self, mut e: &Ast) {
+        loop {
+            let (bound, binding, body) = match &e.node {
+                AstNode::LetNode(id, binding, body) => (identifier_text(id), binding, body),
+                AstNode::LetPatternNode(pattern, binding, body) => {
+                    (pattern_text(pattern), binding, body)
+                }
+                _ => break,
+            };
+            self.line(self.start(e));
+            self.push(&format!("let {} = ", bound));
+            self.expr(binding);
+            e = body;
+        }
+        self.statement(e);
+    }
+
+    fn indented_block(&mut self, e: &Ast) {
+        self.indent += 1;
+        self.opened_block = true;
+        self.block(e);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, e: &Ast) {
+        self.line(self.start(e));
+        self.expr(e);
+    }
+
+    /// Writes an expression on the current line if it fits, and otherwise breaks it up
+    fn expr(&mut self, e: &Ast) {
+        match self.flat(e) {
+            Some(flat) if self.fits(&flat) => self.push(&flat),
+            _ => self.broken_expr(e),
+        }
+        self.written_to = self.written_to.max(self.end(e));
+    }
+
+    fn broken_expr(&mut self, e: &Ast) {
+        let end_keyword = e.src_loc.span.end - "end".len();
+        match &e.node {
+            AstNode::IfNode(conditions_and_bodies, alternate) => {
+                for (i, (condition, body)) in conditions_and_bodies.iter().enumerate() {
+                    if i == 0 {
+                        self.push("if ");
+                    } else {
+                        let elif = self.token_before(self.start(condition), &Token::Elif);
+                        self.closing_line(elif);
+                        self.push("elif ");
+                    }
+                    self.expr(condition);
+                    self.push(":");
+                    self.indented_block(body);
+                }
+                let else_keyword = self.token_before(self.start(alternate), &Token::Else);
+                self.closing_line(else_keyword);
+                self.push("else:");
+                self.indented_block(alternate);
+                self.closing_line(end_keyword);
+                self.push("end");
+            }
+            AstNode::MatchNode(expression_to_match, branches) => {
+                self.push("match ");
+                self.expr(expression_to_match);
+                self.push(":");
+                self.indent += 1;
+                self.opened_block = true;
+                // Or-patterns have pipes of their own, so a branch's pipe is the first one after
+                // the previous branch
+                let mut previous_end = self.end(expression_to_match);
+                for (pattern, guard, body, _) in branches {
+                    let pipe = self.token_after(previous_end, &Token::Pipe);
+                    previous_end = self.end(body);
+                    self.line(pipe);
+                    self.push(&format!("| {}", pattern_text(pattern)));
+                    if let Some(guard) = guard {
+                        self.push(" if ");
+                        self.expr(guard);
+                    }
+                    self.push(" =>");
+                    // Blocks go on the lines after the arrow, and anything else after it
+                    match &body.node {
+                        AstNode::LetNode(..)
+                        | AstNode::LetPatternNode(..)
+                        | AstNode::IfNode(..)
+                        | AstNode::MatchNode(..) => self.indented_block(body),
+                        _ => {
+                            self.push(" ");
+                            self.expr(body);
+                        }
+                    }
+                }
+                self.indent -= 1;
+                self.closing_line(end_keyword);
+                self.push("end");
+            }
+            AstNode::LambdaNode(params, body) => {
+                self.push(&format!("lambda({}):", params_text(params)));
+                self.indented_block(body);
+                self.closing_line(end_keyword);
+                self.push("end");
+            }
+            AstNode::FunctionNode(name, type_params, params, return_type, body) => {
+                self.push(&format!(
+                    "def {}{}({})",
+                    name,
+                    type_params_text(type_params),
+                    params_text(params)
+                ));
+                if let Some(return_type) = return_type {
+                    self.push(&format!(" -> {}", type_text(return_type)));
+                }
+                self.push(":");
+                self.indented_block(body);
+                self.closing_line(end_keyword);
+                self.push("end");
+            }
+            AstNode::DataDeclarationNode(name, type_params, variants) => {
+                self.push(&format!("data {}{}:", name, type_params_text(type_params)));
+                self.indent += 1;
+                self.opened_block = true;
+                let variant_starts = self.variant_starts(e);
+                for ((variant, fields), start) in variants.iter().zip(variant_starts) {
+                    self.line(start);
+                    self.push(&format!("| {}({})", variant, params_text(fields)));
+                }
+                self.indent -= 1;
+                self.closing_line(end_keyword);
+                self.push("end");
+            }
+            AstNode::FunCallNode(callee, args) => {
+                self.callee(callee);
+                if args.is_empty() {
+                    self.push("()");
+                    return;
+                }
+                // One argument per line
+                self.push("(");
+                self.indent += 1;
+                self.opened_block = true;
+                for (i, arg) in args.iter().enumerate() {
+                    self.line(self.start(arg));
+                    self.expr(arg);
+                    if i + 1 < args.len() {
+                        self.push(",");
+                    }
+                }
+                self.indent -= 1;
+                self.closing_line(e.src_loc.span.end - ")".len());
+                self.push(")");
+            }
+            AstNode::BinOpNode(op, left, right) => {
+                // A chain of operators with the same precedence (e.g. `a + b - c`) is broken
+                // before each operator, rather than nesting
+                let mut rest = vec![(*op, right.as_ref())];
+                let mut first = left.as_ref();
+                while let AstNode::BinOpNode(inner_op, inner_left, inner_right) = &first.node {
+                    if binding_power(inner_op) != binding_power(op)
+                        || needs_parens(first, op, false)
+                    {
+                        break;
+                    }
+                    rest.push((*inner_op, inner_right));
+                    first = inner_left;
+                }
+                rest.reverse();
+
+                self.operand(first, op, false);
+                self.indent += 1;
+                for (op, operand) in rest {
+                    self.line(self.start(operand));
+                    self.push(&format!("{} ", op));
+                    self.operand(operand, &op, true);
+                }
+                self.indent -= 1;
+            }
+            AstNode::ListNode(items, rest) => {
+                // One item per line, like the arguments of a call
+                self.push("[");
+                self.indent += 1;
+                self.opened_block = true;
+                for (i, item) in items.iter().enumerate() {
+                    self.line(self.start(item));
+                    self.expr(item);
+                    if i + 1 < items.len() || rest.is_some() {
+                        self.push(",");
+                    }
+                }
+                if let Some(rest) = rest {
+                    self.line(self.token_before(self.start(rest), &Token::Ellipsis));
+                    self.push("...");
+                    self.expr(rest);
+                }
+                self.indent -= 1;
+                self.closing_line(e.src_loc.span.end - "]".len());
+                self.push("]");
+            }
+            AstNode::TupleNode(items) => {
+                self.push("(");
+                self.indent += 1;
+                self.opened_block = true;
+                for (i, item) in items.iter().enumerate() {
+                    self.line(self.start(item));
+                    self.expr(item);
+                    if i + 1 < items.len() {
+                        self.push(",");
+                    }
+                }
+                self.indent -= 1;
+                self.closing_line(e.src_loc.span.end - ")".len());
+                self.push(")");
+            }
+            AstNode::RecordNode(fields) => {
+                self.push("{");
+                self.broken_fields(fields);
+                self.closing_line(e.src_loc.span.end - "}".len());
+                self.push("}");
+            }
+            AstNode::RecordUpdateNode(record, fields) => {
+                self.push("{");
+                self.expr(record);
+                self.push(" with");
+                self.broken_fields(fields);
+                self.closing_line(e.src_loc.span.end - "}".len());
+                self.push("}");
+            }
+            AstNode::FieldAccessNode(value, field) => {
+                self.callee(value);
+                self.push(&format!(".{}", field));
+            }
+            // A `let` outside of a block has to be wrapped in parentheses
+            AstNode::LetNode(..) | AstNode::LetPatternNode(..) => {
+                self.push("(");
+                self.indented_block(e);
+                self.closing_line(self.end(e));
+                self.push(")");
+            }
+            AstNode::LetNodeTopLevel(id, binding) => {
+                self.push(&format!("let {} = ", identifier_text(id)));
+                self.expr(binding);
+            }
+            AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+                self.push(&format!("let {} = ", pattern_text(pattern)));
+                self.expr(binding);
+            }
+            _ => self.push(&flat(e).unwrap_or_default()),
+        }
+    }
+
+    /// Writes the fields of a record one per line, like the items of a list
+    fn broken_fields(&mut self, fields: &[(String, Ast)]) {
+        self.indent += 1;
+        self.opened_block = true;
+        for (i, (field, value)) in fields.iter().enumerate() {
+            let name = Token::Identifier(field.clone());
+            self.line(self.token_before(self.start(value), &name));
+            self.push(&format!("{}: ", field));
+            self.expr(value);
+            if i + 1 < fields.len() {
+                self.push(",");
+            }
+        }
+        self.indent -= 1;
+    }
+
+    fn callee(&mut self, callee: &Ast) {
+        match &callee.node {
+            AstNode::BinOpNode(..) => {
+                self.push("(");
+                self.expr(callee);
+                self.push(")");
+            }
+            _ => self.expr(callee),
+        }
+    }
+
+    fn operand(&mut self, operand: &Ast, op: &BinOp, is_right: bool) {
+        if needs_parens(operand, op, is_right) {
+            self.push("(");
+            self.expr(operand);
+            self.push(")");
+        } else {
+            self.expr(operand);
+        }
+    }
+
+    /// Returns an expression written on one line, unless it can't be or it has comments in it
+    fn flat(&self, e: &Ast) -> Option<String> {
+        let first_comment = self
+            .comments
+            .partition_point(|comment| comment.span.start < self.start(e));
+        match self.comments.get(first_comment) {
+            Some(comment) if comment.span.start < self.end(e) => None,
+            _ => flat(e),
+        }
+    }
+
+    /// Starts a new line for code that starts at `pos` in the source, after writing the
+    /// comments before it. A blank line is kept if there was one in the source.
+    fn line(&mut self, pos: usize) {
+        self.write_comments(pos);
+        self.new_line(pos, true);
+    }
+
+    /// Starts a new line for a keyword or bracket that closes a block. The comments before it
+    /// are indented with the block, and blank lines are dropped.
+    fn closing_line(&mut self, pos: usize) {
+        self.indent += 1;
+        self.write_comments(pos);
+        self.indent -= 1;
+        self.new_line(pos, false);
+    }
+
+    fn new_line(&mut self, pos: usize, keep_blank_line: bool) {
+        if !self.out.is_empty() {
+            if keep_blank_line && !self.opened_block && self.has_blank_line(self.written_to, pos) {
+                self.out.push('\n');
+            }
+            self.out.push('\n');
+        }
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.opened_block = false;
+        self.written_to = self.written_to.max(pos);
+    }
+
+    /// Writes the comments that come before `pos` in the source. A comment that followed code
+    /// stays at the end of the current line.
+    fn write_comments(&mut self, pos: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= pos {
+                break;
+            }
+            let span = comment.span.clone();
+            let text = self.source[span.clone()].trim_end();
+            let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+            if comment.trailing && !self.out[line_start..].trim().is_empty() {
+                self.out.push_str("  ");
+            } else {
+                self.new_line(span.start, true);
+            }
+            self.out.push_str(text);
+            self.written_to = self.written_to.max(span.end);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Whether there's an empty line between two positions in the source
+    fn has_blank_line(&self, from: usize, to: usize) -> bool {
+        if from >= to {
+            return false;
+        }
+        // The first and last lines are partly taken up by the code on either side
+        let lines: Vec<&str> = self.source[from..to].split('\n').collect();
+        lines.len() > 2
+            && lines[1..lines.len() - 1]
+                .iter()
+                .any(|l| l.trim().is_empty())
+    }
+
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count() + text.chars().count() <= MAX_WIDTH
+    }
+
+    /// Where an expression starts in the source. Spans of operators and calls don't cover
+    /// their left operand, and `match` spans start after the keyword.
+    fn start(&self, e: &Ast) -> usize {
+        match &e.node {
+            AstNode::BinOpNode(_, left, _) => self.start(left),
+            AstNode::FunCallNode(callee, _) | AstNode::FieldAccessNode(callee, _) => {
+                self.start(callee)
+            }
+            AstNode::MatchNode(..) => e.src_loc.span.start - "match".len(),
+            AstNode::DataDeclarationNode(..) => {
+                self.token_before(e.src_loc.span.start, &Token::Data)
+            }
+            _ => e.src_loc.span.start,
+        }
+    }
+
+    /// Where an expression ends in the source. Spans of operators and `let`s don't cover
+    /// their operands and bodies.
+    fn end(&self, e: &Ast) -> usize {
+        match &e.node {
+            AstNode::BinOpNode(_, _, right) => self.end(right),
+            AstNode::LetNode(_, _, body) | AstNode::LetPatternNode(_, _, body) => self.end(body),
+            AstNode::LetNodeTopLevel(_, binding) | AstNode::LetPatternNodeTopLevel(_, binding) => {
+                self.end(binding)
+            }
+            _ => e.src_loc.span.end,
+        }
+    }
+
+    /// Finds the start of the last `token` before `pos` in the source
+    fn token_before(&self, pos: usize, token: &Token) -> usize {
+        let before = self.tokens.partition_point(|(_, span)| span.start < pos);
+        self.tokens[..before]
+            .iter()
+            .rev()
+            .find(|(t, _)| t == token)
+            .map_or(pos, |(_, span)| span.start)
+    }
+
+    /// Finds the start of the first `token` at or after `pos` in the source
+    fn token_after(&self, pos: usize, token: &Token) -> usize {
+        let after = self.tokens.partition_point(|(_, span)| span.start < pos);
+        self.tokens[after..]
+            .iter()
+            .find(|(t, _)| t == token)
+            .map_or(pos, |(_, span)| span.start)
+    }
+
+    /// Finds where each variant of a data declaration starts. Variant names are the only
+    /// identifiers after the `:` that aren't inside parentheses.
+    fn variant_starts(&self, e: &Ast) -> Vec<usize> {
+        let span = e.src_loc.span.clone();
+        let mut depth = 0;
+        self.tokens
+            .iter()
+            .filter(|(_, token_span)| span.contains(&token_span.start))
+            .skip_while(|(token, _)| *token != Token::Colon)
+            .filter_map(|(token, token_span)| {
+                match token {
+                    Token::LParen => depth += 1,
+                    Token::RParen => depth -= 1,
+                    Token::Identifier(_) if depth == 0 => return Some(token_span.start),
+                    _ => (),
+                }
+                None
+            })
+            .collect()
+    }
+}
+
+/// Writes an expression on one line, or returns `None` if it always spans several lines
+fn flat(e: &Ast) -> Option<String> {
+    Some(match &e.node {
+        AstNode::NumberNode(n) => n.to_string(),
+        AstNode::BigNumberNode(n) => n.to_string(),
+        AstNode::FloatNode(_, text) => text.clone(),
+        AstNode::BoolNode(b) => b.to_string(),
+        AstNode::StringNode(s) => string_literal(s),
+        AstNode::VarNode(id) => id.clone(),
+        AstNode::BinOpNode(op, left, right) => format!(
+            "{} {} {}",
+            flat_operand(left, op, false)?,
+            op,
+            flat_operand(right, op, true)?
+        ),
+        AstNode::FunCallNode(callee, args) => {
+            format!("{}({})", flat_callee(callee)?, flat_list(args.iter())?)
+        }
+        AstNode::FieldAccessNode(value, field) => format!("{}.{}", flat_callee(value)?, field),
+        AstNode::TupleNode(items) => format!("({})", flat_list(items.iter())?),
+        AstNode::RecordNode(fields) => format!("{{{}}}", flat_fields(fields)?),
+        AstNode::RecordUpdateNode(record, fields) => {
+            format!("{{{} with {}}}", flat(record)?, flat_fields(fields)?)
+        }
+        AstNode::DataLiteralNode(discriminant, values) => format!(
+            "{}({})",
+            discriminant,
+            flat_list(values.iter().map(|value| value.as_ref()))?
+        ),
+        AstNode::ListNode(items, rest) => {
+            let rest = match rest {
+                Some(rest) => Some(format!("...{}", flat(rest)?)),
+                None => None,
+            };
+            list_text(flat_list(items.iter())?, rest)
+        }
+        AstNode::LambdaNode(params, body) => {
+            format!("lambda({}): {} end", params_text(params), flat(body)?)
+        }
+        AstNode::LetNodeTopLevel(id, binding) => {
+            format!("let {} = {}", identifier_text(id), flat(binding)?)
+        }
+        AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+            format!("let {} = {}", pattern_text(pattern), flat(binding)?)
+        }
+        AstNode::ImportNode(path, names) => match names {
+            ImportNames::All => format!("import {}", string_literal(path)),
+            ImportNames::Alias(alias) => format!("import {} as {}", string_literal(path), alias),
+            ImportNames::Selected(selected) => {
+                format!(
+                    "import {} from {}",
+                    selected.join(", "),
+                    string_literal(path)
+                )
+            }
+        },
+        AstNode::LetNode(..)
+        | AstNode::LetPatternNode(..)
+        | AstNode::IfNode(..)
+        | AstNode::MatchNode(..)
+        | AstNode::FunctionNode(..)
+        | AstNode::DataDeclarationNode(..)
+        | AstNode::ErrorNode(..) => return None,
+    })
+}
+
+fn flat_list<'b>(exprs: impl Iterator<Item = &'b Ast>) -> Option<String> {
+    Some(exprs.map(flat).collect::<Option<Vec<_>>>()?.join(", "))
+}
+
+/// Writes the function of a call or the value of a field access, which an operator has to be
+/// wrapped in parentheses for
+fn flat_callee(callee: &Ast) -> Option<String> {
+    match &callee.node {
+        AstNode::BinOpNode(..) => Some(format!("({})", flat(callee)?)),
+        _ => flat(callee),
+    }
+}
+
+fn flat_fields(fields: &[(String, Ast)]) -> Option<String> {
+    let fields = fields
+        .iter()
+        .map(|(field, value)| Some(format!("{}: {}", field, flat(value)?)))
+        .collect::<Option<Vec<_>>>()?;
+    Some(fields.join(", "))
+}
+
+fn flat_operand(operand: &Ast, op: &BinOp, is_right: bool) -> Option<String> {
+    match needs_parens(operand, op, is_right) {
+        true => Some(format!("({})", flat(operand)?)),
+        false => flat(operand),
+    }
+}
+
+fn binding_power(op: &BinOp) -> i64 {
+    get_binding_power(&ast_op_to_token_op(op))
+}
+
+/// Whether an operand of `op` has to be wrapped in parentheses to keep its meaning
+fn needs_parens(operand: &Ast, op: &BinOp, is_right: bool) -> bool {
+    match &operand.node {
+        AstNode::BinOpNode(operand_op, _, _) => {
+            let (operand_power, power) = (binding_power(operand_op), binding_power(op));
+            // `**` is right associative and the other operators are left associative
+            operand_power < power || (operand_power == power && is_right != (*op == BinOp::Exp))
+        }
+        _ => false,
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes a pattern the way it's written in the source
+pub fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::NumLiteral(n) => n.to_string(),
+        Pattern::BoolLiteral(b) => b.to_string(),
+        Pattern::StringLiteral(s) => string_literal(s),
+        Pattern::Identifier(id) => id.clone(),
+        Pattern::List(items, rest) => list_text(
+            items
+                .iter()
+                .map(pattern_text)
+                .collect::<Vec<_>>()
+                .join(", "),
+            rest.as_ref()
+                .map(|rest| format!("...{}", pattern_text(rest))),
+        ),
+        Pattern::Data(name, args) => format!(
+            "{}({})",
+            name,
+            args.iter().map(pattern_text).collect::<Vec<_>>().join(", ")
+        ),
+        Pattern::Tuple(items) => format!(
+            "({})",
+            items
+                .iter()
+                .map(pattern_text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        // A field that binds a variable of the same name is written without its pattern
+        Pattern::Record(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(field, pattern)| match pattern {
+                    Pattern::Identifier(id) if id == field => field.clone(),
+                    _ => format!("{}: {}", field, pattern_text(pattern)),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Pattern::Wildcard => "_".to_string(),
+        // `as` applies to all of the alternatives before it, so an alternative that uses it
+        // needs parentheses
+        Pattern::Or(alternatives) => alternatives
+            .iter()
+            .map(|alternative| match alternative {
+                Pattern::As(..) => format!("({})", pattern_text(alternative)),
+                _ => pattern_text(alternative),
+            })
+            .collect::<Vec<_>>()
+            .join(" | "),
+        Pattern::As(pattern, name) => format!("{} as {}", pattern_text(pattern), name),
+    }
+}
+
+/// Writes the brackets around a list's items and its `...rest`, if it has one
+fn list_text(items: String, rest: Option<String>) -> String {
+    match rest {
+        Some(rest) if items.is_empty() => format!("[{}]", rest),
+        Some(rest) => format!("[{}, {}]", items, rest),
+        None => format!("[{}]", items),
+    }
+}
+
+fn identifier_text(id: &Identifier) -> String {
+    match &id.type_decl {
+        Some(type_decl) => format!("{}: {}", id.id, type_text(type_decl)),
+        None => id.id.clone(),
+    }
+}
+
+fn params_text(params: &[Identifier]) -> String {
+    params
+        .iter()
+        .map(identifier_text)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn type_params_text(type_params: &[String]) -> String {
+    match type_params.is_empty() {
+        true => String::new(),
+        false => format!("<{}>", type_params.join(", ")),
+    }
+}
+
+/// Writes a type as it's annotated. A function type with a single parameter that has no type
+/// arguments is written without parentheses (e.g. `A -> B`).
+fn type_text(t: &Type) -> String {
+    let (id, args) = match t {
+        Type::Constructor(id, args) => (id, args),
+        Type::Record(fields) => {
+            let fields: Vec<String> = (fields.iter())
+                .map(|(field, t)| format!("{}: {}", field, type_text(t)))
+                .collect();
+            return format!("{{{}}}", fields.join(", "));
+        }
+    };
+    let arg_texts: Vec<String> = args.iter().map(type_text).collect();
+    match arg_texts.split_last() {
+        None => id.clone(),
+        Some((return_type, params)) if id == "Function" => match args.front() {
+            Some(param) if params.len() == 1 && param.args().is_empty() => {
+                format!("{} -> {}", params[0], return_type)
+            }
+            _ => format!("({}) -> {}", params.join(", "), return_type),
+        },
+        Some(_) => format!("{}<{}>", id, arg_texts.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn indents_blocks() {
+        assert_formats(
+            "def fact(n:Number)->Number: match n: | 1 => 1 | n => let next = fact(n-1) next*n end end",
+            "def fact(n: Number) -> Number:\n    match n:\n        | 1 => 1\n        | n =>\n            let next = fact(n - 1)\n            next * n\n    end\nend\n",
+        );
+        assert_formats(
+            "if a:\n1\n  elif b: 2 else:\n        3 end",
+            "if a:\n    1\nelif b:\n    2\nelse:\n    3\nend\n",
+        );
+        assert_formats(
+            "data Pair<A,B>: pair(a: A, b: B) end",
+            "data Pair<A, B>:\n    | pair(a: A, b: B)\nend\n",
+        );
+    }
+
+    #[test]
+    fn keeps_the_meaning_of_operators() {
+        assert_formats("(1+2)*3", "(1 + 2) * 3\n");
+        assert_formats("1-(2-3)-4", "1 - (2 - 3) - 4\n");
+        assert_formats("(2**3)**2 + 2**(3**2)", "(2 ** 3) ** 2 + 2 ** 3 ** 2\n");
+        assert_formats("((1 + 2))", "1 + 2\n");
+    }
+
+    #[test]
+    fn writes_types_and_literals() {
+        assert_formats(
+            "def apply(f:(Number,Number)->List<Number>, g: A->B->C) -> Number: f(1, 2) end",
+            "def apply(f: (Number, Number) -> List<Number>, g: A -> B -> C) -> Number:\n    f(1, 2)\nend\n",
+        );
+        assert_formats(
+            "let s: String = \"tab\\there \\\"quoted\\\"\"",
+            "let s: String = \"tab\\there \\\"quoted\\\"\"\n",
+        );
+        // Floats are written as they were, rather than as the numbers they parse to
+        assert_formats("1.50 + 2E3 * 1e-20+1.5e10", "1.50 + 2E3 * 1e-20 + 1.5e10\n");
+        assert_formats(
+            "match [ 1,2 , ...xs ]: | [a, ...[]] => a | [ ] => 0 end",
+            "match [1, 2, ...xs]:\n    | [a, ...[]] => a\n    | [] => 0\nend\n",
+        );
+        assert_formats(
+            "import \"a.boat\"  import \"b.boat\" as b import x,y from \"c.boat\"",
+            "import \"a.boat\"\nimport \"b.boat\" as b\nimport x, y from \"c.boat\"\n",
+        );
+        assert_formats(
+            "def f(p:{y:Number,x:Number}): match {p with x:1}: | {x, y:2} => p.x | { } => 0 end end",
+            "def f(p: {x: Number, y: Number}):\n    match {p with x: 1}:\n        | {x, y: 2} => p.x\n        | {} => 0\n    end\nend\n",
+        );
+        assert_formats(
+            "def f(p:Tuple<Number,Boolean>): let (n,( b )) = p match (b,n): | (true,m) => (m,1) | other => (0,0) end end",
+            "def f(p: Tuple<Number, Boolean>):\n    let (n, b) = p\n    match (b, n):\n        | (true, m) => (m, 1)\n        | other => (0, 0)\n    end\nend\n",
+        );
+        assert_formats(
+            "match p: | (1|2 , _)|(_,0) as q => q | ( a as b )|(_ as b) => b | [_, ...r] => 0 end",
+            "match p:\n    | (1 | 2, _) | (_, 0) as q => q\n    | (a as b) | (_ as b) => b\n    | [_, ...r] => 0\nend\n",
+        );
+        assert_formats(
+            "match n: | x if x>0 => 1 | x if match x: | 0 => true | y => false end => 0 end",
+            "match n:\n    | x if x > 0 => 1\n    | x if match x:\n        | 0 => true\n        | y => false\n    end => 0\nend\n",
+        );
+    }
+
+    #[test]
+    fn breaks_long_lines() {
+        let source = "some_function(first_argument_value, second_argument_value, lambda(x): x + the_third_argument_value end)";
+        assert_formats(
+            source,
+            "some_function(\n    first_argument_value,\n    second_argument_value,\n    lambda(x): x + the_third_argument_value end\n)\n",
+        );
+        let source = "first_value_in_the_sum + second_value_in_the_sum + third_value_in_the_sum * the_fourth_value_in_the_sum";
+        assert_formats(
+            source,
+            "first_value_in_the_sum\n    + second_value_in_the_sum\n    + third_value_in_the_sum * the_fourth_value_in_the_sum\n",
+        );
+        let source = "[first_item_in_the_list, second_item_in_the_list, third_item_in_the_list, ...the_rest_of_the_items_in_the_list]";
+        assert_formats(
+            source,
+            "[\n    first_item_in_the_list,\n    second_item_in_the_list,\n    third_item_in_the_list,\n    ...the_rest_of_the_items_in_the_list\n]\n",
+        );
+        let source = "{the_original_record with first_field: the_first_field_value, second_field: the_second_field_value_here}";
+        assert_formats(
+            source,
+            "{the_original_record with\n    first_field: the_first_field_value,\n    second_field: the_second_field_value_here\n}\n",
+        );
+        let source = "let (first_item_of_the_tuple, second_item_of_the_tuple) = (the_first_value_in_it, the_second_value_here)";
+        assert_formats(
+            source,
+            "let (first_item_of_the_tuple, second_item_of_the_tuple) = (\n    the_first_value_in_it,\n    the_second_value_here\n)\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        assert_formats(
+            "# A header\n\n\n\ndef f(x): # trailing\n\n    # inside\n    x   \n  # before end\nend\nf(1,  # first\n2)",
+            "# A header\n\ndef f(x):  # trailing\n    # inside\n    x\n    # before end\nend\nf(\n    1,  # first\n    2\n)\n",
+        );
+        assert_formats(
+            "match x:\n# first\n| 1 => 1 # one\n\n| n => n\nend # done",
+            "match x:\n    # first\n    | 1 => 1  # one\n\n    | n => n\nend  # done\n",
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert!(matches!(format("let x = $"), Err(SkiffError::Lex())));
+        assert!(matches!(format("let x ="), Err(SkiffError::Parse(_))));
+    }
+}
@@ -0,0 +1,386 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// An integer of any size, which is what integer arithmetic switches to when a result doesn't
+/// fit in an `i64`. It's stored as a sign and a magnitude, whose base 2^32 digits are least
+/// significant first with no zeros at the end. Zero has no digits and isn't negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.digits.first().copied().unwrap_or(0) % 2 == 0
+    }
+
+    /// The number of bits in the magnitude
+    pub fn bits(&self) -> u64 {
+        match self.digits.last() {
+            Some(top) => 32 * self.digits.len() as u64 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// The number of bytes the digits take up, for counting towards the memory limit
+    pub fn size_in_bytes(&self) -> usize {
+        4 * self.digits.len()
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0i128, |acc, digit| (acc << 32) | *digit as i128);
+        i64::try_from(if self.negative { -magnitude } else { magnitude }).ok()
+    }
+
+    /// Converts to the nearest float (or to infinity if it's too big). Integers that floats
+    /// can represent exactly are converted exactly.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |acc, digit| acc * 4_294_967_296.0 + *digit as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Converts a float to an integer by dropping its fractional part. Returns `None` for NaN
+    /// and the infinities.
+    pub fn from_f64(x: f64) -> Option<BigInt> {
+        if !x.is_finite() {
+            return None;
+        }
+        // A finite float is mantissa * 2^exponent, with the implicit leading bit made explicit
+        let bits = x.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mut mantissa = bits & ((1 << 52) - 1);
+        if biased_exponent != 0 {
+            mantissa |= 1 << 52;
+        }
+        let exponent = biased_exponent.max(1) - 1075;
+        let magnitude = if exponent < 0 {
+            BigInt::from(mantissa.checked_shr((-exponent) as u32).unwrap_or(0) as i64)
+        } else {
+            BigInt::from(mantissa as i64).shift_left(exponent as usize)
+        };
+        Some(BigInt::new(x < 0.0, magnitude.digits))
+    }
+
+    fn shift_left(&self, bits: usize) -> BigInt {
+        let (whole_digits, bits) = (bits / 32, bits % 32);
+        let mut digits = vec![0; whole_digits];
+        let mut carry = 0;
+        for digit in &self.digits {
+            let shifted = (*digit as u64) << bits;
+            digits.push(shifted as u32 | carry);
+            carry = (shifted >> 32) as u32;
+        }
+        digits.push(carry);
+        BigInt::new(self.negative, digits)
+    }
+
+    /// Divides, rounding the quotient towards zero so that the remainder has the sign of the
+    /// dividend, like `i64` division. Returns `None` when dividing by zero.
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = divide_magnitudes(&self.digits, &divisor.digits);
+        Some((
+            BigInt::new(self.negative != divisor.negative, quotient),
+            BigInt::new(self.negative, remainder),
+        ))
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = &result * &base;
+            }
+            exponent /= 2;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+    /// Parses a string of decimal digits with an optional leading `-`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(format!("{} isn't an integer", text));
+        }
+        // Digits are added nine at a time, which is the most that fit in a u32
+        let mut magnitude = BigInt::default();
+        for chunk in digits.as_bytes().chunks(9) {
+            let chunk = std::str::from_utf8(chunk).unwrap();
+            let scale = BigInt::from(10i64.pow(chunk.len() as u32));
+            magnitude = &(&magnitude * &scale) + &BigInt::from(chunk.parse::<i64>().unwrap());
+        }
+        Ok(BigInt::new(negative, magnitude.digits))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.digits, &other.digits),
+            (true, true) => compare_magnitudes(&other.digits, &self.digits),
+        }
+    }
+}
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.digits, &other.digits));
+        }
+        // The signs differ, so the smaller magnitude is taken from the bigger one
+        match compare_magnitudes(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                subtract_magnitudes(&other.digits, &self.digits),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                subtract_magnitudes(&self.digits, &other.digits),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, x) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, y) in other.digits.iter().enumerate() {
+                let product = *x as u64 * *y as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, digits)
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Split the magnitude into base 10^9 chunks, least significant first
+        let mut chunks = vec![];
+        let mut magnitude = self.digits.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = divide_magnitudes(&magnitude, &[1_000_000_000]);
+            chunks.push(remainder.first().copied().unwrap_or(0));
+            magnitude = BigInt::new(false, quotient).digits;
+        }
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        text.push_str(&chunks.pop().unwrap().to_string());
+        for chunk in chunks.iter().rev() {
+            text.push_str(&format!("{:09}", chunk));
+        }
+        write!(f, "{}", text)
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        digits.push(sum as u32);
+        carry = sum >> 32;
+    }
+    digits.push(carry as u32);
+    digits
+}
+
+/// Subtracts `b` from `a`, which must be at least as big
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, digit) in a.iter().enumerate() {
+        let mut difference = *digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if difference < 0 {
+            difference += 1 << 32;
+            borrow = 1;
+        }
+        digits.push(difference as u32);
+    }
+    digits
+}
+
+/// Long division of magnitudes, returning the quotient and remainder. The divisor can't be
+/// zero.
+fn divide_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if let [divisor] = b {
+        // Dividing by a single digit is common (e.g. when printing), so it gets a fast path
+        let mut quotient = vec![0; a.len()];
+        let mut remainder = 0u64;
+        for i in (0..a.len()).rev() {
+            let current = (remainder << 32) | a[i] as u64;
+            quotient[i] = (current / *divisor as u64) as u32;
+            remainder = current % *divisor as u64;
+        }
+        return (quotient, vec![remainder as u32]);
+    }
+
+    // Otherwise the quotient is found one bit at a time
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = BigInt::default();
+    let divisor = BigInt::new(false, b.to_vec());
+    for i in (0..a.len() * 32).rev() {
+        remainder = remainder.shift_left(1);
+        if (a[i / 32] >> (i % 32)) & 1 == 1 {
+            remainder = &remainder + &BigInt::from(1);
+        }
+        if remainder >= divisor {
+            remainder = &remainder - &divisor;
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+    (quotient, remainder.digits)
+}
+
+#[cfg(test)]
+mod bigint_tests {
+    use super::*;
+
+    fn big(n: i64) -> BigInt {
+        BigInt::from(n)
+    }
+
+    #[test]
+    fn does_arithmetic_past_i64() {
+        let max = big(i64::MAX);
+        assert_eq!((&max + &big(1)).to_string(), "9223372036854775808");
+        assert_eq!(
+            (&big(i64::MIN) - &big(1)).to_string(),
+            "-9223372036854775809"
+        );
+        assert_eq!(
+            (&max * &max).to_string(),
+            "85070591730234615847396907784232501249"
+        );
+        assert_eq!(big(3).pow(50).to_string(), "717897987691852588770249");
+        assert_eq!((&(&max + &big(1)) - &big(1)).to_i64(), Some(i64::MAX));
+        assert_eq!((&max + &big(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn divides_towards_zero() {
+        let (quotient, remainder) = big(-7).div_rem(&big(2)).unwrap();
+        assert_eq!((quotient, remainder), (big(-3), big(-1)));
+
+        let dividend = &big(10).pow(30) + &big(7);
+        let divisor = big(10).pow(20);
+        let (quotient, remainder) = dividend.div_rem(&divisor).unwrap();
+        assert_eq!(quotient, big(10).pow(10));
+        assert_eq!(remainder, big(7));
+        assert_eq!(dividend.div_rem(&BigInt::default()), None);
+    }
+
+    #[test]
+    fn parses_and_prints_decimal() {
+        let text = "-123456789012345678901234567890";
+        assert_eq!(text.parse::<BigInt>().unwrap().to_string(), text);
+        assert_eq!("000".parse::<BigInt>(), Ok(BigInt::default()));
+        assert!("12a".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn converts_floats() {
+        assert_eq!(BigInt::from_f64(-2.75), Some(big(-2)));
+        assert_eq!(
+            BigInt::from_f64(1e20).unwrap().to_string(),
+            "100000000000000000000"
+        );
+        assert_eq!(BigInt::from_f64(f64::NAN), None);
+        assert_eq!(big(2).pow(70).to_f64(), 2f64.powi(70));
+    }
+}
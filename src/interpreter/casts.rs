@@ -0,0 +1,331 @@
+use crate::ast::{Discriminant, FunctionCast, Identifier, SrcLoc, Type, Val};
+use im::{HashMap, OrdMap, Vector};
+use std::rc::Rc;
+
+/// The parts of a runtime value that casts look at. Both the tree-walking interpreter's
+/// values and the VM's values can be viewed this way, so the backends check types alike.
+pub enum View<'a, V> {
+    Number,
+    Boolean,
+    String,
+    /// (arity, the cast around the function if it has one)
+    Function(usize, Option<&'a FunctionCast>),
+    List(&'a Vector<V>),
+    Tuple(&'a [V]),
+    Record(&'a OrdMap<String, V>),
+    /// (the value's data type and variant, its fields)
+    Data(&'a Discriminant, &'a [V]),
+}
+
+/// What casts know about a data type: its type parameters and the declared types of each
+/// variant's fields, where fields without an annotation hold any value
+#[derive(PartialEq, Debug, Clone)]
+pub struct DataType {
+    pub type_params: Vec<String>,
+    pub variants: HashMap<String, Vec<Option<Type>>>,
+}
+impl DataType {
+    pub fn new(type_params: &[String], variants: &[(String, Vec<Identifier>)]) -> Self {
+        DataType {
+            type_params: type_params.to_vec(),
+            variants: variants
+                .iter()
+                .map(|(name, fields)| {
+                    let field_types = fields.iter().map(|field| annotation(&field.type_decl));
+                    (name.clone(), field_types.collect())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The data types that casts check values against, by name
+pub type DataTypes = HashMap<String, DataType>;
+
+/// A runtime value that can be checked against a type
+pub trait Castable: Clone {
+    fn view(&self) -> View<'_, Self>;
+    fn new_list(items: Vector<Self>) -> Self;
+    fn new_tuple(items: Vec<Self>) -> Self;
+    fn new_record(fields: OrdMap<String, Self>) -> Self;
+    /// Wraps a function in a cast
+    fn new_cast(function: Self, cast: Rc<FunctionCast>) -> Self;
+}
+
+impl Castable for Val {
+    fn view(&self) -> View<'_, Self> {
+        match self {
+            Val::Num(_) | Val::BigNum(_) | Val::Float(_) => View::Number,
+            Val::Bool(_) => View::Boolean,
+            Val::Str(_) => View::String,
+            Val::Lam(params, _, _) => View::Function(params.len(), None),
+            Val::Native(native) => View::Function(native.arity(), None),
+            Val::Cast(_, cast) => View::Function(cast.params.len(), Some(cast)),
+            Val::Data(discriminant, fields) => View::Data(discriminant, fields),
+            Val::List(items) => View::List(items),
+            Val::Record(fields) => View::Record(fields),
+            Val::Tuple(items) => View::Tuple(items),
+        }
+    }
+    fn new_list(items: Vector<Self>) -> Self {
+        Val::List(items)
+    }
+    fn new_tuple(items: Vec<Self>) -> Self {
+        Val::Tuple(Rc::new(items))
+    }
+    fn new_record(fields: OrdMap<String, Self>) -> Self {
+        Val::Record(fields)
+    }
+    fn new_cast(function: Self, cast: Rc<FunctionCast>) -> Self {
+        Val::Cast(Rc::new(function), cast)
+    }
+}
+
+/// Checks a value against a type at runtime, where a value from untyped code (of type `Any`)
+/// meets an annotation. Returns the value to use in the value's place, or None if it doesn't
+/// have the type.
+///
+/// Functions can't be checked until they're called, so they're wrapped in a cast that checks
+/// their arguments and results, which is called `name()` in errors and blames `src_loc` for
+/// its results. Lists, tuples, records and data values are checked item by item, with the type
+/// arguments of a data type standing in for its type parameters in its fields' types (fields
+/// of the data type itself are only checked to be of that type). Type
+/// names that aren't builtin or one of the `data_types` are type parameters, which match any
+/// value.
+pub fn cast<V: Castable>(
+    value: V,
+    t: &Type,
+    data_types: &DataTypes,
+    name: &dyn Fn() -> String,
+    src_loc: &SrcLoc,
+) -> Option<V> {
+    // Only values that hold functions have to be rebuilt, since their functions get wrapped
+    if !mentions_function(t) {
+        return if has_type(&value, t, data_types) {
+            Some(value)
+        } else {
+            None
+        };
+    }
+    let cast_item = |item: &V, t: &Type| cast(item.clone(), t, data_types, name, src_loc);
+    let (id, args) = match t {
+        Type::Constructor(id, args) => (id.as_str(), args),
+        Type::Record(field_types) => {
+            let mut fields = match value.view() {
+                View::Record(fields) if fields.len() == field_types.len() => fields.clone(),
+                _ => return None,
+            };
+            for (field, t) in field_types {
+                let value = cast_item(fields.get(field)?, t)?;
+                fields.insert(field.clone(), value);
+            }
+            return Some(V::new_record(fields));
+        }
+    };
+    match (id, value.view()) {
+        ("Function", View::Function(arity, existing)) => {
+            cast_function(&value, arity, existing, t, name, src_loc)
+        }
+        ("List", View::List(items)) => {
+            let item_type = args.get(0)?;
+            let items = items.iter().map(|item| cast_item(item, item_type));
+            Some(V::new_list(items.collect::<Option<_>>()?))
+        }
+        ("Tuple", View::Tuple(items)) if items.len() == args.len() => {
+            let items = items.iter().zip(args.iter());
+            let items = items.map(|(item, t)| cast_item(item, t));
+            Some(V::new_tuple(items.collect::<Option<_>>()?))
+        }
+        _ if has_type(&value, t, data_types) => Some(value),
+        _ => None,
+    }
+}
+
+/// Checks whether a value has a type, without looking inside functions
+fn has_type<V: Castable>(value: &V, t: &Type, data_types: &DataTypes) -> bool {
+    let (id, args) = match t {
+        Type::Constructor(id, args) => (id.as_str(), args),
+        Type::Record(field_types) => {
+            return match value.view() {
+                View::Record(fields) => {
+                    field_types.len() == fields.len()
+                        && field_types.iter().all(|(name, t)| match fields.get(name) {
+                            Some(field) => has_type(field, t, data_types),
+                            None => false,
+                        })
+                }
+                _ => false,
+            }
+        }
+    };
+    match (id, value.view()) {
+        ("Any", _) => true,
+        ("Number", View::Number) | ("Boolean", View::Boolean) | ("String", View::String) => true,
+        ("Function", View::Function(arity, _)) => arity + 1 == args.len(),
+        ("List", View::List(items)) => match args.get(0) {
+            Some(item_type) => items
+                .iter()
+                .all(|item| has_type(item, item_type, data_types)),
+            None => true,
+        },
+        ("Tuple", View::Tuple(items)) => {
+            items.len() == args.len()
+                && (items.iter().zip(args.iter())).all(|(item, t)| has_type(item, t, data_types))
+        }
+        (id, View::Data(discriminant, fields)) if discriminant.get_type() == id => {
+            let data_type = match data_types.get(id) {
+                Some(data_type) => data_type,
+                None => return true,
+            };
+            let field_types = match data_type.variants.get(discriminant.get_variant()) {
+                Some(field_types) => field_types,
+                None => return true,
+            };
+            fields.len() == field_types.len()
+                && fields
+                    .iter()
+                    .zip(field_types.iter())
+                    .all(|(field, field_type)| match field_type {
+                        // Fields of the data type itself, like the rest of a list, are only
+                        // checked to be of the type, so long values don't take long to check
+                        Some(field_type) if field_type.is(id) => matches!(
+                            field.view(),
+                            View::Data(field_discriminant, _) if field_discriminant.get_type() == id
+                        ),
+                        Some(field_type) => {
+                            let field_type =
+                                field_type.substitute_params(&data_type.type_params, args);
+                            has_type(field, &field_type, data_types)
+                        }
+                        None => true,
+                    })
+        }
+        (id, _) => !is_known_type(id, data_types),
+    }
+}
+
+/// Wraps a function in a cast to a function type, if it takes as many arguments as the type
+fn cast_function<V: Castable>(
+    value: &V,
+    arity: usize,
+    existing: Option<&FunctionCast>,
+    t: &Type,
+    name: &dyn Fn() -> String,
+    src_loc: &SrcLoc,
+) -> Option<V> {
+    let mut params: Vector<Type> = t.args().into_iter().cloned().collect();
+    let return_type = params.pop_back()?;
+    if arity != params.len() {
+        return None;
+    }
+    let params: Vec<Option<Type>> = params
+        .iter()
+        .map(|t| annotation(&Some(t.clone())))
+        .collect();
+    let return_type = annotation(&Some(return_type));
+    // Functions that are passed around a lot would pile up casts to the same type otherwise
+    if let Some(existing) = existing {
+        if existing.params == params && existing.return_type == return_type {
+            return Some(value.clone());
+        }
+    }
+    let function_cast = FunctionCast {
+        name: name(),
+        params,
+        return_type,
+        src_loc: src_loc.clone(),
+    };
+    Some(V::new_cast(value.clone(), Rc::new(function_cast)))
+}
+
+/// The cast a function's annotations give it, or None if none of its parameters or its
+/// result is annotated with a type other than `Any`
+pub fn annotation_cast(
+    name: &str,
+    params: &[Identifier],
+    return_type: &Option<Type>,
+    src_loc: &SrcLoc,
+) -> Option<Rc<FunctionCast>> {
+    let function_cast = FunctionCast {
+        name: name.to_string(),
+        params: params
+            .iter()
+            .map(|param| annotation(&param.type_decl))
+            .collect(),
+        return_type: annotation(return_type),
+        src_loc: src_loc.clone(),
+    };
+    if function_cast.params.iter().all(Option::is_none) && function_cast.return_type.is_none() {
+        return None;
+    }
+    Some(Rc::new(function_cast))
+}
+
+/// Wraps a function in the cast its annotations give it, if they give it one
+pub fn cast_by_annotations<V: Castable>(
+    function: V,
+    name: &str,
+    params: &[Identifier],
+    return_type: &Option<Type>,
+    src_loc: &SrcLoc,
+) -> V {
+    match annotation_cast(name, params, return_type, src_loc) {
+        Some(function_cast) => V::new_cast(function, function_cast),
+        None => function,
+    }
+}
+
+/// The type an annotation checks values against, or None if it allows any value
+pub fn annotation(type_decl: &Option<Type>) -> Option<Type> {
+    type_decl.clone().filter(|t| !t.is("Any"))
+}
+
+/// Whether checking a value against a type can involve wrapping a function
+fn mentions_function(t: &Type) -> bool {
+    t.is("Function") || t.args().into_iter().any(mentions_function)
+}
+
+fn is_known_type(id: &str, data_types: &DataTypes) -> bool {
+    match id {
+        "Number" | "Boolean" | "String" | "List" | "Tuple" | "Function" => true,
+        _ => data_types.contains_key(id),
+    }
+}
+
+/// The message for an argument of a call that doesn't have the type of its parameter
+pub fn argument_error(
+    function_cast: &FunctionCast,
+    position: usize,
+    t: &Type,
+    value: &Val,
+) -> String {
+    format!(
+        "Expected {} to have type {}, but got {}",
+        argument_name(function_cast, position),
+        t,
+        value
+    )
+}
+
+/// The name errors refer to an argument of a cast function by
+pub fn argument_name(function_cast: &FunctionCast, position: usize) -> String {
+    format!("argument {} of {}", position + 1, function_cast.name)
+}
+
+/// The name errors refer to the result of a cast function by
+pub fn result_name(function_cast: &FunctionCast) -> String {
+    format!("the result of {}", function_cast.name)
+}
+
+/// The message for the result of a call that doesn't have the function's return type
+pub fn result_error(function_cast: &FunctionCast, t: &Type, value: &Val) -> String {
+    format!(
+        "Expected {} to return a value of type {}, but it returned {}",
+        function_cast.name, t, value
+    )
+}
+
+/// The message for a let binding whose value doesn't have the type it's annotated with
+pub fn binding_error(id: &Identifier, t: &Type, value: &Val) -> String {
+    format!("Expected `{}` to have type {}, but got {}", id.id, t, value)
+}
@@ -1,20 +1,29 @@
-use crate::ast::{Ast, AstNode, BinOp, Discriminant, Env, Pattern, Program, SrcLoc, Type, Val};
-use crate::error_handling::add_position_info_to_filename;
-use im::{vector, HashMap, Vector};
-use std::convert::TryInto;
+use crate::ast::{
+    Ast, AstNode, BinOp, Discriminant, Env, FunctionCast, Identifier, NativeFunction, Pattern,
+    Program, SrcLoc, Type, Val,
+};
+use crate::error_handling::{add_position_info_to_filename, SourceMap};
+use crate::interpreter::casts::{self, cast_by_annotations, DataType, DataTypes};
+use crate::interpreter::limits::{Budget, EvalLimits};
+use crate::interpreter::numeric;
+use im::{HashMap, OrdMap, Vector};
 use std::fmt::Write;
 use std::{borrow::Borrow, error};
-use std::{fmt, ops::Range};
+use std::{fmt, ops::Range, rc::Rc};
 
 macro_rules! make_throw_interp_error {
     ($src_loc:expr, $env:expr, $stack:expr) => {
         macro_rules! throw_interp_error {
             ($msg:expr) => {
+                throw_interp_error!(InterpErrorKind::Invalid, $msg)
+            };
+            ($kind:expr, $msg:expr) => {
                 return Err(InterpError(
                     $msg.to_string(),
                     $src_loc.span.clone(),
                     $env.clone(),
                     $stack.clone(),
+                    $kind,
                 ));
             };
         }
@@ -25,57 +34,88 @@ macro_rules! make_throw_interp_error {
 pub struct StackFrame {
     src_loc: SrcLoc,
     arg_environment: Env,
+    /// The number of frames this one replaced through calls in tail position
+    pub tail_calls: usize,
 }
 impl StackFrame {
-    fn new(src_loc: SrcLoc, arg_environment: Env) -> StackFrame {
+    pub fn new(src_loc: SrcLoc, arg_environment: Env) -> StackFrame {
         StackFrame {
             src_loc,
             arg_environment,
+            tail_calls: 0,
         }
     }
-    fn new_stack() -> Stack {
-        Vector::unit(StackFrame {
-            src_loc: SrcLoc { span: 0..0 },
-            arg_environment: HashMap::new(),
-        })
+    pub fn new_stack() -> Stack {
+        Vector::unit(StackFrame::new(SrcLoc { span: 0..0 }, HashMap::new()))
     }
     // TODO: incorporate arg_environment in pretty printing
-    pub fn pretty_print(
-        &self,
-        stack_index: usize,
-        filename: &std::path::PathBuf,
-        source: &str,
-    ) -> String {
+    pub fn pretty_print(&self, stack_index: usize, source_map: &SourceMap, source: &str) -> String {
         match self {
             StackFrame {
                 src_loc: SrcLoc { span },
                 arg_environment: _arg_environment,
-            } => format!(
-                "#{}: {}\n\t{}",
-                stack_index,
-                add_position_info_to_filename(source, span.start, filename),
-                source[span.start..span.end].to_string(),
-            )
-            .to_string(),
+                tail_calls,
+            } => {
+                // The bottom frame (from `new_stack`) isn't a call, so it has no location
+                if span.is_empty() {
+                    return format!("#{}: <top level>", stack_index);
+                }
+                let (filename, file_source, span) = source_map.locate(span, source);
+                let mut frame = format!(
+                    "#{}: {}\n\t{}",
+                    stack_index,
+                    add_position_info_to_filename(file_source, span.start, &filename),
+                    file_source[span.start..span.end].to_string(),
+                );
+                if *tail_calls > 0 {
+                    frame += &format!("\n\t({} earlier tail calls collapsed)", tail_calls);
+                }
+                frame
+            }
         }
     }
 
     pub fn print_stack(
         stack: &Vector<Self>,
-        filename: &std::path::PathBuf,
+        source_map: &SourceMap,
         source: &str,
         printer: &mut impl Write,
     ) -> () {
         let _ = writeln!(printer, "Printing stack trace (most recent call last)");
         for (i, frame) in stack.iter().enumerate() {
-            let _ = writeln!(printer, "{}", frame.pretty_print(i, filename, source));
+            let _ = writeln!(printer, "{}", frame.pretty_print(i, source_map, source));
         }
     }
 }
-type Stack = Vector<StackFrame>;
+pub type Stack = Vector<StackFrame>;
 
+/// What stopped a program while it was running
+#[derive(PartialEq, Debug, Clone, Copy, Hash)]
+pub enum InterpErrorKind {
+    /// A value of the wrong type, a missing name or anything else that type checking rules
+    /// out for typed code
+    Invalid,
+    /// An integer division or modulo by zero
+    DivisionByZero,
+    /// The program went past one of the evaluation limits, or a result was too big
+    LimitExceeded,
+    /// A value didn't have the type of an annotation it passed through
+    CastFailed,
+    /// A value didn't match any branch of a match, or the pattern of a let
+    NoMatch,
+    /// A native function failed
+    NativeFailed,
+}
+
+/// (message, span, environment, stack, kind)
 #[derive(PartialEq, Debug, Clone, Hash)]
-pub struct InterpError(pub String, pub Range<usize>, pub Env, pub Stack);
+pub struct InterpError(
+    pub String,
+    pub Range<usize>,
+    pub Env,
+    pub Stack,
+    pub InterpErrorKind,
+);
 impl fmt::Display for InterpError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -83,54 +123,178 @@ impl fmt::Display for InterpError {
 }
 impl error::Error for InterpError {}
 
-#[derive(PartialEq, Debug, Clone, Copy, Hash)]
+#[derive(Debug, Clone, Copy)]
 pub struct InterpretContext<'a> {
     pub env: &'a Env,
     pub func_table: &'a Env,
+    /// The data types that have been declared, which runtime casts check data values against
+    pub data_types: &'a DataTypes,
     pub stack: &'a Stack,
+    pub budget: &'a Budget,
 }
 impl<'a> InterpretContext<'a> {
-    pub fn new(env: &'a Env, func_table: &'a Env, stack: &'a Stack) -> Self {
+    pub fn new(
+        env: &'a Env,
+        func_table: &'a Env,
+        data_types: &'a DataTypes,
+        stack: &'a Stack,
+        budget: &'a Budget,
+    ) -> Self {
         InterpretContext {
             env,
             func_table,
+            data_types,
             stack,
+            budget,
         }
     }
     pub fn new_env(&self, env: &'a Env) -> Self {
         InterpretContext {
             env,
             func_table: self.func_table,
+            data_types: self.data_types,
             stack: self.stack,
+            budget: self.budget,
         }
     }
 }
 
+/// The top-level bindings, function table and data types that persist between successive
+/// calls to `interpret_incremental` (e.g. between lines entered into the REPL)
+#[derive(PartialEq, Debug, Clone)]
+pub struct InterpreterState {
+    pub env: Env,
+    pub func_table: Env,
+    pub data_types: DataTypes,
+}
+impl InterpreterState {
+    pub fn new() -> Self {
+        InterpreterState {
+            env: HashMap::new(),
+            func_table: HashMap::new(),
+            data_types: DataTypes::new(),
+        }
+    }
+
+    /// Adds a native function to the function table, where programs' own functions can
+    /// shadow it
+    pub fn define_native(&mut self, native: &NativeFunction) {
+        self.func_table
+            .insert(native.name.clone(), Val::Native(Rc::new(native.clone())));
+    }
+
+    /// Adds a value from the host to the function table, so that function bodies can see it
+    /// as well as top-level code. It shadows any earlier binding with the same name.
+    pub fn define_host_value(&mut self, name: &str, value: Val) {
+        self.env.remove(name);
+        self.func_table.insert(name.to_string(), value);
+    }
+}
+
 /// Interpret a Skiff program, possibly returning a runtime error
 pub fn interpret(program: &Program) -> Result<Vec<Val>, InterpError> {
+    let (vals, _) =
+        interpret_incremental(program, &InterpreterState::new(), &EvalLimits::default())?;
+    Ok(vals)
+}
+
+/// Interpret a Skiff program on top of the definitions from previously interpreted programs.
+/// Returns the values of the program's expressions along with the extended state. Raises an
+/// error if the program exceeds any of the limits.
+pub fn interpret_incremental(
+    program: &Program,
+    state: &InterpreterState,
+    limits: &EvalLimits,
+) -> Result<(Vec<Val>, InterpreterState), InterpError> {
     // Find every data declaration in the program and add functions declarations
     // to the AST. Add a constructor function for each variant of each data declaration
     let data_funcs_ast = find_data_declarations(program)?;
 
-    // Find all top level function declarations and put them into a map
+    // Find all top level function declarations and put them into a map. Newer
+    // definitions shadow older ones with the same name.
     let funcs = find_functions(program)?;
     let data_funcs = find_functions(&data_funcs_ast)?;
-    let funcs = funcs.into_iter().chain(data_funcs).collect();
+    let funcs: Env = funcs
+        .into_iter()
+        .chain(data_funcs)
+        .collect::<Env>()
+        .union(state.func_table.clone());
+    let data_types: DataTypes = program
+        .iter()
+        .filter_map(|expr| match &expr.node {
+            AstNode::DataDeclarationNode(name, type_params, variants) => {
+                Some((name.clone(), DataType::new(type_params, variants)))
+            }
+            _ => None,
+        })
+        .collect::<DataTypes>()
+        .union(state.data_types.clone());
 
     // Initialize state to keep track of top level definitions and values
-    let mut env = HashMap::new();
+    let mut env = state.env.clone();
     let mut vals = vec![];
+    let budget = Budget::new(limits);
 
     // Loop through each expression/declaration in the program and evaluate it.
     // The result is either value or a new binding in the environment.
     for expr in program {
-        match interpret_top_level(expr, env.clone(), &funcs)? {
+        match interpret_top_level(expr, env.clone(), &funcs, &data_types, &budget)? {
             ValOrEnv::V(val) => vals.push(val),
             ValOrEnv::E(new_env) => env = new_env,
         }
     }
 
-    Ok(vals)
+    Ok((
+        vals,
+        InterpreterState {
+            env,
+            func_table: funcs,
+            data_types,
+        },
+    ))
+}
+
+/// Calls a function from the state of previously interpreted programs (a `def`, a native
+/// function or a top-level binding to a function) with arguments that have already been
+/// evaluated. Raises an error if the call exceeds any of the limits.
+pub fn call_incremental(
+    name: &str,
+    args: Vec<Val>,
+    state: &InterpreterState,
+    limits: &EvalLimits,
+) -> Result<Val, InterpError> {
+    let src_loc = SrcLoc { span: 0..0 };
+    let stack = StackFrame::new_stack();
+    make_throw_interp_error!(src_loc, state.env, stack);
+
+    let function = match state.env.get(name).or_else(|| state.func_table.get(name)) {
+        Some(function) => function.clone(),
+        None => throw_interp_error!(format!("Couldn't find var in environment: {}", name)),
+    };
+    let budget = Budget::new(limits);
+    let context = InterpretContext::new(
+        &state.env,
+        &state.func_table,
+        &state.data_types,
+        &stack,
+        &budget,
+    );
+    check_arity(&function, args.len(), &src_loc, context)?;
+    let mut results = vec![];
+    let (function, args) = cast_arguments(function, args, &src_loc, context, &mut results)?;
+    let value = match function {
+        Val::Lam(params, body, lam_env) => {
+            let mut env = lam_env;
+            env.extend(params.into_iter().zip(args));
+            interpret_expr(&body, context.new_env(&env))?
+        }
+        Val::Native(native) => match native.call(&args) {
+            Ok(value) => value,
+            Err(message) => throw_interp_error!(InterpErrorKind::NativeFailed, message),
+        },
+        _ => throw_interp_error!("Function call with non-function value"),
+    };
+    cast_results(value, &results, context)
 }
 
 /// Find each top-level function declaration in a set of expressions and
@@ -141,17 +305,25 @@ fn find_functions(program: &Program) -> Result<Env, InterpError> {
     for expr in program {
         // Ignore the expression unless it's a function declaration
         match &expr.node {
-            AstNode::FunctionNode(name, params, _, body) => {
-                // Insert a lambda into the environment under the function's name
+            AstNode::FunctionNode(name, _, params, return_type, body) => {
+                // Insert a lambda into the environment under the function's name, checking
+                // calls against its annotations
+                let function = Val::Lam(
+                    params
+                        .iter()
+                        .map(|param| param.id.clone())
+                        .collect::<Vec<String>>(),
+                    Rc::new(*body.clone()),
+                    HashMap::new(),
+                );
                 env.insert(
                     name.clone(),
-                    Val::Lam(
-                        params
-                            .iter()
-                            .map(|param| param.id.clone())
-                            .collect::<Vec<String>>(),
-                        *body.clone(),
-                        HashMap::new(),
+                    cast_by_annotations(
+                        function,
+                        &format!("`{}`", name),
+                        params,
+                        return_type,
+                        &expr.src_loc,
                     ),
                 );
             }
@@ -172,7 +344,7 @@ pub fn find_data_declarations(program: &Program) -> Result<Program, InterpError>
         // Ignore the expression unless it's a data declaration
         match &expr {
             Ast {
-                node: AstNode::DataDeclarationNode(name, variants),
+                node: AstNode::DataDeclarationNode(name, type_params, variants),
                 src_loc: SrcLoc { span },
                 ..
             } => {
@@ -180,7 +352,11 @@ pub fn find_data_declarations(program: &Program) -> Result<Program, InterpError>
                 for (variant_name, variant_fields) in variants {
                     // Add a function body for the variant
                     let body = AstNode::DataLiteralNode(
-                        Discriminant::new(name, &variant_name),
+                        Discriminant::new(
+                            name,
+                            &variant_name,
+                            variant_fields.iter().map(|id| id.id.clone()).collect(),
+                        ),
                         variant_fields
                             .iter()
                             .map(|id| {
@@ -195,8 +371,15 @@ pub fn find_data_declarations(program: &Program) -> Result<Program, InterpError>
                     let func = Ast::new(
                         AstNode::FunctionNode(
                             variant_name.clone(),
+                            type_params.clone(),
                             variant_fields.iter().cloned().collect(),
-                            Some(Type::new(name.clone(), vector![])),
+                            Some(Type::new(
+                                name.clone(),
+                                type_params
+                                    .iter()
+                                    .map(|param| Type::new_unit(param.clone()))
+                                    .collect(),
+                            )),
                             Box::new(Ast::new(body, SrcLoc { span: span.clone() })),
                         ),
                         SrcLoc { span: span.clone() },
@@ -219,141 +402,581 @@ enum ValOrEnv {
 
 /// Interprets a top-level expression from a Skiff program. Result is either a value (for simple expression)
 /// or a binding (for let expressions)
-fn interpret_top_level(expr: &Ast, env: Env, func_table: &Env) -> Result<ValOrEnv, InterpError> {
+fn interpret_top_level(
+    expr: &Ast,
+    env: Env,
+    func_table: &Env,
+    data_types: &DataTypes,
+    budget: &Budget,
+) -> Result<ValOrEnv, InterpError> {
+    let stack = StackFrame::new_stack();
+    let context = InterpretContext::new(&env, func_table, data_types, &stack, budget);
     match &expr.node {
         // Add the let binding to the environment and return
         AstNode::LetNodeTopLevel(id, binding) => {
-            let val = interpret_expr(
-                binding.borrow(),
-                InterpretContext::new(&env, func_table, &StackFrame::new_stack()),
-            )?;
+            let val = interpret_expr(binding.borrow(), context)?;
+            let val = cast_binding(id, val, &expr.src_loc, context)?;
             Ok(ValOrEnv::E(env.update(id.id.clone(), val)))
         }
-        AstNode::LetNode(_, _, _) => Err(InterpError(
+        AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+            let bindings = interpret_let_pattern(pattern, binding, &expr.src_loc, context)?;
+            Ok(ValOrEnv::E(bindings.union(env)))
+        }
+        AstNode::LetNode(_, _, _) | AstNode::LetPatternNode(_, _, _) => Err(InterpError(
             "Found LetNode instead of LetNodeToplevel on top level".to_string(),
             expr.src_loc.span.clone(),
-            env,
-            StackFrame::new_stack(),
+            env.clone(),
+            stack,
+            InterpErrorKind::Invalid,
         )),
-        AstNode::FunctionNode(_, _, _, _) => Ok(ValOrEnv::E(env)),
-        AstNode::DataDeclarationNode(_, _) => Ok(ValOrEnv::E(env)),
+        AstNode::FunctionNode(_, _, _, _, _) => Ok(ValOrEnv::E(env)),
+        AstNode::DataDeclarationNode(_, _, _) => Ok(ValOrEnv::E(env)),
+        AstNode::ImportNode(_, _) => Ok(ValOrEnv::E(env)),
         // Any other expression should be interpreted as a value
-        _ => Ok(ValOrEnv::V(interpret_expr(
-            expr,
-            InterpretContext::new(&env, func_table, &StackFrame::new_stack()),
-        )?)),
+        _ => Ok(ValOrEnv::V(interpret_expr(expr, context)?)),
     }
 }
 
-/// Interprets a Skiff expression to produce either a value or an error
+/// Interprets a Skiff expression to produce either a value or an error. Results of calls to
+/// functions with casts are checked once the value is known, so that calls in tail position
+/// don't have to wait for them.
 fn interpret_expr(expr: &Ast, context: InterpretContext) -> Result<Val, InterpError> {
+    if let Err(message) = context.budget.check_stack(context.stack.len() - 1) {
+        let InterpretContext { env, stack, .. } = context;
+        make_throw_interp_error!(expr.src_loc, env, stack);
+        throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+    }
+    let mut results = vec![];
+    let value = interpret_tail_position(expr, context, &mut results)?;
+    cast_results(value, &results, context)
+}
+
+/// Interprets a Skiff expression, adding the casts whose results have to be checked to
+/// `results`. Expressions in tail position (the body of a let, the branches of an if or
+/// match, and the body of a called function) are evaluated by looping rather than recursing,
+/// so tail calls don't grow the native stack.
+fn interpret_tail_position(
+    expr: &Ast,
+    context: InterpretContext,
+    results: &mut Vec<Rc<FunctionCast>>,
+) -> Result<Val, InterpError> {
+    let func_table = context.func_table;
+    let data_types = context.data_types;
+    let budget = context.budget;
+    let mut env = context.env.clone();
+    let mut stack = context.stack.clone();
+    let mut expr = expr;
+    // Owns the body of the function being evaluated once a call has been made
+    let mut body: Rc<Ast>;
+    // Whether this loop has pushed a stack frame that calls in tail position can reuse
+    let mut in_call = false;
+
+    loop {
+        make_throw_interp_error!(expr.src_loc, env, stack);
+        let context = InterpretContext::new(&env, func_table, data_types, &stack, budget);
+
+        if let Err(message) = budget.step() {
+            throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+        }
+
+        match &expr.node {
+            AstNode::NumberNode(n) => return Ok(Val::Num(n.clone())),
+            AstNode::BigNumberNode(n) => return Ok(Val::BigNum(n.clone())),
+            AstNode::FloatNode(x, _) => return Ok(Val::Float(*x)),
+            AstNode::BoolNode(v) => return Ok(Val::Bool(v.clone())),
+            AstNode::StringNode(v) => return Ok(Val::Str(v.clone())),
+            // Variable nodes are looked up in the environment and then in the function table
+            AstNode::VarNode(id) => match env.get(id) {
+                Some(v) => return Ok(v.clone()),
+                None => match func_table.get(id) {
+                    Some(v) => return Ok(v.clone()),
+                    None => {
+                        throw_interp_error!(format!("Couldn't find var in environment: {}", id))
+                    }
+                },
+            },
+            // Add the let binding to the environment and then interpret the body
+            AstNode::LetNode(id, binding, let_body) => {
+                let val = interpret_expr(binding, context)?;
+                let val = cast_binding(id, val, &expr.src_loc, context)?;
+                env = env.update(id.id.clone(), val);
+                expr = let_body;
+            }
+            AstNode::LetPatternNode(pattern, binding, let_body) => {
+                // Bindings from the pattern shadow any existing variables
+                env = interpret_let_pattern(pattern, binding, &expr.src_loc, context)?.union(env);
+                expr = let_body;
+            }
+            AstNode::LetNodeTopLevel(_, _) | AstNode::LetPatternNodeTopLevel(_, _) => {
+                throw_interp_error!(
+                    "Found LetNodeTopLevel instead of LetNode in expression".to_string()
+                )
+            }
+            AstNode::BinOpNode(op, e1, e2) => {
+                return interpret_binop(*op, e1, e2, expr.src_loc.clone(), context)
+            }
+            AstNode::LambdaNode(params, lam_body) => {
+                if let Err(message) = budget.allocate_value(0) {
+                    throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+                }
+                let function = Val::Lam(
+                    params.iter().map(|id| id.id.clone()).collect(),
+                    Rc::new(*lam_body.clone()),
+                    env.clone(),
+                );
+                return Ok(cast_by_annotations(
+                    function,
+                    "a lambda",
+                    params,
+                    &None,
+                    &expr.src_loc,
+                ));
+            }
+            AstNode::FunCallNode(fun, args) => {
+                let (fun_value, values) =
+                    interpret_call(fun, args, &expr.src_loc, context, results)?;
+                match fun_value {
+                    Val::Lam(params, lam_body, lam_env) => {
+                        // Create a new environment with the function's parameters bound to the arguments
+                        let new_env: Env = params.into_iter().zip(values).collect();
+
+                        // Make the new frame. A call in tail position replaces the frame of the
+                        // function it's returning from, keeping count of the frames it replaced.
+                        let mut new_frame = StackFrame::new(expr.src_loc.clone(), new_env.clone());
+                        if in_call {
+                            if let Some(replaced) = stack.pop_back() {
+                                new_frame.tail_calls = replaced.tail_calls + 1;
+                            }
+                        }
+                        stack.push_back(new_frame);
+                        in_call = true;
+                        if let Err(message) = budget.check_call_depth(stack.len() - 1) {
+                            throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+                        }
+
+                        // Make the new environment
+                        let mut lam_env = lam_env;
+                        lam_env.extend(new_env);
+                        env = lam_env;
+
+                        // evaluate the body
+                        body = lam_body;
+                        expr = &*body;
+                    }
+                    Val::Native(native) => {
+                        return call_native(&native, &values, &expr.src_loc, context)
+                    }
+                    _ => throw_interp_error!("Function call with non-function value".to_string()),
+                }
+            }
+            AstNode::IfNode(conditions_and_bodies, alternate) => {
+                // Loop through conditions in order and see if any match. If none match, then
+                // evaluate the alternate.
+                let mut branch: &Ast = alternate;
+                for (condition, if_body) in conditions_and_bodies {
+                    match interpret_expr(condition, context)? {
+                        Val::Bool(true) => {
+                            branch = if_body;
+                            break;
+                        }
+                        Val::Bool(false) => continue,
+                        _ => {
+                            throw_interp_error!("Conditional expression with non-boolean condition")
+                        }
+                    }
+                }
+                expr = branch;
+            }
+            AstNode::FunctionNode(_, _, _, _, _) => {
+                throw_interp_error!("Function node not at top level")
+            }
+            // Merged into one arm to keep this function's stack frame small
+            AstNode::DataDeclarationNode(_, _, _)
+            | AstNode::ImportNode(_, _)
+            | AstNode::ErrorNode(_, _) => throw_interp_error!(match &expr.node {
+                AstNode::ErrorNode(message, _) => message.as_str(),
+                _ => "Found a top-level declaration in expression",
+            }),
+            AstNode::DataLiteralNode(discriminant, fields) => {
+                // Create a data value with the proper discriminant
+                let mut values = vec![];
+                for expr in fields {
+                    values.push(interpret_expr(expr, context)?);
+                }
+                if let Err(message) = budget.allocate_value(values.len()) {
+                    throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+                }
+                return Ok(Val::Data(discriminant.clone(), Rc::new(values)));
+            }
+            AstNode::ListNode(items, rest) => {
+                return interpret_list(items, rest.as_deref(), &expr.src_loc, context)
+            }
+            AstNode::TupleNode(items) => return interpret_tuple(items, &expr.src_loc, context),
+            AstNode::RecordNode(_)
+            | AstNode::RecordUpdateNode(_, _)
+            | AstNode::FieldAccessNode(_, _) => return interpret_record(expr, context),
+            AstNode::MatchNode(expression_to_match, branches) => {
+                // Find the first branch that matches, then evaluate it with the bindings from
+                // its pattern
+                let val = interpret_expr(expression_to_match, context)?;
+                match find_match_branch(branches, &val, &expr.src_loc, context)? {
+                    Some((match_env, branch)) => {
+                        // Bindings from the pattern shadow any existing variables
+                        env = match_env.union(env);
+                        expr = branch;
+                    }
+                    None => throw_interp_error!(
+                        InterpErrorKind::NoMatch,
+                        "No branch of match expression matched value"
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Calls a native function with the values of a call's arguments
+fn call_native(
+    native: &NativeFunction,
+    values: &[Val],
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Val, InterpError> {
+    let InterpretContext { env, stack, .. } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    match native.call(values) {
+        Ok(value) => Ok(value),
+        Err(message) => throw_interp_error!(InterpErrorKind::NativeFailed, message),
+    }
+}
+
+/// Evaluates the function and arguments of a call, returning the function underneath any
+/// casts around it along with the arguments' values (see `cast_arguments`)
+fn interpret_call(
+    fun: &Ast,
+    args: &[Ast],
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+    results: &mut Vec<Rc<FunctionCast>>,
+) -> Result<(Val, Vec<Val>), InterpError> {
+    let function = interpret_expr(fun, context)?;
+    // The arg count is checked before the args are evaluated
+    check_arity(&function, args.len(), src_loc, context)?;
+    let mut values = vec![];
+    for arg in args {
+        values.push(interpret_expr(arg, context)?);
+    }
+    cast_arguments(function, values, src_loc, context, results)
+}
+
+/// Ensures that a value is a function that takes the given number of arguments
+fn check_arity(
+    function: &Val,
+    arg_count: usize,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<(), InterpError> {
+    let InterpretContext { env, stack, .. } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    let arity = match function {
+        Val::Lam(params, _, _) => params.len(),
+        Val::Native(native) => native.arity(),
+        Val::Cast(function, _) => return check_arity(function, arg_count, src_loc, context),
+        _ => throw_interp_error!("Function call with non-function value"),
+    };
+    if arity != arg_count {
+        throw_interp_error!(format!(
+            "Function takes {} arguments but {} were provided",
+            arity, arg_count
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the arguments of a call against the parameter types of the casts around a function,
+/// from the outermost cast in. An argument of the wrong type is blamed on the call. The casts
+/// whose results have to be checked are added to `results`, unless an identical cast is
+/// already there (e.g. from an earlier call in tail position). Returns the function underneath
+/// the casts along with the arguments to call it with.
+fn cast_arguments(
+    function: Val,
+    values: Vec<Val>,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+    results: &mut Vec<Rc<FunctionCast>>,
+) -> Result<(Val, Vec<Val>), InterpError> {
     let InterpretContext {
         env,
-        func_table,
         stack,
+        data_types,
+        ..
     } = context;
+    make_throw_interp_error!(src_loc, env, stack);
 
-    make_throw_interp_error!(expr.src_loc, env, stack);
+    let mut function = function;
+    let mut values = values;
+    while let Val::Cast(inner, function_cast) = function {
+        for (i, (value, t)) in values.iter_mut().zip(&function_cast.params).enumerate() {
+            let t = match t {
+                Some(t) => t,
+                None => continue,
+            };
+            let name = || casts::argument_name(&function_cast, i);
+            match casts::cast(value.clone(), t, data_types, &name, src_loc) {
+                Some(checked) => *value = checked,
+                None => throw_interp_error!(
+                    InterpErrorKind::CastFailed,
+                    casts::argument_error(&function_cast, i, t, value)
+                ),
+            }
+        }
+        if function_cast.return_type.is_some() && !results.contains(&function_cast) {
+            results.push(function_cast);
+        }
+        function = (*inner).clone();
+    }
+    Ok((function, values))
+}
 
-    match &expr.node {
-        AstNode::NumberNode(n) => Ok(Val::Num(n.clone())),
-        AstNode::BoolNode(v) => Ok(Val::Bool(v.clone())),
-        // Variable nodes are looked up in the environment and then in the function table
-        AstNode::VarNode(id) => match env.get(id) {
-            Some(v) => Ok(v.clone()),
-            None => match func_table.get(id) {
-                Some(v) => Ok(v.clone()),
-                None => throw_interp_error!(format!("Couldn't find var in environment: {}", id)),
-            },
-        },
-        // Add the let binding to the environment and then interpret the body
-        AstNode::LetNode(id, binding, body) => interpret_expr(
-            body,
-            context.new_env(&env.update(id.id.clone(), interpret_expr(binding, context)?)),
+/// Checks the result of a call against the return types of casts, from the innermost cast
+/// (the last one) out. A result of the wrong type is blamed on where the cast came from.
+fn cast_results(
+    value: Val,
+    results: &[Rc<FunctionCast>],
+    context: InterpretContext,
+) -> Result<Val, InterpError> {
+    let mut value = value;
+    for function_cast in results.iter().rev() {
+        let InterpretContext { env, stack, .. } = context;
+        make_throw_interp_error!(function_cast.src_loc, env, stack);
+        let t = match &function_cast.return_type {
+            Some(t) => t,
+            None => continue,
+        };
+        let name = || casts::result_name(function_cast);
+        value = match casts::cast(
+            value.clone(),
+            t,
+            context.data_types,
+            &name,
+            &function_cast.src_loc,
+        ) {
+            Some(checked) => checked,
+            None => throw_interp_error!(
+                InterpErrorKind::CastFailed,
+                casts::result_error(function_cast, t, &value)
+            ),
+        };
+    }
+    Ok(value)
+}
+
+/// Checks the value of a `let` binding against the type its identifier is annotated with
+fn cast_binding(
+    id: &Identifier,
+    value: Val,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Val, InterpError> {
+    let InterpretContext {
+        env,
+        stack,
+        data_types,
+        ..
+    } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    let t = match casts::annotation(&id.type_decl) {
+        Some(t) => t,
+        None => return Ok(value),
+    };
+    let name = || format!("`{}`", id.id);
+    match casts::cast(value.clone(), &t, data_types, &name, &id.src_loc) {
+        Some(checked) => Ok(checked),
+        None => throw_interp_error!(
+            InterpErrorKind::CastFailed,
+            casts::binding_error(id, &t, &value)
         ),
-        AstNode::LetNodeTopLevel(_, _) => {
-            throw_interp_error!("Found LetNodeTopLevel instead of LetNode in expression".to_string())
+    }
+}
+
+/// Creates a list from the values of its items, putting them in front of the rest of the list
+fn interpret_list(
+    items: &[Ast],
+    rest: Option<&Ast>,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Val, InterpError> {
+    let InterpretContext {
+        env, stack, budget, ..
+    } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    let mut values = Vector::new();
+    for item in items {
+        values.push_back(interpret_expr(item, context)?);
+    }
+    if let Some(rest) = rest {
+        match interpret_expr(rest, context)? {
+            Val::List(rest) => values.append(rest),
+            value => throw_interp_error!(format!(
+                "Can only put items in front of a list, not {}",
+                value
+            )),
         }
-        AstNode::BinOpNode(op, e1, e2) => {
-            interpret_binop(*op, e1, e2, expr.src_loc.clone(), context)
+    }
+    // The items of the rest are shared rather than copied
+    if let Err(message) = budget.allocate_value(items.len()) {
+        throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+    }
+    Ok(Val::List(values))
+}
+
+fn interpret_tuple(
+    items: &[Ast],
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Val, InterpError> {
+    let InterpretContext {
+        env, stack, budget, ..
+    } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    let mut values = vec![];
+    for item in items {
+        values.push(interpret_expr(item, context)?);
+    }
+    if let Err(message) = budget.allocate_value(values.len()) {
+        throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+    }
+    Ok(Val::Tuple(Rc::new(values)))
+}
+
+/// Evaluates the binding of a let with a pattern, returning the variables that the pattern
+/// binds. It's an error if the value doesn't match the pattern.
+/// Finds the first branch of a match whose pattern matches a value and whose guard (if it has
+/// one) is true, returning the bindings from its pattern along with its body
+fn find_match_branch<'a>(
+    branches: &'a [(Pattern, Option<Ast>, Ast, Range<usize>)],
+    val: &Val,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Option<(Env, &'a Ast)>, InterpError> {
+    let InterpretContext { env, stack, .. } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    for (pattern, guard, branch, _) in branches {
+        let match_env = match match_pattern_with_value(pattern, val) {
+            Some(match_env) => match_env,
+            None => continue,
+        };
+        let guard = match guard {
+            Some(guard) => guard,
+            None => return Ok(Some((match_env, branch))),
+        };
+        // The guard can use the bindings from the pattern
+        let guard_env = match_env.clone().union(env.clone());
+        match interpret_expr(guard, context.new_env(&guard_env))? {
+            Val::Bool(true) => return Ok(Some((match_env, branch))),
+            Val::Bool(false) => (),
+            _ => throw_interp_error!("Conditional expression with non-boolean condition"),
         }
-        AstNode::LambdaNode(params, body) => Ok(Val::Lam(
-            params.iter().map(|id| id.id.clone()).collect(),
-            *body.clone(),
-            env.clone(),
-        )),
-        AstNode::FunCallNode(fun, args) => {
-            // First, ensure that the value is a function
-            let fun_value = interpret_expr(fun, context)?;
-            match fun_value {
-                Val::Lam(params, body, lam_env) => {
-                    // Ensure the arg count of the function definition matches the arg count of the call
-                    if params.len() != args.len() {
-                        throw_interp_error!(format!(
-                            "Function takes {} arguments but {} were provided",
-                            params.len(),
-                            args.len()
-                        ));
-                    }
-                    // Create a new environment with the function's parameters bound to the arguments
-                    let mut new_env: Env = HashMap::new();
-                    for (param, arg) in params.iter().zip(args) {
-                        new_env.insert(param.to_string(), interpret_expr(arg, context)?);
-                    }
+    }
+    Ok(None)
+}
 
-                    // Make the new stack and frame
-                    let new_frame = StackFrame::new(expr.src_loc.clone(), new_env.clone());
-                    let mut new_stack = stack.clone();
-                    new_stack.push_back(new_frame);
+fn interpret_let_pattern(
+    pattern: &Pattern,
+    binding: &Ast,
+    src_loc: &SrcLoc,
+    context: InterpretContext,
+) -> Result<Env, InterpError> {
+    let InterpretContext { env, stack, .. } = context;
+    make_throw_interp_error!(src_loc, env, stack);
+
+    let value = interpret_expr(binding, context)?;
+    match match_pattern_with_value(pattern, &value) {
+        Some(bindings) => Ok(bindings),
+        None => throw_interp_error!(
+            InterpErrorKind::NoMatch,
+            format!("Pattern of let statement didn't match value {}", value)
+        ),
+    }
+}
 
-                    // Make the new environment
-                    let mut lam_env = lam_env.clone();
-                    lam_env.extend(new_env);
+/// Interprets a record literal, a record update or a field access. Updates and field accesses
+/// work on the named fields of data values as well as records.
+fn interpret_record(expr: &Ast, context: InterpretContext) -> Result<Val, InterpError> {
+    let InterpretContext {
+        env, stack, budget, ..
+    } = context;
+    make_throw_interp_error!(expr.src_loc, env, stack);
 
-                    // evaluate the body
-                    interpret_expr(
-                        &body,
-                        InterpretContext::new(&lam_env, func_table, &new_stack),
-                    )
-                }
-                _ => throw_interp_error!("Function call with non-function value".to_string()),
+    match &expr.node {
+        AstNode::RecordNode(fields) => {
+            let mut values = OrdMap::new();
+            for (field, value) in fields {
+                values.insert(field.clone(), interpret_expr(value, context)?);
+            }
+            if let Err(message) = budget.allocate_value(values.len()) {
+                throw_interp_error!(InterpErrorKind::LimitExceeded, message)
             }
+            Ok(Val::Record(values))
         }
-        AstNode::IfNode(conditions_and_bodies, alternate) => {
-            // Loop through conditions in order and see if any match
-            for (condition, body) in conditions_and_bodies {
-                match interpret_expr(condition, context)? {
-                    Val::Bool(true) => return interpret_expr(body, context),
-                    Val::Bool(false) => continue,
-                    _ => throw_interp_error!("Conditional expression with non-boolean condition"),
+        AstNode::RecordUpdateNode(record, fields) => {
+            let mut record = interpret_expr(record, context)?;
+            let mut updates = vec![];
+            for (field, value) in fields {
+                updates.push((field, interpret_expr(value, context)?));
+            }
+            for (field, value) in updates {
+                let updated = match &mut record {
+                    Val::Record(values) if values.contains_key(field) => {
+                        values.insert(field.clone(), value);
+                        true
+                    }
+                    Val::Data(discriminant, values) => match discriminant.field_index(field) {
+                        Some(i) => {
+                            Rc::make_mut(values)[i] = value;
+                            true
+                        }
+                        None => false,
+                    },
+                    Val::Record(_) => false,
+                    _ => throw_interp_error!(format!(
+                        "Can only update the fields of a record or data value, not {}",
+                        record
+                    )),
+                };
+                if !updated {
+                    throw_interp_error!(format!("{} has no field {}", record, field))
                 }
             }
-            // If no conditions match, then evaluate the alternate
-            return interpret_expr(alternate, context);
-        }
-        AstNode::FunctionNode(_, _, _, _) => throw_interp_error!("Function node not at top level"),
-        AstNode::DataDeclarationNode(_, _) => {
-            throw_interp_error!("Found DataDeclarationNode instead of LetNode in expression")
-        }
-        AstNode::DataLiteralNode(discriminant, fields) => {
-            // Create a data value with the proper discriminant
-            let mut values = vec![];
-            for expr in fields {
-                values.push(interpret_expr(expr, context)?);
+            if let Err(message) = budget.allocate_value(fields.len()) {
+                throw_interp_error!(InterpErrorKind::LimitExceeded, message)
             }
-            return Ok(Val::Data(discriminant.clone(), values));
+            Ok(record)
         }
-        AstNode::MatchNode(expression_to_match, branches) => {
-            // Loop through each pattern and see if it matches. If it does, then evaluate
-            // the body with the bindings from the pattern
-            for (pattern, expr) in branches {
-                let val = interpret_expr(expression_to_match, context)?;
-                if let Some(match_env) = match_pattern_with_value(pattern, &val) {
-                    return interpret_expr(expr, context.new_env(&env.clone().union(match_env)));
+        AstNode::FieldAccessNode(value, field) => {
+            let value = interpret_expr(value, context)?;
+            let field_value = match &value {
+                Val::Record(values) => values.get(field).cloned(),
+                Val::Data(discriminant, values) => {
+                    discriminant.field_index(field).map(|i| values[i].clone())
                 }
+                _ => throw_interp_error!(format!(
+                    "Can only get the fields of a record or data value, not {}",
+                    value
+                )),
+            };
+            match field_value {
+                Some(field_value) => Ok(field_value),
+                None => throw_interp_error!(format!("{} has no field {}", value, field)),
             }
-            throw_interp_error!("No branch of match expression matched value")
         }
+        _ => throw_interp_error!("Expected a record expression"),
     }
 }
 
@@ -375,13 +998,23 @@ fn match_pattern_with_value(pattern: &Pattern, value: &Val) -> Option<Env> {
                 None
             }
         }
-        Pattern::Identifier(s) => {
-            if s == "_" {
+        Pattern::StringLiteral(s) => {
+            if *value == Val::Str(s.clone()) {
                 Some(HashMap::new())
             } else {
-                Some(HashMap::unit(s.clone(), value.clone()))
+                None
             }
         }
+        Pattern::Identifier(s) => Some(HashMap::unit(s.clone(), value.clone())),
+        Pattern::Wildcard => Some(HashMap::new()),
+        // The first alternative that matches provides the bindings
+        Pattern::Or(alternatives) => alternatives
+            .iter()
+            .find_map(|alternative| match_pattern_with_value(alternative, value)),
+        Pattern::As(pattern, name) => Some(
+            match_pattern_with_value(pattern, value)?
+                .union(HashMap::unit(name.clone(), value.clone())),
+        ),
         Pattern::Data(pattern_discriminant, patterns) => match value {
             Val::Data(value_discriminant, values) => {
                 // The pattern matches if the discriminants match and the pattern has the
@@ -389,7 +1022,7 @@ fn match_pattern_with_value(pattern: &Pattern, value: &Val) -> Option<Env> {
                 if pattern_discriminant == value_discriminant.get_variant() {
                     if patterns.len() == values.len() {
                         let mut env = HashMap::new();
-                        for (pattern, value) in patterns.into_iter().zip(values) {
+                        for (pattern, value) in patterns.into_iter().zip(values.iter()) {
                             env = env.union(match_pattern_with_value(pattern, value)?);
                         }
                         Some(env)
@@ -402,6 +1035,49 @@ fn match_pattern_with_value(pattern: &Pattern, value: &Val) -> Option<Env> {
             }
             _ => None,
         },
+        Pattern::List(patterns, rest) => match value {
+            Val::List(values) => {
+                // Without a rest pattern, the list must have exactly as many items as the
+                // pattern
+                let fits = match rest {
+                    Some(_) => values.len() >= patterns.len(),
+                    None => values.len() == patterns.len(),
+                };
+                if !fits {
+                    return None;
+                }
+                let mut env = HashMap::new();
+                for (pattern, value) in patterns.iter().zip(values.iter()) {
+                    env = env.union(match_pattern_with_value(pattern, value)?);
+                }
+                if let Some(rest) = rest {
+                    let rest_value = Val::List(values.skip(patterns.len()));
+                    env = env.union(match_pattern_with_value(rest, &rest_value)?);
+                }
+                Some(env)
+            }
+            _ => None,
+        },
+        Pattern::Record(patterns) => match value {
+            Val::Record(values) => {
+                let mut env = HashMap::new();
+                for (field, pattern) in patterns {
+                    env = env.union(match_pattern_with_value(pattern, values.get(field)?)?);
+                }
+                Some(env)
+            }
+            _ => None,
+        },
+        Pattern::Tuple(patterns) => match value {
+            Val::Tuple(values) if values.len() == patterns.len() => {
+                let mut env = HashMap::new();
+                for (pattern, value) in patterns.iter().zip(values.iter()) {
+                    env = env.union(match_pattern_with_value(pattern, value)?);
+                }
+                Some(env)
+            }
+            _ => None,
+        },
     }
 }
 
@@ -414,18 +1090,21 @@ macro_rules! interpret_binop {
                 $src_loc.span,
                 $env.clone(),
                 $stack.clone(),
+                InterpErrorKind::Invalid,
             )),
             (e, Val::$type2(_)) => Err(InterpError(
                 format!("Bad first op to {}: {}", stringify!($op), e).to_string(),
                 $src_loc.span,
                 $env.clone(),
                 $stack.clone(),
+                InterpErrorKind::Invalid,
             )),
             (e1, e2) => Err(InterpError(
                 format!("Bad ops to {}: {}\n{}", stringify!($op), e1, e2).to_string(),
                 $src_loc.span,
                 $env.clone(),
                 $stack.clone(),
+                InterpErrorKind::Invalid,
             )),
         }
     };
@@ -439,34 +1118,52 @@ fn interpret_binop(
     src_loc: SrcLoc,
     context: InterpretContext,
 ) -> Result<Val, InterpError> {
-    let InterpretContext { env, stack, .. } = context;
+    let InterpretContext {
+        env, stack, budget, ..
+    } = context;
 
     make_throw_interp_error!(src_loc, env, stack);
 
     let v1 = interpret_expr(e1, context)?;
     let v2 = interpret_expr(e2, context)?;
 
+    // Strings can be concatenated and compared, so handle them before the numeric cases
+    match (op, &v1, &v2) {
+        (BinOp::Plus, Val::Str(s1), Val::Str(s2)) => {
+            if let Err(message) = budget.allocate(s1.len() + s2.len()) {
+                throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+            }
+            return Ok(Val::Str(format!("{}{}", s1, s2)));
+        }
+        (BinOp::Gt, Val::Str(s1), Val::Str(s2)) => return Ok(Val::Bool(s1 > s2)),
+        (BinOp::Lt, Val::Str(s1), Val::Str(s2)) => return Ok(Val::Bool(s1 < s2)),
+        (BinOp::GtEq, Val::Str(s1), Val::Str(s2)) => return Ok(Val::Bool(s1 >= s2)),
+        (BinOp::LtEq, Val::Str(s1), Val::Str(s2)) => return Ok(Val::Bool(s1 <= s2)),
+        _ => (),
+    }
+
     match op {
-        BinOp::Plus => interpret_binop!(v1, v2, src_loc, +, Num, Num, Num, env, stack),
-        BinOp::Minus => interpret_binop!(v1, v2, src_loc, -, Num, Num, Num, env, stack),
-        BinOp::Times => interpret_binop!(v1, v2, src_loc, *, Num, Num, Num, env, stack),
-        BinOp::Divide => interpret_binop!(v1, v2, src_loc, /, Num, Num, Num, env, stack),
-        BinOp::Modulo => interpret_binop!(v1, v2, src_loc, %, Num, Num, Num, env, stack),
-        BinOp::Exp => match (v1, v2) {
-            (Val::Num(xv), Val::Num(yv)) => Ok(Val::Num(xv.pow(yv.try_into().unwrap()))),
-            (Val::Num(_), e) => throw_interp_error!(format!("Bad second op to {}: {}", "**", e)),
-            (e, Val::Num(_)) => throw_interp_error!(format!("Bad first op to {}: {}", "**", e)),
-            (e1, e2) => throw_interp_error!(format!("Bad ops to {}: {}\n{}", "**", e1, e2)),
-        },
         BinOp::Eq => Ok(Val::Bool(v1 == v2)),
-        BinOp::Gt => interpret_binop!(v1, v2, src_loc, >, Num, Num, Bool, env, stack),
-        BinOp::Lt => interpret_binop!(v1, v2, src_loc, <, Num, Num, Bool, env, stack),
-        BinOp::GtEq => interpret_binop!(v1, v2, src_loc, >=, Num, Num, Bool, env, stack),
-        BinOp::LtEq => interpret_binop!(v1, v2, src_loc, <=, Num, Num, Bool, env, stack),
         BinOp::LAnd => interpret_binop!(v1, v2, src_loc, &&, Bool, Bool, Bool, env, stack),
         BinOp::LOr => interpret_binop!(v1, v2, src_loc, ||, Bool, Bool, Bool, env, stack),
-        BinOp::BitAnd => interpret_binop!(v1, v2, src_loc, &, Num, Num, Num, env, stack),
-        BinOp::BitOr => interpret_binop!(v1, v2, src_loc, |, Num, Num, Num, env, stack),
-        BinOp::BitXor => interpret_binop!(v1, v2, src_loc, ^, Num, Num, Num, env, stack),
+        _ => match (v1.to_number(), v2.to_number()) {
+            (Some(x), Some(y)) => match op {
+                BinOp::Gt | BinOp::Lt | BinOp::GtEq | BinOp::LtEq => {
+                    Ok(Val::Bool(numeric::comparison(op, &x, &y)))
+                }
+                _ => match numeric::arithmetic(op, &x, &y) {
+                    Ok(number) => match budget.allocate(number.size_in_bytes()) {
+                        Ok(()) => Ok(number.into()),
+                        Err(message) => {
+                            throw_interp_error!(InterpErrorKind::LimitExceeded, message)
+                        }
+                    },
+                    Err((kind, message)) => throw_interp_error!(kind, message),
+                },
+            },
+            (Some(_), None) => throw_interp_error!(format!("Bad second op to {}: {}", op, v2)),
+            (None, Some(_)) => throw_interp_error!(format!("Bad first op to {}: {}", op, v1)),
+            (None, None) => throw_interp_error!(format!("Bad ops to {}: {}\n{}", op, v1, v2)),
+        },
     }
 }
@@ -0,0 +1,117 @@
+use crate::ast::Val;
+use std::cell::Cell;
+use std::mem::size_of;
+use structopt::StructOpt;
+
+// Limits on the resources a program can use while it runs. Limits that aren't set are
+// unlimited. (This isn't a doc comment because it would replace the CLI's description in
+// `skiff --help`, where the struct is flattened.)
+#[derive(PartialEq, Debug, Clone, Default, StructOpt)]
+pub struct EvalLimits {
+    /// The maximum depth of nested function calls. Calls in tail position don't add to the
+    /// depth
+    #[structopt(long = "max-call-depth")]
+    pub max_call_depth: Option<usize>,
+
+    /// The maximum number of evaluation steps (expressions evaluated by the tree-walking
+    /// interpreter or instructions executed by the bytecode VM)
+    #[structopt(long = "max-steps")]
+    pub max_steps: Option<u64>,
+
+    /// The approximate number of bytes the program can allocate for strings, data values and
+    /// closures
+    #[structopt(long = "max-memory")]
+    pub max_memory: Option<usize>,
+
+    /// The bytes of native stack that are free when a program starts running, measured from
+    /// where its `Budget` is made. The tree-walking interpreter raises an error for calls
+    /// nested too deeply to fit, rather than overflow the stack. Set this when running on a
+    /// thread bigger than `DEFAULT_STACK_SIZE`, or the extra space goes unused.
+    #[structopt(skip)]
+    pub stack_size: Option<usize>,
+}
+
+/// The stack size that's assumed when `EvalLimits::stack_size` isn't set: the size of the
+/// threads that Rust spawns. The VM doesn't use the native stack for calls, so only the
+/// tree-walking interpreter is bounded by it
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// The stack space that's kept free for the frames the interpreter uses between the checks it
+/// makes
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// Tracks the resources used by a running program against its limits. Each check returns
+/// the message of the error to raise if the limit has been exceeded.
+#[derive(Debug)]
+pub struct Budget {
+    limits: EvalLimits,
+    steps: Cell<u64>,
+    memory: Cell<usize>,
+    /// The address of the stack when the budget was made, which the stack's use is measured from
+    stack_start: usize,
+}
+impl Budget {
+    pub fn new(limits: &EvalLimits) -> Self {
+        Budget {
+            limits: limits.clone(),
+            steps: Cell::new(0),
+            memory: Cell::new(0),
+            stack_start: stack_address(),
+        }
+    }
+
+    /// Checks the depth of the call stack after a call has been made
+    pub fn check_call_depth(&self, depth: usize) -> Result<(), String> {
+        match self.limits.max_call_depth {
+            Some(max) if depth > max => Err(format!("Maximum call depth of {} exceeded", max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that there's enough stack left for another level of nested evaluation, where
+    /// `depth` is the depth of the call stack
+    pub fn check_stack(&self, depth: usize) -> Result<(), String> {
+        let stack_size = self.limits.stack_size.unwrap_or(DEFAULT_STACK_SIZE);
+        let used = self.stack_start.abs_diff(stack_address());
+        match used.saturating_add(STACK_RED_ZONE) > stack_size {
+            true => Err(format!(
+                "Maximum call depth exceeded: ran out of stack space after {} nested calls",
+                depth
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Counts one evaluation step
+    pub fn step(&self) -> Result<(), String> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        match self.limits.max_steps {
+            Some(max) if steps > max => Err(format!("Evaluation step limit of {} exceeded", max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts an allocation of the given number of bytes
+    pub fn allocate(&self, bytes: usize) -> Result<(), String> {
+        let memory = self.memory.get().saturating_add(bytes);
+        self.memory.set(memory);
+        match self.limits.max_memory {
+            Some(max) if memory > max => Err(format!("Memory limit of {} bytes exceeded", max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts the allocation of a data value with the given number of fields, or a closure
+    /// (which has no fields)
+    pub fn allocate_value(&self, fields: usize) -> Result<(), String> {
+        self.allocate(size_of::<Val>() * (fields + 1))
+    }
+}
+
+/// The address of a local variable, which is on the native stack
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
@@ -0,0 +1,373 @@
+use super::bigint::BigInt;
+use super::interpret::InterpErrorKind;
+use crate::ast::{BinOp, Val};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+/// A number in a running program. Integers and floats are both `Number`s to the type checker,
+/// so they can be mixed freely, but they're kept apart at runtime so that integer arithmetic
+/// stays exact. Integers that don't fit in an `i64` are big integers, and a result that fits
+/// is always an `Int`. The interpreter and the VM both do their arithmetic through this type.
+#[derive(Debug, Clone)]
+pub enum Number {
+    Int(i64),
+    Big(Rc<BigInt>),
+    Float(f64),
+}
+
+impl Number {
+    fn to_float(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Big(n) => n.to_f64(),
+            Number::Float(x) => *x,
+        }
+    }
+
+    /// The number of bytes a big integer takes up, for counting towards the memory limit
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            Number::Big(n) => n.size_in_bytes(),
+            _ => 0,
+        }
+    }
+
+    /// The number as a big integer, or `None` if it's a float
+    fn to_big(&self) -> Option<Rc<BigInt>> {
+        match self {
+            Number::Int(n) => Some(Rc::new(BigInt::from(*n))),
+            Number::Big(n) => Some(n.clone()),
+            Number::Float(_) => None,
+        }
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Self {
+        match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(Rc::new(n)),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::Big(n) => write!(f, "{}", n),
+            Number::Float(x) => write!(f, "{}", format_float(*x)),
+        }
+    }
+}
+
+impl Val {
+    /// Returns the number this value holds, if it's a number
+    pub fn to_number(&self) -> Option<Number> {
+        match self {
+            Val::Num(n) => Some(Number::Int(*n)),
+            Val::BigNum(n) => Some(Number::Big(n.clone())),
+            Val::Float(x) => Some(Number::Float(*x)),
+            _ => None,
+        }
+    }
+}
+
+impl From<Number> for Val {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(n) => Val::Num(n),
+            Number::Big(n) => Val::BigNum(n),
+            Number::Float(x) => Val::Float(x),
+        }
+    }
+}
+
+/// Writes a float so that it can't be mistaken for an integer: `1.0`, `0.1`, `1e100`, `1e-7`.
+/// Floats use the shortest digits that read back as the same float, and an exponent once they
+/// are at least 1e16 or smaller than 1e-4. The special values are `NaN`, `inf` and `-inf`.
+pub fn format_float(x: f64) -> String {
+    format!("{:?}", x)
+}
+
+/// The most bits the result of `**` can have. Other operators can only grow a number a little
+/// at a time, so the evaluation limits catch runaway growth, but one `**` could take all the
+/// memory and time there is.
+const MAX_POWER_BITS: u64 = 1 << 16;
+
+/// Applies an arithmetic (`+ - * / % **`) or bitwise (`& | ^`) operator. Two integers give an
+/// integer, where `/` rounds towards zero; the exception is a negative power, which gives a
+/// float. If either operand is a float, the other is converted and the result is a float.
+/// Returns the kind of error and its message for an integer division by zero, a power that's
+/// too big, or a bitwise operator applied to a float or a big integer.
+pub fn arithmetic(op: BinOp, x: &Number, y: &Number) -> Result<Number, ArithmeticError> {
+    match (x, y) {
+        (Number::Int(xv), Number::Int(yv)) => match small_arithmetic(op, *xv, *yv)? {
+            Some(n) => Ok(Number::Int(n)),
+            // The result doesn't fit in an i64
+            None => big_arithmetic(op, &BigInt::from(*xv), &BigInt::from(*yv)),
+        },
+        (Number::Float(_), _) | (_, Number::Float(_)) => {
+            let (xv, yv) = (x.to_float(), y.to_float());
+            Ok(Number::Float(match op {
+                BinOp::Plus => xv + yv,
+                BinOp::Minus => xv - yv,
+                BinOp::Times => xv * yv,
+                BinOp::Divide => xv / yv,
+                BinOp::Modulo => xv % yv,
+                BinOp::Exp => xv.powf(yv),
+                _ => return Err(invalid(format!("Bad ops to {}: {}\n{}", op, x, y))),
+            }))
+        }
+        _ => match (x.to_big(), y.to_big()) {
+            (Some(xv), Some(yv)) => big_arithmetic(op, &xv, &yv),
+            _ => Err(invalid(format!("Bad ops to {}: {}\n{}", op, x, y))),
+        },
+    }
+}
+
+/// The kind of error an operator ran into and its message
+type ArithmeticError = (InterpErrorKind, String);
+
+fn invalid(message: String) -> ArithmeticError {
+    (InterpErrorKind::Invalid, message)
+}
+
+fn division_by_zero(op: BinOp) -> ArithmeticError {
+    let message = match op {
+        BinOp::Modulo => "Modulo by zero",
+        _ => "Division by zero",
+    };
+    (InterpErrorKind::DivisionByZero, message.to_string())
+}
+
+/// Applies an operator to two integers, returning `None` if the result doesn't fit in an i64
+fn small_arithmetic(op: BinOp, x: i64, y: i64) -> Result<Option<i64>, ArithmeticError> {
+    Ok(match op {
+        BinOp::Plus => x.checked_add(y),
+        BinOp::Minus => x.checked_sub(y),
+        BinOp::Times => x.checked_mul(y),
+        BinOp::Divide if y == 0 => return Err(division_by_zero(op)),
+        BinOp::Divide => x.checked_div(y),
+        BinOp::Modulo if y == 0 => return Err(division_by_zero(op)),
+        BinOp::Modulo => x.checked_rem(y),
+        // Negative exponents are handled with the big integers
+        BinOp::Exp => u32::try_from(y).ok().and_then(|y| x.checked_pow(y)),
+        BinOp::BitAnd => Some(x & y),
+        BinOp::BitOr => Some(x | y),
+        BinOp::BitXor => Some(x ^ y),
+        _ => return Err(invalid(format!("{} isn't an arithmetic operator", op))),
+    })
+}
+
+fn big_arithmetic(op: BinOp, x: &BigInt, y: &BigInt) -> Result<Number, ArithmeticError> {
+    let result = match op {
+        BinOp::Plus => x + y,
+        BinOp::Minus => x - y,
+        BinOp::Times => x * y,
+        BinOp::Divide => x.div_rem(y).ok_or_else(|| division_by_zero(op))?.0,
+        BinOp::Modulo => x.div_rem(y).ok_or_else(|| division_by_zero(op))?.1,
+        BinOp::Exp => return power(x, y),
+        BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
+            return Err(invalid(format!(
+                "{} only works on integers that fit in 64 bits, not {} and {}",
+                op, x, y
+            )))
+        }
+        _ => return Err(invalid(format!("{} isn't an arithmetic operator", op))),
+    };
+    Ok(Number::from(result))
+}
+
+fn power(x: &BigInt, y: &BigInt) -> Result<Number, ArithmeticError> {
+    if y.is_negative() {
+        return Ok(Number::Float(x.to_f64().powf(y.to_f64())));
+    }
+    if y.is_zero() {
+        return Ok(Number::Int(1));
+    }
+    // Powers of 0, 1 and -1 stay small however big the exponent is
+    match x.to_i64() {
+        Some(n @ 0) | Some(n @ 1) => return Ok(Number::Int(n)),
+        Some(-1) => return Ok(Number::Int(if y.is_even() { 1 } else { -1 })),
+        _ => (),
+    }
+    // Otherwise the result has at least (bits of x - 1) * y bits
+    match y.to_i64().and_then(|y| u32::try_from(y).ok()) {
+        Some(exponent) if (x.bits() - 1).saturating_mul(exponent as u64) <= MAX_POWER_BITS => {
+            Ok(Number::from(x.pow(exponent)))
+        }
+        _ => Err((
+            InterpErrorKind::LimitExceeded,
+            format!(
+                "The result of {} ** {} is too big (the limit is {} bits)",
+                x, y, MAX_POWER_BITS
+            ),
+        )),
+    }
+}
+
+/// Compares two numbers by their exact values, so `1` equals `1.0` and big integers aren't
+/// rounded to the nearest float first. Returns `None` if either number is NaN.
+pub fn compare(x: &Number, y: &Number) -> Option<Ordering> {
+    match (x, y) {
+        (Number::Int(xv), Number::Int(yv)) => Some(xv.cmp(yv)),
+        (Number::Float(xv), Number::Float(yv)) => xv.partial_cmp(yv),
+        (_, Number::Float(yv)) => compare_integer_with_float(x, *yv),
+        (Number::Float(xv), _) => compare_integer_with_float(y, *xv).map(Ordering::reverse),
+        _ => Some(x.to_big().cmp(&y.to_big())),
+    }
+}
+
+fn compare_integer_with_float(n: &Number, x: f64) -> Option<Ordering> {
+    if x.is_nan() {
+        None
+    } else if x.is_infinite() {
+        Some(if x > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        })
+    } else {
+        // A finite float's integer part converts exactly
+        let floor = x.floor();
+        match n.to_big().cmp(&BigInt::from_f64(floor).map(Rc::new)) {
+            Ordering::Equal if x > floor => Some(Ordering::Less),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+/// Applies a comparison operator (`== > < >= <=`). Any comparison involving NaN is false,
+/// including `==` between a NaN and itself.
+pub fn comparison(op: BinOp, x: &Number, y: &Number) -> bool {
+    let ordering = compare(x, y);
+    match op {
+        BinOp::Gt => ordering == Some(Ordering::Greater),
+        BinOp::Lt => ordering == Some(Ordering::Less),
+        BinOp::GtEq => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        BinOp::LtEq => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        _ => ordering == Some(Ordering::Equal),
+    }
+}
+
+/// Hashes a number consistently with `compare`, for values that are used as keys
+pub fn hash_key(number: &Number) -> u64 {
+    let x = number.to_float();
+    // 0.0 and -0.0 are equal, so they need the same hash
+    if x == 0.0 {
+        0
+    } else {
+        x.to_bits()
+    }
+}
+
+#[cfg(test)]
+mod numeric_tests {
+    use super::*;
+
+    #[test]
+    fn mixes_integers_and_floats() {
+        let (int, float) = (Number::Int(3), Number::Float(0.5));
+        assert!(matches!(arithmetic(BinOp::Plus, &int, &float), Ok(Number::Float(x)) if x == 3.5));
+        assert!(matches!(
+            arithmetic(BinOp::Divide, &int, &Number::Int(2)),
+            Ok(Number::Int(1))
+        ));
+        assert!(
+            matches!(arithmetic(BinOp::Exp, &Number::Int(2), &Number::Int(-2)), Ok(Number::Float(x)) if x == 0.25)
+        );
+        assert!(arithmetic(BinOp::BitAnd, &int, &float).is_err());
+    }
+
+    #[test]
+    fn promotes_to_big_integers() {
+        let max = Number::Int(i64::MAX);
+        let sum = arithmetic(BinOp::Plus, &max, &Number::Int(1)).unwrap();
+        assert_eq!(sum.to_string(), "9223372036854775808");
+        // Results that fit go back to being small
+        let difference = arithmetic(BinOp::Minus, &sum, &Number::Int(1)).unwrap();
+        assert!(matches!(difference, Number::Int(i64::MAX)));
+        let quotient = arithmetic(BinOp::Divide, &Number::Int(i64::MIN), &Number::Int(-1));
+        assert_eq!(quotient.unwrap().to_string(), "9223372036854775808");
+        let power = arithmetic(BinOp::Exp, &Number::Int(2), &Number::Int(100)).unwrap();
+        assert_eq!(power.to_string(), "1267650600228229401496703205376");
+    }
+
+    #[test]
+    fn reports_errors_instead_of_panicking() {
+        let (one, zero) = (Number::Int(1), Number::Int(0));
+        assert_eq!(
+            arithmetic(BinOp::Divide, &one, &zero).unwrap_err(),
+            (
+                InterpErrorKind::DivisionByZero,
+                "Division by zero".to_string()
+            )
+        );
+        assert_eq!(
+            arithmetic(BinOp::Modulo, &one, &zero).unwrap_err(),
+            (
+                InterpErrorKind::DivisionByZero,
+                "Modulo by zero".to_string()
+            )
+        );
+        let huge = Number::Int(i64::MAX);
+        assert!(arithmetic(BinOp::Exp, &Number::Int(10), &huge).is_err());
+        assert!(matches!(
+            arithmetic(BinOp::Exp, &Number::Int(-1), &huge),
+            Ok(Number::Int(-1))
+        ));
+        assert!(matches!(
+            arithmetic(BinOp::Divide, &one, &Number::Float(0.0)),
+            Ok(Number::Float(x)) if x == f64::INFINITY
+        ));
+    }
+
+    #[test]
+    fn compares_exactly() {
+        let compare_numbers = |x, y| compare(&x, &y);
+        assert_eq!(
+            compare_numbers(Number::Int(1), Number::Float(1.0)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare_numbers(Number::Float(1.5), Number::Int(1)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_numbers(Number::Int(-2), Number::Float(-1.5)),
+            Some(Ordering::Less)
+        );
+        // 2^53 + 1 isn't a float, so it's bigger than the float it would round to
+        let big = 9_007_199_254_740_993;
+        assert_eq!(
+            compare_numbers(Number::Int(big), Number::Float(big as f64)),
+            Some(Ordering::Greater)
+        );
+        let huge = Number::from(BigInt::from(i64::MAX).pow(3));
+        assert_eq!(
+            compare_numbers(huge.clone(), Number::Int(i64::MAX)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_numbers(huge, Number::Float(f64::INFINITY)),
+            Some(Ordering::Less)
+        );
+        assert!(!comparison(
+            BinOp::Eq,
+            &Number::Float(f64::NAN),
+            &Number::Float(f64::NAN)
+        ));
+    }
+
+    #[test]
+    fn formats_floats() {
+        assert_eq!(format_float(1.0), "1.0");
+        assert_eq!(format_float(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_float(1e100), "1e100");
+        assert_eq!(format_float(0.00001), "1e-5");
+        assert_eq!(format_float(-f64::INFINITY), "-inf");
+    }
+}
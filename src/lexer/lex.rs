@@ -1,10 +1,56 @@
+use crate::interpreter::bigint::BigInt;
 use logos::{Lexer, Logos};
+use std::ops::Range;
 
+/// Strips the quotes from a string literal and replaces its escape sequences.
+/// Unknown escape sequences make the token an error.
 fn string_token(lex: &mut Lexer<Token>) -> Option<String> {
     let slice = lex.slice();
-    Some(slice[1..slice.len() - 1].into())
+    let mut out = String::new();
+    let mut chars = slice[1..slice.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '0' => out.push('\0'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Extends an identifier with the `::name` segments that qualify it with the module it comes
+/// from (e.g. `lists::map`). Logos can't backtrack out of a partial `::`, so the segments are
+/// matched by hand rather than in the identifier's regex.
+/// A float's value along with the literal as it's written, which the formatter keeps
+fn float_token(lex: &mut Lexer<Token>) -> Option<(f64, String)> {
+    let text = lex.slice();
+    Some((text.parse().ok()?, text.to_string()))
+}
+
+fn identifier_token(lex: &mut Lexer<Token>) -> String {
+    loop {
+        let remainder = lex.remainder();
+        let segment = match remainder.strip_prefix("::") {
+            Some(rest) if rest.starts_with(|c: char| c.is_ascii_alphabetic()) => rest,
+            _ => break,
+        };
+        let len = segment
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(segment.len());
+        lex.bump(len + 2);
+    }
+    lex.slice().to_string()
 }
-#[derive(Logos, Debug, Clone, PartialEq, Hash)]
+
+#[derive(Logos, Debug, Clone, PartialEq)]
 pub enum Token {
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
@@ -21,14 +67,22 @@ pub enum Token {
     LParen,
     #[token(")")]
     RParen,
+    #[token("[")]
+    LSquareBracket,
+    #[token("]")]
+    RSquareBracket,
     #[token(".")]
     Dot,
+    #[token("...")]
+    Ellipsis,
     #[token(",")]
     Comma,
     #[token(":")]
     Colon,
     #[token("|")]
     Pipe,
+    #[token("_")]
+    Underscore,
     #[token("=>")]
     FatArrow,
     #[token("->")]
@@ -51,6 +105,14 @@ pub enum Token {
     Lambda,
     #[token("def")]
     Def,
+    #[token("import")]
+    Import,
+    #[token("as")]
+    As,
+    #[token("from")]
+    From,
+    #[token("with")]
+    With,
     #[token("-")]
     Minus,
     #[token("+")]
@@ -85,16 +147,46 @@ pub enum Token {
     BitXor,
     #[regex("[0-9]+", |lex| lex.slice().parse())]
     Number(i64),
-    #[regex("[a-zA-Z][a-zA-Z0-9_]*", |lex| lex.slice().parse())]
+    // Literals of at least 19 digits, which might not fit in an i64. Logos doesn't support
+    // `{19,}`, so the digits are spelled out.
+    #[regex("[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]+", |lex| lex.slice().parse())]
+    BigNumber(BigInt),
+    /// (val, the literal as written)
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", float_token)]
+    #[regex(r"[0-9]+[eE][+-]?[0-9]+", float_token)]
+    Float((f64, String)),
+    #[regex("[a-zA-Z][a-zA-Z0-9_]*", identifier_token)]
     Identifier(String),
     #[token("true", |_| true)]
     #[token("false", |_| false)]
     Bool(bool),
 
-    #[token(r#""[^"]*""#, string_token)]
+    #[regex(r#""([^"\\]|\\.)*""#, string_token)]
     String(String),
 }
 
+/// Finds the spans of the `#` comments in a program, which the lexer skips. Comments can only
+/// be in the gaps between tokens (a `#` in a string is part of the string's token), so each
+/// gap is searched for them.
+pub fn comments(source: &str) -> Vec<Range<usize>> {
+    let mut comments = vec![];
+    let mut gap_start = 0;
+    let token_spans = Token::lexer(source).spanned().map(|(_, span)| span);
+    for span in token_spans.chain(std::iter::once(source.len()..source.len())) {
+        let mut i = gap_start;
+        while let Some(offset) = source[i..span.start].find('#') {
+            let start = i + offset;
+            let end = source[start..span.start]
+                .find('\n')
+                .map_or(span.start, |len| start + len);
+            comments.push(start..end);
+            i = end;
+        }
+        gap_start = span.end;
+    }
+    comments
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,11 +218,121 @@ mod tests {
         assert_eq!(lex.slice(), "3");
     }
 
+    #[test]
+    fn lexes_floats() {
+        let mut lex = Token::lexer("1.5 2e3 2.5E-1 3.x");
+
+        assert_eq!(lex.next(), Some(Token::Float((1.5, "1.5".to_string()))));
+        assert_eq!(lex.next(), Some(Token::Float((2000.0, "2e3".to_string()))));
+        assert_eq!(lex.next(), Some(Token::Float((0.25, "2.5E-1".to_string()))));
+        assert_eq!(lex.next(), Some(Token::Number(3)));
+        assert_eq!(lex.next(), Some(Token::Dot));
+        assert_eq!(lex.next(), Some(Token::Identifier("x".to_string())));
+    }
+
+    #[test]
+    fn lexes_list_syntax() {
+        let mut lex = Token::lexer("[1, ...rest]");
+
+        assert_eq!(lex.next(), Some(Token::LSquareBracket));
+        assert_eq!(lex.next(), Some(Token::Number(1)));
+        assert_eq!(lex.next(), Some(Token::Comma));
+        assert_eq!(lex.next(), Some(Token::Ellipsis));
+        assert_eq!(lex.next(), Some(Token::Identifier("rest".to_string())));
+        assert_eq!(lex.next(), Some(Token::RSquareBracket));
+    }
+
+    #[test]
+    fn lexes_record_syntax() {
+        let mut lex = Token::lexer("{p with x: 1}.x");
+
+        assert_eq!(lex.next(), Some(Token::LBracket));
+        assert_eq!(lex.next(), Some(Token::Identifier("p".to_string())));
+        assert_eq!(lex.next(), Some(Token::With));
+        assert_eq!(lex.next(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(lex.next(), Some(Token::Colon));
+        assert_eq!(lex.next(), Some(Token::Number(1)));
+        assert_eq!(lex.next(), Some(Token::RBracket));
+        assert_eq!(lex.next(), Some(Token::Dot));
+        assert_eq!(lex.next(), Some(Token::Identifier("x".to_string())));
+    }
+
+    #[test]
+    fn lexes_big_numbers() {
+        let mut lex = Token::lexer("123456789012345678 1234567890123456789012");
+
+        assert_eq!(lex.next(), Some(Token::Number(123456789012345678)));
+        assert_eq!(
+            lex.next(),
+            Some(Token::BigNumber("1234567890123456789012".parse().unwrap()))
+        );
+    }
+
     #[test]
     fn lexes_identifiers() {
         let mut lex = Token::lexer("x");
 
         assert_eq!(lex.next(), Some(Token::Identifier("x".to_string())));
         assert_eq!(lex.slice(), "x");
+
+        // An underscore on its own is a wildcard rather than an identifier
+        let mut lex = Token::lexer("_ a_b");
+        assert_eq!(lex.next(), Some(Token::Underscore));
+        assert_eq!(lex.next(), Some(Token::Identifier("a_b".to_string())));
+    }
+
+    #[test]
+    fn lexes_qualified_identifiers() {
+        let mut lex = Token::lexer("import \"lists.boat\" as lists\nlists::map if x: y");
+
+        assert_eq!(lex.next(), Some(Token::Import));
+        assert_eq!(lex.next(), Some(Token::String("lists.boat".to_string())));
+        assert_eq!(lex.next(), Some(Token::As));
+        assert_eq!(lex.next(), Some(Token::Identifier("lists".to_string())));
+        assert_eq!(
+            lex.next(),
+            Some(Token::Identifier("lists::map".to_string()))
+        );
+        assert_eq!(lex.next(), Some(Token::If));
+        assert_eq!(lex.next(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(lex.next(), Some(Token::Colon));
+    }
+
+    #[test]
+    fn lexes_strings() {
+        let mut lex = Token::lexer(r#""hello" + "world""#);
+
+        assert_eq!(lex.next(), Some(Token::String("hello".to_string())));
+        assert_eq!(lex.slice(), "\"hello\"");
+
+        assert_eq!(lex.next(), Some(Token::Plus));
+
+        assert_eq!(lex.next(), Some(Token::String("world".to_string())));
+        assert_eq!(lex.slice(), "\"world\"");
+    }
+
+    #[test]
+    fn lexes_string_escapes() {
+        let mut lex = Token::lexer(r#""a\"b\\c\nd""#);
+
+        assert_eq!(lex.next(), Some(Token::String("a\"b\\c\nd".to_string())));
+    }
+
+    #[test]
+    fn errors_on_unknown_string_escape() {
+        let mut lex = Token::lexer(r#""\q""#);
+
+        assert_eq!(lex.next(), Some(Token::Error));
+    }
+
+    #[test]
+    fn finds_comments() {
+        let source = "# first\nlet x = \"# not a comment\" # second\nx # last";
+        let comments: Vec<_> = comments(source)
+            .into_iter()
+            .map(|span| &source[span])
+            .collect();
+
+        assert_eq!(comments, vec!["# first", "# second", "# last"]);
     }
 }
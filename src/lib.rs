@@ -1,8 +1,13 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod analysis;
 pub mod ast;
+pub mod engine;
 pub mod error_handling;
+pub mod formatter;
+pub mod modules;
+pub mod prelude;
 pub mod runtime;
 pub mod lexer {
     pub mod lex;
@@ -29,7 +34,17 @@ pub mod type_inferencer {
 }
 
 pub mod interpreter {
+    pub mod bigint;
+    pub mod casts;
     pub mod interpret;
+    pub mod limits;
+    pub mod numeric;
+}
+
+pub mod bytecode {
+    pub mod compiler;
+    pub mod instruction;
+    pub mod vm;
 }
 
 pub mod wasm {
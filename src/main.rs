@@ -1,9 +1,16 @@
-use skiff::runtime::{evaluate, CliArgs};
+use skiff::error_handling::ErrorFormat;
+use skiff::formatter::format;
+use skiff::interpreter::limits::EvalLimits;
+use skiff::runtime::{CliArgs, Session};
 use std::error;
 use std::fmt::Write;
+use std::process;
 use std::{fmt, fs};
 use structopt::StructOpt;
 
+#[cfg(feature = "repl")]
+mod repl;
+
 /// The interpreter for the Skiff programming language
 #[derive(Debug, StructOpt)]
 struct Cli {
@@ -19,9 +26,54 @@ struct Cli {
     #[structopt(short = "t", long = "type-check")]
     stop_after_types: bool,
 
-    /// The path to the file to interpret
+    /// Run the program with the bytecode VM instead of the tree-walking interpreter
+    #[structopt(short = "b", long = "bytecode")]
+    use_bytecode: bool,
+
+    #[structopt(flatten)]
+    limits: EvalLimits,
+
+    /// How to print errors and warnings: `human` or `json` (one object per line)
+    #[structopt(long = "error-format", default_value = "human")]
+    error_format: ErrorFormat,
+
+    /// The path to the file to interpret. Start an interactive session if omitted or `repl`
     #[structopt(parse(from_os_str))]
-    path: std::path::PathBuf,
+    path: Option<std::path::PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Format Skiff files in place
+    Fmt {
+        /// List the files that aren't formatted instead of changing them, and fail if there
+        /// are any
+        #[structopt(long)]
+        check: bool,
+
+        /// The files to format
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<std::path::PathBuf>,
+    },
+}
+impl Cli {
+    fn into_cli_args(self, path: std::path::PathBuf) -> CliArgs {
+        CliArgs {
+            stop_after_lexing: self.stop_after_lexing,
+            stop_after_parsing: self.stop_after_parsing,
+            stop_after_types: self.stop_after_types,
+            use_bytecode: self.use_bytecode,
+            limits: EvalLimits {
+                stack_size: Some(STACK_SIZE),
+                ..self.limits
+            },
+            error_format: self.error_format,
+            path,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -33,7 +85,7 @@ impl<'a> fmt::Display for SkiffError<'a> {
 }
 impl<'a> error::Error for SkiffError<'a> {}
 
-struct ConsolePrinter;
+pub struct ConsolePrinter;
 impl Write for ConsolePrinter {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         print!("{}", s);
@@ -41,18 +93,85 @@ impl Write for ConsolePrinter {
     }
 }
 
-fn main() -> Result<(), Box<dyn error::Error>> {
-    let args = CliArgs::from_args();
+/// The size of the stack that programs run on. Deeply nested calls need a lot of stack on the
+/// tree-walking interpreter, and the stack's memory isn't used until they need it.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() {
+    // Run on a thread whose stack is big and of a known size, which the tree-walking
+    // interpreter keeps nested calls within. Errors are reported like `main` would report them.
+    let thread = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| match run() {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                1
+            }
+        })
+        .expect("Couldn't start the interpreter thread");
+    process::exit(thread.join().unwrap_or(101));
+}
+
+fn run() -> Result<(), Box<dyn error::Error>> {
+    let cli = Cli::from_args();
+
+    if let Some(Command::Fmt { check, paths }) = cli.command {
+        return format_files(check, paths);
+    }
+
+    let path = match cli.path.clone() {
+        Some(path) if path != std::path::Path::new("repl") => path,
+        #[cfg(feature = "repl")]
+        _ => {
+            let args = cli.into_cli_args(std::path::PathBuf::from("<repl>"));
+            return repl::run(args);
+        }
+        #[cfg(not(feature = "repl"))]
+        _ => {
+            return Err("This build of skiff doesn't have a REPL, so pass it a file to run".into())
+        }
+    };
+    let args = cli.into_cli_args(path);
 
     let raw = fs::read_to_string(args.path.clone()).expect("Something went wrong reading the file");
 
-    let output = evaluate(args, raw, &mut ConsolePrinter)?;
+    // Printed lines are shown as they're printed, so they aren't lost if the program never finishes
+    let mut session = Session::new();
+    session.stream_printed(|line| println!("{}", line));
+    let output = session.evaluate(&args, raw, &mut ConsolePrinter)?;
 
     if let Some(output) = output {
-        for val in output {
+        for (val, _) in output {
             println!("{}", val);
         }
     }
 
     Ok(())
 }
+
+/// Formats each file in place, or with `check` lists the files that would change
+fn format_files(check: bool, paths: Vec<std::path::PathBuf>) -> Result<(), Box<dyn error::Error>> {
+    let mut failed = false;
+    for path in paths {
+        let raw = fs::read_to_string(&path)?;
+        match format(&raw) {
+            Ok(formatted) if formatted == raw => (),
+            Ok(_) if check => {
+                println!("{}", path.display());
+                failed = true;
+            }
+            Ok(formatted) => fs::write(&path, formatted)?,
+            Err(e) => {
+                eprintln!("Couldn't format {}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    match failed {
+        true if check => Err(Box::new(SkiffError("Some files aren't formatted"))),
+        true => Err(Box::new(SkiffError("Some files couldn't be formatted"))),
+        false => Ok(()),
+    }
+}
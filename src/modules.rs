@@ -0,0 +1,662 @@
+use crate::ast::{Ast, AstNode, Identifier, ImportNames, Pattern, Program, Type};
+use crate::error_handling::SourceMap;
+use crate::lexer::lex::Token;
+use crate::parser::parse::parse_program;
+use crate::parser::util::ParseError;
+use im::{HashMap, HashSet};
+use logos::Logos;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, ops::Range};
+
+#[derive(PartialEq, Debug, Clone, Hash)]
+pub struct ModuleError(pub String, pub Range<usize>);
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl error::Error for ModuleError {}
+
+/// The names visible in a module, mapped to the names their declarations are known by once
+/// every module has been combined into one program. Values (functions, let bindings and data
+/// constructors) and data types are kept separately.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Scope {
+    values: HashMap<String, String>,
+    types: HashMap<String, String>,
+}
+
+/// A module that has been loaded from a file
+#[derive(PartialEq, Debug, Clone)]
+struct Module {
+    /// The name the module's declarations are qualified with (e.g. `lists` for `lists.boat`)
+    qualifier: String,
+    /// The module's own declarations. Names the module imports aren't exported again.
+    exports: Scope,
+    /// The names visible inside the module: its own declarations and the names it imports
+    scope: Scope,
+}
+
+/// The modules loaded so far and the names in scope in the root program. Persists between
+/// successive programs (e.g. between lines entered into the REPL) so that each file is only
+/// loaded once.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ModuleState {
+    modules: HashMap<PathBuf, Module>,
+    root_scope: Scope,
+}
+impl ModuleState {
+    pub fn new() -> Self {
+        ModuleState::default()
+    }
+
+    /// Renames the constructors in a pattern to the names they're visible as in a file (the
+    /// root program if the file isn't a module), so that a pattern made up by a check reads
+    /// the way the file would write it. Constructors that aren't visible keep their
+    /// qualified names.
+    pub fn pattern_in_scope(&self, pattern: &Pattern, path: &Path) -> Pattern {
+        let scope = match self.modules.get(path) {
+            Some(module) => &module.scope,
+            None => &self.root_scope,
+        };
+        let mut pattern = pattern.clone();
+        rename_constructors(&mut pattern, &|constructor| {
+            scope
+                .values
+                .iter()
+                .filter(|(_, resolved)| *resolved == constructor)
+                .map(|(name, _)| name)
+                // Prefer names without a qualifier, then shorter qualifiers
+                .min_by_key(|name| (name.len(), name.as_str()))
+                .unwrap_or(constructor)
+                .clone()
+        });
+        pattern
+    }
+}
+
+/// Loads the files a program imports (and the files they import) and combines them into one
+/// program. The declarations of each imported module come first, in dependency order, with
+/// their names qualified by the module's name so that modules can't clash. The names used in
+/// every module are then resolved to those qualified names. Each file's source is appended to
+/// `source` and recorded in `source_map` so that errors can name the right file.
+pub fn resolve_imports(
+    program: Program,
+    path: &Path,
+    state: &ModuleState,
+    source: &mut String,
+    source_map: &mut SourceMap,
+) -> Result<(Program, ModuleState), ModuleError> {
+    let mut loader = Loader {
+        state: state.clone(),
+        // The root program can be part of an import cycle if it's a file
+        importing: fs::canonicalize(path).into_iter().collect(),
+        source,
+        source_map,
+        declarations: vec![],
+    };
+
+    let mut scope = loader.state.root_scope.clone();
+    loader.import_all(&program, path, &mut scope)?;
+    // The root program's own declarations keep their names
+    declare(&program, "", &mut scope)?;
+
+    let mut resolved = loader.declarations;
+    resolved.extend(
+        program
+            .into_iter()
+            .filter(|expr| !matches!(expr.node, AstNode::ImportNode(_, _)))
+            .map(|expr| resolve_top_level(expr, &scope)),
+    );
+
+    let mut state = loader.state;
+    state.root_scope = scope;
+    Ok((resolved, state))
+}
+
+struct Loader<'a> {
+    state: ModuleState,
+    /// The files that are being loaded, used to detect import cycles
+    importing: Vec<PathBuf>,
+    source: &'a mut String,
+    source_map: &'a mut SourceMap,
+    /// The declarations from every module loaded so far
+    declarations: Program,
+}
+
+impl<'a> Loader<'a> {
+    /// Loads every module imported by a program and adds the names they export to its scope
+    fn import_all(
+        &mut self,
+        program: &Program,
+        path: &Path,
+        scope: &mut Scope,
+    ) -> Result<(), ModuleError> {
+        // Imports are relative to the importing file
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for expr in program {
+            if let AstNode::ImportNode(import_path, names) = &expr.node {
+                let span = expr.src_loc.span.clone();
+                let module = self.load(&directory.join(import_path), span.clone())?;
+                import(scope, &module, import_path, names, span)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a module unless it's already loaded, returning the names it exports
+    fn load(&mut self, path: &Path, span: Range<usize>) -> Result<Module, ModuleError> {
+        let path = match fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(e) => {
+                let message = format!("Couldn't import {}: {}", path.display(), e);
+                return Err(ModuleError(message, span));
+            }
+        };
+        if let Some(module) = self.state.modules.get(&path) {
+            return Ok(module.clone());
+        }
+        if self.importing.contains(&path) {
+            let cycle = self
+                .importing
+                .iter()
+                .skip_while(|importing| **importing != path)
+                .chain(std::iter::once(&path))
+                .map(|path| path.display().to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ");
+            return Err(ModuleError(format!("Import cycle: {}", cycle), span));
+        }
+
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) => {
+                let message = format!("Couldn't import {}: {}", path.display(), e);
+                return Err(ModuleError(message, span));
+            }
+        };
+        let program = self.parse_file(&path, &raw)?;
+
+        self.importing.push(path.clone());
+        let qualifier = self.qualifier(&path);
+        let mut scope = Scope::default();
+        self.import_all(&program, &path, &mut scope)?;
+        let mut exports = Scope::default();
+        declare(&program, &qualifier, &mut exports)?;
+        // The module's own declarations shadow the names it imports
+        scope.values = exports.values.clone().union(scope.values);
+        scope.types = exports.types.clone().union(scope.types);
+
+        // Only declarations are kept from imported modules
+        self.declarations.extend(
+            program
+                .into_iter()
+                .filter(|expr| {
+                    matches!(
+                        expr.node,
+                        AstNode::FunctionNode(_, _, _, _, _)
+                            | AstNode::DataDeclarationNode(_, _, _)
+                            | AstNode::LetNodeTopLevel(_, _)
+                            | AstNode::LetPatternNodeTopLevel(_, _)
+                    )
+                })
+                .map(|expr| resolve_top_level(expr, &scope)),
+        );
+        self.importing.pop();
+
+        let module = Module {
+            qualifier,
+            exports,
+            scope,
+        };
+        self.state.modules.insert(path, module.clone());
+        Ok(module)
+    }
+
+    /// Lexes and parses an imported file, adding its source to the combined source
+    fn parse_file(&mut self, path: &Path, raw: &str) -> Result<Program, ModuleError> {
+        let offset = self.source.len();
+        self.source.push_str(raw);
+        self.source_map
+            .add_file(path.to_path_buf(), offset..self.source.len());
+
+        let mut tokens = vec![];
+        for (token, span) in Token::lexer(raw).spanned() {
+            let span = (span.start + offset)..(span.end + offset);
+            if token == Token::Error {
+                return Err(ModuleError("Invalid token".to_string(), span));
+            }
+            tokens.push((token, span));
+        }
+        tokens.reverse();
+
+        parse_program(&mut tokens).map_err(|mut errors| {
+            // Only the first syntax error in an imported file is reported
+            let ParseError(message, span, _) = errors.remove(0);
+            // Errors without a location are at the end of the file
+            let end = offset + raw.len();
+            ModuleError(message, span.unwrap_or(end..end))
+        })
+    }
+
+    /// Picks the name to qualify a module's declarations with, based on its file name
+    fn qualifier(&self, path: &Path) -> String {
+        let stem = module_name(path);
+        let taken = |name: &String| {
+            self.state
+                .modules
+                .values()
+                .any(|module| module.qualifier == *name)
+        };
+        let mut qualifier = stem.clone();
+        let mut suffix = 1;
+        while taken(&qualifier) {
+            suffix += 1;
+            qualifier = format!("{}{}", stem, suffix);
+        }
+        qualifier
+    }
+}
+
+/// The name a module is referred to by when it's imported without an alias
+fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Adds the names a module exports to a scope, as selected by an import
+fn import(
+    scope: &mut Scope,
+    module: &Module,
+    import_path: &str,
+    names: &ImportNames,
+    span: Range<usize>,
+) -> Result<(), ModuleError> {
+    let Scope { values, types } = &module.exports;
+    match names {
+        ImportNames::All => {
+            let prefix = module_name(Path::new(import_path));
+            for (name, resolved) in values.iter() {
+                scope.values.insert(name.clone(), resolved.clone());
+                scope
+                    .values
+                    .insert(format!("{}::{}", prefix, name), resolved.clone());
+            }
+            for (name, resolved) in types.iter() {
+                scope.types.insert(name.clone(), resolved.clone());
+                scope
+                    .types
+                    .insert(format!("{}::{}", prefix, name), resolved.clone());
+            }
+        }
+        ImportNames::Alias(alias) => {
+            for (name, resolved) in values.iter() {
+                scope
+                    .values
+                    .insert(format!("{}::{}", alias, name), resolved.clone());
+            }
+            for (name, resolved) in types.iter() {
+                scope
+                    .types
+                    .insert(format!("{}::{}", alias, name), resolved.clone());
+            }
+        }
+        ImportNames::Selected(selected) => {
+            for name in selected {
+                let value = values.get(name);
+                let data_type = types.get(name);
+                if value.is_none() && data_type.is_none() {
+                    let message = format!("{} doesn't declare {}", import_path, name);
+                    return Err(ModuleError(message, span));
+                }
+                if let Some(resolved) = value {
+                    scope.values.insert(name.clone(), resolved.clone());
+                }
+                if let Some(resolved) = data_type {
+                    scope.types.insert(name.clone(), resolved.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds the names a module declares to a scope, qualified with the module's name (the root
+/// program's declarations aren't qualified)
+fn declare(program: &Program, qualifier: &str, scope: &mut Scope) -> Result<(), ModuleError> {
+    let qualify = |name: &str, span: &Range<usize>| {
+        if name.contains("::") {
+            let message = format!("Can't declare a module-qualified name: {}", name);
+            return Err(ModuleError(message, span.clone()));
+        }
+        Ok(match qualifier {
+            "" => name.to_string(),
+            _ => format!("{}::{}", qualifier, name),
+        })
+    };
+    for expr in program {
+        let span = &expr.src_loc.span;
+        match &expr.node {
+            AstNode::FunctionNode(name, _, _, _, _) => {
+                scope.values.insert(name.clone(), qualify(name, span)?);
+            }
+            AstNode::LetNodeTopLevel(id, _) => {
+                scope.values.insert(id.id.clone(), qualify(&id.id, span)?);
+            }
+            AstNode::LetPatternNodeTopLevel(pattern, _) => {
+                for name in pattern.variables() {
+                    scope.values.insert(name.clone(), qualify(&name, span)?);
+                }
+            }
+            AstNode::DataDeclarationNode(name, _, variants) => {
+                scope.types.insert(name.clone(), qualify(name, span)?);
+                for (variant, _) in variants {
+                    scope
+                        .values
+                        .insert(variant.clone(), qualify(variant, span)?);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves the names in a top-level expression or declaration, including the names it
+/// declares
+fn resolve_top_level(mut expr: Ast, scope: &Scope) -> Ast {
+    let declared = |name: &String| scope.values.get(name).unwrap_or(name).clone();
+    expr.node = match expr.node {
+        AstNode::FunctionNode(name, type_params, params, return_type, mut body) => {
+            let type_params_set = type_params.iter().cloned().collect();
+            let locals = params.iter().map(|param| param.id.clone()).collect();
+            resolve_expr(&mut body, scope, &locals);
+            AstNode::FunctionNode(
+                declared(&name),
+                type_params,
+                params
+                    .into_iter()
+                    .map(|param| resolve_identifier(param, scope, &type_params_set))
+                    .collect(),
+                return_type.map(|t| resolve_type(t, scope, &type_params_set)),
+                body,
+            )
+        }
+        AstNode::DataDeclarationNode(name, type_params, variants) => {
+            let type_params_set = type_params.iter().cloned().collect();
+            AstNode::DataDeclarationNode(
+                scope.types.get(&name).unwrap_or(&name).clone(),
+                type_params,
+                variants
+                    .into_iter()
+                    .map(|(variant, fields)| {
+                        let fields = fields
+                            .into_iter()
+                            .map(|field| resolve_identifier(field, scope, &type_params_set))
+                            .collect();
+                        (declared(&variant), fields)
+                    })
+                    .collect(),
+            )
+        }
+        AstNode::LetNodeTopLevel(id, mut binding) => {
+            resolve_expr(&mut binding, scope, &HashSet::new());
+            let mut id = resolve_identifier(id, scope, &HashSet::new());
+            id.id = declared(&id.id);
+            AstNode::LetNodeTopLevel(id, binding)
+        }
+        AstNode::LetPatternNodeTopLevel(mut pattern, mut binding) => {
+            resolve_expr(&mut binding, scope, &HashSet::new());
+            resolve_pattern(&mut pattern, scope, &mut HashSet::new());
+            rename_variables(&mut pattern, &declared);
+            AstNode::LetPatternNodeTopLevel(pattern, binding)
+        }
+        node => {
+            let mut expr = Ast { node, ..expr };
+            resolve_expr(&mut expr, scope, &HashSet::new());
+            return expr;
+        }
+    };
+    expr
+}
+
+/// Resolves the names an expression refers to, except for local variables
+fn resolve_expr(expr: &mut Ast, scope: &Scope, locals: &HashSet<String>) {
+    match &mut expr.node {
+        AstNode::NumberNode(_)
+        | AstNode::BigNumberNode(_)
+        | AstNode::FloatNode(_, _)
+        | AstNode::BoolNode(_)
+        | AstNode::StringNode(_)
+        | AstNode::ImportNode(_, _)
+        | AstNode::ErrorNode(_, _) => (),
+        AstNode::VarNode(id) => {
+            if !locals.contains(id) {
+                if let Some(resolved) = scope.values.get(id) {
+                    *id = resolved.clone();
+                }
+            }
+        }
+        AstNode::LetNode(id, binding, body) => {
+            resolve_expr(binding, scope, locals);
+            *id = resolve_identifier(id.clone(), scope, &HashSet::new());
+            resolve_expr(body, scope, &locals.update(id.id.clone()));
+        }
+        AstNode::LetPatternNode(pattern, binding, body) => {
+            resolve_expr(binding, scope, locals);
+            let mut locals = locals.clone();
+            resolve_pattern(pattern, scope, &mut locals);
+            resolve_expr(body, scope, &locals);
+        }
+        AstNode::LetNodeTopLevel(_, binding) | AstNode::LetPatternNodeTopLevel(_, binding) => {
+            resolve_expr(binding, scope, locals)
+        }
+        AstNode::IfNode(conditions_and_bodies, alternate) => {
+            for (condition, body) in conditions_and_bodies {
+                resolve_expr(condition, scope, locals);
+                resolve_expr(body, scope, locals);
+            }
+            resolve_expr(alternate, scope, locals);
+        }
+        AstNode::BinOpNode(_, e1, e2) => {
+            resolve_expr(e1, scope, locals);
+            resolve_expr(e2, scope, locals);
+        }
+        AstNode::FunCallNode(fun, args) => {
+            resolve_expr(fun, scope, locals);
+            for arg in args {
+                resolve_expr(arg, scope, locals);
+            }
+        }
+        AstNode::LambdaNode(params, body) => {
+            let mut locals = locals.clone();
+            for param in params.iter_mut() {
+                *param = resolve_identifier(param.clone(), scope, &HashSet::new());
+                locals.insert(param.id.clone());
+            }
+            resolve_expr(body, scope, &locals);
+        }
+        AstNode::FunctionNode(_, _, _, _, body) => resolve_expr(body, scope, locals),
+        AstNode::DataDeclarationNode(_, _, _) => (),
+        AstNode::DataLiteralNode(_, fields) => {
+            for field in fields {
+                resolve_expr(field, scope, locals);
+            }
+        }
+        AstNode::ListNode(items, rest) => {
+            for item in items.iter_mut().chain(rest.as_deref_mut()) {
+                resolve_expr(item, scope, locals);
+            }
+        }
+        AstNode::TupleNode(items) => {
+            for item in items {
+                resolve_expr(item, scope, locals);
+            }
+        }
+        AstNode::RecordNode(fields) => {
+            for (_, value) in fields {
+                resolve_expr(value, scope, locals);
+            }
+        }
+        AstNode::RecordUpdateNode(record, fields) => {
+            resolve_expr(record, scope, locals);
+            for (_, value) in fields {
+                resolve_expr(value, scope, locals);
+            }
+        }
+        AstNode::FieldAccessNode(value, _) => resolve_expr(value, scope, locals),
+        AstNode::MatchNode(expression_to_match, branches) => {
+            resolve_expr(expression_to_match, scope, locals);
+            for (pattern, guard, body, _) in branches {
+                let mut locals = locals.clone();
+                resolve_pattern(pattern, scope, &mut locals);
+                if let Some(guard) = guard {
+                    resolve_expr(guard, scope, &locals);
+                }
+                resolve_expr(body, scope, &locals);
+            }
+        }
+    }
+}
+
+/// Resolves the constructors in a pattern, adding the variables it binds to `locals`
+fn resolve_pattern(pattern: &mut Pattern, scope: &Scope, locals: &mut HashSet<String>) {
+    match pattern {
+        Pattern::Data(constructor, patterns) => {
+            if let Some(resolved) = scope.values.get(constructor) {
+                *constructor = resolved.clone();
+            }
+            for pattern in patterns {
+                resolve_pattern(pattern, scope, locals);
+            }
+        }
+        Pattern::List(patterns, rest) => {
+            for pattern in patterns.iter_mut().chain(rest.as_deref_mut()) {
+                resolve_pattern(pattern, scope, locals);
+            }
+        }
+        Pattern::Record(fields) => {
+            for (_, pattern) in fields {
+                resolve_pattern(pattern, scope, locals);
+            }
+        }
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                resolve_pattern(pattern, scope, locals);
+            }
+        }
+        Pattern::Or(alternatives) => {
+            for pattern in alternatives {
+                resolve_pattern(pattern, scope, locals);
+            }
+        }
+        Pattern::As(pattern, name) => {
+            resolve_pattern(pattern, scope, locals);
+            locals.insert(name.clone());
+        }
+        Pattern::Identifier(id) => {
+            locals.insert(id.clone());
+        }
+        Pattern::NumLiteral(_)
+        | Pattern::BoolLiteral(_)
+        | Pattern::StringLiteral(_)
+        | Pattern::Wildcard => (),
+    }
+}
+
+/// Renames the variables a pattern binds, for a top-level let that declares them
+fn rename_variables(pattern: &mut Pattern, rename: &impl Fn(&String) -> String) {
+    match pattern {
+        Pattern::Identifier(id) => *id = rename(id),
+        Pattern::As(pattern, name) => {
+            rename_variables(pattern, rename);
+            *name = rename(name);
+        }
+        Pattern::Data(_, patterns) | Pattern::Tuple(patterns) | Pattern::Or(patterns) => {
+            for pattern in patterns {
+                rename_variables(pattern, rename);
+            }
+        }
+        Pattern::List(patterns, rest) => {
+            for pattern in patterns.iter_mut().chain(rest.as_deref_mut()) {
+                rename_variables(pattern, rename);
+            }
+        }
+        Pattern::Record(fields) => {
+            for (_, pattern) in fields {
+                rename_variables(pattern, rename);
+            }
+        }
+        Pattern::NumLiteral(_)
+        | Pattern::BoolLiteral(_)
+        | Pattern::StringLiteral(_)
+        | Pattern::Wildcard => (),
+    }
+}
+
+/// Renames the data constructors in a pattern
+fn rename_constructors(pattern: &mut Pattern, rename: &impl Fn(&String) -> String) {
+    match pattern {
+        Pattern::Data(constructor, patterns) => {
+            *constructor = rename(constructor);
+            for pattern in patterns {
+                rename_constructors(pattern, rename);
+            }
+        }
+        Pattern::Tuple(patterns) | Pattern::Or(patterns) => {
+            for pattern in patterns {
+                rename_constructors(pattern, rename);
+            }
+        }
+        Pattern::List(patterns, rest) => {
+            for pattern in patterns.iter_mut().chain(rest.as_deref_mut()) {
+                rename_constructors(pattern, rename);
+            }
+        }
+        Pattern::Record(fields) => {
+            for (_, pattern) in fields {
+                rename_constructors(pattern, rename);
+            }
+        }
+        Pattern::As(pattern, _) => rename_constructors(pattern, rename),
+        Pattern::Identifier(_)
+        | Pattern::NumLiteral(_)
+        | Pattern::BoolLiteral(_)
+        | Pattern::StringLiteral(_)
+        | Pattern::Wildcard => (),
+    }
+}
+
+fn resolve_identifier(
+    mut id: Identifier,
+    scope: &Scope,
+    type_params: &HashSet<String>,
+) -> Identifier {
+    id.type_decl = id.type_decl.map(|t| resolve_type(t, scope, type_params));
+    id
+}
+
+/// Resolves the data types in a type annotation, except for type parameters
+fn resolve_type(t: Type, scope: &Scope, type_params: &HashSet<String>) -> Type {
+    match t {
+        Type::Constructor(id, args) => {
+            let id = match scope.types.get(&id) {
+                Some(resolved) if !type_params.contains(&id) => resolved.clone(),
+                _ => id,
+            };
+            Type::new(
+                id,
+                args.into_iter()
+                    .map(|arg| resolve_type(arg, scope, type_params))
+                    .collect(),
+            )
+        }
+        Type::Record(fields) => Type::Record(
+            fields
+                .into_iter()
+                .map(|(field, t)| (field, resolve_type(t, scope, type_params)))
+                .collect(),
+        ),
+    }
+}
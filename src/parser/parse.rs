@@ -1,9 +1,9 @@
 use std::borrow::Borrow;
 
-use crate::ast::{Ast, BinOp, Identifier, Program};
+use crate::ast::{Ast, AstNode, BinOp, Identifier, Program, SrcLoc};
 use crate::lexer::lex::Token;
 use crate::parser::parselets::*;
-use crate::parser::util::ParseError;
+use crate::parser::util::{ParseError, ParseErrorKind};
 
 use super::types::parse::parse_type;
 
@@ -19,23 +19,29 @@ pub fn get_binding_power(op: &Token) -> i64 {
         Token::Plus | Token::Minus => 80,
         Token::Times | Token::Divide | Token::Modulo => 90,
         Token::Exp => 100,
-        Token::LParen => 110,
+        Token::LParen | Token::Dot => 110,
         _ => panic!("Tried to get binding power of non-op token {:?}", op),
     }
 }
 
 fn prefix_map(tok: &Token) -> Option<Box<dyn PrefixParselet>> {
     match *tok {
-        Token::Number(_) => Some(Box::new(NumberParselet {})),
+        Token::Number(_) | Token::BigNumber(_) | Token::Float(_) => {
+            Some(Box::new(NumberParselet {}))
+        }
         Token::Bool(_) => Some(Box::new(BoolParselet {})),
+        Token::String(_) => Some(Box::new(StringParselet {})),
         Token::Identifier(_) => Some(Box::new(IdentifierParselet {})),
         Token::LParen => Some(Box::new(ParenthesisParselet {})),
+        Token::LSquareBracket => Some(Box::new(ListParselet {})),
+        Token::LBracket => Some(Box::new(RecordParselet {})),
         Token::Lambda => Some(Box::new(LambdaParselet {})),
         Token::If => Some(Box::new(IfParselet {})),
         Token::Let => Some(Box::new(LetParselet {})),
         Token::Def => Some(Box::new(FunctionParselet {})),
         Token::Data => Some(Box::new(DataParselet {})),
         Token::Match => Some(Box::new(MatchParselet {})),
+        Token::Import => Some(Box::new(ImportParselet {})),
         _ => None,
     }
 }
@@ -65,6 +71,7 @@ fn infix_map(tok: &Token) -> Option<Box<dyn InfixParselet>> {
 fn postfix_map(tok: &Token) -> Option<Box<dyn PostfixParselet>> {
     match *tok {
         Token::LParen => Some(Box::new(FunCallParselet {})),
+        Token::Dot => Some(Box::new(FieldAccessParselet {})),
         _ => None,
     }
 }
@@ -74,21 +81,28 @@ pub fn parse_expr(
     current_binding_power: i64,
     is_top_level: bool,
 ) -> Result<Ast, ParseError> {
-    // Pop the first token and find which parselet we should use
-    let initial_token = match tokens.pop() {
-        Some(v) => v,
-        None => return Err(ParseError("Unexpected end of file".to_string(), None)),
-    };
-
-    let initial_parselet = match prefix_map(&initial_token.0) {
+    // Find which parselet we should use for the first token, leaving the token in place if
+    // there isn't one
+    let initial_parselet = match tokens.last() {
+        Some((token, span)) => match prefix_map(token) {
+            Some(v) => v,
+            None => {
+                return Err(ParseError(
+                    format!("Unexpected Token: {:?}", token).to_string(),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+        },
         None => {
             return Err(ParseError(
-                format!("Unexpected Token: {:?}", initial_token).to_string(),
+                "Unexpected end of file".to_string(),
                 None,
+                ParseErrorKind::EndOfFile,
             ))
         }
-        Some(v) => v,
     };
+    let initial_token = tokens.pop().unwrap();
 
     let mut left_node = (*initial_parselet).parse(tokens, initial_token, is_top_level)?;
 
@@ -150,6 +164,7 @@ pub fn parse_args(
         None => Err(ParseError(
             "Expected right paren or function arg".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
@@ -157,9 +172,14 @@ pub fn parse_args(
 fn parse_rest_args(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
 ) -> Result<(Vec<Ast>, usize), ParseError> {
-    match tokens.pop() {
-        Some((Token::RParen, span)) => Ok((vec![], span.end)),
+    match tokens.last() {
+        Some((Token::RParen, span)) => {
+            let end = span.end;
+            tokens.pop();
+            Ok((vec![], end))
+        }
         Some((Token::Comma, _span)) => {
+            tokens.pop();
             let expr = parse_expr(tokens, 0, false)?;
             let (mut rest, end) = parse_rest_args(tokens)?;
             rest.push(expr);
@@ -167,11 +187,13 @@ fn parse_rest_args(
         }
         Some((e, span)) => Err(ParseError(
             format!("Expected comma but got {:?}", e).to_string(),
-            Some(span),
+            Some(span.clone()),
+            ParseErrorKind::UnexpectedToken,
         )),
         None => Err(ParseError(
             "Ran out of tokens while parsing args".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
@@ -197,6 +219,7 @@ pub fn parse_params(
         None => Err(ParseError(
             "Ran out of tokens while parsing params".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
@@ -216,31 +239,187 @@ fn parse_rest_params(
             rest.push(param);
             Ok(rest)
         }
-        Some((_, span)) => Err(ParseError("Expected comma".to_string(), Some(span.clone()))),
+        Some((_, span)) => Err(ParseError(
+            "Expected comma".to_string(),
+            Some(span.clone()),
+            ParseErrorKind::UnexpectedToken,
+        )),
         None => Err(ParseError(
             "Ran out of tokens while parsing rest of params".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
 
-// A recursive descent parser for the top-level program
+// A parser for the type parameters of a data declaration (e.g. the `T, U>` in `data Pair<T, U>`)
+pub fn parse_type_params(
+    tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+) -> Result<Vec<String>, ParseError> {
+    let mut params = vec![];
+    loop {
+        match tokens.last() {
+            Some((Token::Identifier(id), _)) => {
+                params.push(id.clone());
+                tokens.pop();
+            }
+            Some((_, span)) => {
+                return Err(ParseError(
+                    "Expected identifier in type parameters".to_string(),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => {
+                return Err(ParseError(
+                    "Ran out of tokens while parsing type parameters".to_string(),
+                    None,
+                    ParseErrorKind::EndOfFile,
+                ))
+            }
+        }
+        match tokens.last() {
+            Some((Token::Comma, _)) => {
+                tokens.pop();
+            }
+            Some((Token::Gt, _)) => {
+                tokens.pop();
+                return Ok(params);
+            }
+            Some((_, span)) => {
+                return Err(ParseError(
+                    "Expected comma or `>` in type parameters".to_string(),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => {
+                return Err(ParseError(
+                    "Ran out of tokens while parsing type parameters".to_string(),
+                    None,
+                    ParseErrorKind::EndOfFile,
+                ))
+            }
+        }
+    }
+}
+
+// A recursive descent parser for the top-level program. Fails with every syntax error found
 pub fn parse_program(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
-) -> Result<Program, ParseError> {
-    let exprs = parse_exprs(tokens)?;
-    Ok(exprs)
+) -> Result<Program, Vec<ParseError>> {
+    let (program, errors) = parse_program_with_errors(tokens);
+    match errors.is_empty() {
+        true => Ok(program),
+        false => Err(errors),
+    }
 }
 
-pub fn parse_exprs(
+/// Parses a program, recovering from syntax errors so that as many of them as possible are
+/// found in one go. Code that couldn't be parsed is replaced by an `ErrorNode` holding the
+/// error, and the errors are also returned in the order they appear in the source. Errors from
+/// running out of tokens don't have a location, since they're at the end of the source, which
+/// only the caller knows.
+pub fn parse_program_with_errors(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
-) -> Result<Vec<Ast>, ParseError> {
+) -> (Program, Vec<ParseError>) {
+    let program = parse_exprs(tokens);
+    let mut errors: Vec<ParseError> = program
+        .iter()
+        .flat_map(|expr| expr.into_vec())
+        .filter_map(|expr| match &expr.node {
+            AstNode::ErrorNode(message, kind) => {
+                let span = match kind {
+                    ParseErrorKind::EndOfFile => None,
+                    _ => Some(expr.src_loc.span.clone()),
+                };
+                Some(ParseError(message.clone(), span, kind.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    errors
+        .sort_by_key(|ParseError(_, span, _)| span.as_ref().map_or(usize::MAX, |span| span.start));
+    (program, errors)
+}
+
+pub fn parse_exprs(tokens: &mut Vec<(Token, std::ops::Range<usize>)>) -> Vec<Ast> {
     let mut exprs = vec![];
-    while tokens.len() != 0 {
-        let expr = parse_expr(tokens, 0, true)?;
-        exprs.push(expr);
+    while let Some((_, span)) = tokens.last() {
+        let start = span.clone();
+        let remaining = tokens.len();
+        match parse_expr(tokens, 0, true) {
+            Ok(expr) => exprs.push(expr),
+            Err(error) => {
+                exprs.push(error_node(error, start));
+                synchronize(tokens, 0);
+                // Always make progress, even if the error was at a declaration
+                if tokens.len() == remaining {
+                    tokens.pop();
+                }
+            }
+        }
     }
-    return Ok(exprs);
+    return exprs;
+}
+
+/// Parses a construct that ends with `end`, whose first token has already been consumed. If
+/// there's a syntax error in it, the rest of it is skipped and an error node takes its place,
+/// so that errors after it can be found too.
+pub fn parse_block(
+    tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+    first_token_span: std::ops::Range<usize>,
+    parse: impl FnOnce(&mut Vec<(Token, std::ops::Range<usize>)>) -> Result<Ast, ParseError>,
+) -> Result<Ast, ParseError> {
+    match parse(tokens) {
+        Ok(ast) => Ok(ast),
+        Err(error) => {
+            synchronize(tokens, 1);
+            Ok(error_node(error, first_token_span))
+        }
+    }
+}
+
+/// Skips tokens after a syntax error until a point where parsing can carry on. Inside
+/// `depth` blocks, that's after the `end` of the outermost one. At the top level, it's before
+/// the next `let` (or after a stray `end`). Skipping always stops before a `def`, `data` or
+/// `import`, which can only start a top-level declaration, so that a block that is missing its
+/// `end` doesn't swallow the declarations after it.
+fn synchronize(tokens: &mut Vec<(Token, std::ops::Range<usize>)>, mut depth: usize) {
+    loop {
+        match tokens.last() {
+            None | Some((Token::Def, _)) | Some((Token::Data, _)) | Some((Token::Import, _)) => {
+                return
+            }
+            Some((Token::Let, _)) if depth == 0 => return,
+            Some((Token::End, _)) => {
+                tokens.pop();
+                if depth <= 1 {
+                    return;
+                }
+                depth -= 1;
+            }
+            Some((Token::If, _)) | Some((Token::Match, _)) | Some((Token::Lambda, _)) => {
+                tokens.pop();
+                depth += 1;
+            }
+            Some(_) => {
+                tokens.pop();
+            }
+        }
+    }
+}
+
+/// Makes the node that stands in for code with a syntax error. Errors without a location
+/// (e.g. from running out of tokens) are placed at `fallback_span`.
+fn error_node(error: ParseError, fallback_span: std::ops::Range<usize>) -> Ast {
+    let ParseError(message, span, kind) = error;
+    Ast::new(
+        AstNode::ErrorNode(message, kind),
+        SrcLoc {
+            span: span.unwrap_or(fallback_span),
+        },
+    )
 }
 
 pub fn parse_identifier(
@@ -251,12 +430,20 @@ pub fn parse_identifier(
 
     match initial_token {
         Some(t) => id_token = t,
-        None => match tokens.pop() {
-            Some(t) => id_token = t,
+        None => match tokens.last() {
+            Some((Token::Identifier(_), _)) => id_token = tokens.pop().unwrap(),
+            Some((_, span)) => {
+                return Err(ParseError(
+                    "Found non identifier token while parsing identifier".to_string(),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
             None => {
                 return Err(ParseError(
                     "Ran out of tokens while parsing identifier".to_string(),
                     None,
+                    ParseErrorKind::EndOfFile,
                 ))
             }
         },
@@ -267,20 +454,195 @@ pub fn parse_identifier(
             Some((Token::Colon, _)) => {
                 tokens.pop();
                 let (type_decl, type_span) = parse_type(tokens)?;
+                let span = id_span.start..type_span.end;
+                let src_loc = SrcLoc { span: span.clone() };
+                Ok((
+                    Identifier {
+                        src_loc,
+                        ..Identifier::new_with_type(id, type_decl)
+                    },
+                    span,
+                ))
+            }
+            Some(_) => {
+                let src_loc = SrcLoc {
+                    span: id_span.clone(),
+                };
                 Ok((
-                    Identifier::new_with_type(id, type_decl),
-                    id_span.start..type_span.end,
+                    Identifier {
+                        src_loc,
+                        ..Identifier::new_without_type(id)
+                    },
+                    id_span,
                 ))
             }
-            Some(_) => Ok((Identifier::new_without_type(id), id_span)),
             None => Err(ParseError(
                 "Ran out of tokens while parsing typed identifier".to_string(),
                 None,
+                ParseErrorKind::EndOfFile,
             )),
         },
         (_, span) => Err(ParseError(
             "Found non identifier token while parsing identifier".to_string(),
             Some(span),
+            ParseErrorKind::UnexpectedToken,
         )),
     };
 }
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+    use crate::ast::Pattern;
+    use logos::Logos;
+
+    /// Parses a program, returning it along with each error's message and the source it
+    /// points at
+    fn parse(source: &str) -> (Program, Vec<(String, &str)>) {
+        let mut tokens: Vec<_> = Token::lexer(source).spanned().collect();
+        tokens.reverse();
+        let (program, errors) = parse_program_with_errors(&mut tokens);
+        let errors = errors
+            .into_iter()
+            .map(|ParseError(message, span, _)| {
+                (message, &source[span.unwrap_or(source.len()..source.len())])
+            })
+            .collect();
+        (program, errors)
+    }
+
+    #[test]
+    fn finds_every_error() {
+        let (_, errors) = parse(
+            "def f(x) x end
+            let y = f(1,, 2)
+            def g(y):
+                if y: 1 end
+            end
+            match 1: | 1 => end",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ("Didn't get expected token Colon".to_string(), "x"),
+                ("Unexpected Token: Comma".to_string(), ","),
+                ("Expected `else` or `elif`".to_string(), "end"),
+                ("Unexpected Token: End".to_string(), "end"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_code_with_errors_by_error_nodes() {
+        let (program, errors) = parse(
+            "def f(x):
+                lambda(y) y end
+            end
+            let z = = 1
+            let w = 2
+            w",
+        );
+        assert_eq!(errors.len(), 2);
+
+        // The rest of the program is still there
+        assert!(
+            matches!(&program[0].node, AstNode::FunctionNode(_, _, _, _, body)
+            if matches!(body.node, AstNode::ErrorNode(..)))
+        );
+        assert!(matches!(program[1].node, AstNode::ErrorNode(..)));
+        assert!(matches!(program[2].node, AstNode::LetNodeTopLevel(_, _)));
+        assert!(matches!(program[3].node, AstNode::VarNode(_)));
+    }
+
+    #[test]
+    fn stops_skipping_at_declarations() {
+        // A block missing its `end` doesn't swallow the declarations after it
+        let (program, errors) = parse(
+            "def f(x):
+                x
+            def g(y): y end
+            data D: d() end",
+        );
+        assert_eq!(
+            errors,
+            vec![("Didn't get expected token End".to_string(), "def")]
+        );
+        assert!(matches!(&program[1].node, AstNode::FunctionNode(name, _, _, _, _) if name == "g"));
+        assert!(matches!(
+            program[2].node,
+            AstNode::DataDeclarationNode(_, _, _)
+        ));
+    }
+
+    #[test]
+    fn tells_tuples_apart_from_grouping() {
+        let (program, _) = parse("(1)");
+        assert!(matches!(program[0].node, AstNode::NumberNode(1)));
+        let (program, _) = parse("((1, 2))");
+        assert!(matches!(&program[0].node, AstNode::TupleNode(items) if items.len() == 2));
+        let (_, errors) = parse("(1, 2,)");
+        assert_eq!(errors, vec![("Unexpected Token: RParen".to_string(), ")")]);
+    }
+
+    #[test]
+    fn parses_alternatives_and_as_patterns() {
+        let (program, errors) = parse("match x: | some(1 | 2) | none() as y => y | _ => 0 end");
+        assert_eq!(errors, vec![]);
+        let branches = match &program[0].node {
+            AstNode::MatchNode(_, branches) => branches,
+            node => panic!("Expected a match, got {:?}", node),
+        };
+        // `as` applies to every alternative before it
+        let some = Pattern::Data(
+            "some".to_string(),
+            vec![Pattern::Or(vec![
+                Pattern::NumLiteral(1),
+                Pattern::NumLiteral(2),
+            ])],
+        );
+        let none = Pattern::Data("none".to_string(), vec![]);
+        assert_eq!(
+            branches[0].0,
+            Pattern::As(Box::new(Pattern::Or(vec![some, none])), "y".to_string())
+        );
+        assert_eq!(branches[1].0, Pattern::Wildcard);
+    }
+
+    #[test]
+    fn tells_the_kinds_of_errors_apart() {
+        let source =
+            "def f(x) x end\nlet y = f(1,, 2)\ndef g(): def h(): 1 end end\nif y: 1 else: 2";
+        let mut tokens: Vec<_> = Token::lexer(source).spanned().collect();
+        tokens.reverse();
+        let (_, errors) = parse_program_with_errors(&mut tokens);
+        let kinds: Vec<ParseErrorKind> = errors.into_iter().map(|error| error.2).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ParseErrorKind::ExpectedToken("Colon".to_string()),
+                ParseErrorKind::UnexpectedToken,
+                ParseErrorKind::Invalid,
+                ParseErrorKind::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_errors_at_the_end_of_the_source_without_a_location() {
+        let mut tokens: Vec<_> = Token::lexer("let x = 1\nif x: 1 else: 2")
+            .spanned()
+            .collect();
+        tokens.reverse();
+        let (program, errors) = parse_program_with_errors(&mut tokens);
+        assert_eq!(
+            errors,
+            vec![ParseError(
+                "No tokens left to consume".to_string(),
+                None,
+                ParseErrorKind::EndOfFile
+            )]
+        );
+        // The code that couldn't be parsed is still replaced where it starts
+        assert_eq!(program[1].src_loc.span, 10..12);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::ast::{Ast, AstNode, BinOp, SrcLoc};
+use crate::ast::{Ast, AstNode, BinOp, ImportNames, SrcLoc};
 use crate::lexer::lex::Token;
 use crate::parser::parse::{self, parse_expr, parse_params};
 use crate::parser::patterns::parse::parse_pattern;
@@ -8,6 +8,7 @@ use util::expect_and_consume;
 use super::parse::parse_identifier;
 use super::types::parse::parse_type;
 use super::util::{ast_op_to_token_op, consume_if_present};
+use std::rc::Rc;
 
 pub trait PrefixParselet {
     fn parse(
@@ -45,6 +46,16 @@ impl PrefixParselet for NumberParselet {
     ) -> Result<Ast, util::ParseError> {
         match current_token {
             (Token::Number(n), span) => Ok(Ast::new(AstNode::NumberNode(n), SrcLoc { span })),
+            (Token::BigNumber(n), span) => Ok(Ast::new(
+                match n.to_i64() {
+                    Some(n) => AstNode::NumberNode(n),
+                    None => AstNode::BigNumberNode(Rc::new(n)),
+                },
+                SrcLoc { span },
+            )),
+            (Token::Float((x, text)), span) => {
+                Ok(Ast::new(AstNode::FloatNode(x, text), SrcLoc { span }))
+            }
             _ => panic!("Tried to use number parselet with non-number token"),
         }
     }
@@ -65,6 +76,21 @@ impl PrefixParselet for BoolParselet {
     }
 }
 
+pub struct StringParselet {}
+impl PrefixParselet for StringParselet {
+    fn parse(
+        &self,
+        _tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        current_token: (Token, std::ops::Range<usize>),
+        _is_top_level: bool,
+    ) -> Result<Ast, util::ParseError> {
+        match current_token {
+            (Token::String(v), span) => Ok(Ast::new(AstNode::StringNode(v), SrcLoc { span })),
+            _ => panic!("Tried to use string parselet with non-string token"),
+        }
+    }
+}
+
 pub struct FunctionParselet {}
 impl PrefixParselet for FunctionParselet {
     fn parse(
@@ -73,64 +99,77 @@ impl PrefixParselet for FunctionParselet {
         current_token: (Token, std::ops::Range<usize>),
         is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        if !is_top_level {
-            return Err(util::ParseError(
-                "Function definitions are only allowed at the top level".to_string(),
-                Some(current_token.1),
-            ));
-        }
+        parse::parse_block(tokens, current_token.1.clone(), |tokens| {
+            if !is_top_level {
+                return Err(util::ParseError(
+                    "Function definitions are only allowed at the top level".to_string(),
+                    Some(current_token.1),
+                    util::ParseErrorKind::Invalid,
+                ));
+            }
 
-        let span_start = current_token.1.start;
+            let span_start = current_token.1.start;
 
-        let func_name = match tokens.pop() {
-            Some((Token::Identifier(name), _)) => Ok(name),
-            Some((_, span)) => Err(util::ParseError(
-                "Found non-identifier in function name".to_string(),
-                Some(span.clone()),
-            )),
-            None => Err(util::ParseError(
-                "Ran out of tokens while parsing function name".to_string(),
-                None,
-            )),
-        }?;
+            let func_name = match tokens.pop() {
+                Some((Token::Identifier(name), _)) => Ok(name),
+                Some((_, span)) => Err(util::ParseError(
+                    "Found non-identifier in function name".to_string(),
+                    Some(span.clone()),
+                    util::ParseErrorKind::UnexpectedToken,
+                )),
+                None => Err(util::ParseError(
+                    "Ran out of tokens while parsing function name".to_string(),
+                    None,
+                    util::ParseErrorKind::EndOfFile,
+                )),
+            }?;
 
-        expect_and_consume(tokens, Token::LParen)?;
+            // Type parameters are optional
+            let type_params = match consume_if_present(tokens, Token::Lt)? {
+                Some(_) => parse::parse_type_params(tokens)?,
+                None => vec![],
+            };
 
-        let params = parse::parse_params(tokens)?;
+            expect_and_consume(tokens, Token::LParen)?;
 
-        // See if there's a return type to parse
-        let mut return_type = None;
-        match tokens.last() {
-            Some((Token::ThinArrow, _)) => {
-                tokens.pop();
-                return_type = Some(parse_type(tokens)?);
-            }
-            None => {
-                return Err(util::ParseError(
-                    "Ran out of tokens while parsing function name".to_string(),
-                    None,
-                ))
-            }
-            _ => {}
-        };
+            let params = parse::parse_params(tokens)?;
+
+            // See if there's a return type to parse
+            let mut return_type = None;
+            match tokens.last() {
+                Some((Token::ThinArrow, _)) => {
+                    tokens.pop();
+                    return_type = Some(parse_type(tokens)?);
+                }
+                None => {
+                    return Err(util::ParseError(
+                        "Ran out of tokens while parsing function name".to_string(),
+                        None,
+                        util::ParseErrorKind::EndOfFile,
+                    ))
+                }
+                _ => {}
+            };
 
-        expect_and_consume(tokens, Token::Colon)?;
+            expect_and_consume(tokens, Token::Colon)?;
 
-        let body = parse::parse_expr(tokens, 0, false)?;
+            let body = parse::parse_expr(tokens, 0, false)?;
 
-        let span_end = expect_and_consume(tokens, Token::End)?.end;
+            let span_end = expect_and_consume(tokens, Token::End)?.end;
 
-        return Ok(Ast::new(
-            AstNode::FunctionNode(
-                func_name,
-                params,
-                return_type.map_or(None, |v| Some(v.0)),
-                Box::new(body),
-            ),
-            SrcLoc {
-                span: span_start..span_end,
-            },
-        ));
+            return Ok(Ast::new(
+                AstNode::FunctionNode(
+                    func_name,
+                    type_params,
+                    params,
+                    return_type.map_or(None, |v| Some(v.0)),
+                    Box::new(body),
+                ),
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        })
     }
 }
 
@@ -142,24 +181,26 @@ impl PrefixParselet for LambdaParselet {
         current_token: (Token, std::ops::Range<usize>),
         _is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        let span_start = current_token.1.start;
+        parse::parse_block(tokens, current_token.1.clone(), |tokens| {
+            let span_start = current_token.1.start;
 
-        expect_and_consume(tokens, Token::LParen)?;
+            expect_and_consume(tokens, Token::LParen)?;
 
-        let params = parse::parse_params(tokens)?;
+            let params = parse::parse_params(tokens)?;
 
-        expect_and_consume(tokens, Token::Colon)?;
+            expect_and_consume(tokens, Token::Colon)?;
 
-        let body = parse::parse_expr(tokens, 0, false)?;
+            let body = parse::parse_expr(tokens, 0, false)?;
 
-        let span_end = expect_and_consume(tokens, Token::End)?.end;
+            let span_end = expect_and_consume(tokens, Token::End)?.end;
 
-        return Ok(Ast::new(
-            AstNode::LambdaNode(params, Box::new(body)),
-            SrcLoc {
-                span: span_start..span_end,
-            },
-        ));
+            return Ok(Ast::new(
+                AstNode::LambdaNode(params, Box::new(body)),
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        })
     }
 }
 
@@ -171,54 +212,61 @@ impl PrefixParselet for IfParselet {
         current_token: (Token, std::ops::Range<usize>),
         _is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        let span_start = current_token.1.start;
-
-        let mut conditions = vec![];
-        let mut bodies = vec![];
-        conditions.push(parse::parse_expr(tokens, 0, false)?);
-        expect_and_consume(tokens, Token::Colon)?;
-        bodies.push(parse::parse_expr(tokens, 0, false)?);
-
-        let altern = loop {
-            match tokens.pop() {
-                Some((Token::Elif, _)) => {
-                    conditions.push(parse::parse_expr(tokens, 0, false)?);
-                    expect_and_consume(tokens, Token::Colon)?;
-                    bodies.push(parse::parse_expr(tokens, 0, false)?);
+        parse::parse_block(tokens, current_token.1.clone(), |tokens| {
+            let span_start = current_token.1.start;
+
+            let mut conditions = vec![];
+            let mut bodies = vec![];
+            conditions.push(parse::parse_expr(tokens, 0, false)?);
+            expect_and_consume(tokens, Token::Colon)?;
+            bodies.push(parse::parse_expr(tokens, 0, false)?);
+
+            let altern = loop {
+                // Any other token is left in place for error recovery
+                match tokens.last() {
+                    Some((Token::Elif, _)) => {
+                        tokens.pop();
+                        conditions.push(parse::parse_expr(tokens, 0, false)?);
+                        expect_and_consume(tokens, Token::Colon)?;
+                        bodies.push(parse::parse_expr(tokens, 0, false)?);
+                    }
+                    Some((Token::Else, _)) => {
+                        tokens.pop();
+                        expect_and_consume(tokens, Token::Colon)?;
+                        break parse::parse_expr(tokens, 0, false)?;
+                    }
+                    Some((_, span)) => {
+                        return Err(util::ParseError(
+                            "Expected `else` or `elif`".to_string(),
+                            Some(span.clone()),
+                            util::ParseErrorKind::UnexpectedToken,
+                        ))
+                    }
+                    None => {
+                        return Err(util::ParseError(
+                            "Ran out of tokens while parsing conditional".to_string(),
+                            None,
+                            util::ParseErrorKind::EndOfFile,
+                        ))
+                    }
                 }
-                Some((Token::Else, _)) => {
-                    expect_and_consume(tokens, Token::Colon)?;
-                    break parse::parse_expr(tokens, 0, false)?;
-                }
-                Some((_, span)) => {
-                    return Err(util::ParseError(
-                        "Expected `else` or `elif`".to_string(),
-                        Some(span),
-                    ))
-                }
-                None => {
-                    return Err(util::ParseError(
-                        "Ran out of tokens while parsing conditional".to_string(),
-                        None,
-                    ))
-                }
-            }
-        };
+            };
 
-        let span_end = expect_and_consume(tokens, Token::End)?.end;
+            let span_end = expect_and_consume(tokens, Token::End)?.end;
 
-        return Ok(Ast::new(
-            AstNode::IfNode(
-                conditions
-                    .into_iter()
-                    .zip(bodies.into_iter())
-                    .collect::<Vec<(Ast, Ast)>>(),
-                Box::new(altern),
-            ),
-            SrcLoc {
-                span: span_start..span_end,
-            },
-        ));
+            return Ok(Ast::new(
+                AstNode::IfNode(
+                    conditions
+                        .into_iter()
+                        .zip(bodies.into_iter())
+                        .collect::<Vec<(Ast, Ast)>>(),
+                    Box::new(altern),
+                ),
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        })
     }
 }
 
@@ -232,6 +280,25 @@ impl PrefixParselet for LetParselet {
     ) -> Result<Ast, util::ParseError> {
         let span_start = current_token.1.start;
 
+        // Anything other than a name is a pattern to destructure the value with
+        if !matches!(tokens.last(), Some((Token::Identifier(_), _))) {
+            let pattern = parse_pattern(tokens, 0)?;
+            let span_end = expect_and_consume(tokens, Token::Eq)?.end;
+            let binding = parse::parse_expr(tokens, 0, false)?;
+            let node = if is_top_level {
+                AstNode::LetPatternNodeTopLevel(pattern, Box::new(binding))
+            } else {
+                let body = parse::parse_expr(tokens, 0, false)?;
+                AstNode::LetPatternNode(pattern, Box::new(binding), Box::new(body))
+            };
+            return Ok(Ast::new(
+                node,
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        }
+
         let (id, _) = parse_identifier(None, tokens)?;
 
         // TODO: make the span_end at the true end of the expression
@@ -277,14 +344,77 @@ impl PrefixParselet for ParenthesisParselet {
     fn parse(
         &self,
         tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
-        _current_token: (Token, std::ops::Range<usize>),
+        current_token: (Token, std::ops::Range<usize>),
         _is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        let expr = parse::parse_expr(tokens, 0, false)?;
+        let (mut items, span_end) =
+            util::parse_parenthesized(tokens, |tokens| parse::parse_expr(tokens, 0, false))?;
 
-        expect_and_consume(tokens, Token::RParen)?;
+        if items.len() == 1 {
+            return Ok(items.remove(0));
+        }
+        return Ok(Ast::new(
+            AstNode::TupleNode(items),
+            SrcLoc {
+                span: current_token.1.start..span_end,
+            },
+        ));
+    }
+}
+
+pub struct ListParselet {}
+impl PrefixParselet for ListParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        current_token: (Token, std::ops::Range<usize>),
+        _is_top_level: bool,
+    ) -> Result<Ast, util::ParseError> {
+        let (items, rest, span_end) =
+            util::parse_list(tokens, |tokens| parse_expr(tokens, 0, false))?;
+        Ok(Ast::new(
+            AstNode::ListNode(items, rest.map(Box::new)),
+            SrcLoc {
+                span: current_token.1.start..span_end,
+            },
+        ))
+    }
+}
 
-        return Ok(expr);
+pub struct RecordParselet {}
+impl PrefixParselet for RecordParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        current_token: (Token, std::ops::Range<usize>),
+        _is_top_level: bool,
+    ) -> Result<Ast, util::ParseError> {
+        // A record literal starts with its first field's name, and an update with an expression
+        let is_literal = match tokens.as_slice() {
+            [.., (Token::Colon, _), (Token::Identifier(_), _)] => true,
+            [.., (Token::RBracket, _)] => true,
+            _ => false,
+        };
+        let (node, span_end) = if is_literal {
+            let (fields, span_end) =
+                util::parse_record_fields(tokens, |tokens| parse_expr(tokens, 0, false), None)?;
+            (AstNode::RecordNode(fields), span_end)
+        } else {
+            let record = parse_expr(tokens, 0, false)?;
+            expect_and_consume(tokens, Token::With)?;
+            let (fields, span_end) =
+                util::parse_record_fields(tokens, |tokens| parse_expr(tokens, 0, false), None)?;
+            (
+                AstNode::RecordUpdateNode(Box::new(record), fields),
+                span_end,
+            )
+        };
+        Ok(Ast::new(
+            node,
+            SrcLoc {
+                span: current_token.1.start..span_end,
+            },
+        ))
     }
 }
 
@@ -296,75 +426,196 @@ impl PrefixParselet for DataParselet {
         current_token: (Token, std::ops::Range<usize>),
         is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        if !is_top_level {
-            return Err(util::ParseError(
-                "Data declarations can only exist at the top level".to_string(),
-                Some(current_token.1),
-            ));
-        }
-
-        let (data_name, span_start) = match tokens.pop() {
-            Some((Token::Identifier(id), span)) => Ok((id, span.start)),
-            Some((_, span)) => Err(util::ParseError(
-                "Found non-identifier as data declaration name".to_string(),
-                Some(span),
-            )),
-            None => Err(util::ParseError(
-                "Ran out of tokens while parsing data declaration".to_string(),
-                None,
-            )),
-        }?;
-
-        expect_and_consume(tokens, Token::Colon)?;
-        // Initial pipe character is optional
-        consume_if_present(tokens, Token::Pipe)?;
-
-        let mut variants = vec![];
+        parse::parse_block(tokens, current_token.1.clone(), |tokens| {
+            if !is_top_level {
+                return Err(util::ParseError(
+                    "Data declarations can only exist at the top level".to_string(),
+                    Some(current_token.1),
+                    util::ParseErrorKind::Invalid,
+                ));
+            }
 
-        let span_end = loop {
-            let variant_name = match tokens.pop() {
-                Some((Token::Identifier(id), _)) => Ok(id),
+            let (data_name, span_start) = match tokens.pop() {
+                Some((Token::Identifier(id), span)) => Ok((id, span.start)),
                 Some((_, span)) => Err(util::ParseError(
-                    "Found non-identifier as data variant name".to_string(),
+                    "Found non-identifier as data declaration name".to_string(),
                     Some(span),
+                    util::ParseErrorKind::UnexpectedToken,
                 )),
                 None => Err(util::ParseError(
-                    "Ran out of tokens while parsing data variant".to_string(),
+                    "Ran out of tokens while parsing data declaration".to_string(),
                     None,
+                    util::ParseErrorKind::EndOfFile,
                 )),
             }?;
 
-            // parse variant body
-            expect_and_consume(tokens, Token::LParen)?;
-            let field_names = parse_params(tokens)?;
+            // Type parameters are optional
+            let type_params = match consume_if_present(tokens, Token::Lt)? {
+                Some(_) => parse::parse_type_params(tokens)?,
+                None => vec![],
+            };
+
+            expect_and_consume(tokens, Token::Colon)?;
+            // Initial pipe character is optional
+            consume_if_present(tokens, Token::Pipe)?;
+
+            let mut variants = vec![];
+
+            let span_end = loop {
+                let variant_name = match tokens.last() {
+                    Some((Token::Identifier(id), _)) => {
+                        let id = id.clone();
+                        tokens.pop();
+                        Ok(id)
+                    }
+                    Some((_, span)) => Err(util::ParseError(
+                        "Found non-identifier as data variant name".to_string(),
+                        Some(span.clone()),
+                        util::ParseErrorKind::UnexpectedToken,
+                    )),
+                    None => Err(util::ParseError(
+                        "Ran out of tokens while parsing data variant".to_string(),
+                        None,
+                        util::ParseErrorKind::EndOfFile,
+                    )),
+                }?;
+
+                // parse variant body
+                expect_and_consume(tokens, Token::LParen)?;
+                let field_names = parse_params(tokens)?;
+
+                variants.push((variant_name, field_names));
+
+                // Determine whether we have another variant to parse or if this is the end
+                match tokens.last() {
+                    Some((Token::Pipe, _)) => {
+                        tokens.pop();
+                    }
+                    Some((Token::End, _)) => break tokens.pop().unwrap().1.end,
+                    Some((_, span)) => {
+                        return Err(util::ParseError(
+                            "Found bad token while parsing data variants".to_string(),
+                            Some(span.clone()),
+                            util::ParseErrorKind::UnexpectedToken,
+                        ))
+                    }
+                    None => {
+                        return Err(util::ParseError(
+                            "Ran out of tokens while parsing data variant".to_string(),
+                            None,
+                            util::ParseErrorKind::EndOfFile,
+                        ))
+                    }
+                }
+            };
 
-            variants.push((variant_name, field_names));
+            return Ok(Ast::new(
+                AstNode::DataDeclarationNode(data_name, type_params, variants),
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        })
+    }
+}
+
+pub struct ImportParselet {}
+impl PrefixParselet for ImportParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        current_token: (Token, std::ops::Range<usize>),
+        is_top_level: bool,
+    ) -> Result<Ast, util::ParseError> {
+        if !is_top_level {
+            return Err(util::ParseError(
+                "Imports can only exist at the top level".to_string(),
+                Some(current_token.1),
+                util::ParseErrorKind::Invalid,
+            ));
+        }
+        let span_start = current_token.1.start;
+
+        // Selected names come before the path (`import map, filter from "lists.boat"`)
+        let mut selected = vec![];
+        if let Some((Token::Identifier(_), _)) = tokens.last() {
+            loop {
+                match tokens.pop() {
+                    Some((Token::Identifier(id), _)) => selected.push(id),
+                    Some((_, span)) => {
+                        return Err(util::ParseError(
+                            "Found non-identifier in list of imported names".to_string(),
+                            Some(span),
+                            util::ParseErrorKind::UnexpectedToken,
+                        ))
+                    }
+                    None => {
+                        return Err(util::ParseError(
+                            "Ran out of tokens while parsing import".to_string(),
+                            None,
+                            util::ParseErrorKind::EndOfFile,
+                        ))
+                    }
+                }
+                if consume_if_present(tokens, Token::Comma)?.is_none() {
+                    break;
+                }
+            }
+            expect_and_consume(tokens, Token::From)?;
+        }
+
+        let (path, mut span_end) = match tokens.pop() {
+            Some((Token::String(path), span)) => (path, span.end),
+            Some((_, span)) => {
+                return Err(util::ParseError(
+                    "Expected a string containing the path of the imported file".to_string(),
+                    Some(span),
+                    util::ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => {
+                return Err(util::ParseError(
+                    "Ran out of tokens while parsing import".to_string(),
+                    None,
+                    util::ParseErrorKind::EndOfFile,
+                ))
+            }
+        };
 
-            // Determine whether we have another variant to parse or if this is the end
+        let names = if !selected.is_empty() {
+            ImportNames::Selected(selected)
+        } else if let Some((Token::As, _)) = tokens.last() {
+            tokens.pop();
             match tokens.pop() {
-                Some((Token::Pipe, _)) => continue,
-                Some((Token::End, span)) => break span.end,
+                Some((Token::Identifier(alias), span)) => {
+                    span_end = span.end;
+                    ImportNames::Alias(alias)
+                }
                 Some((_, span)) => {
                     return Err(util::ParseError(
-                        "Found bad token while parsing data variants".to_string(),
+                        "Found non-identifier as import alias".to_string(),
                         Some(span),
+                        util::ParseErrorKind::UnexpectedToken,
                     ))
                 }
                 None => {
                     return Err(util::ParseError(
-                        "Ran out of tokens while parsing data variant".to_string(),
+                        "Ran out of tokens while parsing import".to_string(),
                         None,
+                        util::ParseErrorKind::EndOfFile,
                     ))
                 }
             }
+        } else {
+            ImportNames::All
         };
 
-        return Ok(Ast::new(
-            AstNode::DataDeclarationNode(data_name, variants),
+        Ok(Ast::new(
+            AstNode::ImportNode(path, names),
             SrcLoc {
                 span: span_start..span_end,
             },
-        ));
+        ))
     }
 }
 
@@ -376,53 +627,69 @@ impl PrefixParselet for MatchParselet {
         current_token: (Token, std::ops::Range<usize>),
         _is_top_level: bool,
     ) -> Result<Ast, util::ParseError> {
-        let span_start = current_token.1.end;
-        let expression_to_match = parse_expr(tokens, 0, false)?;
-
-        expect_and_consume(tokens, Token::Colon)?;
-        // Initial pipe character is optional
-        expect_and_consume(tokens, Token::Pipe)?;
-
-        let mut branches = vec![];
-
-        let span_end = loop {
-            let branch_pattern = parse_pattern(tokens, 0)?;
-
-            // parse variant body
-            expect_and_consume(tokens, Token::FatArrow)?;
-            let branch_body = parse_expr(tokens, 0, false)?;
-
-            branches.push((branch_pattern, branch_body));
-
-            // Determine whether we have another variant to parse or if this is the end
-            match tokens.pop() {
-                Some((Token::Pipe, _)) => continue,
-                Some((Token::End, span)) => break span.end,
-                Some((token, span)) => {
-                    return Err(util::ParseError(
-                        format!(
-                            "Found bad token while parsing match expression: {:?}",
-                            token
-                        )
-                        .to_string(),
-                        Some(span),
-                    ))
+        parse::parse_block(tokens, current_token.1.clone(), |tokens| {
+            let span_start = current_token.1.end;
+            let expression_to_match = parse_expr(tokens, 0, false)?;
+
+            expect_and_consume(tokens, Token::Colon)?;
+            // Initial pipe character is optional
+            expect_and_consume(tokens, Token::Pipe)?;
+
+            let mut branches = vec![];
+
+            let span_end = loop {
+                let pattern_span = util::upcoming_pattern_span(tokens);
+                let branch_pattern = parse_pattern(tokens, 0)?;
+
+                // An optional guard follows the pattern
+                let guard = match tokens.last() {
+                    Some((Token::If, _)) => {
+                        tokens.pop();
+                        Some(parse_expr(tokens, 0, false)?)
+                    }
+                    _ => None,
+                };
+
+                // parse variant body
+                expect_and_consume(tokens, Token::FatArrow)?;
+                let branch_body = parse_expr(tokens, 0, false)?;
+
+                branches.push((branch_pattern, guard, branch_body, pattern_span));
+
+                // Determine whether we have another variant to parse or if this is the end
+                match tokens.last() {
+                    Some((Token::Pipe, _)) => {
+                        tokens.pop();
+                    }
+                    Some((Token::End, _)) => break tokens.pop().unwrap().1.end,
+                    Some((token, span)) => {
+                        return Err(util::ParseError(
+                            format!(
+                                "Found bad token while parsing match expression: {:?}",
+                                token
+                            )
+                            .to_string(),
+                            Some(span.clone()),
+                            util::ParseErrorKind::UnexpectedToken,
+                        ))
+                    }
+                    None => {
+                        return Err(util::ParseError(
+                            "Ran out of tokens while parsing match expression".to_string(),
+                            None,
+                            util::ParseErrorKind::EndOfFile,
+                        ))
+                    }
                 }
-                None => {
-                    return Err(util::ParseError(
-                        "Ran out of tokens while parsing match expression".to_string(),
-                        None,
-                    ))
-                }
-            }
-        };
+            };
 
-        return Ok(Ast::new(
-            AstNode::MatchNode(Box::new(expression_to_match), branches),
-            SrcLoc {
-                span: span_start..span_end,
-            },
-        ));
+            return Ok(Ast::new(
+                AstNode::MatchNode(Box::new(expression_to_match), branches),
+                SrcLoc {
+                    span: span_start..span_end,
+                },
+            ));
+        })
     }
 }
 
@@ -483,3 +750,39 @@ impl PostfixParselet for FunCallParselet {
         ));
     }
 }
+
+pub struct FieldAccessParselet {}
+impl PostfixParselet for FieldAccessParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        left_node: Ast,
+        _current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Ast, util::ParseError> {
+        let (field, field_span) = match tokens.last() {
+            Some((Token::Identifier(field), span)) => (field.clone(), span.clone()),
+            Some((token, span)) => {
+                return Err(util::ParseError(
+                    format!("Expected field name after '.' but got {:?}", token),
+                    Some(span.clone()),
+                    util::ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => {
+                return Err(util::ParseError(
+                    "Ran out of tokens while parsing field access".to_string(),
+                    None,
+                    util::ParseErrorKind::EndOfFile,
+                ))
+            }
+        };
+        tokens.pop();
+        let span_start = left_node.src_loc.span.start;
+        return Ok(Ast::new(
+            AstNode::FieldAccessNode(Box::new(left_node), field),
+            SrcLoc {
+                span: span_start..field_span.end,
+            },
+        ));
+    }
+}
@@ -1,12 +1,14 @@
 use super::parselets;
 use crate::ast::Pattern;
 use crate::lexer::lex::Token;
-use crate::parser::util::ParseError;
+use crate::parser::util::{ParseError, ParseErrorKind};
 use std::borrow::Borrow;
 
 pub fn get_binding_power(op: &Token) -> i64 {
     match op {
         Token::LParen => 50,
+        Token::Pipe => 20,
+        Token::As => 10,
         _ => panic!("Tried to get binding power of non-op token {:?}", op),
     }
 }
@@ -15,7 +17,12 @@ fn prefix_map(tok: &Token) -> Option<Box<dyn parselets::PrefixParselet>> {
     match *tok {
         Token::Number(_) => Some(Box::new(parselets::NumberParselet {})),
         Token::Bool(_) => Some(Box::new(parselets::BoolParselet {})),
+        Token::String(_) => Some(Box::new(parselets::StringParselet {})),
         Token::Identifier(_) => Some(Box::new(parselets::IdentifierParselet {})),
+        Token::LSquareBracket => Some(Box::new(parselets::ListParselet {})),
+        Token::LBracket => Some(Box::new(parselets::RecordParselet {})),
+        Token::LParen => Some(Box::new(parselets::ParenthesisParselet {})),
+        Token::Underscore => Some(Box::new(parselets::WildcardParselet {})),
         _ => None,
     }
 }
@@ -23,6 +30,8 @@ fn prefix_map(tok: &Token) -> Option<Box<dyn parselets::PrefixParselet>> {
 fn infix_map(tok: &Token) -> Option<Box<dyn parselets::InfixParselet>> {
     match *tok {
         Token::LParen => Some(Box::new(parselets::DataParselet {})),
+        Token::Pipe => Some(Box::new(parselets::OrParselet {})),
+        Token::As => Some(Box::new(parselets::AsParselet {})),
         _ => None,
     }
 }
@@ -31,21 +40,28 @@ pub fn parse_pattern(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
     current_binding_power: i64,
 ) -> Result<Pattern, ParseError> {
-    // Pop the first token and find which parselet we should use
-    let (initial_token, start_span) = match tokens.pop() {
-        Some(v) => v,
-        None => return Err(ParseError("Unexpected end of file".to_string(), None)),
-    };
-
-    let initial_parselet = match prefix_map(&initial_token) {
+    // Find which parselet we should use for the first token, leaving the token in place if
+    // there isn't one
+    let initial_parselet = match tokens.last() {
+        Some((token, span)) => match prefix_map(token) {
+            Some(v) => v,
+            None => {
+                return Err(ParseError(
+                    format!("Unexpected Token: {:?}", token).to_string(),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+        },
         None => {
             return Err(ParseError(
-                format!("Unexpected Token: {:?}", initial_token).to_string(),
-                Some(start_span),
+                "Unexpected end of file".to_string(),
+                None,
+                ParseErrorKind::EndOfFile,
             ))
         }
-        Some(v) => v,
     };
+    let (initial_token, start_span) = tokens.pop().unwrap();
 
     let mut left_node = (*initial_parselet).parse(tokens, (initial_token, start_span))?;
 
@@ -93,6 +109,7 @@ pub fn parse_pattern_args(
         None => Err(ParseError(
             "Expected right paren or function arg".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
@@ -100,18 +117,27 @@ pub fn parse_pattern_args(
 fn parse_rest_pattern_args(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
 ) -> Result<Vec<Pattern>, ParseError> {
-    match tokens.pop() {
-        Some((Token::RParen, _)) => Ok(vec![]),
+    match tokens.last() {
+        Some((Token::RParen, _)) => {
+            tokens.pop();
+            Ok(vec![])
+        }
         Some((Token::Comma, _)) => {
+            tokens.pop();
             let expr = parse_pattern(tokens, 0)?;
             let mut rest = parse_rest_pattern_args(tokens)?;
             rest.push(expr);
             Ok(rest)
         }
-        Some((_, span)) => Err(ParseError("Expected comma".to_string(), Some(span))),
+        Some((_, span)) => Err(ParseError(
+            "Expected comma".to_string(),
+            Some(span.clone()),
+            ParseErrorKind::UnexpectedToken,
+        )),
         None => Err(ParseError(
             "Ran out of tokens while parsing pattern args".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
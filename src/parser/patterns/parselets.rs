@@ -47,6 +47,20 @@ impl PrefixParselet for BoolParselet {
     }
 }
 
+pub struct StringParselet {}
+impl PrefixParselet for StringParselet {
+    fn parse(
+        &self,
+        _tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        match current_token {
+            (Token::String(v), _span) => Ok(Pattern::StringLiteral(v)),
+            _ => panic!("Tried to use string parselet with non-string token"),
+        }
+    }
+}
+
 pub struct IdentifierParselet {}
 impl PrefixParselet for IdentifierParselet {
     fn parse(
@@ -61,6 +75,62 @@ impl PrefixParselet for IdentifierParselet {
     }
 }
 
+pub struct WildcardParselet {}
+impl PrefixParselet for WildcardParselet {
+    fn parse(
+        &self,
+        _tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        _current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        Ok(Pattern::Wildcard)
+    }
+}
+
+pub struct ListParselet {}
+impl PrefixParselet for ListParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        _current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        let (items, rest, _) = util::parse_list(tokens, |tokens| parse::parse_pattern(tokens, 0))?;
+        Ok(Pattern::List(items, rest.map(Box::new)))
+    }
+}
+
+pub struct RecordParselet {}
+impl PrefixParselet for RecordParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        _current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        // A field without a pattern (e.g. the `x` in `{x, y: 0}`) binds the field's value
+        let (fields, _) = util::parse_record_fields(
+            tokens,
+            |tokens| parse::parse_pattern(tokens, 0),
+            Some(Pattern::Identifier),
+        )?;
+        Ok(Pattern::Record(fields))
+    }
+}
+
+pub struct ParenthesisParselet {}
+impl PrefixParselet for ParenthesisParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        _current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        let (mut items, _) =
+            util::parse_parenthesized(tokens, |tokens| parse::parse_pattern(tokens, 0))?;
+        if items.len() == 1 {
+            return Ok(items.remove(0));
+        }
+        Ok(Pattern::Tuple(items))
+    }
+}
+
 pub struct DataParselet {}
 impl InfixParselet for DataParselet {
     fn parse(
@@ -74,6 +144,7 @@ impl InfixParselet for DataParselet {
             _ => Err(util::ParseError(
                 "Unexpected '(' while parsing pattern".to_string(),
                 Some(current_token.1),
+                util::ParseErrorKind::UnexpectedToken,
             )),
         }?;
 
@@ -81,3 +152,46 @@ impl InfixParselet for DataParselet {
         return Ok(Pattern::Data(discriminant, args));
     }
 }
+
+pub struct OrParselet {}
+impl InfixParselet for OrParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        left_node: Pattern,
+        current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        let right = parse::parse_pattern(tokens, parse::get_binding_power(&current_token.0))?;
+        // `a | b | c` is one pattern with three alternatives
+        let mut alternatives = match left_node {
+            Pattern::Or(alternatives) => alternatives,
+            left_node => vec![left_node],
+        };
+        alternatives.push(right);
+        Ok(Pattern::Or(alternatives))
+    }
+}
+
+pub struct AsParselet {}
+impl InfixParselet for AsParselet {
+    fn parse(
+        &self,
+        tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+        left_node: Pattern,
+        current_token: (Token, std::ops::Range<usize>),
+    ) -> Result<Pattern, util::ParseError> {
+        match tokens.pop() {
+            Some((Token::Identifier(name), _)) => Ok(Pattern::As(Box::new(left_node), name)),
+            Some((token, span)) => Err(util::ParseError(
+                format!("Expected a name after `as` but got {:?}", token),
+                Some(span),
+                util::ParseErrorKind::UnexpectedToken,
+            )),
+            None => Err(util::ParseError(
+                "Expected a name after `as`".to_string(),
+                Some(current_token.1),
+                util::ParseErrorKind::EndOfFile,
+            )),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::ast::Type;
 use crate::lexer::lex::Token;
-use crate::parser::util::{expect_and_consume, ParseError};
+use crate::parser::util::{expect_and_consume, parse_record_fields, ParseError, ParseErrorKind};
 use im::Vector;
 
 pub fn parse_type(
@@ -10,7 +10,7 @@ pub fn parse_type(
         Some((Token::Identifier(id), id_span)) => match tokens.last() {
             Some((Token::Lt, _)) => {
                 tokens.pop();
-                let (args, args_span_end) = parse_type_args(tokens)?;
+                let (args, args_span_end) = parse_type_args(tokens, Token::Gt)?;
                 return Ok((Type::new(id, args), id_span.start..args_span_end));
             }
             Some((Token::ThinArrow, _)) => {
@@ -25,10 +25,11 @@ pub fn parse_type(
             None => Err(ParseError(
                 "Ran out of tokens while parsing type".to_string(),
                 None,
+                ParseErrorKind::EndOfFile,
             )),
         },
         Some((Token::LParen, open_paren_span)) => {
-            let (args, _) = parse_type_args(tokens)?;
+            let (args, _) = parse_type_args(tokens, Token::RParen)?;
             expect_and_consume(tokens, Token::ThinArrow)?;
             let (return_type, return_type_span) = parse_type(tokens)?;
             return Ok((
@@ -36,37 +37,56 @@ pub fn parse_type(
                 open_paren_span.start..return_type_span.end,
             ));
         }
-        Some((t, span)) => Err(ParseError(
-            format!("Unexpected token in type {:?}", t).to_string(),
-            Some(span),
-        )),
+        Some((Token::LBracket, open_bracket_span)) => {
+            let (fields, span_end) =
+                parse_record_fields(tokens, |tokens| Ok(parse_type(tokens)?.0), None)?;
+            return Ok((Type::new_record(fields), open_bracket_span.start..span_end));
+        }
+        Some((t, span)) => {
+            // Leave the token in place for error recovery
+            let error = ParseError(
+                format!("Unexpected token in type {:?}", t).to_string(),
+                Some(span.clone()),
+                ParseErrorKind::UnexpectedToken,
+            );
+            tokens.push((t, span));
+            Err(error)
+        }
         None => Err(ParseError(
             "Ran out of tokens while parsing type".to_string(),
             None,
+            ParseErrorKind::EndOfFile,
         )),
     }
 }
 
+/// Parses a comma separated list of types ending with the `closing` token
+/// (`>` for type arguments and `)` for function parameter types)
 fn parse_type_args(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+    closing: Token,
 ) -> Result<(Vector<Type>, usize), ParseError> {
     let mut args = Vector::new();
 
     loop {
         args.push_back(parse_type(tokens)?.0);
-        match tokens.pop() {
-            Some((Token::Comma, _)) => continue,
-            Some((Token::RParen, span_end)) => return Ok((args, span_end.end)),
+        match tokens.last() {
+            Some((Token::Comma, _)) => {
+                tokens.pop();
+            }
+            Some((t, _)) if *t == closing => return Ok((args, tokens.pop().unwrap().1.end)),
             Some((t, span)) => {
                 return Err(ParseError(
                     format!("Unexpected token in type args {:?}", t).to_string(),
-                    Some(span),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
                 ))
             }
             None => {
                 return Err(ParseError(
                     "Ran out of tokens while parsing type args".to_string(),
                     None,
+                    ParseErrorKind::EndOfFile,
                 ))
             }
         }
@@ -3,8 +3,22 @@ use crate::lexer::lex::Token;
 use std::fmt;
 use std::{error, ops::Range};
 
+/// (message, where it is, what kind of error it is)
 #[derive(PartialEq, Debug, Clone, Hash)]
-pub struct ParseError(pub String, pub Option<Range<usize>>);
+pub struct ParseError(pub String, pub Option<Range<usize>>, pub ParseErrorKind);
+
+/// The kinds of syntax error, which diagnostics are given codes by
+#[derive(PartialEq, Debug, Clone, Hash)]
+pub enum ParseErrorKind {
+    /// The source ended in the middle of something
+    EndOfFile,
+    /// A token was found where it doesn't belong
+    UnexpectedToken,
+    /// (the name of the token) A particular token was needed where another was found
+    ExpectedToken(String),
+    /// Something that parses, but isn't allowed where it is (e.g. a nested function declaration)
+    Invalid,
+}
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -37,12 +51,18 @@ pub fn expect_and_consume(
     tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
     expected: Token,
 ) -> Result<Range<usize>, ParseError> {
-    match tokens.pop() {
-        None => Err(ParseError("No tokens left to consume".to_string(), None)),
-        Some((v, span)) if v == expected => Ok(span),
+    // An unexpected token is left in place, so that error recovery can see it
+    match tokens.last() {
+        None => Err(ParseError(
+            "No tokens left to consume".to_string(),
+            None,
+            ParseErrorKind::EndOfFile,
+        )),
+        Some((v, _)) if *v == expected => Ok(tokens.pop().unwrap().1),
         Some((_, span)) => Err(ParseError(
             format!("Didn't get expected token {:?}", expected).to_string(),
-            Some(span),
+            Some(span.clone()),
+            ParseErrorKind::ExpectedToken(format!("{:?}", expected)),
         )),
     }
 }
@@ -52,8 +72,173 @@ pub fn consume_if_present(
     expected: Token,
 ) -> Result<Option<(Token, std::ops::Range<usize>)>, ParseError> {
     match tokens.last() {
-        None => Err(ParseError("No tokens left to consume".to_string(), None)),
+        None => Err(ParseError(
+            "No tokens left to consume".to_string(),
+            None,
+            ParseErrorKind::EndOfFile,
+        )),
         Some((v, _)) if v == &expected => Ok(Some(tokens.pop().unwrap())),
         _ => Ok(None),
     }
 }
+
+/// The span of the pattern at the top of the tokens, which runs up to the `if` of a guard or
+/// the `=>` of a match branch (neither of which can be part of a pattern)
+pub fn upcoming_pattern_span(tokens: &[(Token, std::ops::Range<usize>)]) -> Range<usize> {
+    let mut spans = tokens
+        .iter()
+        .rev()
+        .take_while(|(token, _)| !matches!(token, Token::If | Token::FatArrow))
+        .map(|(_, span)| span);
+    let start = match spans.next() {
+        Some(span) => span.clone(),
+        None => return 0..0,
+    };
+    let end = spans.last().map_or(start.end, |span| span.end);
+    start.start..end
+}
+
+/// Parses the items of a list literal or list pattern, whose `[` has been consumed, up to and
+/// including its `]`. The last item can follow a `...` to make it the rest of the list. Returns
+/// the items, the rest and where the list ends.
+pub fn parse_list<T>(
+    tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+    mut parse_item: impl FnMut(&mut Vec<(Token, std::ops::Range<usize>)>) -> Result<T, ParseError>,
+) -> Result<(Vec<T>, Option<T>, usize), ParseError> {
+    let mut items = vec![];
+    loop {
+        match tokens.last() {
+            Some((Token::RSquareBracket, _)) => {
+                let end = tokens.pop().unwrap().1.end;
+                return Ok((items, None, end));
+            }
+            Some((Token::Ellipsis, _)) => {
+                tokens.pop();
+                let rest = parse_item(tokens)?;
+                let end = expect_and_consume(tokens, Token::RSquareBracket)?.end;
+                return Ok((items, Some(rest), end));
+            }
+            Some(_) => items.push(parse_item(tokens)?),
+            None => break,
+        }
+        match tokens.last() {
+            Some((Token::Comma, _)) => {
+                tokens.pop();
+            }
+            Some((Token::RSquareBracket, _)) => (),
+            Some((token, span)) => {
+                return Err(ParseError(
+                    format!("Expected comma or ']' but got {:?}", token),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => break,
+        }
+    }
+    Err(ParseError(
+        "Ran out of tokens while parsing list".to_string(),
+        None,
+        ParseErrorKind::EndOfFile,
+    ))
+}
+
+/// Parses the items of a parenthesized expression or pattern, whose `(` has been consumed, up to
+/// and including its `)`. A single item is just in parentheses, while two or more items make a
+/// tuple. Returns the items and where the parentheses end.
+pub fn parse_parenthesized<T>(
+    tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+    mut parse_item: impl FnMut(&mut Vec<(Token, std::ops::Range<usize>)>) -> Result<T, ParseError>,
+) -> Result<(Vec<T>, usize), ParseError> {
+    let mut items = vec![parse_item(tokens)?];
+    loop {
+        match tokens.last() {
+            Some((Token::Comma, _)) => {
+                tokens.pop();
+                items.push(parse_item(tokens)?);
+            }
+            Some((Token::RParen, _)) => return Ok((items, tokens.pop().unwrap().1.end)),
+            Some((token, span)) => {
+                return Err(ParseError(
+                    format!("Expected comma or ')' but got {:?}", token),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => {
+                return Err(ParseError(
+                    "No tokens left to consume".to_string(),
+                    None,
+                    ParseErrorKind::EndOfFile,
+                ))
+            }
+        }
+    }
+}
+
+/// Parses the `name: value` fields of a record literal, update or pattern up to and including
+/// its `}`. A field without a `: value` is given the value `shorthand` makes from its name, or
+/// is an error if there's no shorthand. Returns the fields and where the record ends.
+pub fn parse_record_fields<T>(
+    tokens: &mut Vec<(Token, std::ops::Range<usize>)>,
+    mut parse_value: impl FnMut(&mut Vec<(Token, std::ops::Range<usize>)>) -> Result<T, ParseError>,
+    shorthand: Option<fn(String) -> T>,
+) -> Result<(Vec<(String, T)>, usize), ParseError> {
+    let mut fields: Vec<(String, T)> = vec![];
+    loop {
+        let (name, span) = match tokens.last() {
+            Some((Token::RBracket, _)) => {
+                let end = tokens.pop().unwrap().1.end;
+                return Ok((fields, end));
+            }
+            Some((Token::Identifier(name), span)) => (name.clone(), span.clone()),
+            Some((token, span)) => {
+                return Err(ParseError(
+                    format!("Expected field name but got {:?}", token),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => break,
+        };
+        if fields.iter().any(|(field, _)| *field == name) {
+            return Err(ParseError(
+                format!("Field {} appears more than once", name),
+                Some(span),
+                ParseErrorKind::Invalid,
+            ));
+        }
+        tokens.pop();
+        let value = match (consume_if_present(tokens, Token::Colon)?, shorthand) {
+            (Some(_), _) => parse_value(tokens)?,
+            (None, Some(shorthand)) => shorthand(name.clone()),
+            (None, None) => {
+                return Err(ParseError(
+                    format!("Expected ':' after field {}", name),
+                    Some(span),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+        };
+        fields.push((name, value));
+        match tokens.last() {
+            Some((Token::Comma, _)) => {
+                tokens.pop();
+            }
+            Some((Token::RBracket, _)) => (),
+            Some((token, span)) => {
+                return Err(ParseError(
+                    format!("Expected comma or '}}' but got {:?}", token),
+                    Some(span.clone()),
+                    ParseErrorKind::UnexpectedToken,
+                ))
+            }
+            None => break,
+        }
+    }
+    Err(ParseError(
+        "Ran out of tokens while parsing record".to_string(),
+        None,
+        ParseErrorKind::EndOfFile,
+    ))
+}
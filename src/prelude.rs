@@ -0,0 +1,158 @@
+use crate::ast::{NativeFunction, Type, Val};
+use crate::interpreter::bigint::BigInt;
+use crate::interpreter::numeric::{self, Number};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// The Skiff source of the prelude, whose declarations are in scope in every program
+pub const SOURCE: &str = include_str!("prelude.boat");
+
+/// Where text written by `print` goes
+pub type Output = Rc<RefCell<Printed>>;
+
+/// Text written by `print`, which is either kept until it's passed on to a printer or streamed
+/// a line at a time as it's printed
+#[derive(Debug, Clone, Default)]
+pub struct Printed {
+    /// Text that hasn't been passed on to a printer yet
+    pub text: String,
+    /// Called with each printed line (without its newline) instead of keeping it in `text`
+    pub stream: Option<fn(&str)>,
+}
+// Function pointers can't be compared meaningfully, so only whether there's a stream is
+impl PartialEq for Printed {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text && self.stream.is_some() == other.stream.is_some()
+    }
+}
+impl Printed {
+    /// Takes the text that hasn't been passed on yet
+    pub fn take(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+}
+
+/// The functions implemented in Rust that are in scope in every program. `print` writes to
+/// `output` rather than to stdout, so that the caller decides where printed text goes.
+pub fn native_functions(output: &Output) -> Vec<NativeFunction> {
+    let t = || Type::new_unit("T".to_string());
+    vec![
+        print(output),
+        NativeFunction::new(
+            "to_string",
+            vec!["T"],
+            vec![t()],
+            Type::new_string(),
+            |args| Ok(Val::Str(to_text(&args[0]))),
+        ),
+        NativeFunction::new(
+            "abs",
+            vec![],
+            vec![Type::new_number()],
+            Type::new_number(),
+            |args| match &args[0] {
+                Val::Num(n) => Ok(match n.checked_abs() {
+                    Some(n) => Val::Num(n),
+                    None => Val::BigNum(Rc::new(-&BigInt::from(*n))),
+                }),
+                Val::BigNum(n) if n.is_negative() => Ok(Val::BigNum(Rc::new(-n.as_ref()))),
+                Val::BigNum(n) => Ok(Val::BigNum(n.clone())),
+                Val::Float(x) => Ok(Val::Float(x.abs())),
+                v => Err(format!("abs expects a number but got {}", v)),
+            },
+        ),
+        NativeFunction::new(
+            "min",
+            vec![],
+            vec![Type::new_number(), Type::new_number()],
+            Type::new_number(),
+            |args| match (args[0].to_number(), args[1].to_number()) {
+                (Some(x), Some(y)) => match numeric::compare(&x, &y) {
+                    Some(Ordering::Greater) => Ok(args[1].clone()),
+                    _ => Ok(args[0].clone()),
+                },
+                _ => Err(format!(
+                    "min expects numbers but got {} and {}",
+                    args[0], args[1]
+                )),
+            },
+        ),
+        NativeFunction::new(
+            "max",
+            vec![],
+            vec![Type::new_number(), Type::new_number()],
+            Type::new_number(),
+            |args| match (args[0].to_number(), args[1].to_number()) {
+                (Some(x), Some(y)) => match numeric::compare(&x, &y) {
+                    Some(Ordering::Less) => Ok(args[1].clone()),
+                    _ => Ok(args[0].clone()),
+                },
+                _ => Err(format!(
+                    "max expects numbers but got {} and {}",
+                    args[0], args[1]
+                )),
+            },
+        ),
+        NativeFunction::new(
+            "to_float",
+            vec![],
+            vec![Type::new_number()],
+            Type::new_number(),
+            |args| match &args[0] {
+                Val::Num(n) => Ok(Val::Float(*n as f64)),
+                Val::BigNum(n) => Ok(Val::Float(n.to_f64())),
+                Val::Float(x) => Ok(Val::Float(*x)),
+                v => Err(format!("to_float expects a number but got {}", v)),
+            },
+        ),
+        rounding("floor", f64::floor),
+        rounding("ceil", f64::ceil),
+        rounding("round", f64::round),
+    ]
+}
+
+/// A function that rounds a number to an integer. Integers are returned as they are, and NaN
+/// and the infinities are errors.
+fn rounding(name: &'static str, round: fn(f64) -> f64) -> NativeFunction {
+    NativeFunction::new(
+        name,
+        vec![],
+        vec![Type::new_number()],
+        Type::new_number(),
+        move |args| match &args[0] {
+            Val::Float(x) => match BigInt::from_f64(round(*x)) {
+                Some(n) => Ok(Number::from(n).into()),
+                None => Err(format!("{} can't turn {} into an integer", name, args[0])),
+            },
+            v if v.to_number().is_some() => Ok(v.clone()),
+            v => Err(format!("{} expects a number but got {}", name, v)),
+        },
+    )
+}
+
+/// The `print` function, which writes a value on its own line of `output` and returns it
+pub fn print(output: &Output) -> NativeFunction {
+    let t = || Type::new_unit("T".to_string());
+    let output = output.clone();
+    NativeFunction::new("print", vec!["T"], vec![t()], t(), move |args| {
+        let mut output = output.borrow_mut();
+        let line = to_text(&args[0]);
+        match output.stream {
+            Some(stream) => stream(&line),
+            None => {
+                output.text.push_str(&line);
+                output.text.push('\n');
+            }
+        }
+        Ok(args[0].clone())
+    })
+}
+
+/// Formats a value for `print` and `to_string`. Strings are written without quotes.
+fn to_text(val: &Val) -> String {
+    match val {
+        Val::Str(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
@@ -0,0 +1,127 @@
+use crate::ConsolePrinter;
+use colored::*;
+use im::HashMap;
+use logos::Logos;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use skiff::lexer::lex::Token;
+use skiff::runtime::{CliArgs, Session};
+use skiff::type_inferencer::ast::Term;
+use std::error;
+
+const PROMPT: &str = "skiff> ";
+const CONTINUATION_PROMPT: &str = "  ...> ";
+
+/// Runs an interactive read-eval-print loop. Top-level declarations are kept in a
+/// session so that later inputs can refer to them.
+pub fn run(args: CliArgs) -> Result<(), Box<dyn error::Error>> {
+    let mut editor = Editor::<()>::new();
+    let mut session = Session::new();
+    session.stream_printed(|line| println!("{}", line));
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+
+                // Keep reading lines until every block has a matching `end`
+                if block_depth(&buffer) > 0 {
+                    continue;
+                }
+
+                let input = std::mem::take(&mut buffer);
+                if input.trim().is_empty() {
+                    continue;
+                }
+                editor.add_history_entry(input.trim_end());
+
+                // Errors have already been reported by the session, so just move on
+                if let Ok(Some(output)) = session.evaluate(&args, input, &mut ConsolePrinter) {
+                    for (val, term) in output {
+                        match term {
+                            Some(term) => {
+                                println!("{} : {}", val, display_term(&term).bright_blue())
+                            }
+                            None => println!("{}", val),
+                        }
+                    }
+                }
+            }
+            // Ctrl-C abandons the current input
+            Err(ReadlineError::Interrupted) => buffer.clear(),
+            // Ctrl-D exits
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts how many blocks are opened but not yet closed by an `end` in some source text
+fn block_depth(source: &str) -> i64 {
+    Token::lexer(source).fold(0, |depth, token| match token {
+        Token::Def | Token::Data | Token::If | Token::Match | Token::Lambda => depth + 1,
+        Token::End => depth - 1,
+        _ => depth,
+    })
+}
+
+/// Formats a type for display, naming any unsolved type variables `T1`, `T2`, etc.
+/// in order of appearance
+fn display_term(term: &Term) -> String {
+    fn helper(term: &Term, names: &mut HashMap<usize, String>) -> String {
+        match term {
+            Term::Var(label) => {
+                let next_name = format!("T{}", names.len() + 1);
+                names.entry(*label).or_insert(next_name).clone()
+            }
+            Term::Record(fields) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(field, arg)| format!("{}: {}", field, helper(arg, names)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Term::Constructor(id, args) if args.len() == 0 => id.clone(),
+            Term::Constructor(id, args) => format!(
+                "{}<{}>",
+                id,
+                args.iter()
+                    .map(|arg| helper(arg, names))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+    helper(term, &mut HashMap::new())
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::*;
+    use im::vector;
+
+    #[test]
+    fn counts_unclosed_blocks() {
+        assert_eq!(block_depth("1 + 2"), 0);
+        assert_eq!(block_depth("def f(x):\n"), 1);
+        assert_eq!(block_depth("def f(x):\n    if x: 1 else: 2 end\n"), 1);
+        assert_eq!(block_depth("def f(x):\n    if x: 1 else: 2 end\nend\n"), 0);
+    }
+
+    #[test]
+    fn names_type_variables_in_order() {
+        let term = Term::function(vector![Term::Var(42)], Term::Var(42));
+        assert_eq!(display_term(&term), "Function<T1, T1>");
+    }
+}
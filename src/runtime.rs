@@ -1,23 +1,29 @@
-use crate::ast::Val;
-use crate::error_handling::pretty_print_warning;
-use crate::interpreter::interpret::{InterpError, StackFrame};
+use crate::ast::Type;
+use crate::ast::{AstNode, NativeFunction, Program, Val};
+use crate::bytecode::instruction::Value;
+use crate::bytecode::vm::{self, VmState};
+use crate::error_handling::{Diagnostic, ErrorFormat, Severity, SourceMap};
+use crate::interpreter::interpret::{InterpError, InterpErrorKind, InterpreterState, StackFrame};
+use crate::interpreter::limits::EvalLimits;
+use crate::modules::{resolve_imports, ModuleError, ModuleState};
 use crate::parser::util::ParseError;
+use crate::prelude;
 use crate::static_checking::exhaustiveness::{
     check_program_exhaustiveness, ExhaustivenessError, ProgramExhaustivenessReport,
 };
-use crate::type_inferencer::constraint_gen::find_types;
-use crate::type_inferencer::type_inference::InferenceError;
+use crate::type_inferencer::ast::{ConstraintSet, Scheme, Term};
+use crate::type_inferencer::constraint_gen::{find_types_incremental, DataDeclTable};
+use crate::type_inferencer::type_inference::{InferenceError, InferenceState};
+use crate::type_inferencer::unification::unify_constraints;
 use crate::type_inferencer::util::add_any_to_declarations;
-use crate::{
-    error_handling, interpreter::interpret, lexer::lex, parser::parse,
-    type_inferencer::type_inference,
-};
+use crate::{interpreter::interpret, lexer::lex, parser::parse, type_inferencer::type_inference};
 use colored::*;
 use im::HashMap;
 use logos::Logos;
+use std::error;
 use std::fmt;
 use std::fmt::Write;
-use std::{borrow::Borrow, error};
+use std::rc::Rc;
 use structopt::StructOpt;
 
 /// The interpreter for the Skiff programming language
@@ -35,6 +41,17 @@ pub struct CliArgs {
     #[structopt(short = "t", long = "type-check")]
     pub stop_after_types: bool,
 
+    /// Run the program with the bytecode VM instead of the tree-walking interpreter
+    #[structopt(short = "b", long = "bytecode")]
+    pub use_bytecode: bool,
+
+    #[structopt(flatten)]
+    pub limits: EvalLimits,
+
+    /// How to print errors and warnings: `human` or `json` (one object per line)
+    #[structopt(long = "error-format", default_value = "human")]
+    pub error_format: ErrorFormat,
+
     /// The path to the file to interpret
     #[structopt(parse(from_os_str))]
     pub path: std::path::PathBuf,
@@ -45,6 +62,9 @@ impl CliArgs {
             stop_after_lexing: false,
             stop_after_parsing: false,
             stop_after_types: false,
+            use_bytecode: false,
+            limits: EvalLimits::default(),
+            error_format: ErrorFormat::Human,
             path,
         }
     }
@@ -53,7 +73,9 @@ impl CliArgs {
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub enum SkiffError {
     Lex(),
-    Parse(ParseError),
+    /// Every syntax error found in the program
+    Parse(Vec<ParseError>),
+    Module(ModuleError),
     Inference(InferenceError),
     Exhaustiveness(ExhaustivenessError),
     Interpret(InterpError),
@@ -66,147 +88,879 @@ impl<'a> fmt::Display for SkiffError {
 }
 impl<'a> error::Error for SkiffError {}
 
+/// Persistent state for evaluating a series of Skiff programs one after another, where later
+/// programs can use the top-level `let`, `def` and `data` declarations of earlier ones
+/// (e.g. the lines entered into the REPL). Every session starts with the built-in functions
+/// and the declarations of the prelude.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Session {
+    /// The source of every program evaluated so far, along with the files they imported.
+    /// Spans in stored declarations index into it
+    source: String,
+    source_map: SourceMap,
+    module_state: ModuleState,
+    data_declarations: Program,
+    data_decl_table: DataDeclTable,
+    inference_state: InferenceState,
+    interpreter_state: InterpreterState,
+    vm_state: VmState,
+    /// Text printed by the program that is running
+    output: prelude::Output,
+}
+
+/// What the static stages learned about a program that was checked without running it
+#[derive(PartialEq, Debug, Clone)]
+pub struct CheckedProgram {
+    /// The session's source followed by the program's and then that of the files it imports.
+    /// Every span refers to it.
+    pub source: String,
+    pub source_map: SourceMap,
+    /// Where the program starts in `source`
+    pub offset: usize,
+    /// The program with its imports resolved, or nothing if it didn't parse
+    pub program: Program,
+    pub data_decl_table: DataDeclTable,
+    /// The session's inference state, extended with the program's types if it type checked
+    pub inference_state: InferenceState,
+    pub problems: Vec<Diagnostic>,
+}
+
+/// The state produced by whichever backend ran a program
+enum BackendState {
+    TreeWalk(InterpreterState),
+    Bytecode(VmState),
+}
+
 pub fn evaluate(
     args: CliArgs,
     raw: String,
     printer: &mut impl Write,
 ) -> Result<Option<Vec<Val>>, SkiffError> {
-    let lexer = lex::Token::lexer(&raw);
+    let output = Session::new().evaluate(&args, raw, printer)?;
+    Ok(output.map(|output| output.into_iter().map(|(val, _)| val).collect()))
+}
 
-    let mut token_vec: Vec<_> = lexer.spanned().collect();
+impl Session {
+    pub fn new() -> Self {
+        let mut session = Session {
+            source: String::new(),
+            source_map: SourceMap::new(),
+            module_state: ModuleState::new(),
+            data_declarations: vec![],
+            data_decl_table: DataDeclTable::new(),
+            inference_state: InferenceState::new(),
+            interpreter_state: InterpreterState::new(),
+            vm_state: VmState::new(),
+            output: Rc::default(),
+        };
+        session.load_prelude();
+        session
+    }
 
-    if args.stop_after_lexing {
-        let _ = writeln!(printer, "{:?}", token_vec);
-        return Ok(None);
+    /// Defines the built-in functions and then the declarations of the prelude. The prelude
+    /// only declares functions and data, so it's run on both backends up front rather than
+    /// going through `evaluate`, which only updates the backend that runs the program.
+    fn load_prelude(&mut self) {
+        for native in prelude::native_functions(&self.output) {
+            self.define_native(&native);
+        }
+
+        let path = std::path::PathBuf::from("<prelude>");
+        self.source = prelude::SOURCE.to_string();
+        self.source_map.add_file(path, 0..self.source.len());
+        let mut tokens: Vec<_> = lex::Token::lexer(&self.source).spanned().collect();
+        tokens.reverse();
+        let program = parse::parse_program(&mut tokens).expect("The prelude should parse");
+        let program = add_any_to_declarations(program);
+
+        self.data_decl_table = find_types_incremental(&program, &self.data_decl_table);
+        self.inference_state = type_inference::infer_types_incremental(
+            &program,
+            &self.data_decl_table,
+            &self.inference_state,
+        )
+        .expect("The prelude should type check");
+        let limits = EvalLimits::default();
+        let (_, interpreter_state) =
+            interpret::interpret_incremental(&program, &self.interpreter_state, &limits)
+                .expect("The prelude should interpret");
+        let (_, vm_state) = vm::run_incremental(&program, &self.vm_state, &limits)
+            .expect("The prelude should compile");
+        self.interpreter_state = interpreter_state;
+        self.vm_state = vm_state;
+        self.data_declarations = program
+            .into_iter()
+            .filter(|expr| matches!(expr.node, AstNode::DataDeclarationNode(_, _, _)))
+            .collect();
     }
 
-    // Check for error tokens
-    for (token, span) in &token_vec {
-        if token == &lex::Token::Error {
-            error_handling::pretty_print_error(
-                "Invalid token",
-                span.clone(),
-                raw.borrow(),
-                args.path.clone(),
-                printer,
-            );
-            return Err(SkiffError::Lex());
+    /// Sends each line that programs print to `stream` as soon as it's printed, rather than
+    /// passing them on to the printer once the program finishes
+    pub fn stream_printed(&mut self, stream: fn(&str)) {
+        self.output.borrow_mut().stream = Some(stream);
+    }
+
+    /// Defines a function implemented in Rust, which shadows any earlier declaration with
+    /// the same name
+    pub fn define_native(&mut self, native: &NativeFunction) {
+        self.inference_state.define_native(native);
+        self.interpreter_state.define_native(native);
+        self.vm_state.define_native(native);
+    }
+
+    /// Defines a binding to a value from outside Skiff, which shadows any earlier binding with
+    /// the same name. Like a native function, it can be used in function bodies as well as at
+    /// the top level. The value can't contain Skiff functions, since those can't be moved
+    /// between backends.
+    pub fn define_value(
+        &mut self,
+        name: &str,
+        value: Val,
+        type_decl: &Type,
+    ) -> Result<(), SkiffError> {
+        let vm_value = match Value::from_val(value.clone(), &[]) {
+            Some(vm_value) => vm_value,
+            None => {
+                return Err(SkiffError::Interpret(InterpError(
+                    format!(
+                        "{} can't be bound to a value containing a Skiff function",
+                        name
+                    ),
+                    0..0,
+                    HashMap::new(),
+                    StackFrame::new_stack(),
+                    InterpErrorKind::Invalid,
+                )))
+            }
+        };
+        self.inference_state
+            .schemes
+            .insert(name.to_string(), Scheme::mono(Term::from_type(type_decl)));
+        self.interpreter_state.define_host_value(name, value);
+        self.vm_state.define_host_value(name, vm_value);
+        Ok(())
+    }
+
+    /// Calls a function declared by an earlier program (or a native function) with arguments
+    /// from outside Skiff, returning the result along with its type. The arguments are checked
+    /// against the function's type before it's called.
+    pub fn call(
+        &self,
+        args: &CliArgs,
+        name: &str,
+        call_args: Vec<Val>,
+        printer: &mut impl Write,
+    ) -> Result<(Val, Option<Term>), SkiffError> {
+        // Functions from programs that failed to type check don't have a type to check against
+        let result_type = match self.inference_state.schemes.get(name) {
+            Some(scheme) => {
+                let arg_terms: Vec<Term> = call_args.iter().map(|_| Term::new_var()).collect();
+                let result_term = Term::new_var();
+                let call_term =
+                    Term::function(arg_terms.iter().cloned().collect(), result_term.clone());
+                let (term, operands) = scheme.instantiate();
+                let constraints = call_args.iter().zip(arg_terms).fold(
+                    ConstraintSet::unit(term, call_term).union(operands),
+                    |constraints, (arg, term)| constraints.union(self.value_constraints(arg, term)),
+                );
+                let substitutions =
+                    unify_constraints(constraints).map_err(SkiffError::Inference)?;
+                Some(result_term.substitute(&substitutions))
+            }
+            None => None,
+        };
+
+        let result = if args.use_bytecode {
+            vm::call_incremental(name, call_args, &self.vm_state, &args.limits)
+        } else {
+            interpret::call_incremental(name, call_args, &self.interpreter_state, &args.limits)
+        };
+        let _ = write!(printer, "{}", self.output.borrow_mut().take());
+        match result {
+            Ok(value) => Ok((value, result_type)),
+            Err(interp_error) => Err(SkiffError::Interpret(interp_error)),
         }
     }
 
-    token_vec.reverse();
+    /// Generates constraints that give a term the type of a value
+    fn value_constraints(&self, value: &Val, term: Term) -> ConstraintSet {
+        match value {
+            Val::Num(_) | Val::BigNum(_) | Val::Float(_) => {
+                ConstraintSet::unit(term, Term::number())
+            }
+            Val::Bool(_) => ConstraintSet::unit(term, Term::boolean()),
+            Val::Str(_) => ConstraintSet::unit(term, Term::string()),
+            // Functions don't keep their types at runtime
+            Val::Lam(_, _, _) | Val::Native(_) | Val::Cast(_, _) => ConstraintSet::new(),
+            Val::Data(discriminant, fields) => {
+                let variant = discriminant.get_variant().to_string();
+                match self.data_decl_table.instantiate_variant(&variant) {
+                    Some((data_term, field_terms)) => fields.iter().zip(field_terms).fold(
+                        ConstraintSet::unit(term, data_term),
+                        |constraints, (field, field_term)| {
+                            constraints.union(self.value_constraints(field, field_term))
+                        },
+                    ),
+                    None => ConstraintSet::new(),
+                }
+            }
+            Val::List(items) => {
+                let item_term = Term::new_var();
+                items.iter().fold(
+                    ConstraintSet::unit(term, Term::list(item_term.clone())),
+                    |constraints, item| {
+                        constraints.union(self.value_constraints(item, item_term.clone()))
+                    },
+                )
+            }
+            Val::Record(fields) => {
+                let field_terms: Vec<(String, Term)> = fields
+                    .keys()
+                    .map(|field| (field.clone(), Term::new_var()))
+                    .collect();
+                fields.values().zip(&field_terms).fold(
+                    ConstraintSet::unit(term, Term::record(field_terms.clone())),
+                    |constraints, (field, (_, field_term))| {
+                        constraints.union(self.value_constraints(field, field_term.clone()))
+                    },
+                )
+            }
+            Val::Tuple(items) => {
+                let item_terms: Vec<Term> = items.iter().map(|_| Term::new_var()).collect();
+                items.iter().zip(item_terms.clone()).fold(
+                    ConstraintSet::unit(term, Term::tuple(item_terms.into_iter().collect())),
+                    |constraints, (item, item_term)| {
+                        constraints.union(self.value_constraints(item, item_term))
+                    },
+                )
+            }
+        }
+    }
 
-    let parsed = match parse::parse_program(&mut token_vec) {
-        Ok(program) => program,
-        Err(parse_error) => {
-            let ParseError(message, span) = parse_error.clone();
-            error_handling::pretty_print_error(
-                &message,
-                span.unwrap_or(0..0),
-                raw.borrow(),
-                args.path,
-                printer,
-            );
-            return Err(SkiffError::Parse(parse_error));
+    /// Checks a program in the context of this session without running it or updating the
+    /// session, collecting the problems found by each stage instead of printing them. Checking
+    /// stops at the first stage that can't produce a program for the next one.
+    pub fn check(&self, path: &std::path::Path, raw: &str) -> CheckedProgram {
+        let offset = self.source.len();
+        let mut checked = CheckedProgram {
+            source: format!("{}{}", self.source, raw),
+            source_map: self.source_map.clone(),
+            offset,
+            program: vec![],
+            data_decl_table: self.data_decl_table.clone(),
+            inference_state: self.inference_state.clone(),
+            problems: vec![],
+        };
+        checked
+            .source_map
+            .add_file(path.to_path_buf(), offset..checked.source.len());
+
+        let mut token_vec: Vec<_> = lex::Token::lexer(raw)
+            .spanned()
+            .map(|(token, span)| (token, (span.start + offset)..(span.end + offset)))
+            .collect();
+        for (token, span) in &token_vec {
+            if token == &lex::Token::Error {
+                checked
+                    .problems
+                    .push(Diagnostic::error("E0001", "Invalid token").with_span(span.clone()));
+            }
         }
-    };
+        if !checked.problems.is_empty() {
+            return checked;
+        }
+
+        token_vec.reverse();
+        let parsed = match parse::parse_program(&mut token_vec) {
+            Ok(program) => program,
+            Err(parse_errors) => {
+                // Errors without a location are at the end of the program
+                let end = checked.source.len();
+                for parse_error in &parse_errors {
+                    let mut diagnostic = Diagnostic::from(parse_error);
+                    diagnostic.span.get_or_insert(end..end);
+                    checked.problems.push(diagnostic);
+                }
+                return checked;
+            }
+        };
+
+        let (parsed, module_state) = match resolve_imports(
+            parsed,
+            path,
+            &self.module_state,
+            &mut checked.source,
+            &mut checked.source_map,
+        ) {
+            Ok(resolved) => resolved,
+            Err(module_error) => {
+                checked.problems.push(Diagnostic::from(&module_error));
+                return checked;
+            }
+        };
+        checked.program = add_any_to_declarations(parsed);
+        checked.data_decl_table = find_types_incremental(&checked.program, &self.data_decl_table);
 
-    if args.stop_after_parsing {
-        for expr in parsed {
-            let _ = writeln!(printer, "{}", expr.pretty_print());
+        // The parts of the program that do type check keep their types, for editors to show.
+        // Matches are still checked, using the types of their patterns where they don't.
+        let (inference_state, inference_error) = type_inference::infer_types_partial(
+            &checked.program,
+            &checked.data_decl_table,
+            &self.inference_state,
+        );
+        checked.inference_state = inference_state;
+        let inference_error = inference_error.map(|e| Diagnostic::from(&e));
+        if let Some(diagnostic) = &inference_error {
+            checked.problems.push(diagnostic.clone());
         }
-        return Ok(None);
+
+        let (match_problems, _) = self.check_matches(
+            &checked.program,
+            &checked.inference_state.substitutions,
+            inference_error.as_ref(),
+            &module_state,
+            &checked.source_map,
+            &checked.source,
+        );
+        checked.problems.extend(match_problems);
+        checked
     }
 
-    let parsed_with_anys = add_any_to_declarations(parsed.clone());
+    /// Evaluates a program in the context of this session, returning the value of each
+    /// top-level expression along with its inferred type (if inference succeeded). The
+    /// session is only updated with the program's declarations if evaluation succeeds.
+    pub fn evaluate(
+        &mut self,
+        args: &CliArgs,
+        raw: String,
+        printer: &mut impl Write,
+    ) -> Result<Option<Vec<(Val, Option<Term>)>>, SkiffError> {
+        // Spans are offset past the source of earlier programs so that they stay valid
+        // when functions from earlier programs are called
+        let offset = self.source.len();
+        let mut raw = format!("{}{}", self.source, raw);
+        let mut source_map = self.source_map.clone();
+        source_map.add_file(args.path.clone(), offset..raw.len());
+        let lexer = lex::Token::lexer(&raw[offset..]);
 
-    let data_decl_table = find_types(&parsed_with_anys);
+        let mut token_vec: Vec<_> = lexer
+            .spanned()
+            .map(|(token, span)| (token, (span.start + offset)..(span.end + offset)))
+            .collect();
 
-    let type_environment = match type_inference::infer_types(&parsed_with_anys, &data_decl_table) {
-        Ok(t_e) => t_e,
-        Err(e) => {
-            match e.clone() {
-                InferenceError::ConstructorMismatch(t1, t2) => {
-                    pretty_print_warning(
-                        &format!("Type mismatch: {} is not {}", t1, t2),
-                        0..0,
-                        raw.borrow(),
-                        args.path.clone(),
-                        printer,
-                    );
-                    return Err(SkiffError::Inference(InferenceError::ConstructorMismatch(t1, t2)));
+        if args.stop_after_lexing {
+            let _ = writeln!(printer, "{:?}", token_vec);
+            return Ok(None);
+        }
+
+        // Check for error tokens
+        for (token, span) in &token_vec {
+            if token == &lex::Token::Error {
+                let diagnostic =
+                    Diagnostic::error("E0001", "Invalid token").with_span(span.clone());
+                args.error_format
+                    .print(&diagnostic, &source_map, &raw, printer);
+                return Err(SkiffError::Lex());
+            }
+        }
+
+        token_vec.reverse();
+
+        let parsed = match parse::parse_program(&mut token_vec) {
+            Ok(program) => program,
+            Err(parse_errors) => {
+                // Errors without a location are at the end of the program
+                let end = raw.len();
+                for parse_error in &parse_errors {
+                    let mut diagnostic = Diagnostic::from(parse_error);
+                    diagnostic.span.get_or_insert(end..end);
+                    args.error_format
+                        .print(&diagnostic, &source_map, &raw, printer);
                 }
-                InferenceError::InfiniteType() => {
-                    let _ = writeln!(printer, "Inference warning: infinite type detected");
+                return Err(SkiffError::Parse(parse_errors));
+            }
+        };
+
+        if args.stop_after_parsing {
+            for expr in parsed {
+                let _ = writeln!(printer, "{}", expr.pretty_print());
+            }
+            return Ok(None);
+        }
+
+        // Load any imported modules and resolve the names that refer to their declarations
+        let (parsed, module_state) = match resolve_imports(
+            parsed,
+            &args.path,
+            &self.module_state,
+            &mut raw,
+            &mut source_map,
+        ) {
+            Ok(resolved) => resolved,
+            Err(module_error) => {
+                let diagnostic = Diagnostic::from(&module_error);
+                args.error_format
+                    .print(&diagnostic, &source_map, &raw, printer);
+                return Err(SkiffError::Module(module_error));
+            }
+        };
+
+        let parsed_with_anys = add_any_to_declarations(parsed);
+
+        let data_decl_table = find_types_incremental(&parsed_with_anys, &self.data_decl_table);
+
+        let (inference_state, inference_error) = match type_inference::infer_types_incremental(
+            &parsed_with_anys,
+            &data_decl_table,
+            &self.inference_state,
+        ) {
+            Ok(state) => (Some(state), None),
+            Err(e) => {
+                // Type mismatches, missing fields, unsupported operands and variables that only
+                // some alternatives bind stop the program, but it can still run without the
+                // other results of inference
+                let stops_program = matches!(
+                    e,
+                    InferenceError::ConstructorMismatch(..)
+                        | InferenceError::MissingField(..)
+                        | InferenceError::UnsupportedOperand(..)
+                        | InferenceError::UnevenAlternatives(..)
+                );
+                let mut diagnostic = Diagnostic::from(&e);
+                if !stops_program {
+                    diagnostic = diagnostic.with_severity(Severity::Warning);
                 }
-                _ => {
-                    pretty_print_warning(
-                        &format!("Inference error: {:?}", e),
-                        0..0,
-                        raw.borrow(),
-                        args.path.clone(),
-                        printer,
+                args.error_format
+                    .print(&diagnostic, &source_map, &raw, printer);
+                if stops_program {
+                    // Report the matches' problems too, since they don't depend on inference
+                    let (match_problems, _) = self.check_matches(
+                        &parsed_with_anys,
+                        &HashMap::new(),
+                        Some(&diagnostic),
+                        &module_state,
+                        &source_map,
+                        &raw,
                     );
+                    for problem in &match_problems {
+                        args.error_format.print(problem, &source_map, &raw, printer);
+                    }
+                    return Err(SkiffError::Inference(e));
                 }
-            };
-            HashMap::new()
+                (None, Some(diagnostic))
+            }
+        };
+        let type_environment = match &inference_state {
+            Some(state) => state.substitutions.clone(),
+            None => HashMap::new(),
+        };
+
+        if args.stop_after_types {
+            let _ = writeln!(printer, "{}", "Parse tree:".bright_yellow().bold());
+            for expr in parsed_with_anys {
+                let _ = writeln!(printer, "{}", expr.pretty_print());
+            }
+            let _ = writeln!(printer, "{}", "Type environment:".bright_yellow().bold());
+            let _ = writeln!(printer, "{:?}", type_environment);
+            return Ok(None);
         }
-    };
 
-    if args.stop_after_types {
-        let _ = writeln!(printer, "{}", "Parse tree:".bright_yellow().bold());
-        for expr in parsed_with_anys {
-            let _ = writeln!(printer, "{}", expr.pretty_print());
+        let (match_problems, match_error) = self.check_matches(
+            &parsed_with_anys,
+            &type_environment,
+            inference_error.as_ref(),
+            &module_state,
+            &source_map,
+            &raw,
+        );
+        for problem in &match_problems {
+            args.error_format.print(problem, &source_map, &raw, printer);
+        }
+        if let Some(e) = match_error {
+            return Err(SkiffError::Exhaustiveness(e));
+        }
+
+        let result = if args.use_bytecode {
+            vm::run_incremental(&parsed_with_anys, &self.vm_state, &args.limits)
+                .map(|(output, state)| (output, BackendState::Bytecode(state)))
+        } else {
+            interpret::interpret_incremental(
+                &parsed_with_anys,
+                &self.interpreter_state,
+                &args.limits,
+            )
+            .map(|(output, state)| (output, BackendState::TreeWalk(state)))
+        };
+        // Pass on whatever the program printed before it finished (or failed)
+        let _ = write!(printer, "{}", self.output.borrow_mut().take());
+        let (output, backend_state) = match result {
+            Ok(output) => output,
+            Err(interp_error) => {
+                let InterpError(_, _, env, stack, _) = &interp_error;
+                // print the error message and source location
+                let diagnostic = Diagnostic::from(&interp_error);
+                args.error_format
+                    .print(&diagnostic, &source_map, &raw, printer);
+                // The stack trace and environment are only for people to read
+                if args.error_format == ErrorFormat::Human {
+                    StackFrame::print_stack(stack, &source_map, &raw, printer);
+                    let _ = writeln!(printer, "Environment when error occured:\n{:?}", env);
+                }
+
+                return Err(SkiffError::Interpret(interp_error));
+            }
+        };
+
+        // Pair each value with the type inferred for the expression that produced it
+        let types = parsed_with_anys.iter().filter_map(|expr| match expr.node {
+            AstNode::LetNodeTopLevel(_, _)
+            | AstNode::LetPatternNodeTopLevel(_, _)
+            | AstNode::FunctionNode(_, _, _, _, _)
+            | AstNode::DataDeclarationNode(_, _, _)
+            | AstNode::ImportNode(_, _) => None,
+            _ => Some(type_environment.get(&expr.label).cloned()),
+        });
+        let output = output.into_iter().zip(types).collect();
+
+        // Evaluation succeeded, so the program's declarations can be added to the session
+        self.data_declarations.extend(
+            parsed_with_anys
+                .into_iter()
+                .filter(|expr| matches!(expr.node, AstNode::DataDeclarationNode(_, _, _))),
+        );
+        self.source = raw;
+        self.source_map = source_map;
+        self.module_state = module_state;
+        self.data_decl_table = data_decl_table;
+        if let Some(inference_state) = inference_state {
+            self.inference_state = inference_state;
         }
-        let _ = writeln!(printer, "{}", "Type environment:".bright_yellow().bold());
-        let _ = writeln!(printer, "{:?}", type_environment);
-        return Ok(None);
+        // Only the backend that ran the program has new state
+        match backend_state {
+            BackendState::TreeWalk(state) => self.interpreter_state = state,
+            BackendState::Bytecode(state) => self.vm_state = state,
+        }
+
+        return Ok(Some(output));
     }
 
-    // Only perform exhaustiveness checking if the type environment was constructed
-    if type_environment.len() != 0 {
-        match check_program_exhaustiveness(&parsed_with_anys, &type_environment) {
+    /// Checks the matches in a program, returning a warning for each match that's missing
+    /// patterns or has branches that can't be reached, or the error for a match that couldn't
+    /// be checked. Matches are checked even if inference failed, using the types of their
+    /// patterns, and the error isn't reported again if it's about the pattern that inference
+    /// failed on. Data declarations from earlier programs in the session are included.
+    /// Missing patterns name constructors the way the file that contains the match can, using
+    /// the program's modules and the source they were combined into.
+    fn check_matches(
+        &self,
+        program: &Program,
+        type_environment: &HashMap<usize, Term>,
+        inference_error: Option<&Diagnostic>,
+        module_state: &ModuleState,
+        source_map: &SourceMap,
+        source: &str,
+    ) -> (Vec<Diagnostic>, Option<ExhaustivenessError>) {
+        let program_with_declarations = self
+            .data_declarations
+            .iter()
+            .chain(program.iter())
+            .cloned()
+            .collect();
+        match check_program_exhaustiveness(&program_with_declarations, type_environment) {
             Ok(ProgramExhaustivenessReport {
                 non_exhaustive_matches,
+                unreachable_branches,
             }) => {
-                for match_loc in non_exhaustive_matches {
-                    pretty_print_warning(
-                        "Non-exhaustive match expression",
-                        match_loc.span,
-                        raw.borrow(),
-                        args.path.clone(),
-                        printer,
-                    )
-                }
+                let problems = non_exhaustive_matches
+                    .into_iter()
+                    .map(|mut non_exhaustive_match| {
+                        let (path, _, _) =
+                            source_map.locate(&non_exhaustive_match.src_loc.span, source);
+                        for pattern in non_exhaustive_match.missing.iter_mut() {
+                            *pattern = module_state.pattern_in_scope(pattern, &path);
+                        }
+                        Diagnostic::from(&non_exhaustive_match)
+                    })
+                    .chain(unreachable_branches.iter().map(Diagnostic::from))
+                    .collect();
+                (problems, None)
             }
-            Err(ExhaustivenessError::CantMatchAny()) => {}
             Err(e) => {
-                let _ = writeln!(printer, "{:?}", e);
-                return Err(SkiffError::Exhaustiveness(e));
+                let diagnostic = Diagnostic::from(&e);
+                let reported = diagnostic.span.is_some()
+                    && inference_error.map(|error| &error.span) == Some(&diagnostic.span);
+                if reported {
+                    (vec![], Some(e))
+                } else {
+                    (vec![diagnostic], Some(e))
+                }
             }
+        }
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    struct NullPrinter;
+    impl Write for NullPrinter {
+        fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+            Ok(())
+        }
+    }
+
+    fn evaluate_in(
+        session: &mut Session,
+        raw: &str,
+    ) -> Result<Vec<(Val, Option<Term>)>, SkiffError> {
+        let args = CliArgs::new(std::path::PathBuf::from("<test>"));
+        Ok(session
+            .evaluate(&args, raw.to_string(), &mut NullPrinter)?
+            .unwrap())
+    }
+
+    #[test]
+    fn keeps_declarations_between_programs() {
+        let mut session = Session::new();
+        evaluate_in(&mut session, "let x = 5").unwrap();
+        evaluate_in(&mut session, "def double(n): n * 2 end").unwrap();
+        evaluate_in(&mut session, "data Option: | some(v) | none() end").unwrap();
+
+        let output = evaluate_in(&mut session, "x + 1\ndouble(x)\nsome(x)").unwrap();
+        let vals: Vec<Val> = output.into_iter().map(|(val, _)| val).collect();
+        assert_eq!(vals[0], Val::Num(6));
+        assert_eq!(vals[1], Val::Num(10));
+        assert_eq!(format!("{}", vals[2]), "some(5)");
+    }
+
+    #[test]
+    fn infers_types_using_earlier_programs() {
+        let mut session = Session::new();
+        evaluate_in(&mut session, "let flag = true").unwrap();
+
+        let output = evaluate_in(&mut session, "flag and false").unwrap();
+        assert_eq!(output[0].1, Some(Term::boolean()));
+    }
+
+    #[test]
+    fn generalizes_definitions_from_earlier_programs() {
+        let mut session = Session::new();
+        evaluate_in(&mut session, "def identity<T>(x: T) -> T: x end").unwrap();
+        evaluate_in(&mut session, "let apply = lambda(f, x): f(x) end").unwrap();
+
+        let output = evaluate_in(
+            &mut session,
+            "identity(1)\nidentity(\"one\")\napply(lambda(b): b and true end, false)",
+        )
+        .unwrap();
+        assert_eq!(output[0].1, Some(Term::number()));
+        assert_eq!(output[1].1, Some(Term::string()));
+        assert_eq!(output[2].1, Some(Term::boolean()));
+    }
+
+    fn evaluate_with_limits(raw: &str, limits: EvalLimits, use_bytecode: bool) -> InterpError {
+        let mut args = CliArgs::new(std::path::PathBuf::from("<test>"));
+        args.limits = limits;
+        args.use_bytecode = use_bytecode;
+        match Session::new().evaluate(&args, raw.to_string(), &mut NullPrinter) {
+            Err(SkiffError::Interpret(error)) => error,
+            result => panic!("Expected a runtime error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn stops_infinite_loops_after_step_limit() {
+        let limits = EvalLimits {
+            max_steps: Some(10_000),
+            ..EvalLimits::default()
         };
+        for use_bytecode in [false, true] {
+            let error = evaluate_with_limits(
+                "def forever(n): forever(n + 1) end
+forever(0)",
+                limits.clone(),
+                use_bytecode,
+            );
+            assert_eq!(error.0, "Evaluation step limit of 10000 exceeded");
+        }
     }
 
-    let output = match interpret::interpret(&parsed_with_anys) {
-        Ok(output) => output,
-        Err(interp_error) => {
-            let InterpError(msg, span, env, stack) = interp_error.clone();
-            // print the error message and source location
-            error_handling::pretty_print_error(
-                msg.borrow(),
-                span,
-                raw.borrow(),
-                args.path.clone(),
-                printer,
+    #[test]
+    fn limits_call_depth() {
+        let limits = EvalLimits {
+            max_call_depth: Some(50),
+            ..EvalLimits::default()
+        };
+        for use_bytecode in [false, true] {
+            let error = evaluate_with_limits(
+                "def count(n): 1 + count(n + 1) end
+count(0)",
+                limits.clone(),
+                use_bytecode,
             );
-            // print a stack trace
-            StackFrame::print_stack(&stack, &args.path, raw.borrow(), printer);
-            // print the environment
-            let _ = writeln!(printer, "Environment when error occured:\n{:?}", env);
+            assert_eq!(error.0, "Maximum call depth of 50 exceeded");
+            // The stack trace includes the call that went too deep
+            assert_eq!(error.3.len(), 52);
+        }
+    }
 
-            return Err(SkiffError::Interpret(interp_error));
+    #[test]
+    fn stops_calls_that_would_overflow_the_stack() {
+        // Nested calls take far more stack in debug builds, where tests usually run
+        let stack_size = 4 * 1024 * 1024;
+        let limits = EvalLimits {
+            stack_size: Some(stack_size),
+            ..EvalLimits::default()
+        };
+        let error = std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn(move || {
+                let error = evaluate_with_limits(
+                    "def count(n): if n == 0: 0 else: 1 + count(n - 1) end end
+count(100000)",
+                    limits,
+                    false,
+                );
+                error.0
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(error.starts_with("Maximum call depth exceeded: ran out of stack space after"));
+    }
+
+    #[test]
+    fn limits_allocations() {
+        let limits = EvalLimits {
+            max_memory: Some(10_000),
+            ..EvalLimits::default()
+        };
+        for use_bytecode in [false, true] {
+            let error = evaluate_with_limits(
+                "def grow(s): grow(s + s) end
+grow(\"a\")",
+                limits.clone(),
+                use_bytecode,
+            );
+            assert_eq!(error.0, "Memory limit of 10000 bytes exceeded");
         }
-    };
+    }
+
+    #[test]
+    fn keeps_imports_between_programs() {
+        let mut session = Session::new();
+        evaluate_in(
+            &mut session,
+            "import \"tests/files/success/modules/lists.boat\" as l",
+        )
+        .unwrap();
+
+        let output = evaluate_in(&mut session, "l::length(l::link(1, l::empty()))").unwrap();
+        assert_eq!(output[0].0, Val::Num(1));
+        assert_eq!(output[0].1, Some(Term::number()));
+    }
 
-    return Ok(Some(output));
+    #[test]
+    fn prints_to_the_printer() {
+        for use_bytecode in [false, true] {
+            let mut args = CliArgs::new(std::path::PathBuf::from("<test>"));
+            args.use_bytecode = use_bytecode;
+            let mut printed = String::new();
+            let output = Session::new()
+                .evaluate(
+                    &args,
+                    "print(\"hi\")\nprint(1 + 1)".to_string(),
+                    &mut printed,
+                )
+                .unwrap()
+                .unwrap();
+            assert_eq!(printed, "hi\n2\n");
+            assert_eq!(
+                output[0],
+                (Val::Str("hi".to_string()), Some(Term::string()))
+            );
+        }
+    }
+
+    thread_local! {
+        static STREAMED: std::cell::RefCell<Vec<String>> = Default::default();
+    }
+
+    #[test]
+    fn streams_printed_lines_as_theyre_printed() {
+        for use_bytecode in [false, true] {
+            let mut args = CliArgs::new(std::path::PathBuf::from("<test>"));
+            args.use_bytecode = use_bytecode;
+            let mut session = Session::new();
+            session.stream_printed(|line| {
+                STREAMED.with(|lines| lines.borrow_mut().push(line.to_string()))
+            });
+            let mut printed = String::new();
+            let result = session.evaluate(
+                &args,
+                "print(\"hi\")\nprint(1 + 1)\n1 / 0".to_string(),
+                &mut printed,
+            );
+            assert!(result.is_err());
+            assert!(!printed.contains("hi"));
+            let streamed = STREAMED.with(|lines| lines.replace(vec![]));
+            assert_eq!(streamed, vec!["hi", "2"]);
+        }
+    }
+
+    #[test]
+    fn shadows_built_in_functions() {
+        let mut session = Session::new();
+        evaluate_in(&mut session, "def abs(n): n end").unwrap();
+
+        let output = evaluate_in(&mut session, "abs(0 - 1)").unwrap();
+        assert_eq!(output[0].0, Val::Num(-1));
+    }
+
+    #[test]
+    fn discards_declarations_from_failed_programs() {
+        let mut session = Session::new();
+        assert!(evaluate_in(&mut session, "let y = 1\n10(1)").is_err());
+        assert!(evaluate_in(&mut session, "y").is_err());
+    }
+    #[test]
+    fn checks_untyped_values_against_annotations() {
+        let mut session = Session::new();
+        let definitions = "def id(x): x end
+            def inc(n: Number) -> Number: n + 1 end
+            def bad(x) -> String: x end
+            def apply(f: (Number) -> Number, n: Number): f(n) end
+            data Box: | box(v) end
+            def unbox(b: Box): match b: | box(v) => v end end";
+        evaluate_in(&mut session, definitions).unwrap();
+        let error_message = |session: &mut Session, raw: &str| match evaluate_in(session, raw) {
+            Err(SkiffError::Interpret(InterpError(message, _, _, _, _))) => message,
+            result => panic!("Expected a runtime error, got {:?}", result),
+        };
+
+        let output = evaluate_in(&mut session, "inc(id(1))\nunbox(id(box(2)))").unwrap();
+        assert_eq!(output[0].0, Val::Num(2));
+        assert_eq!(output[1].0, Val::Num(2));
+        assert_eq!(
+            error_message(&mut session, "inc(id(true))"),
+            "Expected argument 1 of `inc` to have type Number, but got true"
+        );
+        assert_eq!(
+            error_message(&mut session, "bad(1)"),
+            "Expected `bad` to return a value of type String, but it returned 1"
+        );
+        assert_eq!(
+            error_message(&mut session, "let s: String = id(1)"),
+            "Expected `s` to have type String, but got 1"
+        );
+        assert_eq!(
+            error_message(&mut session, "unbox(id([1]))"),
+            "Expected argument 1 of `unbox` to have type Box, but got [1]"
+        );
+        assert_eq!(
+            error_message(&mut session, "apply(id(lambda(n): n == 1 end), 1)"),
+            "Expected argument 1 of `apply` to return a value of type Number, but it returned true"
+        );
+    }
 }
@@ -5,34 +5,75 @@ use crate::{
     type_inferencer::ast::Term,
 };
 
+/// A match that couldn't be checked, along with the branch (or the whole match) it's about.
+/// Errors found while working through the patterns don't have a location until the branch
+/// they came from is known.
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub enum ExhaustivenessError {
-    UnknownTypeToMatchOn(),
-    UnknownTypeVariant(),
-    CantMatchFunction(),
-    CantMatchAny(),
-    NotEnoughArgsInPattern(),
-    TooManyArgsInPattern(),
+    UnknownTypeToMatchOn(SrcLoc),
+    UnknownTypeVariant(SrcLoc),
+    CantMatchFunction(SrcLoc),
+    NotEnoughArgsInPattern(SrcLoc),
+    TooManyArgsInPattern(SrcLoc),
+}
+impl ExhaustivenessError {
+    pub fn src_loc(&self) -> &SrcLoc {
+        match self {
+            ExhaustivenessError::UnknownTypeToMatchOn(src_loc)
+            | ExhaustivenessError::UnknownTypeVariant(src_loc)
+            | ExhaustivenessError::CantMatchFunction(src_loc)
+            | ExhaustivenessError::NotEnoughArgsInPattern(src_loc)
+            | ExhaustivenessError::TooManyArgsInPattern(src_loc) => src_loc,
+        }
+    }
+
+    /// Points the error at `src_loc` if it doesn't have a location yet
+    fn or_at(mut self, src_loc: &SrcLoc) -> Self {
+        let location = match &mut self {
+            ExhaustivenessError::UnknownTypeToMatchOn(location)
+            | ExhaustivenessError::UnknownTypeVariant(location)
+            | ExhaustivenessError::CantMatchFunction(location)
+            | ExhaustivenessError::NotEnoughArgsInPattern(location)
+            | ExhaustivenessError::TooManyArgsInPattern(location) => location,
+        };
+        if location.span.is_empty() {
+            *location = src_loc.clone();
+        }
+        self
+    }
+}
+
+/// A match expression that doesn't cover every value it could be given
+#[derive(PartialEq, Debug, Clone)]
+pub struct NonExhaustiveMatch {
+    pub src_loc: SrcLoc,
+    /// Patterns for values that none of the branches match
+    pub missing: Vec<Pattern>,
+}
+
+/// A match branch that can't be taken, since the branches before it match every value it does
+#[derive(PartialEq, Debug, Clone)]
+pub struct UnreachableBranch {
+    /// Covers the branch's pattern
+    pub src_loc: SrcLoc,
 }
 
 pub struct ProgramExhaustivenessReport {
-    pub non_exhaustive_matches: Vec<SrcLoc>,
+    pub non_exhaustive_matches: Vec<NonExhaustiveMatch>,
+    pub unreachable_branches: Vec<UnreachableBranch>,
 }
 impl ProgramExhaustivenessReport {
     pub fn new() -> Self {
         Self {
             non_exhaustive_matches: Vec::new(),
-        }
-    }
-    pub fn from_vec(v: Vec<SrcLoc>) -> Self {
-        Self {
-            non_exhaustive_matches: v,
+            unreachable_branches: Vec::new(),
         }
     }
 }
 
 type TypeEnv = HashMap<usize, Term>;
-type DataTable = HashMap<String, Vec<(String, Vec<Type>)>>;
+/// Map from data type name to its type parameters and its variants (with their field types)
+type DataTable = HashMap<String, (Vec<String>, Vec<(String, Vec<Type>)>)>;
 
 pub fn check_program_exhaustiveness(
     program: &Program,
@@ -41,59 +82,177 @@ pub fn check_program_exhaustiveness(
     let mut data_table: DataTable = HashMap::new();
     for expr in program {
         match &expr.node {
-            AstNode::DataDeclarationNode(name, variants) => {
+            AstNode::DataDeclarationNode(name, type_params, variants) => {
                 data_table.insert(
                     name.to_string(),
-                    variants
-                        .iter()
-                        .map(|(name, fields)| {
-                            (
-                                name.to_string(),
-                                fields
-                                    .iter()
-                                    .map(|id| match &id.type_decl {
-                                        Some(t) => t.clone(),
-                                        None => Type::new_any(),
-                                    })
-                                    .collect(),
-                            )
-                        })
-                        .collect(),
+                    (
+                        type_params.clone(),
+                        variants
+                            .iter()
+                            .map(|(name, fields)| {
+                                (
+                                    name.to_string(),
+                                    fields
+                                        .iter()
+                                        .map(|id| match &id.type_decl {
+                                            Some(t) => t.clone(),
+                                            None => Type::new_any(),
+                                        })
+                                        .collect(),
+                                )
+                            })
+                            .collect(),
+                    ),
                 );
             }
             _ => (),
         };
     }
 
-    let mut non_exhaustive_matches = vec![];
+    let mut report = ProgramExhaustivenessReport::new();
     for statement in program {
         for expr in statement.into_vec() {
             match &expr.node {
                 AstNode::MatchNode(target, branches) => {
-                    if let Some(term) = type_table.get(&target.label) {
-                        if let Some(t) = term.clone().into_type() {
-                            let is_exhaustive = check_pattern_exhaustiveness(
-                                &t,
-                                &branches
-                                    .iter()
-                                    .map(|(pattern, _)| pattern)
-                                    .cloned()
-                                    .collect(),
-                                &data_table,
-                            )?;
-                            if !is_exhaustive {
-                                non_exhaustive_matches.push(expr.src_loc.clone());
-                            }
-                        }
+                    // The type of a value that inference didn't find (because it failed, or
+                    // the value is gradually typed) is worked out from the patterns
+                    let t = match type_table.get(&target.label) {
+                        Some(term) => term_to_type(term),
+                        None => Type::new_any(),
+                    };
+                    let check = check_match_branches(
+                        &t,
+                        &branches
+                            .iter()
+                            .map(|(pattern, guard, _, pattern_span)| {
+                                let src_loc = SrcLoc {
+                                    span: pattern_span.clone(),
+                                };
+                                (pattern.clone(), guard.is_some(), src_loc)
+                            })
+                            .collect::<Vec<_>>(),
+                        &data_table,
+                    )
+                    .map_err(|error| error.or_at(&expr.src_loc))?;
+                    if !check.missing.is_empty() {
+                        report.non_exhaustive_matches.push(NonExhaustiveMatch {
+                            src_loc: expr.src_loc.clone(),
+                            missing: check.missing,
+                        });
+                    }
+                    for i in check.unreachable {
+                        let (_, _, _, pattern_span) = &branches[i];
+                        report.unreachable_branches.push(UnreachableBranch {
+                            src_loc: SrcLoc {
+                                span: pattern_span.clone(),
+                            },
+                        });
                     }
                 }
                 _ => (),
             }
         }
     }
-    return Ok(ProgramExhaustivenessReport::from_vec(
-        non_exhaustive_matches,
-    ));
+    return Ok(report);
+}
+
+/// Converts a term to a type, treating any unsolved type variables as `Any`
+fn term_to_type(term: &Term) -> Type {
+    match term {
+        Term::Var(_) => Type::new_any(),
+        Term::Constructor(id, args) => {
+            Type::new(id.clone(), args.iter().map(term_to_type).collect())
+        }
+        Term::Record(fields) => Type::Record(
+            fields
+                .iter()
+                .map(|(name, term)| (name.clone(), term_to_type(term)))
+                .collect(),
+        ),
+    }
+}
+
+/// A way of building a value that patterns can tell apart from the other ways of building
+/// values of its type. Patterns match on the constructor of a value and then on its fields.
+#[derive(PartialEq, Debug, Clone)]
+enum Constructor {
+    Boolean(bool),
+    Number(i64),
+    String(String),
+    Variant(String),
+    /// (item count)
+    Tuple(usize),
+    /// (field names) Every record of a type has the same fields
+    Record(Vec<String>),
+    EmptyList,
+    /// A list with a first item, whose fields are the item and the rest of the list
+    NonEmptyList,
+}
+
+impl Constructor {
+    fn is_list(&self) -> bool {
+        matches!(self, Self::EmptyList | Self::NonEmptyList)
+    }
+}
+
+/// What checking the branches of a match expression found
+#[derive(PartialEq, Debug)]
+pub struct MatchCheck {
+    /// Patterns for values that none of the branches match, which is empty if the match is
+    /// exhaustive
+    pub missing: Vec<Pattern>,
+    /// The positions of the branches that can't match any value that the branches before
+    /// them don't
+    pub unreachable: Vec<usize>,
+}
+
+/// Checks the patterns of a match's branches against the type of the matched value. Each
+/// branch has a pattern, whether it has a guard and where its pattern is, which errors in the
+/// pattern are reported at. A guard could always be false, so guarded branches don't cover
+/// any values for the branches after them or for the match as a whole, although they can
+/// still be unreachable themselves.
+pub fn check_match_branches(
+    target_type: &Type,
+    branches: &[(Pattern, bool, SrcLoc)],
+    data_table: &DataTable,
+) -> Result<MatchCheck, ExhaustivenessError> {
+    let types = [target_type.clone()];
+    // Rows of patterns for the values that the branches so far definitely match
+    let mut rows: Vec<Vec<Pattern>> = vec![];
+    let mut unreachable = vec![];
+    for (i, (pattern, has_guard, src_loc)) in branches.iter().enumerate() {
+        // A branch with an or-pattern is reachable if any of its alternatives is
+        let alternatives = alternatives(pattern);
+        let mut reachable = false;
+        for alternative in &alternatives {
+            if !uncovered(&rows, std::slice::from_ref(alternative), &types, data_table)
+                .map_err(|error| error.or_at(src_loc))?
+                .is_empty()
+            {
+                reachable = true;
+                break;
+            }
+        }
+        if !reachable {
+            unreachable.push(i);
+        }
+        if !has_guard {
+            rows.extend(
+                alternatives
+                    .into_iter()
+                    .map(|alternative| vec![alternative]),
+            );
+        }
+    }
+
+    let missing = uncovered(&rows, &[wildcard()], &types, data_table)?
+        .into_iter()
+        .map(|mut witness| witness.remove(0))
+        .collect();
+    Ok(MatchCheck {
+        missing,
+        unreachable,
+    })
 }
 
 pub fn check_pattern_exhaustiveness<'a>(
@@ -101,80 +260,438 @@ pub fn check_pattern_exhaustiveness<'a>(
     patterns: &Vec<Pattern>,
     data_table: &DataTable,
 ) -> Result<bool, ExhaustivenessError> {
-    match target_type {
-        Type { id, .. } if id == "Number" => {
-            if patterns.iter().any(|x| x.is_identifier()) {
-                Ok(true)
+    let branches: Vec<(Pattern, bool, SrcLoc)> = patterns
+        .iter()
+        .map(|pattern| (pattern.clone(), false, SrcLoc::default()))
+        .collect();
+    Ok(check_match_branches(target_type, &branches, data_table)?
+        .missing
+        .is_empty())
+}
+
+/// Finds the values that a row of patterns matches but none of the rows of a matrix do, where
+/// each row has a pattern for a value of each of the types. This is the usefulness check from
+/// Maranget's "Warnings for pattern matching": the rows are split up by the constructor of
+/// the first value, and the row is useful for a constructor if it's useful against the rows
+/// that match the constructor once they're replaced by the patterns for its fields. Returns a
+/// row of patterns (a witness) for each kind of value the matrix misses, or nothing if the row
+/// isn't useful. The patterns can't contain or-patterns or variables.
+fn uncovered(
+    matrix: &[Vec<Pattern>],
+    row: &[Pattern],
+    types: &[Type],
+    data_table: &DataTable,
+) -> Result<Vec<Vec<Pattern>>, ExhaustivenessError> {
+    let (first_type, rest_types) = match types.split_first() {
+        Some(split) => split,
+        // An empty row matches the only combination of no values, unless a row already does
+        None => {
+            return Ok(if matrix.is_empty() {
+                vec![vec![]]
             } else {
-                Ok(false)
+                vec![]
+            })
+        }
+    };
+    let column: Vec<&Pattern> = matrix
+        .iter()
+        .map(|row| &row[0])
+        .chain(std::iter::once(&row[0]))
+        .collect();
+    let record_fields = record_fields(first_type, &column);
+    let heads: Vec<Constructor> = column
+        .iter()
+        .filter_map(|pattern| head(pattern, &record_fields))
+        .collect();
+    let constructors = type_constructors(first_type, &heads, data_table)?;
+
+    if let Some(constructor) = head(&row[0], &record_fields) {
+        let field_types = match &constructors {
+            Some(constructors) => constructors
+                .iter()
+                .find(|(other, _)| *other == constructor)
+                .map(|(_, field_types)| field_types.clone())
+                .ok_or_else(|| ExhaustivenessError::UnknownTypeVariant(SrcLoc::default()))?,
+            None => unknown_field_types(&constructor, &row[0]),
+        };
+        return uncovered_for_constructor(
+            matrix,
+            row,
+            &constructor,
+            &field_types,
+            rest_types,
+            data_table,
+        );
+    }
+
+    // Every constructor of a type with a fixed set of them is checked, so that values are
+    // listed for each constructor the rows miss any values of
+    if let Some(constructors) = constructors {
+        let mut witnesses = vec![];
+        for (constructor, field_types) in constructors {
+            witnesses.extend(uncovered_for_constructor(
+                matrix,
+                row,
+                &constructor,
+                &field_types,
+                rest_types,
+                data_table,
+            )?);
+        }
+        return Ok(witnesses);
+    }
+
+    // Values of other types can always be built with a constructor that none of the rows match
+    // on, so only the rows that match any value can cover them
+    let wildcard_rows: Vec<Vec<Pattern>> = matrix
+        .iter()
+        .filter(|row| head(&row[0], &record_fields).is_none())
+        .map(|row| row[1..].to_vec())
+        .collect();
+    Ok(
+        uncovered(&wildcard_rows, &row[1..], rest_types, data_table)?
+            .into_iter()
+            .map(|witness| [vec![wildcard()], witness].concat())
+            .collect(),
+    )
+}
+
+/// Finds the values built with a constructor that a row matches but a matrix doesn't, by
+/// checking the patterns for the constructor's fields in place of the first pattern of each
+/// row
+fn uncovered_for_constructor(
+    matrix: &[Vec<Pattern>],
+    row: &[Pattern],
+    constructor: &Constructor,
+    field_types: &[Type],
+    rest_types: &[Type],
+    data_table: &DataTable,
+) -> Result<Vec<Vec<Pattern>>, ExhaustivenessError> {
+    let mut constructor_rows = vec![];
+    for matrix_row in matrix {
+        if let Some(matrix_row) = specialize_row(matrix_row, constructor, field_types.len())? {
+            constructor_rows.push(matrix_row);
+        }
+    }
+    let row = match specialize_row(row, constructor, field_types.len())? {
+        Some(row) => row,
+        None => return Ok(vec![]),
+    };
+    let types = [field_types, rest_types].concat();
+    Ok(uncovered(&constructor_rows, &row, &types, data_table)?
+        .into_iter()
+        .map(|mut witness| {
+            let rest = witness.split_off(field_types.len());
+            [vec![rebuild(constructor, witness)], rest].concat()
+        })
+        .collect())
+}
+
+/// Returns each constructor of a type along with the types of its fields, or None if the type
+/// doesn't have a fixed set of constructors. Types are only looked up when some pattern
+/// matches on a constructor of them.
+fn type_constructors(
+    t: &Type,
+    heads: &[Constructor],
+    data_table: &DataTable,
+) -> Result<Option<Vec<(Constructor, Vec<Type>)>>, ExhaustivenessError> {
+    if heads.is_empty() {
+        return Ok(None);
+    }
+    let constructors = match t {
+        Type::Constructor(id, _) if id == "Any" => match type_of_constructors(heads, data_table) {
+            Some(t) => return type_constructors(&t, heads, data_table),
+            None => return Ok(None),
+        },
+        Type::Constructor(id, _) if id == "Number" || id == "String" => return Ok(None),
+        Type::Constructor(id, _) if id == "Function" => {
+            return Err(ExhaustivenessError::CantMatchFunction(SrcLoc::default()))
+        }
+        Type::Constructor(id, _) if id == "Boolean" => vec![
+            (Constructor::Boolean(true), vec![]),
+            (Constructor::Boolean(false), vec![]),
+        ],
+        Type::Constructor(id, args) if id == "Tuple" => vec![(
+            Constructor::Tuple(args.len()),
+            args.iter().cloned().collect(),
+        )],
+        Type::Record(fields) => vec![(
+            Constructor::Record(fields.iter().map(|(name, _)| name.clone()).collect()),
+            fields.iter().map(|(_, t)| t.clone()).collect(),
+        )],
+        // A user-declared `List` type is matched with its variants rather than list patterns
+        Type::Constructor(id, args)
+            if id == "List"
+                && (heads.iter().any(Constructor::is_list) || !data_table.contains_key(id)) =>
+        {
+            let item_type = args.get(0).cloned().unwrap_or_else(Type::new_any);
+            vec![
+                (Constructor::EmptyList, vec![]),
+                (
+                    Constructor::NonEmptyList,
+                    vec![item_type.clone(), Type::new_list(item_type)],
+                ),
+            ]
+        }
+        Type::Constructor(id, args) => {
+            let (type_params, variants) = data_table
+                .get(id)
+                .ok_or_else(|| ExhaustivenessError::UnknownTypeToMatchOn(SrcLoc::default()))?;
+            variants
+                .iter()
+                .map(|(variant, field_types)| {
+                    (
+                        Constructor::Variant(variant.clone()),
+                        field_types
+                            .iter()
+                            .map(|field_type| field_type.substitute_params(type_params, args))
+                            .collect(),
+                    )
+                })
+                .collect()
+        }
+    };
+    Ok(Some(constructors))
+}
+
+/// Works out the type of a value of type Any from the constructors that patterns match it
+/// against, assuming the patterns were written for values of one type. Type arguments are
+/// unknown, so they're Any. Returns None if the constructors don't all belong to one type.
+fn type_of_constructors(heads: &[Constructor], data_table: &DataTable) -> Option<Type> {
+    let mut types = heads.iter().map(|head| match head {
+        Constructor::Boolean(_) => Some(Type::new_boolean()),
+        Constructor::Number(_) => Some(Type::new_number()),
+        Constructor::String(_) => Some(Type::new_string()),
+        Constructor::Tuple(count) => Some(Type::new_tuple(
+            std::iter::repeat(Type::new_any()).take(*count).collect(),
+        )),
+        Constructor::Record(names) => Some(Type::new_record(
+            names
+                .iter()
+                .map(|name| (name.clone(), Type::new_any()))
+                .collect(),
+        )),
+        Constructor::EmptyList | Constructor::NonEmptyList => Some(Type::new_list(Type::new_any())),
+        // The variant has to belong to exactly one data type
+        Constructor::Variant(name) => {
+            let mut declaring = data_table
+                .iter()
+                .filter(|(_, (_, variants))| variants.iter().any(|(variant, _)| variant == name));
+            match (declaring.next(), declaring.next()) {
+                (Some((id, (type_params, _))), None) => Some(Type::new(
+                    id.clone(),
+                    type_params.iter().map(|_| Type::new_any()).collect(),
+                )),
+                _ => None,
             }
         }
-        Type { id, .. } if id == "Boolean" => {
-            if patterns.iter().any(|x| x.is_identifier())
-                || (patterns.iter().any(|x| *x == Pattern::BoolLiteral(true))
-                    && patterns.iter().any(|x| *x == Pattern::BoolLiteral(false)))
-            {
-                Ok(true)
+    });
+    let first = types.next()??;
+    for t in types {
+        if t.as_ref() != Some(&first) {
+            return None;
+        }
+    }
+    Some(first)
+}
+
+/// Returns the names of the fields of the records in a column of patterns. When the type
+/// isn't known, the records have at least the fields that any of the patterns name.
+fn record_fields(t: &Type, column: &[&Pattern]) -> Vec<String> {
+    if let Type::Record(fields) = t {
+        return fields.iter().map(|(name, _)| name.clone()).collect();
+    }
+    let mut fields: Vec<String> = column
+        .iter()
+        .flat_map(|pattern| match pattern {
+            Pattern::Record(fields) => fields.iter().map(|(name, _)| name.clone()).collect(),
+            _ => vec![],
+        })
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+/// Returns the constructor a pattern matches on, or None if it matches any value
+fn head(pattern: &Pattern, record_fields: &[String]) -> Option<Constructor> {
+    match pattern {
+        Pattern::BoolLiteral(b) => Some(Constructor::Boolean(*b)),
+        Pattern::NumLiteral(n) => Some(Constructor::Number(*n)),
+        Pattern::StringLiteral(s) => Some(Constructor::String(s.clone())),
+        Pattern::Data(name, _) => Some(Constructor::Variant(name.clone())),
+        Pattern::Tuple(items) => Some(Constructor::Tuple(items.len())),
+        Pattern::Record(_) => Some(Constructor::Record(record_fields.to_vec())),
+        Pattern::List(items, _) if items.is_empty() => Some(Constructor::EmptyList),
+        Pattern::List(..) => Some(Constructor::NonEmptyList),
+        _ => None,
+    }
+}
+
+/// The types of the fields of a constructor that a pattern matches on when the type of the
+/// value isn't known
+fn unknown_field_types(constructor: &Constructor, pattern: &Pattern) -> Vec<Type> {
+    match (constructor, pattern) {
+        (Constructor::Variant(_), Pattern::Data(_, fields)) => vec![Type::new_any(); fields.len()],
+        (Constructor::Tuple(count), _) => vec![Type::new_any(); *count],
+        (Constructor::Record(names), _) => vec![Type::new_any(); names.len()],
+        (Constructor::NonEmptyList, _) => vec![Type::new_any(), Type::new_list(Type::new_any())],
+        _ => vec![],
+    }
+}
+
+/// Replaces the first pattern of a row with the patterns for the fields of a constructor, or
+/// returns None if the row can't match the constructor. A wildcard matches any fields.
+fn specialize_row(
+    row: &[Pattern],
+    constructor: &Constructor,
+    field_count: usize,
+) -> Result<Option<Vec<Pattern>>, ExhaustivenessError> {
+    let fields = match (&row[0], constructor) {
+        (Pattern::Wildcard, _) => vec![wildcard(); field_count],
+        (Pattern::BoolLiteral(a), Constructor::Boolean(b)) if a == b => vec![],
+        (Pattern::NumLiteral(a), Constructor::Number(b)) if a == b => vec![],
+        (Pattern::StringLiteral(a), Constructor::String(b)) if a == b => vec![],
+        (Pattern::Data(a, fields), Constructor::Variant(b)) if a == b => fields.clone(),
+        (Pattern::Tuple(items), Constructor::Tuple(_)) => items.clone(),
+        // A record pattern without one of the fields matches any value of it
+        (Pattern::Record(fields), Constructor::Record(names)) => names
+            .iter()
+            .map(
+                |name| match fields.iter().find(|(field, _)| field == name) {
+                    Some((_, pattern)) => pattern.clone(),
+                    None => wildcard(),
+                },
+            )
+            .collect(),
+        (Pattern::List(items, None), Constructor::EmptyList) if items.is_empty() => vec![],
+        (Pattern::List(items, rest), Constructor::NonEmptyList) if !items.is_empty() => vec![
+            items[0].clone(),
+            list_pattern(items[1..].to_vec(), rest.clone()),
+        ],
+        _ => return Ok(None),
+    };
+    if fields.len() < field_count {
+        return Err(ExhaustivenessError::NotEnoughArgsInPattern(
+            SrcLoc::default(),
+        ));
+    } else if fields.len() > field_count {
+        return Err(ExhaustivenessError::TooManyArgsInPattern(SrcLoc::default()));
+    }
+    Ok(Some([fields, row[1..].to_vec()].concat()))
+}
+
+/// Builds a pattern that matches a constructor with the given patterns for its fields
+fn rebuild(constructor: &Constructor, fields: Vec<Pattern>) -> Pattern {
+    match constructor {
+        Constructor::Boolean(b) => Pattern::BoolLiteral(*b),
+        Constructor::Number(n) => Pattern::NumLiteral(*n),
+        Constructor::String(s) => Pattern::StringLiteral(s.clone()),
+        Constructor::Variant(name) => Pattern::Data(name.clone(), fields),
+        Constructor::Tuple(_) => Pattern::Tuple(fields),
+        // Fields that can have any value are left out
+        Constructor::Record(names) => {
+            let fields: Vec<(String, Pattern)> = names
+                .iter()
+                .cloned()
+                .zip(fields)
+                .filter(|(_, pattern)| !pattern.is_wildcard())
+                .collect();
+            if fields.is_empty() {
+                wildcard()
             } else {
-                Ok(false)
+                Pattern::Record(fields)
             }
         }
-        Type { id, .. } if id == "Function" => Err(ExhaustivenessError::CantMatchFunction()),
-        Type { id, .. } if id == "Any" => Err(ExhaustivenessError::CantMatchAny()),
-        Type { id, .. } => {
-            if patterns.iter().any(|x| x.is_identifier()) {
-                Ok(true)
-            } else {
-                if let Some(variants) = data_table.get(id) {
-                    for (variant_name, variant_types) in variants {
-                        let args_of_valid_patterns: Vec<&Vec<Pattern>> = patterns
-                            .iter()
-                            .filter_map(|pattern| match pattern {
-                                Pattern::Data(name, pattern_args) => {
-                                    if name == variant_name {
-                                        Some(pattern_args)
-                                    } else {
-                                        None
-                                    }
-                                }
-                                _ => None,
-                            })
-                            .collect();
-
-                        // If there aren't any patterns that match this variant then the match is not exhaustive
-                        if args_of_valid_patterns.len() == 0 {
-                            return Ok(false);
-                        }
-
-                        for (i, type_arg) in variant_types.iter().enumerate() {
-                            let args_for_this_match: Vec<&Pattern> = args_of_valid_patterns
-                                .iter()
-                                .map(|pattern_args| match pattern_args.get(i) {
-                                    Some(v) => Ok(v),
-                                    None => Err(ExhaustivenessError::NotEnoughArgsInPattern()),
-                                })
-                                .collect::<Result<Vec<&Pattern>, ExhaustivenessError>>()?;
-
-                            if check_pattern_exhaustiveness(
-                                type_arg,
-                                &args_for_this_match.into_iter().cloned().collect(),
-                                data_table,
-                            )? {
-                                continue;
-                            } else {
-                                return Ok(false);
-                            }
-                        }
-                    }
-                    return Ok(true);
-                } else {
-                    Err(ExhaustivenessError::UnknownTypeToMatchOn())
+        Constructor::EmptyList => Pattern::List(vec![], None),
+        Constructor::NonEmptyList => {
+            let mut fields = fields.into_iter();
+            let first = fields.next().unwrap_or_else(wildcard);
+            match fields.next() {
+                Some(Pattern::List(mut items, rest)) => {
+                    items.insert(0, first);
+                    Pattern::List(items, rest)
                 }
+                rest => Pattern::List(vec![first], rest.map(Box::new)),
             }
         }
     }
 }
 
+/// Builds a list pattern, which is just the pattern for the rest of the list if there aren't
+/// any items before it
+fn list_pattern(items: Vec<Pattern>, rest: Option<Box<Pattern>>) -> Pattern {
+    match rest {
+        Some(rest) if items.is_empty() => *rest,
+        _ => Pattern::List(items, rest),
+    }
+}
+
+/// Splits a pattern into patterns without or-patterns that match the same values between them,
+/// replacing the variables it binds with wildcards
+fn alternatives(pattern: &Pattern) -> Vec<Pattern> {
+    match pattern {
+        Pattern::Identifier(_) | Pattern::Wildcard => vec![wildcard()],
+        Pattern::As(pattern, _) => alternatives(pattern),
+        Pattern::Or(patterns) => patterns.iter().flat_map(alternatives).collect(),
+        Pattern::NumLiteral(_) | Pattern::BoolLiteral(_) | Pattern::StringLiteral(_) => {
+            vec![pattern.clone()]
+        }
+        Pattern::Data(name, patterns) => combinations(patterns)
+            .into_iter()
+            .map(|patterns| Pattern::Data(name.clone(), patterns))
+            .collect(),
+        Pattern::Tuple(patterns) => combinations(patterns)
+            .into_iter()
+            .map(Pattern::Tuple)
+            .collect(),
+        Pattern::List(patterns, rest) => {
+            let all: Vec<Pattern> = patterns.iter().chain(rest.as_deref()).cloned().collect();
+            combinations(&all)
+                .into_iter()
+                .map(|mut patterns| {
+                    let rest = rest.as_ref().map(|_| Box::new(patterns.pop().unwrap()));
+                    list_pattern(patterns, rest)
+                })
+                .collect()
+        }
+        Pattern::Record(fields) => {
+            let patterns: Vec<Pattern> =
+                fields.iter().map(|(_, pattern)| pattern.clone()).collect();
+            combinations(&patterns)
+                .into_iter()
+                .map(|patterns| {
+                    let names = fields.iter().map(|(field, _)| field.clone());
+                    Pattern::Record(names.zip(patterns).collect())
+                })
+                .collect()
+        }
+    }
+}
+
+/// Returns every way of picking one alternative of each pattern
+fn combinations(patterns: &[Pattern]) -> Vec<Vec<Pattern>> {
+    let mut combinations = vec![vec![]];
+    for pattern in patterns {
+        let choices = alternatives(pattern);
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                choices.iter().map(move |choice| {
+                    let mut combination = combination.clone();
+                    combination.push(choice.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+fn wildcard() -> Pattern {
+    Pattern::Wildcard
+}
+
 #[cfg(test)]
 mod exhaustiveness_tests {
     use im::vector;
@@ -205,6 +722,32 @@ mod exhaustiveness_tests {
         assert_eq!(result, expected_output);
     }
 
+    #[test]
+    fn fails_string_without_identifier() {
+        let input_type = Type::new_string();
+        let input_patterns: Vec<Pattern> = vec![
+            Pattern::StringLiteral("a".to_string()),
+            Pattern::StringLiteral("b".to_string()),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        let expected_output = Ok(false);
+        assert_eq!(result, expected_output);
+    }
+
+    #[test]
+    fn passes_string_with_identifier() {
+        let input_type = Type::new_string();
+        let input_patterns: Vec<Pattern> = vec![
+            Pattern::StringLiteral("a".to_string()),
+            Pattern::Identifier("s".to_string()),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        let expected_output = Ok(true);
+        assert_eq!(result, expected_output);
+    }
+
     #[test]
     fn fails_baseline_boolean() {
         let input_type = Type::new_boolean();
@@ -249,10 +792,13 @@ mod exhaustiveness_tests {
         ];
         let type_table: DataTable = vec![(
             "Option".to_string(),
-            vec![
-                ("some".to_string(), vec![Type::new_number()]),
-                ("none".to_string(), vec![]),
-            ],
+            (
+                vec![],
+                vec![
+                    ("some".to_string(), vec![Type::new_number()]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -268,16 +814,21 @@ mod exhaustiveness_tests {
         let input_patterns: Vec<Pattern> = vec![Pattern::Data("some".to_string(), vec![])];
         let type_table: DataTable = vec![(
             "Option".to_string(),
-            vec![
-                ("some".to_string(), vec![Type::new_number()]),
-                ("none".to_string(), vec![]),
-            ],
+            (
+                vec![],
+                vec![
+                    ("some".to_string(), vec![Type::new_number()]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
 
         let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &type_table);
-        let expected_output = Err(ExhaustivenessError::NotEnoughArgsInPattern());
+        let expected_output = Err(ExhaustivenessError::NotEnoughArgsInPattern(
+            SrcLoc::default(),
+        ));
         assert_eq!(result, expected_output);
     }
 
@@ -287,10 +838,13 @@ mod exhaustiveness_tests {
         let input_patterns: Vec<Pattern> = vec![Pattern::Identifier("_".to_string())];
         let type_table: DataTable = vec![(
             "Option".to_string(),
-            vec![
-                ("some".to_string(), vec![Type::new_number()]),
-                ("none".to_string(), vec![]),
-            ],
+            (
+                vec![],
+                vec![
+                    ("some".to_string(), vec![Type::new_number()]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -306,14 +860,17 @@ mod exhaustiveness_tests {
         let input_patterns: Vec<Pattern> = vec![
             Pattern::Data("some".to_string(), vec![Pattern::NumLiteral(10)]),
             Pattern::Data("some".to_string(), vec![Pattern::NumLiteral(20)]),
-            Pattern::Data("none".to_string(), vec![Pattern::BoolLiteral(false)]),
+            Pattern::Data("none".to_string(), vec![]),
         ];
         let type_table: DataTable = vec![(
             "Option".to_string(),
-            vec![
-                ("some".to_string(), vec![Type::new_number()]),
-                ("none".to_string(), vec![]),
-            ],
+            (
+                vec![],
+                vec![
+                    ("some".to_string(), vec![Type::new_number()]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -333,14 +890,17 @@ mod exhaustiveness_tests {
                 "some".to_string(),
                 vec![Pattern::Identifier("_".to_string())],
             ),
-            Pattern::Data("none".to_string(), vec![Pattern::BoolLiteral(false)]),
+            Pattern::Data("none".to_string(), vec![]),
         ];
         let type_table: DataTable = vec![(
             "Option".to_string(),
-            vec![
-                ("some".to_string(), vec![Type::new_number()]),
-                ("none".to_string(), vec![]),
-            ],
+            (
+                vec![],
+                vec![
+                    ("some".to_string(), vec![Type::new_number()]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -367,13 +927,16 @@ mod exhaustiveness_tests {
         ];
         let type_table: DataTable = vec![(
             "MaybeBools".to_string(),
-            vec![
-                ("one".to_string(), vec![Type::new_boolean()]),
-                (
-                    "two".to_string(),
-                    vec![Type::new_boolean(), Type::new_boolean()],
-                ),
-            ],
+            (
+                vec![],
+                vec![
+                    ("one".to_string(), vec![Type::new_boolean()]),
+                    (
+                        "two".to_string(),
+                        vec![Type::new_boolean(), Type::new_boolean()],
+                    ),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -391,7 +954,10 @@ mod exhaustiveness_tests {
             Pattern::Data("one".to_string(), vec![Pattern::BoolLiteral(false)]),
             Pattern::Data(
                 "two".to_string(),
-                vec![Pattern::BoolLiteral(false), Pattern::BoolLiteral(false)],
+                vec![
+                    Pattern::BoolLiteral(false),
+                    Pattern::Identifier("b".to_string()),
+                ],
             ),
             Pattern::Data(
                 "two".to_string(),
@@ -403,13 +969,16 @@ mod exhaustiveness_tests {
         ];
         let type_table: DataTable = vec![(
             "MaybeBools".to_string(),
-            vec![
-                ("one".to_string(), vec![Type::new_boolean()]),
-                (
-                    "two".to_string(),
-                    vec![Type::new_boolean(), Type::new_boolean()],
-                ),
-            ],
+            (
+                vec![],
+                vec![
+                    ("one".to_string(), vec![Type::new_boolean()]),
+                    (
+                        "two".to_string(),
+                        vec![Type::new_boolean(), Type::new_boolean()],
+                    ),
+                ],
+            ),
         )]
         .into_iter()
         .collect();
@@ -418,4 +987,369 @@ mod exhaustiveness_tests {
         let expected_output = Ok(true);
         assert_eq!(result, expected_output);
     }
+
+    #[test]
+    fn substitutes_type_arguments_into_fields() {
+        let input_type = Type::new("Option".to_string(), vector![Type::new_boolean()]);
+        let input_patterns: Vec<Pattern> = vec![
+            Pattern::Data("some".to_string(), vec![Pattern::BoolLiteral(true)]),
+            Pattern::Data("none".to_string(), vec![]),
+        ];
+        let type_table: DataTable = vec![(
+            "Option".to_string(),
+            (
+                vec!["T".to_string()],
+                vec![
+                    ("some".to_string(), vec![Type::new_unit("T".to_string())]),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
+        )]
+        .into_iter()
+        .collect();
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &type_table);
+        let expected_output = Ok(false);
+        assert_eq!(result, expected_output);
+    }
+
+    #[test]
+    fn passes_list_with_empty_and_rest_patterns() {
+        let input_type = Type::new_list(Type::new_number());
+        let input_patterns: Vec<Pattern> = vec![
+            Pattern::List(vec![], None),
+            Pattern::List(vec![wildcard()], Some(Box::new(wildcard()))),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(true));
+    }
+
+    #[test]
+    fn fails_list_without_longer_lists() {
+        let input_type = Type::new_list(Type::new_number());
+        let input_patterns: Vec<Pattern> = vec![
+            Pattern::List(vec![], None),
+            Pattern::List(vec![wildcard()], None),
+            Pattern::List(vec![wildcard(), wildcard()], None),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+    }
+
+    #[test]
+    fn fails_list_without_empty_list() {
+        let input_type = Type::new_list(Type::new_number());
+        let input_patterns: Vec<Pattern> =
+            vec![Pattern::List(vec![wildcard()], Some(Box::new(wildcard())))];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+    }
+
+    #[test]
+    fn checks_list_items_against_item_type() {
+        let input_type = Type::new_list(Type::new_boolean());
+        let mut input_patterns: Vec<Pattern> = vec![
+            Pattern::List(vec![], None),
+            Pattern::List(vec![Pattern::BoolLiteral(true)], Some(Box::new(wildcard()))),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+
+        // `[false, ...[]]` and `[false, ...[_, ..._]]` cover the rest of the lists
+        input_patterns.push(Pattern::List(
+            vec![Pattern::BoolLiteral(false)],
+            Some(Box::new(Pattern::List(vec![], None))),
+        ));
+        input_patterns.push(Pattern::List(
+            vec![Pattern::BoolLiteral(false)],
+            Some(Box::new(Pattern::List(
+                vec![wildcard()],
+                Some(Box::new(wildcard())),
+            ))),
+        ));
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(true));
+    }
+
+    #[test]
+    fn checks_record_fields() {
+        let input_type = Type::new_record(vec![
+            ("a".to_string(), Type::new_boolean()),
+            ("b".to_string(), Type::new_number()),
+        ]);
+        let mut input_patterns: Vec<Pattern> = vec![Pattern::Record(vec![
+            ("a".to_string(), Pattern::BoolLiteral(true)),
+            ("b".to_string(), wildcard()),
+        ])];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+
+        // A record pattern that leaves out `b` matches any value of it
+        input_patterns.push(Pattern::Record(vec![(
+            "a".to_string(),
+            Pattern::BoolLiteral(false),
+        )]));
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(true));
+    }
+
+    #[test]
+    fn checks_combinations_of_tuple_items() {
+        let input_type = Type::new_tuple(vector![Type::new_boolean(), Type::new_boolean()]);
+        let mut input_patterns: Vec<Pattern> = vec![
+            Pattern::Tuple(vec![Pattern::BoolLiteral(true), wildcard()]),
+            Pattern::Tuple(vec![
+                Pattern::BoolLiteral(false),
+                Pattern::BoolLiteral(true),
+            ]),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+
+        input_patterns.push(Pattern::Tuple(vec![
+            Pattern::BoolLiteral(false),
+            Pattern::BoolLiteral(false),
+        ]));
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(true));
+    }
+
+    #[test]
+    fn checks_data_types_in_tuples() {
+        let option_type = Type::new("Option".to_string(), vector![Type::new_boolean()]);
+        let input_type = Type::new_tuple(vector![option_type.clone(), option_type]);
+        let type_table: DataTable = vec![(
+            "Option".to_string(),
+            (
+                vec!["T".to_string()],
+                vec![
+                    (
+                        "some".to_string(),
+                        vec![Type::new("T".to_string(), vector![])],
+                    ),
+                    ("none".to_string(), vec![]),
+                ],
+            ),
+        )]
+        .into_iter()
+        .collect();
+        let some = |pattern| Pattern::Data("some".to_string(), vec![pattern]);
+        let none = || Pattern::Data("none".to_string(), vec![]);
+        let mut input_patterns: Vec<Pattern> = vec![
+            Pattern::Tuple(vec![none(), wildcard()]),
+            Pattern::Tuple(vec![wildcard(), none()]),
+            Pattern::Tuple(vec![some(Pattern::BoolLiteral(true)), some(wildcard())]),
+        ];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &type_table);
+        assert_eq!(result, Ok(false));
+
+        input_patterns.push(Pattern::Tuple(vec![
+            some(Pattern::BoolLiteral(false)),
+            some(wildcard()),
+        ]));
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &type_table);
+        assert_eq!(result, Ok(true));
+    }
+
+    #[test]
+    fn checks_each_alternative_of_a_pattern() {
+        let input_type = Type::new_tuple(vector![Type::new_boolean(), Type::new_boolean()]);
+        let either = Pattern::Or(vec![
+            Pattern::BoolLiteral(true),
+            Pattern::BoolLiteral(false),
+        ]);
+        let mut input_patterns: Vec<Pattern> = vec![Pattern::Or(vec![
+            Pattern::Tuple(vec![Pattern::BoolLiteral(true), Pattern::Wildcard]),
+            Pattern::Tuple(vec![Pattern::Wildcard, Pattern::BoolLiteral(true)]),
+        ])];
+
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(false));
+
+        // Alternatives nested in other patterns cover each of their values
+        input_patterns.push(Pattern::As(
+            Box::new(Pattern::Tuple(vec![either, Pattern::BoolLiteral(false)])),
+            "pair".to_string(),
+        ));
+        let result = check_pattern_exhaustiveness(&input_type, &input_patterns, &HashMap::new());
+        assert_eq!(result, Ok(true));
+    }
+
+    fn maybe_bools_table() -> DataTable {
+        vec![(
+            "MaybeBools".to_string(),
+            (
+                vec![],
+                vec![
+                    ("one".to_string(), vec![Type::new_boolean()]),
+                    (
+                        "two".to_string(),
+                        vec![Type::new_boolean(), Type::new_boolean()],
+                    ),
+                ],
+            ),
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    fn unguarded(patterns: Vec<Pattern>) -> Vec<(Pattern, bool, SrcLoc)> {
+        patterns
+            .into_iter()
+            .map(|pattern| (pattern, false, SrcLoc::default()))
+            .collect()
+    }
+
+    #[test]
+    fn errors_on_too_many_pattern_args() {
+        let input_type = Type::new("MaybeBools".to_string(), vector![]);
+        let input_patterns: Vec<Pattern> = vec![Pattern::Data(
+            "one".to_string(),
+            vec![Pattern::BoolLiteral(true), Pattern::BoolLiteral(false)],
+        )];
+
+        let result =
+            check_pattern_exhaustiveness(&input_type, &input_patterns, &maybe_bools_table());
+        assert_eq!(
+            result,
+            Err(ExhaustivenessError::TooManyArgsInPattern(SrcLoc::default()))
+        );
+    }
+
+    #[test]
+    fn reports_missing_combinations_of_fields() {
+        let input_type = Type::new("MaybeBools".to_string(), vector![]);
+        let two = |a, b| Pattern::Data("two".to_string(), vec![a, b]);
+        let branches = unguarded(vec![
+            Pattern::Data("one".to_string(), vec![wildcard()]),
+            two(Pattern::BoolLiteral(false), Pattern::BoolLiteral(false)),
+            two(Pattern::BoolLiteral(true), wildcard()),
+        ]);
+
+        let result = check_match_branches(&input_type, &branches, &maybe_bools_table());
+        assert_eq!(
+            result.map(|check| check.missing),
+            Ok(vec![two(
+                Pattern::BoolLiteral(false),
+                Pattern::BoolLiteral(true)
+            )])
+        );
+    }
+
+    #[test]
+    fn reports_missing_variants_and_list_lengths() {
+        let input_type = Type::new("MaybeBools".to_string(), vector![]);
+        let branches = unguarded(vec![Pattern::Data(
+            "one".to_string(),
+            vec![Pattern::BoolLiteral(true)],
+        )]);
+        let result = check_match_branches(&input_type, &branches, &maybe_bools_table());
+        assert_eq!(
+            result.map(|check| check.missing),
+            Ok(vec![
+                Pattern::Data("one".to_string(), vec![Pattern::BoolLiteral(false)]),
+                Pattern::Data("two".to_string(), vec![wildcard(), wildcard()]),
+            ])
+        );
+
+        // Lists longer than any of the patterns are written with a rest pattern
+        let input_type = Type::new_list(Type::new_number());
+        let branches = unguarded(vec![
+            Pattern::List(vec![], None),
+            Pattern::List(vec![wildcard()], None),
+        ]);
+        let result = check_match_branches(&input_type, &branches, &HashMap::new());
+        assert_eq!(
+            result.map(|check| check.missing),
+            Ok(vec![Pattern::List(
+                vec![wildcard(), wildcard()],
+                Some(Box::new(wildcard()))
+            )])
+        );
+    }
+
+    #[test]
+    fn finds_unreachable_branches() {
+        let input_type = Type::new_boolean();
+        let branch = |pattern, has_guard| (pattern, has_guard, SrcLoc::default());
+        let mut branches = vec![
+            branch(Pattern::BoolLiteral(true), true),
+            branch(Pattern::BoolLiteral(true), false),
+            branch(Pattern::BoolLiteral(true), false),
+            branch(Pattern::Identifier("b".to_string()), false),
+            branch(Pattern::BoolLiteral(false), false),
+        ];
+
+        // A guarded branch doesn't cover the values it matches for the branches after it
+        let result = check_match_branches(&input_type, &branches, &HashMap::new());
+        assert_eq!(
+            result,
+            Ok(MatchCheck {
+                missing: vec![],
+                unreachable: vec![2, 4],
+            })
+        );
+
+        // An or-pattern is reachable as long as one of its alternatives is
+        branches.truncate(2);
+        branches.push(branch(
+            Pattern::Or(vec![
+                Pattern::BoolLiteral(true),
+                Pattern::BoolLiteral(false),
+            ]),
+            false,
+        ));
+        let result = check_match_branches(&input_type, &branches, &HashMap::new());
+        assert_eq!(result.map(|check| check.unreachable), Ok(vec![]));
+    }
+
+    #[test]
+    fn infers_the_data_type_of_any_values_from_patterns() {
+        let type_table: DataTable = vec![
+            (
+                "Option".to_string(),
+                (
+                    vec!["T".to_string()],
+                    vec![
+                        ("some".to_string(), vec![Type::new_unit("T".to_string())]),
+                        ("none".to_string(), vec![]),
+                    ],
+                ),
+            ),
+            (
+                "Shape".to_string(),
+                (vec![], vec![("circle".to_string(), vec![Type::new_any()])]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let some = |pattern| Pattern::Data("some".to_string(), vec![pattern]);
+        let none = || Pattern::Data("none".to_string(), vec![]);
+        let mut branches = unguarded(vec![some(Pattern::BoolLiteral(true))]);
+
+        let result = check_match_branches(&Type::new_any(), &branches, &type_table);
+        assert_eq!(
+            result.map(|check| check.missing),
+            Ok(vec![some(Pattern::BoolLiteral(false)), none()])
+        );
+
+        branches.extend(unguarded(vec![some(wildcard()), none()]));
+        let result = check_match_branches(&Type::new_any(), &branches, &type_table);
+        assert_eq!(result.map(|check| check.missing), Ok(vec![]));
+
+        // Variants of different types could match any value
+        let branches = unguarded(vec![
+            none(),
+            Pattern::Data("circle".to_string(), vec![wildcard()]),
+        ]);
+        let result = check_match_branches(&Type::new_any(), &branches, &type_table);
+        assert_eq!(result.map(|check| check.missing), Ok(vec![wildcard()]));
+    }
 }
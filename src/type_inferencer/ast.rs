@@ -1,55 +1,260 @@
 use std::fmt::{self, Display};
 
-use crate::ast::{gensym, Symbol, Type};
+use crate::ast::{gensym, Ast, Identifier, SrcLoc, Symbol, Type};
 use im::{HashMap, HashSet, Vector};
 
 pub type TypeEnv = HashMap<String, Symbol>;
 
-pub type Constraint = (Term, Term);
+/// Map from the name of a top-level definition to its (possibly polymorphic) type
+pub type SchemeEnv = HashMap<String, Scheme>;
+
+/// Why a constraint holds: the expression or type annotation it was generated for. Type errors
+/// point at the origins of the constraints that conflict.
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub struct Origin {
+    /// The label of the expression or annotated identifier
+    pub label: Symbol,
+    pub src_loc: SrcLoc,
+    pub is_annotation: bool,
+}
+impl Origin {
+    pub fn expr(expr: &Ast) -> Self {
+        Origin {
+            label: expr.label,
+            src_loc: expr.src_loc.clone(),
+            is_annotation: false,
+        }
+    }
+    /// The type annotation on an identifier (e.g. a parameter or `let` binding)
+    pub fn annotation(id: &Identifier) -> Self {
+        Origin {
+            label: id.label,
+            src_loc: id.src_loc.clone(),
+            is_annotation: true,
+        }
+    }
+    /// The return type annotation of a function declaration
+    pub fn return_annotation(function: &Ast) -> Self {
+        Origin {
+            is_annotation: true,
+            ..Origin::expr(function)
+        }
+    }
+}
+
+/// A requirement that two terms are the same type. Constraints on values passed in from
+/// outside of the program (e.g. by an embedding) have no origin.
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub struct Constraint {
+    pub left: Term,
+    pub right: Term,
+    pub origin: Option<Origin>,
+}
+
+/// A requirement that `target` is a record or data type with a field called `field` of type
+/// `field_term`. It can't be solved until the type constructor of `target` is known.
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub struct FieldConstraint {
+    pub target: Term,
+    pub field: String,
+    pub field_term: Term,
+    /// Each data type with a field of that name, along with the field's type. Both are
+    /// instantiated with the same fresh type variables for the data type's parameters.
+    pub data_fields: Vec<(Term, Term)>,
+    pub origin: Option<Origin>,
+}
+
+/// A requirement that `operand` is one of the types that `operator` works on. It can't be
+/// checked until the type constructor of `operand` is known.
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub struct OperandConstraint {
+    pub operand: Term,
+    pub operator: String,
+    /// The type constructors of the types the operator works on
+    pub types: Vec<String>,
+    pub origin: Option<Origin>,
+}
+
+/// A set of constraints, each with a priority and a number recording the order it was created
+/// in (which breaks ties when ordering them), along with the set's field and operand
+/// constraints and the order they were created in
 #[derive(Eq, PartialEq, Debug, Clone, Hash)]
 pub struct ConstraintSet {
-    set: HashSet<((Term, Term), usize)>,
+    set: HashSet<(Constraint, usize, Symbol)>,
+    fields: HashSet<(FieldConstraint, Symbol)>,
+    operands: HashSet<(OperandConstraint, Symbol)>,
 }
 impl ConstraintSet {
     pub fn new() -> Self {
         ConstraintSet {
             set: HashSet::new(),
+            fields: HashSet::new(),
+            operands: HashSet::new(),
         }
     }
     pub fn new_constraint(t1: Term, t2: Term) -> Constraint {
-        (t1, t2)
+        Constraint {
+            left: t1,
+            right: t2,
+            origin: None,
+        }
     }
     pub fn unit(t1: Term, t2: Term) -> Self {
         ConstraintSet {
-            set: HashSet::unit(((t1, t2), 0)),
+            set: HashSet::unit((ConstraintSet::new_constraint(t1, t2), 0, gensym())),
+            fields: HashSet::new(),
+            operands: HashSet::new(),
         }
     }
     pub fn priority_unit(t1: Term, t2: Term) -> Self {
         ConstraintSet {
-            set: HashSet::unit(((t1, t2), 1)),
+            set: HashSet::unit((ConstraintSet::new_constraint(t1, t2), 1, gensym())),
+            fields: HashSet::new(),
+            operands: HashSet::new(),
+        }
+    }
+    /// A set with one field constraint
+    pub fn field_unit(
+        target: Term,
+        field: &str,
+        field_term: Term,
+        data_fields: Vec<(Term, Term)>,
+    ) -> Self {
+        let constraint = FieldConstraint {
+            target,
+            field: field.to_string(),
+            field_term,
+            data_fields,
+            origin: None,
+        };
+        ConstraintSet {
+            set: HashSet::new(),
+            fields: HashSet::unit((constraint, gensym())),
+            operands: HashSet::new(),
+        }
+    }
+    /// A set with one operand constraint
+    pub fn operand_unit(operand: Term, operator: &str, types: &[&str]) -> Self {
+        let constraint = OperandConstraint {
+            operand,
+            operator: operator.to_string(),
+            types: types.iter().map(|t| t.to_string()).collect(),
+            origin: None,
+        };
+        ConstraintSet {
+            set: HashSet::new(),
+            fields: HashSet::new(),
+            operands: HashSet::unit((constraint, gensym())),
+        }
+    }
+    /// A set with the given operand constraints, in order
+    pub fn from_operands(operands: Vec<OperandConstraint>) -> Self {
+        ConstraintSet {
+            set: HashSet::new(),
+            fields: HashSet::new(),
+            operands: operands.into_iter().map(|c| (c, gensym())).collect(),
+        }
+    }
+    /// Gives every constraint in the set without an origin the given one
+    pub fn because(self, origin: Origin) -> Self {
+        ConstraintSet {
+            set: (self.set.into_iter())
+                .map(|(constraint, priority, order)| {
+                    let origin = constraint.origin.or_else(|| Some(origin.clone()));
+                    (
+                        Constraint {
+                            origin,
+                            ..constraint
+                        },
+                        priority,
+                        order,
+                    )
+                })
+                .collect(),
+            fields: (self.fields.into_iter())
+                .map(|(constraint, order)| {
+                    let origin = constraint.origin.or_else(|| Some(origin.clone()));
+                    (
+                        FieldConstraint {
+                            origin,
+                            ..constraint
+                        },
+                        order,
+                    )
+                })
+                .collect(),
+            operands: (self.operands.into_iter())
+                .map(|(constraint, order)| {
+                    let origin = constraint.origin.or_else(|| Some(origin.clone()));
+                    (
+                        OperandConstraint {
+                            origin,
+                            ..constraint
+                        },
+                        order,
+                    )
+                })
+                .collect(),
         }
     }
     pub fn union(self, other: Self) -> Self {
         ConstraintSet {
             set: self.set.union(other.set),
+            fields: self.fields.union(other.fields),
+            operands: self.operands.union(other.operands),
         }
     }
     pub fn from_vec(vec: Vec<Constraint>) -> Self {
         ConstraintSet {
-            set: (vec.into_iter().map(|c| (c, 0)).collect()),
+            set: (vec.into_iter().map(|c| (c, 0, gensym())).collect()),
+            fields: HashSet::new(),
+            operands: HashSet::new(),
         }
     }
+    /// Lists the field constraints in the order they were created
+    pub fn field_constraints(&self) -> Vec<FieldConstraint> {
+        let mut fields: Vec<(FieldConstraint, Symbol)> = self.fields.iter().cloned().collect();
+        fields.sort_by_key(|(_, order)| *order);
+        fields.into_iter().map(|(c, _)| c).collect()
+    }
+    /// Lists the operand constraints in the order they were created
+    pub fn operand_constraints(&self) -> Vec<OperandConstraint> {
+        let mut operands: Vec<(OperandConstraint, Symbol)> =
+            self.operands.iter().cloned().collect();
+        operands.sort_by_key(|(_, order)| *order);
+        operands.into_iter().map(|(c, _)| c).collect()
+    }
+    /// Lists the constraints in the order they should be solved, from last to first:
+    /// prioritized constraints first, and then the rest in the order of the code they came
+    /// from, so that type errors are found where a person reading the code would find them
     pub fn into_vec(self) -> Vec<Constraint> {
-        let mut vec: Vec<((Term, Term), usize)> = self.set.into_iter().collect();
-        vec.sort_by(|(_, x), (_, y)| x.cmp(y));
-        vec.into_iter().map(|(c, _)| c).collect()
+        let mut vec: Vec<(Constraint, usize, Symbol)> = self.set.into_iter().collect();
+        let position = |c: &Constraint, order: Symbol| {
+            let span = c.origin.as_ref().map(|origin| origin.src_loc.span.clone());
+            (span.map(|span| (span.start, span.end)), order)
+        };
+        vec.sort_by(|(c1, x, order1), (c2, y, order2)| {
+            x.cmp(y)
+                .then(position(c2, *order2).cmp(&position(c1, *order1)))
+        });
+        vec.into_iter().map(|(c, _, _)| c).collect()
     }
     pub fn unions<I>(i: I) -> Self
     where
         I: IntoIterator<Item = Self>,
     {
+        let mut sets = vec![];
+        let mut fields = vec![];
+        let mut operands = vec![];
+        for i in i {
+            sets.push(i.set);
+            fields.push(i.fields);
+            operands.push(i.operands);
+        }
         ConstraintSet {
-            set: HashSet::unions(i.into_iter().map(|i| i.set)),
+            set: HashSet::unions(sets),
+            fields: HashSet::unions(fields),
+            operands: HashSet::unions(operands),
         }
     }
 }
@@ -60,6 +265,8 @@ pub type SubstitutionSet = HashMap<Symbol, Term>;
 pub enum Term {
     Var(Symbol),
     Constructor(String, Vector<Term>),
+    /// (fields) The type of records with the given fields, in order of their names
+    Record(Vec<(String, Term)>),
 }
 impl Term {
     pub fn number() -> Term {
@@ -68,19 +275,60 @@ impl Term {
     pub fn boolean() -> Term {
         Term::Constructor("Boolean".to_string(), Vector::new())
     }
+    pub fn string() -> Term {
+        Term::Constructor("String".to_string(), Vector::new())
+    }
     pub fn function(args: Vector<Term>, return_type: Term) -> Term {
         let mut v = args.clone();
         v.push_back(return_type);
         Term::Constructor("Function".to_string(), v)
     }
+    pub fn list(item: Term) -> Term {
+        Term::Constructor("List".to_string(), Vector::unit(item))
+    }
+    pub fn tuple(items: Vector<Term>) -> Term {
+        Term::Constructor("Tuple".to_string(), items)
+    }
+    /// The type of records with the given fields, which can be in any order
+    pub fn record(mut fields: Vec<(String, Term)>) -> Term {
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Term::Record(fields)
+    }
     pub fn any() -> Term {
         Term::Constructor("Any".to_string(), Vector::new())
     }
     pub fn from_type(t: &Type) -> Term {
-        Term::Constructor(
-            t.id.clone(),
-            t.args.iter().map(|t| Term::from_type(t)).collect(),
-        )
+        Term::from_type_with_params(t, &HashMap::new())
+    }
+    /// Converts a type into a term, replacing any type parameters with the given terms
+    pub fn from_type_with_params(t: &Type, params: &HashMap<String, Term>) -> Term {
+        match t {
+            Type::Constructor(id, args) => match params.get(id) {
+                Some(term) if args.is_empty() => term.clone(),
+                _ => Term::Constructor(
+                    id.clone(),
+                    args.iter()
+                        .map(|t| Term::from_type_with_params(t, params))
+                        .collect(),
+                ),
+            },
+            Type::Record(fields) => Term::Record(
+                fields
+                    .iter()
+                    .map(|(field, t)| (field.clone(), Term::from_type_with_params(t, params)))
+                    .collect(),
+            ),
+        }
+    }
+    /// Replaces the types named after type parameters (which stand for the parameters while
+    /// their function is checked) with the given terms
+    pub fn replace_params(&self, params: &HashMap<String, Term>) -> Term {
+        match self {
+            Term::Constructor(head, args) if args.is_empty() && params.contains_key(head) => {
+                params[head].clone()
+            }
+            _ => self.map_args(|arg| arg.replace_params(params)),
+        }
     }
     pub fn into_type(self) -> Option<Type> {
         match self {
@@ -92,11 +340,148 @@ impl Term {
                     None
                 }
             }
+            Term::Record(fields) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(field, term)| Some((field, term.into_type()?)));
+                Some(Type::Record(fields.collect::<Option<_>>()?))
+            }
+        }
+    }
+    /// The term's arguments: the arguments of a type constructor, or the types of a record's
+    /// fields
+    pub fn args(&self) -> Vec<&Term> {
+        match self {
+            Term::Var(_) => vec![],
+            Term::Constructor(_, args) => args.iter().collect(),
+            Term::Record(fields) => fields.iter().map(|(_, term)| term).collect(),
+        }
+    }
+    /// Returns the term with a function applied to each of its arguments
+    pub fn map_args(&self, mut f: impl FnMut(&Term) -> Term) -> Term {
+        match self {
+            Term::Var(_) => self.clone(),
+            Term::Constructor(head, args) => {
+                Term::Constructor(head.clone(), args.iter().map(f).collect())
+            }
+            Term::Record(fields) => Term::Record(
+                fields
+                    .iter()
+                    .map(|(field, term)| (field.clone(), f(term)))
+                    .collect(),
+            ),
+        }
+    }
+    /// Whether two terms are built by the same type constructor, with the same number of
+    /// arguments (like tuples or functions of the same size), or are records with the same
+    /// fields. Their arguments can then be unified pairwise.
+    pub fn same_shape(&self, other: &Term) -> bool {
+        match (self, other) {
+            (Term::Constructor(head1, args1), Term::Constructor(head2, args2)) => {
+                head1 == head2 && args1.len() == args2.len()
+            }
+            (Term::Record(fields1), Term::Record(fields2)) => {
+                fields1.len() == fields2.len()
+                    && (fields1.iter().zip(fields2)).all(|((name1, _), (name2, _))| name1 == name2)
+            }
+            _ => false,
         }
     }
     pub fn new_var() -> Self {
         Term::Var(gensym())
     }
+    /// Replaces every type variable that has a substitution with its substituted term
+    pub fn substitute(&self, substitutions: &SubstitutionSet) -> Term {
+        match self {
+            Term::Var(label) => match substitutions.get(label) {
+                Some(term) => term.clone(),
+                None => self.clone(),
+            },
+            _ => self.map_args(|arg| arg.substitute(substitutions)),
+        }
+    }
+    /// Returns every type variable that occurs in the term
+    pub fn free_vars(&self) -> HashSet<Symbol> {
+        match self {
+            Term::Var(label) => HashSet::unit(*label),
+            _ => HashSet::unions(self.args().into_iter().map(|arg| arg.free_vars())),
+        }
+    }
+}
+
+/// A type that is polymorphic over some type variables, e.g. `Function<T, T>` for all `T`
+#[derive(Eq, PartialEq, Debug, Clone, Hash)]
+pub struct Scheme {
+    pub vars: Vec<Symbol>,
+    pub term: Term,
+    /// The operand constraints on the quantified variables, e.g. that the arguments of
+    /// `lambda(a, b): a + b end` are numbers or strings, which are checked at every use
+    pub operands: Vec<OperandConstraint>,
+}
+impl Scheme {
+    /// A scheme that isn't polymorphic over any type variables
+    pub fn mono(term: Term) -> Self {
+        Scheme {
+            vars: vec![],
+            term,
+            operands: vec![],
+        }
+    }
+    /// Creates a scheme for a top-level definition by solving its type with a substitution
+    /// set and then quantifying over every type variable that is left. Top-level definitions
+    /// are only ever in scope of other generalized definitions, so none of the variables can
+    /// be constrained by their environment, except by the unsolved operand constraints that
+    /// are on them.
+    pub fn generalize(
+        term: &Term,
+        substitutions: &SubstitutionSet,
+        operands: &[OperandConstraint],
+    ) -> Self {
+        let term = term.substitute(substitutions);
+        let free_vars = term.free_vars();
+        let mut vars: Vec<Symbol> = free_vars.iter().cloned().collect();
+        vars.sort();
+        let operands = operands
+            .iter()
+            .map(|constraint| OperandConstraint {
+                operand: constraint.operand.substitute(substitutions),
+                ..constraint.clone()
+            })
+            .filter(|constraint| {
+                let vars = constraint.operand.free_vars();
+                vars.iter().any(|var| free_vars.contains(var))
+            })
+            .collect();
+        Scheme {
+            vars,
+            term,
+            operands,
+        }
+    }
+    /// Returns the scheme's type with a fresh type variable in place of each quantified one,
+    /// so that every use of a definition can be at a different type, along with its operand
+    /// constraints on the fresh variables. They don't have an origin, so that they're
+    /// reported at the use.
+    pub fn instantiate(&self) -> (Term, ConstraintSet) {
+        let fresh_vars: SubstitutionSet = self
+            .vars
+            .iter()
+            .map(|var| (*var, Term::new_var()))
+            .collect();
+        let operands = self
+            .operands
+            .iter()
+            .map(|constraint| OperandConstraint {
+                operand: constraint.operand.substitute(&fresh_vars),
+                origin: None,
+                ..constraint.clone()
+            })
+            .collect();
+        (
+            self.term.substitute(&fresh_vars),
+            ConstraintSet::from_operands(operands),
+        )
+    }
 }
 impl Display for Term {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -104,6 +489,15 @@ impl Display for Term {
             Term::Var(label) => {
                 write!(f, "{}", label)
             }
+            Term::Record(fields) => write!(
+                f,
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(field, term)| format!("{}: {}", field, term))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
             Term::Constructor(id, args) => {
                 if args.len() == 0 {
                     write!(f, "{}", id)
@@ -1,187 +1,370 @@
 use super::{
-    ast::{ConstraintSet, Term, TypeEnv},
+    ast::{ConstraintSet, Origin, Scheme, SchemeEnv, Term, TypeEnv},
     type_inference::InferenceError,
 };
-use crate::{
-    ast::{Ast, AstNode, BinOp, Identifier, Pattern, Program, Symbol},
-    interpreter::interpret::find_data_declarations,
-};
+use crate::ast::{gensym, Ast, AstNode, BinOp, Identifier, Pattern, Program, SrcLoc, Type};
 use im::{hashmap, vector, HashMap};
 
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub struct DataDeclTable {
+    /// Map from variant name to the name of its data type and its fields
     pub table: HashMap<String, (String, Vec<Identifier>)>,
+    /// Map from data type name to its type parameters
+    pub type_params: HashMap<String, Vec<String>>,
 }
 impl DataDeclTable {
     pub fn new() -> Self {
         DataDeclTable {
             table: HashMap::new(),
+            type_params: HashMap::new(),
         }
     }
     pub fn from_hashmap(table: HashMap<String, (String, Vec<Identifier>)>) -> Self {
-        DataDeclTable { table }
+        DataDeclTable {
+            table,
+            type_params: HashMap::new(),
+        }
     }
     pub fn get(&self, key: &String) -> Option<&(String, Vec<Identifier>)> {
         self.table.get(key)
     }
+    /// Returns the type of a variant's data type along with the types of the variant's fields.
+    /// Each of the data type's parameters is replaced with a fresh type variable, so every use
+    /// of a variant can be instantiated at different types.
+    pub fn instantiate_variant(&self, variant: &String) -> Option<(Term, Vec<Term>)> {
+        let (type_name, fields) = self.get(variant)?;
+        let params = self.type_params.get(type_name).cloned().unwrap_or_default();
+        let param_terms: HashMap<String, Term> = params
+            .iter()
+            .map(|param| (param.clone(), Term::new_var()))
+            .collect();
+
+        let data_term = Term::Constructor(
+            type_name.clone(),
+            params.iter().map(|param| param_terms[param].clone()).collect(),
+        );
+        let field_terms = fields
+            .iter()
+            .map(|field| match &field.type_decl {
+                Some(t) => Term::from_type_with_params(t, &param_terms),
+                None => Term::any(),
+            })
+            .collect();
+        Some((data_term, field_terms))
+    }
+    /// Returns every data type with a field of the given name, along with the type of the
+    /// field, each instantiated with fresh type variables for the data type's parameters
+    pub fn instantiate_fields(&self, field: &str) -> Vec<(Term, Term)> {
+        let mut variants: Vec<&String> = self.table.keys().collect();
+        variants.sort();
+        variants
+            .into_iter()
+            .filter_map(|variant| {
+                let (_, fields) = self.get(variant)?;
+                let i = fields.iter().position(|id| id.id == field)?;
+                let (data_term, field_terms) = self.instantiate_variant(variant)?;
+                Some((data_term, field_terms[i].clone()))
+            })
+            .collect()
+    }
+    /// Returns the type of the constructor function for a variant, instantiated with fresh
+    /// type variables for the data type's parameters
+    pub fn instantiate_constructor(&self, variant: &String) -> Option<Term> {
+        let (data_term, field_terms) = self.instantiate_variant(variant)?;
+        Some(Term::function(field_terms.into_iter().collect(), data_term))
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub struct InferenceContext<'a> {
     pub env: TypeEnv,
-    pub func_table: &'a TypeEnv,
+    pub schemes: &'a SchemeEnv,
     pub data_decl_table: &'a DataDeclTable,
 }
 impl<'a> InferenceContext<'a> {
-    pub fn new(env: TypeEnv, func_table: &'a TypeEnv, data_decl_table: &'a DataDeclTable) -> Self {
+    pub fn new(env: TypeEnv, schemes: &'a SchemeEnv, data_decl_table: &'a DataDeclTable) -> Self {
         InferenceContext {
             env,
-            func_table,
+            schemes,
             data_decl_table,
         }
     }
     pub fn new_env(&self, env: TypeEnv) -> Self {
         InferenceContext {
             env,
-            func_table: self.func_table,
+            schemes: self.schemes,
             data_decl_table: self.data_decl_table,
         }
     }
     pub fn update_env(&self, env: TypeEnv) -> Self {
         InferenceContext {
             env: self.env.clone().union(env),
-            func_table: self.func_table,
+            schemes: self.schemes,
             data_decl_table: self.data_decl_table,
         }
     }
 }
 
-pub fn generate_constraints(
-    program: &Program,
-    data_decl_table: &DataDeclTable,
-) -> Result<ConstraintSet, InferenceError> {
-    let data_funcs_ast = match find_data_declarations(&program) {
-        Ok(v) => Ok(v),
-        Err(e) => Err(InferenceError::DataDeclarationError(e)),
-    }?;
-
-    // Find functions and functions to make ADT literals
-    let (user_funcs_constraints, user_funcs) = find_functions(&program)?;
-    let (data_funcs_constraints, data_funcs) = find_functions(&data_funcs_ast)?;
-    let func_table = user_funcs.union(data_funcs);
-
-    let mut env: TypeEnv = HashMap::new();
-    let mut constraint_set = ConstraintSet::new();
-    constraint_set = constraint_set.union(user_funcs_constraints);
-    constraint_set = constraint_set.union(data_funcs_constraints);
-
-    for expr in program {
-        let context = InferenceContext::new(env, &func_table, &data_decl_table);
-        let (new_constraint_set, new_env) = generate_constraints_top_level(&expr, context)?;
-
-        env = new_env;
-        constraint_set = constraint_set.union(new_constraint_set);
-    }
-
-    Ok(constraint_set)
+pub fn find_types(program: &Program) -> DataDeclTable {
+    find_types_incremental(program, &DataDeclTable::new())
 }
 
-pub fn find_types(program: &Program) -> DataDeclTable {
-    let mut table: im::HashMap<String, (String, Vec<Identifier>)> = hashmap![];
+/// Adds the data declarations in a program to an existing table of data declarations
+pub fn find_types_incremental(program: &Program, data_decl_table: &DataDeclTable) -> DataDeclTable {
+    let mut data_decl_table = data_decl_table.clone();
     for expr in program {
         match expr {
             Ast {
-                node: AstNode::DataDeclarationNode(name, variants),
+                node: AstNode::DataDeclarationNode(name, type_params, variants),
                 ..
             } => {
                 for (variant_name, id_decls) in variants {
-                    table.insert(variant_name.clone(), (name.clone(), id_decls.clone()));
+                    data_decl_table
+                        .table
+                        .insert(variant_name.clone(), (name.clone(), id_decls.clone()));
                 }
+                data_decl_table
+                    .type_params
+                    .insert(name.clone(), type_params.clone());
             }
             _ => (),
         }
     }
-    return DataDeclTable::from_hashmap(table);
+    return data_decl_table;
 }
 
-fn find_functions(program: &Program) -> Result<(ConstraintSet, TypeEnv), InferenceError> {
-    let mut env: TypeEnv = HashMap::new();
-    let mut constraint_set = ConstraintSet::new();
-    for expr in program {
-        match &expr.node {
-            AstNode::FunctionNode(name, params, return_type, body) => {
-                // ensure the function has full type annotations
-                let mut param_types = vector![];
-                let mut param_type_constraints = ConstraintSet::new();
-                for param in params {
-                    match param.type_decl.clone() {
-                        Some(t) => {
-                            param_types.push_back(Term::from_type(&t));
-                            param_type_constraints =
-                                param_type_constraints.union(ConstraintSet::priority_unit(
-                                    Term::Var(param.label),
-                                    Term::from_type(&t),
-                                ));
-                        }
-                        None => param_types.push_back(Term::new_var()),
+/// Splits a program's function declarations into groups of mutually recursive functions
+/// (the strongly connected components of the call graph). A group is always ordered after
+/// every group that it calls, so the groups can be type checked one after another.
+pub fn group_functions(program: &Program) -> Vec<Vec<&Ast>> {
+    let functions: Vec<&Ast> = program
+        .iter()
+        .filter(|expr| matches!(expr.node, AstNode::FunctionNode(_, _, _, _, _)))
+        .collect();
+    let indices: HashMap<&String, usize> = functions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, expr)| match &expr.node {
+            AstNode::FunctionNode(name, _, _, _, _) => Some((name, i)),
+            _ => None,
+        })
+        .collect();
+
+    // Find the functions that each function refers to
+    let calls: Vec<Vec<usize>> = functions
+        .iter()
+        .map(|expr| {
+            let mut callees: Vec<usize> = expr
+                .into_vec()
+                .into_iter()
+                .filter_map(|node| match &node.node {
+                    AstNode::VarNode(id) => indices.get(id).cloned(),
+                    _ => None,
+                })
+                .collect();
+            callees.sort();
+            callees.dedup();
+            callees
+        })
+        .collect();
+
+    // Tarjan's algorithm produces each component after all of the components it can reach
+    struct Tarjan<'a> {
+        calls: &'a Vec<Vec<usize>>,
+        index: Vec<Option<usize>>,
+        low_link: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next_index: usize,
+        components: Vec<Vec<usize>>,
+    }
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next_index);
+            self.low_link[v] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+
+            for &w in &self.calls[v] {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.low_link[v] = self.low_link[v].min(self.low_link[w]);
+                    }
+                    Some(w_index) if self.on_stack[w] => {
+                        self.low_link[v] = self.low_link[v].min(w_index)
                     }
+                    Some(_) => (),
                 }
-                let return_type_term = match return_type {
+            }
+
+            if Some(self.low_link[v]) == self.index[v] {
+                let mut component = vec![];
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        calls: &calls,
+        index: vec![None; functions.len()],
+        low_link: vec![0; functions.len()],
+        on_stack: vec![false; functions.len()],
+        stack: vec![],
+        next_index: 0,
+        components: vec![],
+    };
+    for v in 0..functions.len() {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+
+    tarjan
+        .components
+        .into_iter()
+        .map(|component| component.into_iter().map(|i| functions[i]).collect())
+        .collect()
+}
+
+/// The names of some functions along with their types
+pub type FunctionTerms = Vec<(String, Term)>;
+
+/// Generates constraints for a group of mutually recursive function declarations. Within the
+/// group, every function has a single type so that recursive calls agree with each other. The
+/// types are returned alongside the constraints so they can be generalized once solved.
+///
+/// A function's type parameters are rigid while its body is checked: each one is a type of its
+/// own, named after the parameter, so a body that only works for some types doesn't check.
+/// Their names are returned too, so they can be turned back into type variables once solved
+/// (see `Term::replace_params`).
+pub fn generate_constraints_functions(
+    group: &[&Ast],
+    schemes: &SchemeEnv,
+    data_decl_table: &DataDeclTable,
+) -> Result<(ConstraintSet, FunctionTerms, Vec<String>), InferenceError> {
+    let mut constraint_set = ConstraintSet::new();
+    let mut function_terms = vec![];
+    let mut rigid_params = vec![];
+    for expr in group {
+        match &expr.node {
+            AstNode::FunctionNode(name, type_params, params, return_type, body) => {
+                rigid_params.extend(type_params.iter().cloned());
+                let to_term = |t: &Option<Type>| match t {
                     Some(t) => Term::from_type(t),
                     None => Term::new_var(),
                 };
 
+                let mut param_types = vector![];
+                for param in params {
+                    let param_type = to_term(&param.type_decl);
+                    param_types.push_back(param_type.clone());
+                    constraint_set = constraint_set.union(
+                        ConstraintSet::priority_unit(Term::Var(param.label), param_type)
+                            .because(Origin::annotation(param)),
+                    );
+                }
+                let return_type_term = to_term(return_type);
+
                 let return_type_constraint =
-                    ConstraintSet::priority_unit(Term::Var(body.label), return_type_term.clone());
+                    ConstraintSet::priority_unit(Term::Var(body.label), return_type_term.clone())
+                        .because(Origin::return_annotation(expr));
 
-                let expr_constraint = ConstraintSet::priority_unit(
-                    Term::Var(expr.label),
-                    Term::function(param_types, return_type_term),
-                );
+                let function_term = Term::function(param_types, return_type_term);
+                let expr_constraint =
+                    ConstraintSet::priority_unit(Term::Var(expr.label), function_term.clone())
+                        .because(Origin::expr(expr));
 
-                // TODO: possibly more constraints here
                 constraint_set = constraint_set
-                    .union(param_type_constraints)
                     .union(return_type_constraint)
                     .union(expr_constraint);
-                env.insert(name.clone(), expr.label);
+                function_terms.push((name.clone(), function_term));
+            }
+            _ => (),
+        }
+    }
+
+    // Functions in the group can only refer to each other at their (monomorphic) types
+    let group_schemes: SchemeEnv = function_terms
+        .iter()
+        .map(|(name, term)| (name.clone(), Scheme::mono(term.clone())))
+        .collect::<SchemeEnv>()
+        .union(schemes.clone());
+
+    // Function bodies can only see their parameters and other top-level functions
+    for expr in group {
+        match &expr.node {
+            AstNode::FunctionNode(_, _, params, _, body) => {
+                let env: TypeEnv = params
+                    .iter()
+                    .map(|param| (param.id.clone(), param.label))
+                    .collect();
+                let context = InferenceContext::new(env, &group_schemes, data_decl_table);
+                constraint_set = constraint_set.union(generate_constraint_expr(body, context)?);
             }
             _ => (),
-        };
+        }
     }
 
-    return Ok((constraint_set, env));
+    Ok((constraint_set, function_terms, rigid_params))
 }
 
-fn generate_constraints_top_level(
+/// Generates constraints for a top-level expression. Function declarations are handled
+/// separately by `generate_constraints_functions`. Also returns the names that a let binding
+/// declares, along with the labels of their types.
+pub fn generate_constraints_top_level(
     expr: &Ast,
     context: InferenceContext,
 ) -> Result<(ConstraintSet, TypeEnv), InferenceError> {
-    let InferenceContext { env, .. } = context.clone();
+    let data_decl_table = context.data_decl_table;
     match &expr.node {
         AstNode::LetNodeTopLevel(id, binding) => {
             let body_constraints = generate_constraint_expr(binding, context)?;
-            let let_constraint = ConstraintSet::unit(Term::Var(id.label), Term::Var(binding.label));
+            let let_constraint = ConstraintSet::unit(Term::Var(id.label), Term::Var(binding.label))
+                .because(Origin::expr(binding));
             let type_annotation_constraint = if let Some(type_annotation) = &id.type_decl {
                 ConstraintSet::unit(Term::Var(id.label), Term::from_type(type_annotation))
+                    .because(Origin::annotation(id))
             } else {
                 ConstraintSet::new()
             };
 
-            let mut new_env = env.clone();
-            new_env.insert(id.id.clone(), binding.label);
-
             Ok((
                 body_constraints
                     .union(let_constraint)
                     .union(type_annotation_constraint),
-                new_env,
+                hashmap![id.id.clone() => binding.label],
             ))
         }
-        AstNode::LetNode(_, _, _) => Err(InferenceError::TopLevelError(expr.src_loc.clone())),
-        AstNode::FunctionNode(_, _, _, _) => Ok((ConstraintSet::new(), env)),
-        AstNode::DataDeclarationNode(_, _) => Ok((ConstraintSet::new(), env)),
-        _ => Ok((generate_constraint_expr(expr, context)?, env)),
+        AstNode::LetPatternNodeTopLevel(pattern, binding) => {
+            let body_constraints = generate_constraint_expr(binding, context)?;
+            let (pattern_env, pattern_constraints) = get_identifiers_from_pattern(
+                Term::Var(binding.label),
+                pattern,
+                &expr.src_loc,
+                data_decl_table,
+            )?;
+            Ok((
+                body_constraints.union(pattern_constraints.because(Origin::expr(binding))),
+                pattern_env,
+            ))
+        }
+        AstNode::LetNode(_, _, _) | AstNode::LetPatternNode(_, _, _) => {
+            Err(InferenceError::TopLevelError(expr.src_loc.clone()))
+        }
+        AstNode::FunctionNode(_, _, _, _, _)
+        | AstNode::DataDeclarationNode(_, _, _)
+        | AstNode::ImportNode(_, _) => Ok((ConstraintSet::new(), TypeEnv::new())),
+        _ => Ok((generate_constraint_expr(expr, context)?, TypeEnv::new())),
     }
 }
 
@@ -191,24 +374,43 @@ pub fn generate_constraint_expr(
 ) -> Result<ConstraintSet, InferenceError> {
     let InferenceContext {
         env,
-        func_table,
+        schemes,
         data_decl_table,
     } = context.clone();
+    let origin = Origin::expr(expr);
     match &expr.node {
-        AstNode::NumberNode(_val) => Ok(ConstraintSet::unit(Term::Var(expr.label), Term::number())),
-        AstNode::BoolNode(_val) => Ok(ConstraintSet::unit(Term::Var(expr.label), Term::boolean())),
+        // Integers and floats are both `Number`s
+        AstNode::NumberNode(_) | AstNode::BigNumberNode(_) | AstNode::FloatNode(_, _) => {
+            Ok(ConstraintSet::unit(Term::Var(expr.label), Term::number()).because(origin))
+        }
+        AstNode::BoolNode(_val) => {
+            Ok(ConstraintSet::unit(Term::Var(expr.label), Term::boolean()).because(origin))
+        }
+        AstNode::StringNode(_val) => {
+            Ok(ConstraintSet::unit(Term::Var(expr.label), Term::string()).because(origin))
+        }
         AstNode::VarNode(id) => {
             if let Some(id) = env.get(id) {
-                Ok(ConstraintSet::unit(Term::Var(expr.label), Term::Var(*id)))
-            } else if let Some(id) = func_table.get(id) {
-                Ok(ConstraintSet::unit(Term::Var(expr.label), Term::Var(*id)))
+                Ok(ConstraintSet::unit(Term::Var(expr.label), Term::Var(*id)).because(origin))
+            } else if let Some(constructor) = data_decl_table.instantiate_constructor(id) {
+                // Data constructors get fresh type parameters at every use
+                Ok(ConstraintSet::unit(Term::Var(expr.label), constructor).because(origin))
+            } else if let Some(scheme) = schemes.get(id) {
+                // Top-level definitions are instantiated at a fresh type at every use
+                let (term, operands) = scheme.instantiate();
+                Ok(ConstraintSet::unit(Term::Var(expr.label), term)
+                    .union(operands)
+                    .because(origin))
             } else {
-                Err(InferenceError::UnboundIdentifier(id.to_string(), env))
+                Err(InferenceError::UnboundIdentifier(
+                    id.to_string(),
+                    expr.src_loc.clone(),
+                ))
             }
         }
-        AstNode::LetNodeTopLevel(_id, expr) => Err(InferenceError::TopLevelExpressionOutOfPlace(
-            expr.src_loc.clone(),
-        )),
+        AstNode::LetNodeTopLevel(_, expr) | AstNode::LetPatternNodeTopLevel(_, expr) => Err(
+            InferenceError::TopLevelExpressionOutOfPlace(expr.src_loc.clone()),
+        ),
         AstNode::LetNode(id, expr, body) => {
             let mut new_env = env.clone();
             new_env.insert(id.id.clone(), expr.label);
@@ -216,6 +418,7 @@ pub fn generate_constraint_expr(
             let body_constraints = generate_constraint_expr(&body, context.new_env(new_env))?;
             let type_annotation_constraint = if let Some(type_annotation) = &id.type_decl {
                 ConstraintSet::unit(Term::Var(id.label), Term::from_type(type_annotation))
+                    .because(Origin::annotation(id))
             } else {
                 ConstraintSet::new()
             };
@@ -223,6 +426,23 @@ pub fn generate_constraint_expr(
                 .union(body_constraints)
                 .union(type_annotation_constraint))
         }
+        AstNode::LetPatternNode(pattern, binding, body) => {
+            let (pattern_env, pattern_constraints) = get_identifiers_from_pattern(
+                Term::Var(binding.label),
+                pattern,
+                &expr.src_loc,
+                data_decl_table,
+            )?;
+            let binding_constraints = generate_constraint_expr(binding, context.clone())?;
+            let body_constraints = generate_constraint_expr(body, context.update_env(pattern_env))?;
+            Ok(binding_constraints
+                .union(pattern_constraints.because(Origin::expr(binding)))
+                .union(body_constraints)
+                .union(
+                    ConstraintSet::unit(Term::Var(expr.label), Term::Var(body.label))
+                        .because(Origin::expr(body)),
+                ))
+        }
         AstNode::IfNode(conditions_and_bodies, alternate) => {
             let mut first_term: Option<Term> = None;
             let mut constraints = ConstraintSet::new();
@@ -230,13 +450,15 @@ pub fn generate_constraint_expr(
                 constraints =
                     constraints.union(generate_constraint_expr(&condition, context.clone())?);
                 constraints = constraints.union(generate_constraint_expr(&body, context.clone())?);
-                constraints = constraints.union(ConstraintSet::unit(
-                    Term::Var(condition.label),
-                    Term::boolean(),
-                ));
+                constraints = constraints.union(
+                    ConstraintSet::unit(Term::Var(condition.label), Term::boolean())
+                        .because(Origin::expr(condition)),
+                );
                 if let Some(t) = &first_term {
-                    constraints =
-                        constraints.union(ConstraintSet::unit(t.clone(), Term::Var(body.label)))
+                    constraints = constraints.union(
+                        ConstraintSet::unit(t.clone(), Term::Var(body.label))
+                            .because(Origin::expr(body)),
+                    )
                 } else {
                     first_term = Some(Term::Var(body.label));
                 }
@@ -245,20 +467,21 @@ pub fn generate_constraint_expr(
             constraints = constraints.union(generate_constraint_expr(&alternate, context)?);
 
             if let Some(v) = first_term {
-                constraints = constraints.union(ConstraintSet::unit(v, Term::Var(alternate.label)));
+                constraints = constraints.union(
+                    ConstraintSet::unit(v, Term::Var(alternate.label))
+                        .because(Origin::expr(alternate)),
+                );
             }
 
             // The overall expression must have same type as all branches
-            constraints = constraints.union(ConstraintSet::unit(
-                Term::Var(expr.label),
-                Term::Var(alternate.label),
-            ));
+            constraints = constraints.union(
+                ConstraintSet::unit(Term::Var(expr.label), Term::Var(alternate.label))
+                    .because(origin),
+            );
 
             Ok(constraints)
         }
-        AstNode::BinOpNode(op, e1, e2) => {
-            constraint_gen_binop(op.clone(), expr.label, &e1, &e2, context)
-        }
+        AstNode::BinOpNode(op, e1, e2) => constraint_gen_binop(op.clone(), expr, &e1, &e2, context),
         AstNode::FunCallNode(fun_value, arg_list) => {
             // 1. expressions type is a value
             // 2. functions type is a function
@@ -266,7 +489,8 @@ pub fn generate_constraint_expr(
             let new_constraint = ConstraintSet::unit(
                 Term::Var(fun_value.label),
                 Term::function(arg_terms, Term::Var(expr.label)),
-            );
+            )
+            .because(origin);
 
             let mut arg_constraints = vec![];
             for arg in arg_list {
@@ -292,42 +516,100 @@ pub fn generate_constraint_expr(
             let param_constraints = ConstraintSet::unit(
                 Term::Var(expr.label),
                 Term::function(param_labels, Term::Var(body.label)),
-            );
+            )
+            .because(origin);
 
             Ok(body_constraints.union(param_constraints))
         }
-        AstNode::FunctionNode(_function_name, _param_list, _return_type, _body) => Err(
+        AstNode::FunctionNode(_function_name, _type_params, _param_list, _return_type, _body) => Err(
             InferenceError::TopLevelExpressionOutOfPlace(expr.src_loc.clone()),
         ),
-        AstNode::DataDeclarationNode(_data_name, _data_variants) => Err(
+        AstNode::DataDeclarationNode(_data_name, _type_params, _data_variants) => Err(
             InferenceError::TopLevelExpressionOutOfPlace(expr.src_loc.clone()),
         ),
-        AstNode::DataLiteralNode(discriminant, _values) => Ok(ConstraintSet::unit(
-            Term::Var(expr.label),
-            Term::Constructor(discriminant.get_type().to_string(), vector![]),
+        AstNode::ImportNode(_path, _names) => Err(InferenceError::TopLevelExpressionOutOfPlace(
+            expr.src_loc.clone(),
         )),
+        // Code with a syntax error could have any type
+        AstNode::ErrorNode(_, _) => Ok(ConstraintSet::new()),
+        AstNode::DataLiteralNode(discriminant, _values) => {
+            let type_args = match data_decl_table.type_params.get(discriminant.get_type()) {
+                Some(params) => params.iter().map(|_| Term::new_var()).collect(),
+                None => vector![],
+            };
+            Ok(ConstraintSet::unit(
+                Term::Var(expr.label),
+                Term::Constructor(discriminant.get_type().to_string(), type_args),
+            )
+            .because(origin))
+        }
+        AstNode::ListNode(items, rest) => {
+            // Every item has the list's item type, and the rest is a list of the same type
+            let item_term = Term::new_var();
+            let mut constraints =
+                ConstraintSet::unit(Term::Var(expr.label), Term::list(item_term.clone()))
+                    .because(origin);
+            for item in items {
+                constraints = constraints.union(generate_constraint_expr(item, context.clone())?);
+                constraints = constraints.union(
+                    ConstraintSet::unit(item_term.clone(), Term::Var(item.label))
+                        .because(Origin::expr(item)),
+                );
+            }
+            if let Some(rest) = rest {
+                constraints = constraints.union(generate_constraint_expr(rest, context)?);
+                constraints = constraints.union(
+                    ConstraintSet::unit(Term::Var(expr.label), Term::Var(rest.label))
+                        .because(Origin::expr(rest)),
+                );
+            }
+            Ok(constraints)
+        }
+        AstNode::TupleNode(items) => {
+            let item_terms = items.iter().map(|item| Term::Var(item.label)).collect();
+            let mut constraints =
+                ConstraintSet::unit(Term::Var(expr.label), Term::tuple(item_terms)).because(origin);
+            for item in items {
+                constraints = constraints.union(generate_constraint_expr(item, context.clone())?);
+            }
+            Ok(constraints)
+        }
+        AstNode::RecordNode(_)
+        | AstNode::RecordUpdateNode(_, _)
+        | AstNode::FieldAccessNode(_, _) => constraint_gen_record(expr, context),
         AstNode::MatchNode(expression_to_match, branches) => {
-            let mut last_term: Option<Term> = None;
             let mut constraints = ConstraintSet::new();
             constraints = constraints.union(generate_constraint_expr(
                 expression_to_match,
                 context.clone(),
             )?);
-            for (pattern, body) in branches {
-                let pattern_env = get_identifiers_from_pattern(
-                    expression_to_match.label,
+            for (pattern, guard, body, pattern_span) in branches {
+                let (pattern_env, pattern_constraints) = get_identifiers_from_pattern(
+                    Term::Var(expression_to_match.label),
                     pattern,
+                    &SrcLoc {
+                        span: pattern_span.clone(),
+                    },
                     data_decl_table,
                 )?;
-                constraints = constraints.union(generate_constraint_expr(
-                    &body,
-                    context.update_env(pattern_env),
-                )?);
-                if let Some(v) = last_term {
-                    constraints = constraints.union(ConstraintSet::unit(v, Term::Var(body.label)));
-
-                    last_term = Some(Term::Var(body.label));
+                // Patterns have no location of their own
+                constraints = constraints
+                    .union(pattern_constraints.because(Origin::expr(expression_to_match)));
+                let branch_context = context.update_env(pattern_env);
+                if let Some(guard) = guard {
+                    constraints =
+                        constraints.union(generate_constraint_expr(guard, branch_context.clone())?);
+                    constraints = constraints.union(
+                        ConstraintSet::unit(Term::Var(guard.label), Term::boolean())
+                            .because(Origin::expr(guard)),
+                    );
                 }
+                constraints = constraints.union(generate_constraint_expr(&body, branch_context)?);
+                // Every branch must have the same type as the overall expression
+                constraints = constraints.union(
+                    ConstraintSet::unit(Term::Var(expr.label), Term::Var(body.label))
+                        .because(Origin::expr(body)),
+                );
             }
 
             Ok(constraints)
@@ -335,73 +617,243 @@ pub fn generate_constraint_expr(
     }
 }
 
-fn get_identifiers_from_pattern(
-    target_label: Symbol,
-    pattern: &Pattern,
-    data_decl_table: &DataDeclTable,
-) -> Result<TypeEnv, InferenceError> {
-    match pattern {
-        Pattern::Identifier(id) => Ok(hashmap![id.clone() => target_label]),
-        pattern => Ok(get_identifiers_from_pattern_helper(
-            pattern,
-            data_decl_table,
-        )?),
+/// Generates constraints for a record literal, a record update or a field access. The fields
+/// of a value whose type isn't known yet are found once it is, as either a record's field or
+/// the named field of a data type.
+fn constraint_gen_record(
+    expr: &Ast,
+    context: InferenceContext,
+) -> Result<ConstraintSet, InferenceError> {
+    let data_decl_table = context.data_decl_table;
+    let origin = Origin::expr(expr);
+    match &expr.node {
+        AstNode::RecordNode(fields) => {
+            let field_terms = fields
+                .iter()
+                .map(|(field, value)| (field.clone(), Term::Var(value.label)))
+                .collect();
+            let mut constraints =
+                ConstraintSet::unit(Term::Var(expr.label), Term::record(field_terms))
+                    .because(origin);
+            for (_, value) in fields {
+                constraints = constraints.union(generate_constraint_expr(value, context.clone())?);
+            }
+            Ok(constraints)
+        }
+        AstNode::RecordUpdateNode(record, fields) => {
+            // The updated value has the same type as the original
+            let mut constraints = generate_constraint_expr(record, context.clone())?.union(
+                ConstraintSet::unit(Term::Var(expr.label), Term::Var(record.label))
+                    .because(origin.clone()),
+            );
+            for (field, value) in fields {
+                constraints = constraints.union(generate_constraint_expr(value, context.clone())?);
+                constraints = constraints.union(
+                    ConstraintSet::field_unit(
+                        Term::Var(record.label),
+                        field,
+                        Term::Var(value.label),
+                        data_decl_table.instantiate_fields(field),
+                    )
+                    .because(Origin::expr(value)),
+                );
+            }
+            Ok(constraints)
+        }
+        AstNode::FieldAccessNode(value, field) => {
+            let constraints = generate_constraint_expr(value, context)?;
+            Ok(constraints.union(
+                ConstraintSet::field_unit(
+                    Term::Var(value.label),
+                    field,
+                    Term::Var(expr.label),
+                    data_decl_table.instantiate_fields(field),
+                )
+                .because(origin),
+            ))
+        }
+        _ => Ok(ConstraintSet::new()),
     }
 }
-fn get_identifiers_from_pattern_helper(
+
+/// Finds the identifiers bound by a pattern that matches a value of type `target`. Each
+/// identifier is given a fresh type variable, and the returned constraints relate those
+/// variables (and `target`) to the types of the data variants and lists in the pattern.
+/// Mistakes in the pattern are reported at `src_loc`, the code that the pattern is part of.
+fn get_identifiers_from_pattern(
+    target: Term,
     pattern: &Pattern,
+    src_loc: &SrcLoc,
     data_decl_table: &DataDeclTable,
-) -> Result<TypeEnv, InferenceError> {
+) -> Result<(TypeEnv, ConstraintSet), InferenceError> {
     match pattern {
-        Pattern::Data(name, patterns) => match data_decl_table.get(name) {
-            Some((_type_name, identifier_decls)) => {
-                if patterns.len() != identifier_decls.len() {
-                    return Err(InferenceError::MalformedPattern(pattern.clone()));
-                }
-                let mut out = hashmap![];
-                for (pattern, id_decl) in patterns.iter().zip(identifier_decls) {
-                    match pattern {
-                        Pattern::Identifier(id) => {
-                            out.insert(id.clone(), id_decl.label);
-                        }
-                        pattern => {
-                            out = out.union(get_identifiers_from_pattern_helper(
-                                pattern,
-                                data_decl_table,
-                            )?);
-                        }
+        Pattern::Identifier(id) => {
+            let label = gensym();
+            Ok((
+                hashmap![id.clone() => label],
+                ConstraintSet::unit(Term::Var(label), target),
+            ))
+        }
+        Pattern::Wildcard => Ok((hashmap![], ConstraintSet::new())),
+        Pattern::As(pattern, name) => {
+            let (env, constraints) =
+                get_identifiers_from_pattern(target.clone(), pattern, src_loc, data_decl_table)?;
+            let label = gensym();
+            Ok((
+                env.update(name.clone(), label),
+                constraints.union(ConstraintSet::unit(Term::Var(label), target)),
+            ))
+        }
+        // Every alternative has to bind the same variables, with the same types
+        Pattern::Or(alternatives) => {
+            let mut env: Option<TypeEnv> = None;
+            let mut constraints = ConstraintSet::new();
+            for alternative in alternatives {
+                let (alternative_env, alternative_constraints) = get_identifiers_from_pattern(
+                    target.clone(),
+                    alternative,
+                    src_loc,
+                    data_decl_table,
+                )?;
+                constraints = constraints.union(alternative_constraints);
+                let first_env = match &env {
+                    Some(first_env) => first_env,
+                    None => {
+                        env = Some(alternative_env);
+                        continue;
                     }
+                };
+                if let Some(name) = first_env.keys().chain(alternative_env.keys()).find(|name| {
+                    !first_env.contains_key(*name) || !alternative_env.contains_key(*name)
+                }) {
+                    return Err(InferenceError::UnevenAlternatives(
+                        name.clone(),
+                        src_loc.clone(),
+                    ));
+                }
+                for (name, label) in alternative_env {
+                    constraints = constraints.union(ConstraintSet::unit(
+                        Term::Var(first_env[&name]),
+                        Term::Var(label),
+                    ));
                 }
-                return Ok(out);
+            }
+            Ok((env.unwrap_or_default(), constraints))
+        }
+        Pattern::NumLiteral(_) => Ok((hashmap![], ConstraintSet::unit(target, Term::number()))),
+        Pattern::BoolLiteral(_) => Ok((hashmap![], ConstraintSet::unit(target, Term::boolean()))),
+        Pattern::StringLiteral(_) => {
+            Ok((hashmap![], ConstraintSet::unit(target, Term::string())))
+        }
+        Pattern::Data(name, patterns) => match data_decl_table.instantiate_variant(name) {
+            Some((data_term, field_terms)) => {
+                if patterns.len() != field_terms.len() {
+                    return Err(InferenceError::MalformedPattern(
+                        pattern.clone(),
+                        src_loc.clone(),
+                    ));
+                }
+                let mut env = hashmap![];
+                let mut constraints = ConstraintSet::unit(target, data_term);
+                for (pattern, field_term) in patterns.iter().zip(field_terms) {
+                    let (field_env, field_constraints) = get_identifiers_from_pattern(
+                        field_term,
+                        pattern,
+                        src_loc,
+                        data_decl_table,
+                    )?;
+                    env = env.union(field_env);
+                    constraints = constraints.union(field_constraints);
+                }
+                Ok((env, constraints))
             }
             None => Err(InferenceError::UnboundPattern(
                 name.clone(),
-                data_decl_table.clone(),
+                src_loc.clone(),
             )),
         },
-        Pattern::Identifier(id) => panic!("Identifier found while typechecking pattern: {}", id),
-        _ => Ok(hashmap![]),
+        Pattern::List(patterns, rest) => {
+            let item_term = Term::new_var();
+            let list_term = Term::list(item_term.clone());
+            let mut env = hashmap![];
+            let mut constraints = ConstraintSet::unit(target, list_term.clone());
+            for pattern in patterns {
+                let (item_env, item_constraints) = get_identifiers_from_pattern(
+                    item_term.clone(),
+                    pattern,
+                    src_loc,
+                    data_decl_table,
+                )?;
+                env = env.union(item_env);
+                constraints = constraints.union(item_constraints);
+            }
+            if let Some(rest) = rest {
+                let (rest_env, rest_constraints) =
+                    get_identifiers_from_pattern(list_term, rest, src_loc, data_decl_table)?;
+                env = env.union(rest_env);
+                constraints = constraints.union(rest_constraints);
+            }
+            Ok((env, constraints))
+        }
+        Pattern::Tuple(patterns) => {
+            let item_terms: Vec<Term> = patterns.iter().map(|_| Term::new_var()).collect();
+            let mut env = hashmap![];
+            let mut constraints =
+                ConstraintSet::unit(target, Term::tuple(item_terms.iter().cloned().collect()));
+            for (pattern, item_term) in patterns.iter().zip(item_terms) {
+                let (item_env, item_constraints) =
+                    get_identifiers_from_pattern(item_term, pattern, src_loc, data_decl_table)?;
+                env = env.union(item_env);
+                constraints = constraints.union(item_constraints);
+            }
+            Ok((env, constraints))
+        }
+        // Only records match record patterns, so data types' fields aren't candidates
+        Pattern::Record(fields) => {
+            let mut env = hashmap![];
+            let mut constraints = ConstraintSet::new();
+            for (field, pattern) in fields {
+                let field_term = Term::new_var();
+                let (field_env, field_constraints) = get_identifiers_from_pattern(
+                    field_term.clone(),
+                    pattern,
+                    src_loc,
+                    data_decl_table,
+                )?;
+                env = env.union(field_env);
+                constraints =
+                    constraints
+                        .union(field_constraints)
+                        .union(ConstraintSet::field_unit(
+                            target.clone(),
+                            field,
+                            field_term,
+                            vec![],
+                        ));
+            }
+            Ok((env, constraints))
+        }
     }
 }
 
+/// Requires the operands and result of an operator to have the given types
 pub fn constraint_gen_binop_helper(
-    label: Symbol,
-    left_label: Symbol,
-    right_label: Symbol,
+    expr: &Ast,
+    e1: &Ast,
+    e2: &Ast,
     left_type: Term,
     right_type: Term,
     output_type: Term,
 ) -> ConstraintSet {
-    ConstraintSet::from_vec(vec![
-        ConstraintSet::new_constraint(Term::Var(left_label), left_type),
-        ConstraintSet::new_constraint(Term::Var(right_label), right_type),
-        ConstraintSet::new_constraint(Term::Var(label), output_type),
-    ])
+    ConstraintSet::unit(Term::Var(e1.label), left_type)
+        .because(Origin::expr(e1))
+        .union(ConstraintSet::unit(Term::Var(e2.label), right_type).because(Origin::expr(e2)))
+        .union(ConstraintSet::unit(Term::Var(expr.label), output_type).because(Origin::expr(expr)))
 }
 
 fn constraint_gen_binop(
     op: BinOp,
-    label: Symbol,
+    expr: &Ast,
     e1: &Ast,
     e2: &Ast,
     context: InferenceContext,
@@ -410,126 +862,99 @@ fn constraint_gen_binop(
     let c2 = generate_constraint_expr(e2, context)?;
 
     let c3 = match op {
-        BinOp::Plus => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
-            Term::number(),
-            Term::number(),
-            Term::number(),
-        ),
+        // Plus works on both numbers and strings, so require that the operands and the result
+        // all share a type, which is one of those
+        BinOp::Plus => ConstraintSet::unit(Term::Var(e1.label), Term::Var(e2.label))
+            .because(Origin::expr(e2))
+            .union(
+                ConstraintSet::unit(Term::Var(expr.label), Term::Var(e1.label))
+                    .union(ConstraintSet::operand_unit(
+                        Term::Var(e1.label),
+                        "+",
+                        &["Number", "String"],
+                    ))
+                    .because(Origin::expr(expr)),
+            ),
         BinOp::Minus => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::Times => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::Divide => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::Modulo => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::Exp => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
-            Term::number(),
-            Term::number(),
-            Term::number(),
-        ),
-        BinOp::Eq => ConstraintSet::from_vec(vec![
-            ConstraintSet::new_constraint(Term::Var(e1.label), Term::Var(e2.label)),
-            ConstraintSet::new_constraint(Term::Var(label), Term::boolean()),
-        ]),
-        BinOp::Gt => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
-            Term::number(),
-            Term::number(),
-            Term::boolean(),
-        ),
-        BinOp::Lt => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
-            Term::number(),
+            expr,
+            e1,
+            e2,
             Term::number(),
-            Term::boolean(),
-        ),
-        BinOp::GtEq => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
             Term::number(),
             Term::number(),
-            Term::boolean(),
-        ),
-        BinOp::LtEq => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
-            Term::number(),
-            Term::number(),
-            Term::boolean(),
         ),
+        BinOp::Eq => comparison_constraints(expr, e1, e2),
+        // Numbers and strings can both be compared, so only require that the operands
+        // share a type
+        BinOp::Gt | BinOp::Lt | BinOp::GtEq | BinOp::LtEq => comparison_constraints(expr, e1, e2),
         BinOp::LAnd => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::boolean(),
             Term::boolean(),
             Term::boolean(),
         ),
         BinOp::LOr => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::boolean(),
             Term::boolean(),
             Term::boolean(),
         ),
         BinOp::BitAnd => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::BitOr => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
         ),
         BinOp::BitXor => constraint_gen_binop_helper(
-            label,
-            e1.label,
-            e2.label,
+            expr,
+            e1,
+            e2,
             Term::number(),
             Term::number(),
             Term::number(),
@@ -538,3 +963,12 @@ fn constraint_gen_binop(
 
     return Ok(c1.union(c2).union(c3));
 }
+
+/// Requires the operands of a comparison to share a type, and its result to be a boolean
+fn comparison_constraints(expr: &Ast, e1: &Ast, e2: &Ast) -> ConstraintSet {
+    ConstraintSet::unit(Term::Var(e1.label), Term::Var(e2.label))
+        .because(Origin::expr(e2))
+        .union(
+            ConstraintSet::unit(Term::Var(expr.label), Term::boolean()).because(Origin::expr(expr)),
+        )
+}
@@ -1,21 +1,36 @@
 use super::{
-    ast::{SubstitutionSet, Term, TypeEnv},
-    constraint_gen::{generate_constraints, DataDeclTable},
-    unification::unify_constraints,
+    ast::{Origin, Scheme, SchemeEnv, SubstitutionSet, Term},
+    constraint_gen::{
+        generate_constraints_functions, generate_constraints_top_level, group_functions,
+        DataDeclTable, InferenceContext,
+    },
+    unification::unify_constraints_incremental,
 };
 use crate::{
-    ast::{Pattern, Program, SrcLoc},
-    interpreter::interpret::InterpError,
+    ast::{AstNode, NativeFunction, Pattern, Program, SrcLoc},
+    interpreter::interpret::{find_data_declarations, InterpError},
 };
+use im::HashMap;
 use std::ops::Range;
 
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub enum InferenceError {
-    UnboundIdentifier(String, TypeEnv),
-    UnboundPattern(String, DataDeclTable),
-    MalformedPattern(Pattern),
-    ConstructorMismatch(Term, Term),
-    InfiniteType(),
+    UnboundIdentifier(String, SrcLoc),
+    /// (constructor, where the pattern is)
+    UnboundPattern(String, SrcLoc),
+    /// (pattern, where the pattern is)
+    MalformedPattern(Pattern, SrcLoc),
+    /// (variable, where the pattern is) A variable that some alternatives of an or-pattern bind
+    /// and others don't
+    UnevenAlternatives(String, SrcLoc),
+    /// (expected, found, where it was found, why it was expected)
+    ConstructorMismatch(Term, Term, Option<Origin>, Option<Origin>),
+    /// (where the type was found)
+    InfiniteType(Option<Origin>),
+    /// (type, field, where the field was used)
+    MissingField(Term, String, Option<Origin>),
+    /// (type, operator, the types the operator works on, where the operator was used)
+    UnsupportedOperand(Term, String, Vec<String>, Option<Origin>),
     MissingAnnotation(Range<usize>),
     TopLevelError(SrcLoc),
     TopLevelExpressionOutOfPlace(SrcLoc),
@@ -26,9 +41,162 @@ pub fn infer_types(
     program: &Program,
     data_decl_table: &DataDeclTable,
 ) -> Result<SubstitutionSet, InferenceError> {
-    // println!("PROGRAM: {:?}", program);
-    let constraint_set = generate_constraints(&program, data_decl_table)?;
-    // println!("CONSTRAINTS: {:?}", constraint_set);
-    let substition_set = unify_constraints(constraint_set)?;
-    Ok(substition_set)
+    let state = infer_types_incremental(program, data_decl_table, &InferenceState::new())?;
+    Ok(state.substitutions)
+}
+
+/// The results of type inference that persist between successive calls to
+/// `infer_types_incremental` (e.g. between lines entered into the REPL)
+#[derive(PartialEq, Debug, Clone)]
+pub struct InferenceState {
+    /// The generalized types of top-level functions and let bindings
+    pub schemes: SchemeEnv,
+    pub substitutions: SubstitutionSet,
+}
+impl InferenceState {
+    pub fn new() -> Self {
+        InferenceState {
+            schemes: HashMap::new(),
+            substitutions: HashMap::new(),
+        }
+    }
+
+    /// Gives a native function the type declared by its signature. The signature's type
+    /// parameters are quantified, so the function can be called at different types.
+    pub fn define_native(&mut self, native: &NativeFunction) {
+        let params: HashMap<String, Term> = native
+            .type_params
+            .iter()
+            .map(|param| (param.clone(), Term::new_var()))
+            .collect();
+        let term = Term::function(
+            native
+                .params
+                .iter()
+                .map(|t| Term::from_type_with_params(t, &params))
+                .collect(),
+            Term::from_type_with_params(&native.return_type, &params),
+        );
+        self.schemes.insert(
+            native.name.clone(),
+            Scheme::generalize(&term, &HashMap::new(), &[]),
+        );
+    }
+}
+
+/// Infers types for a program that may refer to the top-level definitions of
+/// previously inferred programs, returning the extended inference state.
+///
+/// Functions and top-level let bindings are solved one at a time and generalized before
+/// anything that uses them, so that a definition like `identity` can be used at different
+/// types throughout the rest of the program.
+pub fn infer_types_incremental(
+    program: &Program,
+    data_decl_table: &DataDeclTable,
+    state: &InferenceState,
+) -> Result<InferenceState, InferenceError> {
+    match infer_types_partial(program, data_decl_table, state) {
+        (state, None) => Ok(state),
+        (_, Some(e)) => Err(e),
+    }
+}
+
+/// Infers types for as much of a program as possible, returning the inference state along
+/// with the first error, if there is one. A group of functions or a top-level expression whose
+/// types can't be inferred is left without types, and the names it declares are given type
+/// `Any`, so that the rest of the program still gets types (e.g. for editors to show).
+pub fn infer_types_partial(
+    program: &Program,
+    data_decl_table: &DataDeclTable,
+    state: &InferenceState,
+) -> (InferenceState, Option<InferenceError>) {
+    if let Err(e) = find_data_declarations(program) {
+        return (state.clone(), Some(InferenceError::DataDeclarationError(e)));
+    }
+
+    let mut schemes = state.schemes.clone();
+    let mut substitutions = state.substitutions.clone();
+    let mut first_error = None;
+    let mut fail = |e: InferenceError, schemes: &mut SchemeEnv, names: Vec<String>| {
+        first_error.get_or_insert(e);
+        for name in names {
+            schemes.insert(name, Scheme::mono(Term::any()));
+        }
+    };
+
+    // Functions can be called before they are declared, so they're all checked first. Each
+    // group of mutually recursive functions is checked after the functions that it calls.
+    for group in group_functions(program) {
+        let names = group.iter().filter_map(|expr| match &expr.node {
+            AstNode::FunctionNode(name, ..) => Some(name.clone()),
+            _ => None,
+        });
+        let (constraint_set, function_terms, rigid_params) =
+            match generate_constraints_functions(&group, &schemes, data_decl_table) {
+                Ok(generated) => generated,
+                Err(e) => {
+                    fail(e, &mut schemes, names.collect());
+                    continue;
+                }
+            };
+        let (solved, operands) =
+            match unify_constraints_incremental(constraint_set, substitutions.clone()) {
+                Ok(solved) => solved,
+                Err(e) => {
+                    fail(e, &mut schemes, names.collect());
+                    continue;
+                }
+            };
+        substitutions = solved;
+        // Outside of their functions' bodies, type parameters can be any type again
+        let params: HashMap<String, Term> = rigid_params
+            .into_iter()
+            .map(|param| (param, Term::new_var()))
+            .collect();
+        if !params.is_empty() {
+            substitutions = substitutions
+                .into_iter()
+                .map(|(label, term)| (label, term.replace_params(&params)))
+                .collect();
+        }
+        for (name, term) in function_terms {
+            let term = term.substitute(&substitutions).replace_params(&params);
+            schemes.insert(name, Scheme::generalize(&term, &substitutions, &operands));
+        }
+    }
+
+    for expr in program {
+        let names = || match &expr.node {
+            AstNode::LetNodeTopLevel(id, _) => vec![id.id.clone()],
+            AstNode::LetPatternNodeTopLevel(pattern, _) => pattern.variables(),
+            _ => vec![],
+        };
+        let context = InferenceContext::new(HashMap::new(), &schemes, data_decl_table);
+        let (constraint_set, declared) = match generate_constraints_top_level(expr, context) {
+            Ok(generated) => generated,
+            Err(e) => {
+                fail(e, &mut schemes, names());
+                continue;
+            }
+        };
+        let (solved, operands) =
+            match unify_constraints_incremental(constraint_set, substitutions.clone()) {
+                Ok(solved) => solved,
+                Err(e) => {
+                    fail(e, &mut schemes, names());
+                    continue;
+                }
+            };
+        substitutions = solved;
+        for (name, label) in declared {
+            let scheme = Scheme::generalize(&Term::Var(label), &substitutions, &operands);
+            schemes.insert(name, scheme);
+        }
+    }
+
+    let state = InferenceState {
+        schemes,
+        substitutions,
+    };
+    (state, first_error)
 }
@@ -3,69 +3,310 @@ use std::iter::once;
 use crate::ast::Symbol;
 
 use super::{
-    ast::{Constraint, ConstraintSet, SubstitutionSet, Term},
+    ast::{
+        Constraint, ConstraintSet, FieldConstraint, OperandConstraint, Origin, SubstitutionSet,
+        Term,
+    },
     type_inference::InferenceError,
 };
-use im::HashMap;
+use im::{HashMap, HashSet};
+use std::collections::VecDeque;
 
 pub fn unify_constraints(constraint_set: ConstraintSet) -> Result<SubstitutionSet, InferenceError> {
-    let mut constraint_set: Vec<Constraint> = constraint_set.into_vec();
-    let mut substitution_set: SubstitutionSet = HashMap::new();
+    let (substitution_set, _) = unify_constraints_incremental(constraint_set, HashMap::new())?;
+    Ok(substitution_set)
+}
+
+/// A constraint waiting to be solved, along with the type variables that each of its sides
+/// had before anything was substituted into them. They explain where its terms came from if
+/// it can't be solved.
+struct Pending {
+    constraint: Constraint,
+    left_vars: HashSet<Symbol>,
+    right_vars: HashSet<Symbol>,
+}
+
+/// How a type variable was solved: the origin of the constraint that solved it, and the type
+/// variables that constraint was between
+#[derive(Clone)]
+struct Solution {
+    origin: Option<Origin>,
+    vars: HashSet<Symbol>,
+}
+
+/// Solves a set of constraints on top of an existing (already solved) substitution set.
+/// The substitution set is applied to the new constraints before unification begins.
+///
+/// Field constraints are solved once the other constraints have been, when the types they're
+/// on might be known. Solving them can make new constraints, so this repeats until none of
+/// the field constraints that are left can be solved. Those are left unsolved, so the types of
+/// their fields can be anything. Likewise, operand constraints are checked once the types of
+/// their operands are known. The ones whose operands are never known are returned alongside
+/// the substitution set, so that generalized definitions can check them at their uses.
+pub fn unify_constraints_incremental(
+    constraint_set: ConstraintSet,
+    substitution_set: SubstitutionSet,
+) -> Result<(SubstitutionSet, Vec<OperandConstraint>), InferenceError> {
+    let mut field_constraints = constraint_set.field_constraints();
+    let mut operand_constraints = constraint_set.operand_constraints();
+    let mut constraint_set: Vec<Pending> = constraint_set
+        .into_vec()
+        .into_iter()
+        .map(|constraint| Pending {
+            left_vars: constraint.left.free_vars(),
+            right_vars: constraint.right.free_vars(),
+            constraint: Constraint {
+                left: constraint.left.substitute(&substitution_set),
+                right: constraint.right.substitute(&substitution_set),
+                origin: constraint.origin,
+            },
+        })
+        .collect();
+    let mut substitution_set = substitution_set;
+    let mut solutions: HashMap<Symbol, Solution> = HashMap::new();
 
     loop {
-        match constraint_set.pop() {
-            Some(constraint) => {
-                let (left, right) = constraint;
-                match left {
-                    Term::Var(l) => {
-                        if left != right {
-                            if occurs_check(&left, &right) {
-                                return Err(InferenceError::InfiniteType());
-                            }
-                            constraint_set = replace_in_constraints(l, &right, constraint_set);
-                            substitution_set =
-                                replace_in_substitutions(l, &right, substitution_set);
+        solve(&mut constraint_set, &mut substitution_set, &mut solutions)?;
+
+        let mut unsolved = vec![];
+        for field_constraint in field_constraints {
+            match solve_field(&field_constraint, &substitution_set)? {
+                Some(constraints) => constraint_set.extend(constraints),
+                None => unsolved.push(field_constraint),
+            }
+        }
+        field_constraints = unsolved;
+        operand_constraints = operand_constraints
+            .into_iter()
+            .filter_map(|operand_constraint| {
+                check_operand(&operand_constraint, &substitution_set)
+                    .map(|known| {
+                        if known {
+                            None
+                        } else {
+                            Some(operand_constraint)
                         }
+                    })
+                    .transpose()
+            })
+            .collect::<Result<_, _>>()?;
+        if constraint_set.is_empty() {
+            return Ok((substitution_set, operand_constraints));
+        }
+    }
+}
+
+/// Solves every pending constraint, adding the solutions to the substitution set
+fn solve(
+    constraint_set: &mut Vec<Pending>,
+    substitution_set: &mut SubstitutionSet,
+    solutions: &mut HashMap<Symbol, Solution>,
+) -> Result<(), InferenceError> {
+    while let Some(pending) = constraint_set.pop() {
+        let Pending {
+            constraint:
+                Constraint {
+                    left,
+                    right,
+                    origin,
+                },
+            left_vars,
+            right_vars,
+        } = pending;
+        match left {
+            Term::Var(l) => {
+                if left != right {
+                    if occurs_check(&left, &right) {
+                        return Err(InferenceError::InfiniteType(origin));
                     }
-                    Term::Constructor(head1, args1) => match right {
-                        Term::Var(r) => {
-                            constraint_set.push((Term::Var(r), Term::Constructor(head1, args1)));
-                        }
-                        Term::Constructor(head2, args2) => {
-                            // If either type is any then the type check automatically passes
-                            if head1 == "Any" || head2 == "Any" {
-                                continue;
-                            }
-                            if head1 == head2 {
-                                constraint_set.extend(args1.into_iter().zip(args2))
-                            } else {
-                                return Err(InferenceError::ConstructorMismatch(
-                                    Term::Constructor(head1, args1),
-                                    Term::Constructor(head2, args2),
-                                ));
-                            }
-                        }
-                    },
+                    *constraint_set =
+                        replace_in_constraints(l, &right, std::mem::take(constraint_set));
+                    *substitution_set =
+                        replace_in_substitutions(l, &right, std::mem::take(substitution_set));
+                    solutions.insert(
+                        l,
+                        Solution {
+                            origin,
+                            vars: left_vars.union(right_vars),
+                        },
+                    );
+                }
+            }
+            _ => match right {
+                Term::Var(r) => {
+                    constraint_set.push(Pending {
+                        constraint: Constraint {
+                            left: Term::Var(r),
+                            right: left,
+                            origin,
+                        },
+                        left_vars: right_vars,
+                        right_vars: left_vars,
+                    });
                 }
+                _ => {
+                    // If either type is any then the type check automatically passes
+                    if left == Term::any() || right == Term::any() {
+                        continue;
+                    }
+                    // Constructors with the same name can still differ in their number of
+                    // arguments, like tuples or functions of different sizes
+                    if left.same_shape(&right) {
+                        let args = left.args().into_iter().zip(right.args());
+                        constraint_set.extend(args.map(|(left, right)| Pending {
+                            constraint: Constraint {
+                                left: left.clone(),
+                                right: right.clone(),
+                                origin: origin.clone(),
+                            },
+                            left_vars: left_vars.clone(),
+                            right_vars: right_vars.clone(),
+                        }))
+                    } else {
+                        // The side that an earlier constraint explains is the expected one.
+                        // Otherwise it's the right, where constraints are generated with the
+                        // type that an expression needs to have.
+                        let left_reason = explain(&left_vars, solutions, &origin);
+                        let right_reason = explain(&right_vars, solutions, &origin);
+                        return Err(match left_reason {
+                            Some(reason) => InferenceError::ConstructorMismatch(
+                                left,
+                                right,
+                                origin,
+                                Some(reason),
+                            ),
+                            None => InferenceError::ConstructorMismatch(
+                                right,
+                                left,
+                                origin,
+                                right_reason,
+                            ),
+                        });
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Turns a field constraint into constraints on the type of the field, or returns None if the
+/// type constructor of its target isn't known yet
+fn solve_field(
+    field_constraint: &FieldConstraint,
+    substitution_set: &SubstitutionSet,
+) -> Result<Option<Vec<Pending>>, InferenceError> {
+    let target = field_constraint.target.substitute(substitution_set);
+    let pending = |left: Term, right: Term| Pending {
+        left_vars: left.free_vars(),
+        right_vars: right.free_vars(),
+        constraint: Constraint {
+            left: left.substitute(substitution_set),
+            right: right.substitute(substitution_set),
+            origin: field_constraint.origin.clone(),
+        },
+    };
+    let FieldConstraint {
+        field, field_term, ..
+    } = field_constraint;
+    let head = match &target {
+        Term::Var(_) => return Ok(None),
+        Term::Constructor(head, _) => head.as_str(),
+        Term::Record(fields) => match fields.iter().find(|(name, _)| name == field) {
+            Some((_, term)) => return Ok(Some(vec![pending(field_term.clone(), term.clone())])),
+            None => "",
+        },
+    };
+    if head == "Any" {
+        return Ok(Some(vec![pending(field_term.clone(), Term::any())]));
+    }
+    let data_field = field_constraint.data_fields.iter().find(
+        |(data_term, _)| matches!(data_term, Term::Constructor(data_head, _) if data_head == head),
+    );
+    match data_field {
+        Some((data_term, data_field_term)) => Ok(Some(vec![
+            pending(target.clone(), data_term.clone()),
+            pending(field_term.clone(), data_field_term.clone()),
+        ])),
+        None => Err(InferenceError::MissingField(
+            target.clone(),
+            field.clone(),
+            field_constraint.origin.clone(),
+        )),
+    }
+}
+
+/// Checks that the operand of an operator has a type the operator works on, returning whether
+/// its type constructor is known yet
+fn check_operand(
+    operand_constraint: &OperandConstraint,
+    substitution_set: &SubstitutionSet,
+) -> Result<bool, InferenceError> {
+    let OperandConstraint {
+        operand,
+        operator,
+        types,
+        origin,
+    } = operand_constraint;
+    match operand.substitute(substitution_set) {
+        Term::Var(_) => Ok(false),
+        Term::Constructor(head, _) if head == "Any" || types.contains(&head) => Ok(true),
+        operand => Err(InferenceError::UnsupportedOperand(
+            operand,
+            operator.clone(),
+            types.clone(),
+            origin.clone(),
+        )),
+    }
+}
+
+/// Finds why some type variables have the types they were solved to, by following the
+/// constraints that solved them back to a type annotation. Failing that, it's the first
+/// constraint found that came from somewhere other than the constraint that failed.
+fn explain(
+    vars: &HashSet<Symbol>,
+    solutions: &HashMap<Symbol, Solution>,
+    failed: &Option<Origin>,
+) -> Option<Origin> {
+    let failed_span = failed.as_ref().map(|origin| &origin.src_loc);
+    let mut reason = None;
+    let mut visited = HashSet::new();
+    let mut queue: VecDeque<Symbol> = vars.iter().cloned().collect();
+    while let Some(var) = queue.pop_front() {
+        if visited.insert(var).is_some() {
+            continue;
+        }
+        let solution = match solutions.get(&var) {
+            Some(solution) => solution,
+            None => continue,
+        };
+        if let Some(origin) = &solution.origin {
+            if origin.is_annotation {
+                return Some(origin.clone());
+            }
+            if reason.is_none() && Some(&origin.src_loc) != failed_span {
+                reason = Some(origin.clone());
             }
-            None => break,
         }
+        queue.extend(solution.vars.iter().cloned());
     }
-    Ok(substitution_set)
+    reason
 }
 
 fn replace_in_constraints(
     replace: Symbol,
     with: &Term,
-    constraint_set: Vec<Constraint>,
-) -> Vec<Constraint> {
+    constraint_set: Vec<Pending>,
+) -> Vec<Pending> {
     constraint_set
         .into_iter()
-        .map(|(left, right)| {
-            (
-                replace_in_term(replace, with, left),
-                replace_in_term(replace, with, right),
-            )
+        .map(|pending| Pending {
+            constraint: Constraint {
+                left: replace_in_term(replace, with, pending.constraint.left),
+                right: replace_in_term(replace, with, pending.constraint.right),
+                origin: pending.constraint.origin,
+            },
+            ..pending
         })
         .collect()
 }
@@ -82,12 +323,7 @@ fn replace_in_term(replace: Symbol, with: &Term, target: Term) -> Term {
                     target
                 }
             }
-            Term::Constructor(head, args) => Term::Constructor(
-                head,
-                args.into_iter()
-                    .map(|arg| replace_in_term(replace, with, arg))
-                    .collect(),
-            ),
+            _ => target.map_args(|arg| replace_in_term(replace, with, arg.clone())),
         }
     }
 }
@@ -108,8 +344,9 @@ fn occurs_check(replace: &Term, with: &Term) -> bool {
     *replace == *with
         || match with {
             Term::Var(_) => false,
-            Term::Constructor(_head, args) => {
-                args.into_iter().any(|arg| occurs_check(replace, arg))
-            }
+            _ => with
+                .args()
+                .into_iter()
+                .any(|arg| occurs_check(replace, arg)),
         }
 }
@@ -6,23 +6,27 @@ pub fn add_any_to_declarations(program: Program) -> Program {
     program
         .into_iter()
         .map(|mut expr| match expr.node {
-            AstNode::FunctionNode(function_name, param_list, return_type, body) => {
+            AstNode::FunctionNode(function_name, type_params, param_list, return_type, body) => {
                 // Convert any parameters that don't have a type to any
                 let param_list = param_list
                     .into_iter()
-                    .map(|param| Identifier::new(param.id, Type::none_to_any(param.type_decl)))
+                    .map(|param| Identifier {
+                        src_loc: param.src_loc,
+                        ..Identifier::new(param.id, Type::none_to_any(param.type_decl))
+                    })
                     .collect();
 
                 // Convert any return types that don't have a type to any
                 expr.node = AstNode::FunctionNode(
                     function_name,
+                    type_params,
                     param_list,
                     Type::none_to_any(return_type),
                     body,
                 );
                 expr
             }
-            AstNode::DataDeclarationNode(data_name, data_variants) => {
+            AstNode::DataDeclarationNode(data_name, type_params, data_variants) => {
                 // Convert any variant members that don't have a type to any
                 let data_variants = data_variants
                     .into_iter()
@@ -31,14 +35,18 @@ pub fn add_any_to_declarations(program: Program) -> Program {
                             variant_name,
                             variant_members
                                 .into_iter()
-                                .map(|member| {
-                                    Identifier::new(member.id, Type::none_to_any(member.type_decl))
+                                .map(|member| Identifier {
+                                    src_loc: member.src_loc,
+                                    ..Identifier::new(
+                                        member.id,
+                                        Type::none_to_any(member.type_decl),
+                                    )
                                 })
                                 .collect(),
                         )
                     })
                     .collect();
-                expr.node = AstNode::DataDeclarationNode(data_name, data_variants);
+                expr.node = AstNode::DataDeclarationNode(data_name, type_params, data_variants);
                 expr
             }
             _ => expr,
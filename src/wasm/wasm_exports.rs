@@ -1,4 +1,5 @@
-use crate::runtime::CliArgs;
+use crate::interpreter::limits::EvalLimits;
+use crate::runtime::{CliArgs, Session};
 use std::fmt::Write;
 use wasm_bindgen::prelude::*;
 
@@ -57,12 +58,24 @@ pub fn evaluate(raw: String) -> () {
     colored::control::set_override(true);
     set_panic_hook();
 
-    let args = CliArgs::new(std::path::PathBuf::from("main.boat"));
+    let mut args = CliArgs::new(std::path::PathBuf::from("main.boat"));
+    // Keep runaway programs from hanging the page or overflowing the (small) wasm stack
+    args.limits = EvalLimits {
+        max_call_depth: Some(500),
+        max_steps: Some(10_000_000),
+        max_memory: Some(64 * 1024 * 1024),
+        // The default stack size of Rust's wasm target
+        stack_size: Some(1024 * 1024),
+    };
 
-    let output = crate::runtime::evaluate(args, raw, &mut WasmPrinter::new());
+    let mut session = Session::new();
+    session.stream_printed(|line| {
+        writeTermLn(line);
+    });
+    let output = session.evaluate(&args, raw, &mut WasmPrinter::new());
 
     if let Ok(Some(e)) = output {
-        for val in e {
+        for (val, _) in e {
             writeTermLn(&format!("{}", val));
         }
     } else {
@@ -4,21 +4,42 @@ use std::collections::HashMap;
 #[derive(Clone, Debug, PartialEq)]
 pub enum SimpleVal {
     Num(i64),
+    /// The digits of an integer that doesn't fit in an i64
+    BigNum(String),
+    Float(f64),
     Bool(bool),
+    Str(String),
     Lam(),
     Data(String, Vec<SimpleVal>),
+    List(Vec<SimpleVal>),
+    /// The fields of a record, in order of their names
+    Record(Vec<(String, SimpleVal)>),
+    Tuple(Vec<SimpleVal>),
 }
 
 impl<'a> SimpleVal {
     pub fn new(val: &Val) -> SimpleVal {
         match val {
             Val::Num(n) => SimpleVal::Num(*n),
+            Val::BigNum(n) => SimpleVal::BigNum(n.to_string()),
+            Val::Float(x) => SimpleVal::Float(*x),
             Val::Bool(b) => SimpleVal::Bool(*b),
-            Val::Lam(_, _, _) => SimpleVal::Lam(),
+            Val::Str(s) => SimpleVal::Str(s.clone()),
+            Val::Lam(_, _, _) | Val::Native(_) | Val::Cast(_, _) => SimpleVal::Lam(),
             Val::Data(discriminant, fields) => SimpleVal::Data(
                 discriminant.get_variant().to_string(),
                 fields.iter().map(|x| SimpleVal::new(x)).collect(),
             ),
+            Val::List(items) => SimpleVal::List(items.iter().map(|x| SimpleVal::new(x)).collect()),
+            Val::Record(fields) => SimpleVal::Record(
+                fields
+                    .iter()
+                    .map(|(field, x)| (field.clone(), SimpleVal::new(x)))
+                    .collect(),
+            ),
+            Val::Tuple(items) => {
+                SimpleVal::Tuple(items.iter().map(|x| SimpleVal::new(x)).collect())
+            }
         }
     }
 }
@@ -38,14 +59,198 @@ pub fn get_expected_output<'a>() -> HashMap<&'a str, Vec<SimpleVal>> {
         ("if_elif.boat", vec![SimpleVal::Num(3)]),
         ("if_elif_else.boat", vec![SimpleVal::Num(4)]),
         ("let_recursive_shadow.boat", vec![SimpleVal::Num(1)]),
+        (
+            "let_polymorphism.boat",
+            vec![
+                SimpleVal::Num(1),
+                SimpleVal::Bool(true),
+                SimpleVal::Num(2),
+                SimpleVal::Str("hi!".to_string()),
+                SimpleVal::Num(3),
+                SimpleVal::Data(
+                    "link".to_string(),
+                    vec![
+                        SimpleVal::Bool(false),
+                        SimpleVal::Data(
+                            "link".to_string(),
+                            vec![
+                                SimpleVal::Bool(true),
+                                SimpleVal::Data("empty".to_string(), vec![]),
+                            ],
+                        ),
+                    ],
+                ),
+            ],
+        ),
+        (
+            "imports.boat",
+            vec![
+                SimpleVal::Num(3),
+                SimpleVal::Num(3),
+                SimpleVal::Num(6),
+                SimpleVal::Str("math".to_string()),
+                SimpleVal::Str("lists".to_string()),
+                SimpleVal::Num(5),
+                SimpleVal::Num(0),
+                SimpleVal::Num(2),
+            ],
+        ),
         ("let_with_no_exprs.boat", vec![]),
         (
             "let_with_simple_exprs.boat",
             vec![SimpleVal::Num(7), SimpleVal::Num(15), SimpleVal::Num(6)],
         ),
+        (
+            "parameterized_types.boat",
+            vec![
+                SimpleVal::Num(3),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(false),
+                SimpleVal::Data(
+                    "link".to_string(),
+                    vec![
+                        SimpleVal::Str("a".to_string()),
+                        SimpleVal::Data("empty".to_string(), vec![]),
+                    ],
+                ),
+            ],
+        ),
         ("paren_overrides_precedence.boat", vec![SimpleVal::Num(9)]),
         ("plus_and_times_precedence.boat", vec![SimpleVal::Num(7)]),
+        (
+            "prelude.boat",
+            vec![
+                SimpleVal::List(vec![
+                    SimpleVal::Num(3),
+                    SimpleVal::Num(4),
+                    SimpleVal::Num(5),
+                ]),
+                SimpleVal::List(vec![SimpleVal::Num(3), SimpleVal::Num(5)]),
+                SimpleVal::Num(4),
+                SimpleVal::Num(5),
+                SimpleVal::Num(2),
+                SimpleVal::Str("some(1) and true".to_string()),
+                SimpleVal::Data("ok".to_string(), vec![SimpleVal::Num(5)]),
+                SimpleVal::Data(
+                    "err".to_string(),
+                    vec![SimpleVal::Str("division by zero".to_string())],
+                ),
+            ],
+        ),
+        (
+            "list_literals.boat",
+            vec![
+                SimpleVal::List(vec![
+                    SimpleVal::Num(0),
+                    SimpleVal::Num(1),
+                    SimpleVal::Num(2),
+                    SimpleVal::Num(3),
+                ]),
+                SimpleVal::List(vec![]),
+                SimpleVal::Num(6),
+                SimpleVal::Str("empty".to_string()),
+                SimpleVal::Str("one item: a".to_string()),
+                SimpleVal::Str("two items".to_string()),
+                SimpleVal::Str("starts with 1 and 2".to_string()),
+                SimpleVal::List(vec![
+                    SimpleVal::Num(2),
+                    SimpleVal::Num(1),
+                    SimpleVal::Num(3),
+                ]),
+                SimpleVal::List(vec![
+                    SimpleVal::Num(1),
+                    SimpleVal::Num(2),
+                    SimpleVal::Num(3),
+                ]),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(false),
+                SimpleVal::List(vec![
+                    SimpleVal::List(vec![SimpleVal::Num(1)]),
+                    SimpleVal::List(vec![]),
+                ]),
+                SimpleVal::List(vec![
+                    SimpleVal::Num(10),
+                    SimpleVal::Num(20),
+                    SimpleVal::Num(30),
+                ]),
+            ],
+        ),
+        (
+            "records.boat",
+            vec![
+                SimpleVal::Num(7),
+                SimpleVal::Record(vec![
+                    ("x".to_string(), SimpleVal::Num(10)),
+                    ("y".to_string(), SimpleVal::Num(4)),
+                ]),
+                SimpleVal::Record(vec![
+                    ("x".to_string(), SimpleVal::Num(3)),
+                    ("y".to_string(), SimpleVal::Num(4)),
+                ]),
+                SimpleVal::Num(25),
+                SimpleVal::Num(6),
+                SimpleVal::Data(
+                    "rect".to_string(),
+                    vec![SimpleVal::Num(2), SimpleVal::Num(5)],
+                ),
+                SimpleVal::Str("origin".to_string()),
+                SimpleVal::Str("on the x axis at 2".to_string()),
+                SimpleVal::Str("on the y axis".to_string()),
+                SimpleVal::Str("elsewhere".to_string()),
+                SimpleVal::Num(2),
+                SimpleVal::Bool(true),
+            ],
+        ),
+        (
+            "match_guards.boat",
+            vec![
+                SimpleVal::Str("zero".to_string()),
+                SimpleVal::Str("small".to_string()),
+                SimpleVal::Str("large".to_string()),
+                SimpleVal::Str("big 500".to_string()),
+                SimpleVal::Str("medium 50".to_string()),
+                SimpleVal::Str("tiny 5".to_string()),
+                SimpleVal::Str("nothing".to_string()),
+                SimpleVal::Tuple(vec![SimpleVal::Num(1), SimpleVal::Num(3)]),
+            ],
+        ),
+        (
+            "pattern_alternatives.boat",
+            vec![
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(false),
+                SimpleVal::Bool(false),
+                SimpleVal::Num(4),
+                SimpleVal::Num(9),
+                SimpleVal::Num(2),
+                SimpleVal::Data(
+                    "link".to_string(),
+                    vec![
+                        SimpleVal::Num(1),
+                        SimpleVal::Data("stop".to_string(), vec![]),
+                    ],
+                ),
+                SimpleVal::Str("fizzbuzz".to_string()),
+                SimpleVal::Str("fizz".to_string()),
+                SimpleVal::Str("buzz".to_string()),
+                SimpleVal::Str("neither".to_string()),
+                SimpleVal::Num(2),
+            ],
+        ),
         ("simple_bool.boat", vec![SimpleVal::Bool(false)]),
+        (
+            "tuples.boat",
+            vec![
+                SimpleVal::Tuple(vec![SimpleVal::Num(1), SimpleVal::Str("one".to_string())]),
+                SimpleVal::Bool(true),
+                SimpleVal::Num(32),
+                SimpleVal::Tuple(vec![SimpleVal::Str("one".to_string()), SimpleVal::Num(1)]),
+                SimpleVal::Str("first".to_string()),
+                SimpleVal::Str("neither".to_string()),
+                SimpleVal::Num(2),
+                SimpleVal::Num(6),
+            ],
+        ),
         ("simple_hof.skf", vec![SimpleVal::Num(3)]),
         ("simple_if.boat", vec![SimpleVal::Num(1)]),
         (
@@ -75,6 +280,38 @@ pub fn get_expected_output<'a>() -> HashMap<&'a str, Vec<SimpleVal>> {
                 SimpleVal::Num(9),
             ],
         ),
+        (
+            "big_integers.boat",
+            vec![
+                SimpleVal::BigNum("265252859812191058636308480000000".to_string()),
+                SimpleVal::Num(870),
+                SimpleVal::Num(9223372036854775807),
+                SimpleVal::BigNum("-12157665459056928801".to_string()),
+                SimpleVal::BigNum("18446744073709551616".to_string()),
+                SimpleVal::Float(0.25),
+                SimpleVal::Num(1),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(true),
+            ],
+        ),
+        (
+            "floats.boat",
+            vec![
+                SimpleVal::Float(3.75),
+                SimpleVal::Num(3),
+                SimpleVal::Float(3.5),
+                SimpleVal::Float(0.5),
+                SimpleVal::Float(1000.0),
+                SimpleVal::Float(1.0),
+                SimpleVal::Float(1.5),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(false),
+                SimpleVal::Bool(true),
+                SimpleVal::Num(2),
+                SimpleVal::Float(1.5),
+                SimpleVal::Str("0.3333333333333333".to_string()),
+            ],
+        ),
         ("pattern_match_simple.boat", vec![SimpleVal::Num(2)]),
         ("pattern_match_moderate.boat", vec![SimpleVal::Num(8)]),
         (
@@ -124,15 +361,62 @@ pub fn get_expected_output<'a>() -> HashMap<&'a str, Vec<SimpleVal>> {
                 SimpleVal::Num(10),
             ],
         ),
+        (
+            "strings.boat",
+            vec![
+                SimpleVal::Str("hello, world".to_string()),
+                SimpleVal::Str("tab\there \"quoted\"".to_string()),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(false),
+                SimpleVal::Bool(true),
+                SimpleVal::Bool(true),
+                SimpleVal::Num(2),
+                SimpleVal::Str("Hi, Skiff!".to_string()),
+            ],
+        ),
+        (
+            "tail_calls.boat",
+            vec![SimpleVal::Num(200010000), SimpleVal::Bool(false)],
+        ),
         ("type_annotation_parsing.boat", vec![]),
         ("type_inference_simple.boat", vec![SimpleVal::Num(5)]),
         (
             "type_inference_DDs_default_to_any.boat",
             vec![SimpleVal::Num(1)],
         ),
+        ("type_inference_fails_omega.boat", vec![]),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    return map;
+}
+
+/// The code of each diagnostic that a file in the error directory prints, along with the
+/// 1-based line and column it points at (if it has a location). Files that aren't listed
+/// only have to fail.
+pub fn get_expected_diagnostics<'a>() -> HashMap<&'a str, Vec<(&'a str, Option<(usize, usize)>)>> {
+    let map: HashMap<&str, Vec<(&str, Option<(usize, usize)>)>> = [
+        (
+            "match_non_exhaustive_after_type_error.boat",
+            vec![("E0203", Some((7, 17))), ("E0301", Some((9, 6)))],
+        ),
+        (
+            "cast_untyped_data_field.boat",
+            vec![("E0404", Some((10, 5)))],
+        ),
+        (
+            "or_pattern_uneven_bindings.boat",
+            vec![("E0211", Some((8, 7)))],
+        ),
+        (
+            "let_polymorphism_operand_mismatch.boat",
+            vec![("E0212", Some((6, 1)))],
+        ),
         (
-            "type_inference_fails_omega.boat",
-            vec![],
+            "match_pattern_wrong_arity.boat",
+            vec![("E0205", Some((3, 7)))],
         ),
     ]
     .iter()
@@ -1,20 +1,12 @@
 mod common;
 use common::SimpleVal;
+use skiff::error_handling::ErrorFormat;
 use skiff::runtime::{evaluate, CliArgs, SkiffError};
 use std::collections::HashMap;
-use std::fmt::Write;
 use std::fs::{self, ReadDir};
 
 static VERBOSE: bool = true;
 
-struct ConsolePrinter;
-impl Write for ConsolePrinter {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        print!("{}", s);
-        Ok(())
-    }
-}
-
 #[derive(PartialEq, Debug, Clone, Hash)]
 struct TestError(SkiffError);
 
@@ -23,16 +15,30 @@ pub fn diff_test() {
     let success_directory = "./tests/files/success";
     let error_directory = "./tests/files/error";
 
-    let success_paths = fs::read_dir(success_directory).unwrap();
-    let error_paths = fs::read_dir(error_directory).unwrap();
+    // Every file should behave the same with both backends
+    for use_bytecode in [false, true] {
+        let success_paths = fs::read_dir(success_directory).unwrap();
+        let error_paths = fs::read_dir(error_directory).unwrap();
 
-    let expected_outputs = common::get_expected_output();
+        let expected_outputs = common::get_expected_output();
+        let expected_diagnostics = common::get_expected_diagnostics();
 
-    run_paths(success_paths, Some(expected_outputs));
-    run_paths(error_paths, None);
+        run_paths(
+            success_paths,
+            Some(expected_outputs),
+            &expected_diagnostics,
+            use_bytecode,
+        );
+        run_paths(error_paths, None, &expected_diagnostics, use_bytecode);
+    }
 }
 
-fn run_paths(paths: ReadDir, expected_outputs: Option<HashMap<&str, Vec<SimpleVal>>>) {
+fn run_paths(
+    paths: ReadDir,
+    expected_outputs: Option<HashMap<&str, Vec<SimpleVal>>>,
+    expected_diagnostics: &HashMap<&str, Vec<(&str, Option<(usize, usize)>)>>,
+    use_bytecode: bool,
+) {
     for path in paths {
         let path = path.unwrap();
 
@@ -40,10 +46,10 @@ fn run_paths(paths: ReadDir, expected_outputs: Option<HashMap<&str, Vec<SimpleVa
         if path.file_type().unwrap().is_dir() {
             continue;
         }
-        println!("Running file {:?}", &path);
+        println!("Running file {:?} (bytecode: {})", &path, use_bytecode);
 
         // run the file and see if it returned a result or errored
-        match run_file(path.path().clone()) {
+        match run_file(path.path().clone(), use_bytecode) {
             Ok(actual_output) => {
                 match &expected_outputs {
                     Some(expected_outputs) => {
@@ -64,8 +70,9 @@ fn run_paths(paths: ReadDir, expected_outputs: Option<HashMap<&str, Vec<SimpleVa
                         assert_eq!(
                             *expected_output,
                             actual_output,
-                            "Testing file {:?}",
-                            path.file_name()
+                            "Testing file {:?} (bytecode: {})",
+                            path.file_name(),
+                            use_bytecode
                         )
                     }
                     None => {
@@ -80,7 +87,7 @@ fn run_paths(paths: ReadDir, expected_outputs: Option<HashMap<&str, Vec<SimpleVa
                     }
                 }
             }
-            Err(e) => {
+            Err((e, printed)) => {
                 match expected_outputs {
                     Some(_) => {
                         // If running the file errors, log the name and fail.
@@ -88,20 +95,91 @@ fn run_paths(paths: ReadDir, expected_outputs: Option<HashMap<&str, Vec<SimpleVa
                         println!("Error: {:?}", e);
                         assert!(false);
                     }
-                    None => assert!(true),
+                    None => {
+                        // Check what was reported if the file has expected diagnostics
+                        let file_name = path.file_name().into_string().unwrap();
+                        if let Some(expected) = expected_diagnostics.get(file_name.as_str()) {
+                            let actual: Vec<(String, Option<(usize, usize)>)> = printed
+                                .lines()
+                                // Skip anything the program printed itself
+                                .filter(|line| line.starts_with("{\"severity\":"))
+                                .map(diagnostic_position)
+                                .collect();
+                            let expected: Vec<(String, Option<(usize, usize)>)> = expected
+                                .iter()
+                                .map(|(code, position)| (code.to_string(), *position))
+                                .collect();
+                            assert_eq!(
+                                expected, actual,
+                                "Testing file {:?} (bytecode: {})",
+                                file_name, use_bytecode
+                            )
+                        }
+                    }
                 }
             }
         }
     }
 }
 
-fn run_file<'a>(path: std::path::PathBuf) -> Result<Vec<SimpleVal>, TestError> {
+/// The tree-walking interpreter keeps each nested call on the native stack, so it stops deep
+/// recursion with an error once the stack it was given runs out. The VM keeps its frames on the
+/// heap, so the same program runs to completion there.
+#[test]
+pub fn nesting_depth_differs_between_backends() {
+    const STACK_SIZE: usize = 8 * 1024 * 1024;
+    let raw = "def count(n):\n    if n == 0: 0 else: 1 + count(n - 1) end\nend\ncount(100000)\n";
+
+    let run = |use_bytecode: bool| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut args = CliArgs::new("nesting.boat".into());
+                args.use_bytecode = use_bytecode;
+                args.error_format = ErrorFormat::Json;
+                args.limits.stack_size = Some(STACK_SIZE);
+                let mut printed = String::new();
+                let result = evaluate(args, raw.to_string(), &mut printed)
+                    .map(|output| output.map(|vals| vals.iter().map(SimpleVal::new).collect()));
+                (result.is_ok(), result.ok().flatten(), printed)
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    };
+
+    let (succeeded, _, printed) = run(false);
+    assert!(!succeeded);
+    let diagnostic = printed
+        .lines()
+        .find(|line| line.starts_with("{\"severity\":"))
+        .expect("Expected a diagnostic");
+    assert_eq!(diagnostic_position(diagnostic).0, "E0403");
+    assert!(printed.contains("ran out of stack space"), "{}", printed);
+
+    let (succeeded, output, printed) = run(true);
+    assert!(succeeded, "{}", printed);
+    assert_eq!(output, Some(vec![SimpleVal::Num(100000)]));
+}
+
+/// Runs a file, returning its values, or the error it failed with along with the diagnostics it
+/// printed as JSON
+fn run_file<'a>(
+    path: std::path::PathBuf,
+    use_bytecode: bool,
+) -> Result<Vec<SimpleVal>, (TestError, String)> {
     let raw = fs::read_to_string(path.clone()).expect("Something went wrong reading the file");
 
-    let output = match evaluate(CliArgs::new(path), raw, &mut ConsolePrinter) {
+    let mut args = CliArgs::new(path);
+    args.use_bytecode = use_bytecode;
+    args.error_format = ErrorFormat::Json;
+    let mut printed = String::new();
+    let result = evaluate(args, raw, &mut printed);
+    print!("{}", printed);
+    let output = match result {
         Ok(Some(o)) => o,
         Ok(None) => panic!("Test run aborted early"),
-        Err(e) => return Err(TestError(e)),
+        Err(e) => return Err((TestError(e), printed)),
     };
 
     // Gather output
@@ -112,3 +190,26 @@ fn run_file<'a>(path: std::path::PathBuf) -> Result<Vec<SimpleVal>, TestError> {
 
     return Ok(out);
 }
+
+/// Finds the code of a diagnostic printed as JSON and the line and column that it points at
+fn diagnostic_position(json: &str) -> (String, Option<(usize, usize)>) {
+    let field = |json: &str, name: &str| -> Option<String> {
+        let start = json.find(&format!("\"{}\":", name))? + name.len() + 3;
+        let value: String = json[start..]
+            .chars()
+            .take_while(|c| *c != ',' && *c != '}')
+            .collect();
+        Some(value.trim_matches('"').to_string())
+    };
+    let code = field(json, "code").unwrap_or_default();
+    let position = match field(json, "span") {
+        Some(span) if span == "null" => None,
+        _ => {
+            // The first line and column are the diagnostic's own, before those of its labels
+            let line = field(json, "line").and_then(|line| line.parse().ok());
+            let column = field(json, "column").and_then(|column| column.parse().ok());
+            line.zip(column)
+        }
+    };
+    (code, position)
+}
@@ -0,0 +1,69 @@
+mod common;
+use common::SimpleVal;
+use skiff::formatter::format;
+use skiff::prelude;
+use skiff::runtime::{evaluate, CliArgs};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct NullPrinter;
+impl Write for NullPrinter {
+    fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+        Ok(())
+    }
+}
+
+/// Lists the Skiff files in a directory and its subdirectories
+fn skiff_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(skiff_files(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+#[test]
+pub fn formatting_is_idempotent() {
+    for path in skiff_files(Path::new("./tests/files")) {
+        let raw = fs::read_to_string(&path).unwrap();
+        // Files that don't parse can't be formatted
+        if let Ok(formatted) = format(&raw) {
+            assert_eq!(
+                format(&formatted).unwrap(),
+                formatted,
+                "Formatting the formatted {:?} changed it",
+                path
+            );
+        }
+    }
+}
+
+#[test]
+pub fn formatting_keeps_the_meaning_of_programs() {
+    let expected_outputs = common::get_expected_output();
+    for path in skiff_files(Path::new("./tests/files/success")) {
+        let expected_output = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if expected_outputs.contains_key(name) => &expected_outputs[name],
+            _ => continue,
+        };
+        let formatted = format(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        // Imports are resolved relative to the original file
+        let output = evaluate(CliArgs::new(path.clone()), formatted, &mut NullPrinter)
+            .unwrap_or_else(|e| panic!("The formatted {:?} failed: {:?}", path, e))
+            .unwrap();
+        let output: Vec<_> = output.iter().map(SimpleVal::new).collect();
+        assert_eq!(*expected_output, output, "Testing formatted {:?}", path);
+    }
+}
+
+#[test]
+pub fn prelude_is_formatted() {
+    assert_eq!(format(prelude::SOURCE).unwrap(), prelude::SOURCE);
+}